@@ -0,0 +1,53 @@
+//! Number of packets relayed per hour, chain, channel, signer and outcome.
+//!
+//! The `packets_hourly` table is maintained by a trigger as packets are inserted,
+//! so that reports over long periods do not need to scan the packets themselves.
+
+use sqlx::SqlitePool;
+
+use crate::{api::Scope, db::HourlyPacketsRow, Result};
+
+/// Maximum number of rows returned by `hourly`
+pub const MAX_ROWS: u32 = 10000;
+
+/// The hourly packet counts matching the given filters within the given scope, oldest first.
+/// Defaults to the last 7 days when `from` is not given.
+pub async fn hourly(
+    pool: &SqlitePool,
+    scope: Scope<'_>,
+    chain: Option<&str>,
+    channel: Option<&str>,
+    signer: Option<&str>,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Vec<HourlyPacketsRow>> {
+    let query = format!(
+        r#"
+        SELECT chain, hour, src_channel, dst_channel, signer, effected, packets
+        FROM packets_hourly
+        WHERE   hour >= strftime('%Y-%m-%d %H:00:00', COALESCE(?1, datetime('now', '-7 days')))
+            AND (?2 IS NULL OR hour < datetime(?2))
+            AND (?3 IS NULL OR chain = ?3)
+            AND (?4 IS NULL OR src_channel = ?4 OR dst_channel = ?4)
+            AND (?5 IS NULL OR signer = ?5)
+            AND {}
+        ORDER BY hour ASC, chain, src_channel, dst_channel, signer, effected
+        LIMIT ?6
+        "#,
+        Scope::filter(7, "chain", &["src_channel", "dst_channel"])
+    );
+
+    let rows = sqlx::query_as(&query)
+        .bind(from)
+        .bind(to)
+        .bind(chain)
+        .bind(channel)
+        .bind(signer)
+        .bind(MAX_ROWS)
+        .bind(scope.chains()?)
+        .bind(scope.channels()?)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows)
+}
@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{config::Config, Result};
+
+/// A Prometheus rules file, see
+/// https://prometheus.io/docs/prometheus/latest/configuration/alerting_rules/
+#[derive(Clone, Debug, Serialize)]
+pub struct RuleFile {
+    pub groups: Vec<RuleGroup>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RuleGroup {
+    pub name: String,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Rule {
+    pub alert: String,
+    pub expr: String,
+    #[serde(rename = "for")]
+    pub for_: String,
+    pub labels: BTreeMap<String, String>,
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl Rule {
+    fn new(alert: &str, expr: String, for_: &str, severity: &str, summary: String) -> Self {
+        Self {
+            alert: alert.to_string(),
+            expr,
+            for_: for_.to_string(),
+            labels: BTreeMap::from([("severity".to_string(), severity.to_string())]),
+            annotations: BTreeMap::from([("summary".to_string(), summary)]),
+        }
+    }
+}
+
+/// Generate alerting rules for the chains in the given configuration.
+///
+/// Only metrics exported by Chain Pulse are covered.
+pub fn rules(config: &Config) -> RuleFile {
+    let groups = config
+        .chains
+        .endpoints
+        .keys()
+        .map(|chain_id| {
+            let mut rules = vec![
+                Rule::new(
+                    "ChainpulseCollectorStale",
+                    format!(r#"increase(chainpulse_txs{{chain_id="{chain_id}"}}[15m]) == 0"#),
+                    "5m",
+                    "warning",
+                    format!("No txs were collected on {chain_id} in the last 15 minutes"),
+                ),
+                Rule::new(
+                    "ChainpulseCollectorTimeouts",
+                    format!(r#"increase(chainpulse_timeouts{{chain_id="{chain_id}"}}[15m]) > 2"#),
+                    "0m",
+                    "warning",
+                    format!("The WebSocket connection to {chain_id} keeps timing out"),
+                ),
+                Rule::new(
+                    "ChainpulseCollectorErrors",
+                    format!(r#"increase(chainpulse_errors{{chain_id="{chain_id}"}}[15m]) > 10"#),
+                    "0m",
+                    "warning",
+                    format!("The collector for {chain_id} is encountering errors"),
+                ),
+                Rule::new(
+                    "ChainpulseCircuitOpen",
+                    format!(r#"increase(chainpulse_circuit_breaks{{chain_id="{chain_id}"}}[30m]) > 0"#),
+                    "0m",
+                    "warning",
+                    format!("The endpoint for {chain_id} keeps failing, the collector is backing off or switching endpoints"),
+                ),
+                Rule::new(
+                    "ChainpulseChainIdMismatch",
+                    format!(r#"increase(chainpulse_chain_id_mismatches{{chain_id="{chain_id}"}}[15m]) > 0"#),
+                    "0m",
+                    "critical",
+                    format!("An endpoint configured for {chain_id} reports another chain"),
+                ),
+            ];
+
+            rules.push(Rule::new(
+                "IbcChannelTrafficAnomaly",
+                format!(
+                    r#"abs(ibc_channel_traffic_anomaly{{chain_id="{chain_id}"}}) > {}"#,
+                    config.anomalies.threshold
+                ),
+                "15m",
+                "warning",
+                format!("The traffic of {{{{ $labels.src_channel }}}} on {chain_id} deviates from its baseline by {{{{ $value }}}} standard deviations"),
+            ));
+
+            rules.push(Rule::new(
+                "IbcFrontrunBurst",
+                format!(r#"increase(ibc_frontrun_bursts{{chain_id="{chain_id}"}}[15m]) > 0"#),
+                "0m",
+                "warning",
+                format!("{{{{ $labels.signer }}}} is suddenly losing many more packets to frontrunners on {chain_id} than usual"),
+            ));
+
+            rules.push(Rule::new(
+                "IbcChannelIdle",
+                format!(
+                    r#"ibc_channel_idle_seconds{{chain_id="{chain_id}"}} > {}"#,
+                    config.channel_discovery.max_idle
+                ),
+                "0m",
+                "warning",
+                format!("No traffic was seen on the open channel {{{{ $labels.channel }}}} of {chain_id} for {{{{ $value | humanizeDuration }}}}"),
+            ));
+
+            rules.push(Rule::new(
+                "IbcClientExpiring",
+                format!(
+                    r#"ibc_client_expiry_timestamp_seconds{{chain_id="{chain_id}"}} - time() < {}"#,
+                    config.channel_discovery.min_client_expiry
+                ),
+                "0m",
+                "critical",
+                format!("The client {{{{ $labels.client }}}} of {{{{ $labels.counterparty_chain }}}} on {chain_id} expires in {{{{ $value | humanizeDuration }}}}"),
+            ));
+
+            for hermes in &config.hermes {
+                for (denom, min_balance) in &hermes.min_balances {
+                    rules.push(Rule::new(
+                        "IbcRelayerLowBalance",
+                        format!(
+                            r#"ibc_relayer_wallet_balance{{relayer="{}", chain_id="{chain_id}", denom="{denom}"}} < {min_balance}"#,
+                            hermes.name
+                        ),
+                        "5m",
+                        "warning",
+                        format!("{{{{ $labels.signer }}}} of {} on {chain_id} is running low on {denom}: {{{{ $value }}}} left", hermes.name),
+                    ));
+                }
+            }
+
+            let endpoint = &config.chains.endpoints[chain_id];
+
+            if !endpoint.relayer_addresses.is_empty() {
+                rules.push(Rule::new(
+                    "IbcRelayerSequenceStuck",
+                    format!(r#"changes(ibc_relayer_account_sequence{{chain_id="{chain_id}"}}[1h]) == 0"#),
+                    "0m",
+                    "warning",
+                    format!("{{{{ $labels.address }}}} has not landed any tx on {chain_id} in the last hour"),
+                ));
+            }
+
+            let escrow_channels = &endpoint.escrow_channels;
+
+            if !escrow_channels.is_empty() {
+                let balance = format!(r#"ibc_escrow_balance{{chain_id="{chain_id}"}}"#);
+                let max_drop = config.escrow.max_drop;
+
+                rules.push(Rule::new(
+                    "IbcEscrowBalanceDrop",
+                    format!("({balance} offset 1h - {balance}) / ({balance} offset 1h > 0) > {max_drop}"),
+                    "0m",
+                    "critical",
+                    format!(
+                        "The escrow account of {{{{ $labels.channel }}}} on {chain_id} lost more than {}% of its {{{{ $labels.denom }}}} in the last hour",
+                        max_drop * 100.0
+                    ),
+                ));
+            }
+
+            if config.metrics.stuck_packets {
+                rules.push(Rule::new(
+                    "IbcStuckPackets",
+                    format!(
+                        r#"ibc_stuck_packets{{src_chain="{chain_id}"}} > 0 or ibc_stuck_packets{{dst_chain="{chain_id}"}} > 0"#
+                    ),
+                    "15m",
+                    "critical",
+                    "{{ $value }} packets are stuck on {{ $labels.src_chain }} {{ $labels.src_channel }} --> {{ $labels.dst_chain }}".to_string(),
+                ));
+            }
+
+            // Multiwindow burn-rate alerts: page when 2% of the budget of a 30 day period is spent in an hour,
+            // and warn when 5% is spent in 6 hours
+            for slo in config.slos.iter().filter(|slo| slo.chain == *chain_id) {
+                let burn_rate = |window: &str| {
+                    format!(
+                        r#"ibc_slo_burn_rate{{slo="{}", chain_id="{chain_id}", window="{window}"}}"#,
+                        slo.name
+                    )
+                };
+
+                rules.push(Rule::new(
+                    "IbcSloFastBurn",
+                    format!("{} > 14.4 and {} > 14.4", burn_rate("1h"), burn_rate("5m")),
+                    "2m",
+                    "critical",
+                    format!("The error budget of {} on {} of {chain_id} is burning {{{{ $value }}}} times too fast", slo.name, slo.channel),
+                ));
+
+                rules.push(Rule::new(
+                    "IbcSloSlowBurn",
+                    format!("{} > 6 and {} > 6", burn_rate("6h"), burn_rate("30m")),
+                    "15m",
+                    "warning",
+                    format!("The error budget of {} on {} of {chain_id} is burning {{{{ $value }}}} times too fast", slo.name, slo.channel),
+                ));
+            }
+
+            RuleGroup {
+                name: format!("chainpulse-{chain_id}"),
+                rules,
+            }
+        })
+        .collect();
+
+    RuleFile { groups }
+}
+
+pub fn print_rules(config: &Config) -> Result<()> {
+    let rules = rules(config);
+    print!("{}", serde_yaml::to_string(&rules)?);
+
+    Ok(())
+}
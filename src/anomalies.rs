@@ -0,0 +1,161 @@
+//! Detection of anomalies in the traffic of the channels, eg. a channel falling silent
+//! because its relayers are down, or a sudden spike of packets.
+//!
+//! The packets relayed on each channel are counted in fixed-size buckets over the baseline period,
+//! and the count of the last complete bucket is compared to the exponentially weighted moving average
+//! and standard deviation of the previous ones. Everything is computed from the database,
+//! so that the baseline survives restarts.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tokio::time;
+use tracing::error;
+
+use crate::{config::Anomalies, metrics::Metrics, Result};
+
+/// Periodically compute the baseline and anomaly score of the traffic of each channel
+pub async fn run(pool: SqlitePool, metrics: Metrics, config: Anomalies) {
+    loop {
+        if let Err(e) = update(&pool, &metrics, &config).await {
+            error!("Failed to compute traffic anomalies: {e}");
+        }
+
+        time::sleep(config.interval()).await;
+    }
+}
+
+async fn update(pool: &SqlitePool, metrics: &Metrics, config: &Anomalies) -> Result<()> {
+    let query = r#"
+        SELECT txs.chain, packets.src_channel, packets.dst_channel,
+            CAST(strftime('%s', packets.block_time) AS INTEGER) / ?, COUNT(*)
+        FROM packets JOIN txs ON packets.tx_id = txs.id
+        WHERE packets.block_time >= datetime('now', ?)
+        GROUP BY 1, 2, 3, 4
+    "#;
+
+    let bucket = config.bucket.max(1);
+    let buckets = (config.baseline / bucket).max(2);
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    // The last complete bucket, the current one being still filled
+    let last = (now / bucket).saturating_sub(1);
+    let first = last.saturating_sub(buckets);
+
+    let rows: Vec<(String, String, String, i64, i64)> = sqlx::query_as(query)
+        .bind(bucket as i64)
+        .bind(format!("-{} seconds", (buckets + 1) * bucket))
+        .fetch_all(pool)
+        .await?;
+
+    let mut channels = HashMap::<_, BTreeMap<u64, u64>>::new();
+
+    for (chain, src_channel, dst_channel, bucket, count) in rows {
+        channels
+            .entry((chain, src_channel, dst_channel))
+            .or_default()
+            .insert(bucket as u64, count as u64);
+    }
+
+    // Reset the gauges so that channels without traffic over the baseline period are removed
+    metrics.reset_ibc_channel_traffic();
+
+    for ((chain, src_channel, dst_channel), counts) in channels {
+        let Ok(chain_id) = chain::Id::try_from(chain) else {
+            continue;
+        };
+
+        let history = (first..last)
+            .map(|b| counts.get(&b).copied().unwrap_or_default())
+            .collect::<Vec<_>>();
+
+        let current = counts.get(&last).copied().unwrap_or_default();
+        let (baseline, score) = score(&history, current);
+
+        metrics.ibc_channel_traffic(&chain_id, &src_channel, &dst_channel, baseline, score);
+    }
+
+    Ok(())
+}
+
+/// The baseline of a series of counts, ie. their exponentially weighted moving average,
+/// and the anomaly score of the current count, ie. the number of standard deviations
+/// it lies above (positive) or below (negative) the baseline.
+///
+/// The smoothing factor is chosen so that the average spans the whole series, and the
+/// standard deviation is floored by the one of a Poisson process with the same average,
+/// so that channels with sparse traffic do not look anomalous on every packet.
+fn score(history: &[u64], current: u64) -> (f64, f64) {
+    let Some((&first, rest)) = history.split_first() else {
+        return (0.0, 0.0);
+    };
+
+    let alpha = 2.0 / (history.len() as f64 + 1.0);
+
+    let mut mean = first as f64;
+    let mut variance = 0.0;
+
+    for &count in rest {
+        let diff = count as f64 - mean;
+        let incr = alpha * diff;
+
+        mean += incr;
+        variance = (1.0 - alpha) * (variance + diff * incr);
+    }
+
+    let std_dev = variance.sqrt().max(mean.sqrt()).max(1.0);
+    let score = (current as f64 - mean) / std_dev;
+
+    (mean, score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_steady() {
+        let history = [100; 96];
+
+        let (baseline, score) = score(&history, 100);
+        assert_eq!(baseline, 100.0);
+        assert_eq!(score, 0.0);
+    }
+
+    #[test]
+    fn test_score_silence() {
+        let history = [100; 96];
+
+        let (_, score) = score(&history, 0);
+        assert!(score < -5.0, "{score}");
+    }
+
+    #[test]
+    fn test_score_spike() {
+        let history = [10; 96];
+
+        let (_, score) = score(&history, 100);
+        assert!(score > 5.0, "{score}");
+    }
+
+    #[test]
+    fn test_score_sparse() {
+        let mut history = [0; 96];
+        history[10] = 1;
+        history[50] = 2;
+
+        // A couple of packets on a quiet channel is not an anomaly
+        let (_, score) = score(&history, 2);
+        assert!(score < 3.0, "{score}");
+    }
+
+    #[test]
+    fn test_score_empty() {
+        assert_eq!(score(&[], 42), (0.0, 0.0));
+    }
+}
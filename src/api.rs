@@ -0,0 +1,375 @@
+//! Read access to the collected packets and txs, served under `/api/v1`, so that dashboards and scripts
+//! can query them without opening the database.
+//!
+//! The lists are returned most recent first, one page at a time. A page holds up to `limit` items,
+//! along with the cursor to pass as `before` to fetch the next page, which is absent on the last page.
+//! The chains and channels an API key is restricted to are filtered in the queries themselves,
+//! so that a page is only ever made of, and points to, packets the key can see.
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tendermint::chain;
+
+use crate::{
+    db::{FrontrunRow, PacketApiRow, TxApiRow},
+    Result,
+};
+
+/// Default number of items returned per page
+pub const DEFAULT_LIMIT: u32 = 100;
+
+/// Maximum number of items returned per page
+pub const MAX_LIMIT: u32 = 1000;
+
+/// The number of items to return per page, given the requested limit, if any.
+/// Shared by every list of the API, so that they all page the same way.
+pub fn limit(limit: Option<u32>) -> u32 {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+/// A page of a list, along with the cursor of the next page, if any
+#[derive(Clone, Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<i64>,
+}
+
+impl<T> Page<T> {
+    /// A page of the items fetched with the given limit, identified by `id`, in decreasing order
+    fn new(items: Vec<T>, limit: u32, id: impl Fn(&T) -> i64) -> Self {
+        let next = if items.len() >= limit as usize {
+            items.last().map(id)
+        } else {
+            None
+        };
+
+        Self { items, next }
+    }
+}
+
+/// The chains and channels whose packets can be listed, where `None` means all of them
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Scope<'a> {
+    pub chains: Option<&'a [chain::Id]>,
+    pub channels: Option<&'a [String]>,
+}
+
+impl Scope<'_> {
+    /// The condition restricting the rows of a query to the scope, given the column holding their chain
+    /// and the columns holding their channels, any of which must be in scope.
+    /// The chains are to be bound to `?{n}` and the channels to `?{n + 1}`, if the rows have any channel.
+    pub fn filter(n: usize, chain: &str, channels: &[&str]) -> String {
+        let chains = format!("(?{n} IS NULL OR {chain} IN (SELECT value FROM json_each(?{n})))");
+
+        if channels.is_empty() {
+            return chains;
+        }
+
+        let m = n + 1;
+        let channels = channels
+            .iter()
+            .map(|column| format!("{column} IN (SELECT value FROM json_each(?{m}))"))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+
+        format!("{chains} AND (?{m} IS NULL OR {channels})")
+    }
+
+    /// The chains as a JSON array, to be expanded with `json_each` in the queries
+    pub fn chains(&self) -> Result<Option<String>> {
+        self.chains
+            .map(|chains| {
+                let chains = chains
+                    .iter()
+                    .map(|chain| chain.as_str())
+                    .collect::<Vec<_>>();
+                serde_json::to_string(&chains)
+            })
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// The channels as a JSON array, to be expanded with `json_each` in the queries
+    pub fn channels(&self) -> Result<Option<String>> {
+        self.channels
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(Into::into)
+    }
+}
+
+/// A tx along with the packets it relayed
+#[derive(Clone, Debug, Serialize)]
+pub struct Tx {
+    #[serde(flatten)]
+    pub tx: TxApiRow,
+    pub packets: Vec<PacketApiRow>,
+}
+
+const PACKET_COLUMNS: &str = r#"
+    packets.id, txs.chain, txs.height, txs.hash AS tx_hash, packets.msg_type_url,
+    packets.src_port, packets.src_channel, packets.dst_port, packets.dst_channel, packets.sequence,
+    packets.signer, packets.effected, packets.effected_signer, txs.memo,
+    packets.block_time, packets.created_at
+"#;
+
+/// Fetch a page of the packets relayed within the given scope, optionally only the ones of the given chain,
+/// sent or received over the given channel, or relayed by the given signer
+pub async fn packets(
+    pool: &SqlitePool,
+    scope: Scope<'_>,
+    chain: Option<&str>,
+    channel: Option<&str>,
+    signer: Option<&str>,
+    before: Option<i64>,
+    limit: Option<u32>,
+) -> Result<Page<PacketApiRow>> {
+    let filter = scope_filter();
+    let query = format!(
+        r#"
+        SELECT {PACKET_COLUMNS}
+        FROM packets JOIN txs ON packets.tx_id = txs.id
+        WHERE   (?1 IS NULL OR packets.chain = ?1)
+            AND (?2 IS NULL OR packets.src_channel = ?2 OR packets.dst_channel = ?2)
+            AND (?3 IS NULL OR packets.signer = ?3)
+            AND (?4 IS NULL OR packets.id < ?4)
+            AND {filter}
+        ORDER BY packets.id DESC
+        LIMIT ?7
+        "#
+    );
+
+    let limit = self::limit(limit);
+
+    let rows = sqlx::query_as(&query)
+        .bind(chain)
+        .bind(channel)
+        .bind(signer)
+        .bind(before)
+        .bind(scope.chains()?)
+        .bind(scope.channels()?)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(Page::new(rows, limit, |row: &PacketApiRow| row.id))
+}
+
+/// Restricts the packets to the chains bound to `?5` and the channels bound to `?6`, if any
+fn scope_filter() -> String {
+    Scope::filter(
+        5,
+        "packets.chain",
+        &["packets.src_channel", "packets.dst_channel"],
+    )
+}
+
+/// Fetch the txs with the given hash, along with the packets they relayed, optionally only the one of the given chain
+pub async fn txs(pool: &SqlitePool, hash: &str, chain: Option<&str>) -> Result<Vec<Tx>> {
+    let query = r#"
+        SELECT id, chain, height, hash, memo, tx_index, proposer, block_time, created_at
+        FROM txs
+        WHERE   hash = ?1
+            AND (?2 IS NULL OR chain = ?2)
+        ORDER BY id
+    "#;
+
+    let rows: Vec<TxApiRow> = sqlx::query_as(query)
+        .bind(hash.to_uppercase())
+        .bind(chain)
+        .fetch_all(pool)
+        .await?;
+
+    let query = format!(
+        r#"
+        SELECT {PACKET_COLUMNS}
+        FROM packets JOIN txs ON packets.tx_id = txs.id
+        WHERE packets.tx_id = ?
+        ORDER BY packets.id
+        "#
+    );
+
+    let mut txs = Vec::with_capacity(rows.len());
+
+    for tx in rows {
+        let packets = sqlx::query_as(&query).bind(tx.id).fetch_all(pool).await?;
+        txs.push(Tx { tx, packets });
+    }
+
+    Ok(txs)
+}
+
+/// Fetch a page of the packets which were frontrun within the given scope, optionally only the ones of the given chain,
+/// sent or received over the given channel, or the ones of the given signer which were frontrun
+pub async fn frontruns(
+    pool: &SqlitePool,
+    scope: Scope<'_>,
+    chain: Option<&str>,
+    channel: Option<&str>,
+    signer: Option<&str>,
+    before: Option<i64>,
+    limit: Option<u32>,
+) -> Result<Page<FrontrunRow>> {
+    let filter = scope_filter();
+    let query = format!(
+        r#"
+        SELECT packets.id, txs.chain, txs.height, txs.hash AS tx_hash, packets.msg_type_url,
+            packets.src_channel, packets.dst_channel, packets.sequence, packets.signer,
+            packets.effected_signer AS frontrunner, effected_txs.hash AS frontrunner_tx_hash,
+            packets.block_time
+        FROM packets
+        JOIN txs ON packets.tx_id = txs.id
+        LEFT JOIN txs AS effected_txs ON packets.effected_tx = effected_txs.id
+        WHERE   NOT packets.effected
+            AND (?1 IS NULL OR packets.chain = ?1)
+            AND (?2 IS NULL OR packets.src_channel = ?2 OR packets.dst_channel = ?2)
+            AND (?3 IS NULL OR packets.signer = ?3)
+            AND (?4 IS NULL OR packets.id < ?4)
+            AND {filter}
+        ORDER BY packets.id DESC
+        LIMIT ?7
+        "#
+    );
+
+    let limit = self::limit(limit);
+
+    let rows = sqlx::query_as(&query)
+        .bind(chain)
+        .bind(channel)
+        .bind(signer)
+        .bind(before)
+        .bind(scope.chains()?)
+        .bind(scope.channels()?)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(Page::new(rows, limit, |row: &FrontrunRow| row.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit() {
+        assert_eq!(limit(None), DEFAULT_LIMIT);
+        assert_eq!(limit(Some(0)), 1);
+        assert_eq!(limit(Some(10)), 10);
+        assert_eq!(limit(Some(MAX_LIMIT + 1)), MAX_LIMIT);
+    }
+
+    #[test]
+    fn test_scope_filter() {
+        assert_eq!(
+            Scope::filter(3, "chain", &[]),
+            "(?3 IS NULL OR chain IN (SELECT value FROM json_each(?3)))"
+        );
+
+        assert_eq!(
+            Scope::filter(2, "chain", &["src_channel", "dst_channel"]),
+            "(?2 IS NULL OR chain IN (SELECT value FROM json_each(?2))) \
+             AND (?3 IS NULL OR src_channel IN (SELECT value FROM json_each(?3)) \
+             OR dst_channel IN (SELECT value FROM json_each(?3)))"
+        );
+    }
+
+    #[test]
+    fn test_page() {
+        let page = Page::new(vec![9, 7, 4], 3, |id| *id);
+        assert_eq!(page.next, Some(4));
+
+        // The last page is not full
+        let page = Page::new(vec![3, 1], 3, |id| *id);
+        assert_eq!(page.next, None);
+
+        let page = Page::new(Vec::<i64>::new(), 3, |id| *id);
+        assert_eq!(page.next, None);
+    }
+
+    async fn insert_packet(pool: &SqlitePool, chain: &str, src_channel: &str, effected: bool) {
+        let tx_id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO txs (chain, height, hash, memo, created_at, block_time)
+            VALUES (?1, 1, hex(randomblob(32)), '', datetime('now'), datetime('now'))
+            RETURNING id
+            "#,
+        )
+        .bind(chain)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+
+        sqlx::query(
+            r#"
+            INSERT INTO packets (tx_id, chain, sequence, src_channel, src_port, dst_channel, dst_port,
+                msg_type_url, signer, effected, created_at, block_time)
+            VALUES (?1, ?2, ?1, ?3, 'transfer', 'channel-9', 'transfer',
+                '/ibc.core.channel.v1.MsgRecvPacket', 'relayer', ?4, datetime('now'), datetime('now'))
+            "#,
+        )
+        .bind(tx_id)
+        .bind(chain)
+        .bind(src_channel)
+        .bind(effected)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scoped_pages() {
+        let pool = crate::db::connect_in_memory().await.unwrap();
+        crate::db::setup(&pool).await.unwrap();
+
+        // Interleave the packets the key can see with the ones it cannot
+        for _ in 0..3 {
+            insert_packet(&pool, "osmosis-1", "channel-0", false).await;
+            insert_packet(&pool, "osmosis-1", "channel-1", false).await;
+            insert_packet(&pool, "cosmoshub-4", "channel-0", false).await;
+        }
+
+        let chains = [chain::Id::try_from("osmosis-1").unwrap()];
+        let channels = ["channel-0".to_string()];
+        let scope = Scope {
+            chains: Some(&chains),
+            channels: Some(&channels),
+        };
+
+        let page = packets(&pool, scope, None, None, None, None, Some(2))
+            .await
+            .unwrap();
+
+        let ids = page.items.iter().map(|row| row.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![7, 4]);
+        assert_eq!(page.next, Some(4));
+
+        for row in &page.items {
+            assert_eq!(row.chain, "osmosis-1");
+            assert_eq!(row.src_channel, "channel-0");
+        }
+
+        let page = packets(&pool, scope, None, None, None, page.next, Some(2))
+            .await
+            .unwrap();
+
+        let ids = page.items.iter().map(|row| row.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![1]);
+        assert_eq!(page.next, None);
+
+        let page = frontruns(&pool, scope, None, None, None, None, Some(2))
+            .await
+            .unwrap();
+
+        let ids = page.items.iter().map(|row| row.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![7, 4]);
+        assert_eq!(page.next, Some(4));
+
+        // Without any restriction, every packet is listed
+        let page = packets(&pool, Scope::default(), None, None, None, None, Some(100))
+            .await
+            .unwrap();
+
+        assert_eq!(page.items.len(), 9);
+    }
+}
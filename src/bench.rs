@@ -0,0 +1,357 @@
+//! Synthetic load, to size the hardware Chain Pulse runs on before pointing it at a busy chain.
+//!
+//! Blocks of txs relaying IBC packets are generated and fed through the same processing as the
+//! collectors, ie. decoding, frontrun detection, database and metrics, at a given rate or as fast
+//! as possible, and the throughput and the latency of each block are reported at the end.
+//! The benchmark runs against a database of its own, which is removed once done.
+
+use std::{
+    env,
+    fmt::Write,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+
+use ibc_proto::{
+    cosmos::{
+        base::v1beta1::Coin,
+        tx::v1beta1::{AuthInfo, Fee, SignerInfo, Tx, TxBody},
+    },
+    google::protobuf::Any,
+    ibc::core::channel::v1::{MsgRecvPacket, Packet},
+};
+use prost::Message;
+use tendermint::{block::Height, chain, Time};
+use tokio::time::{self, Instant, MissedTickBehavior};
+use tracing::info;
+
+use crate::{
+    collect, config::Config, db, metrics::Metrics, msg::Decoders, plugins::Plugins,
+    trace::BlockTrace, Result,
+};
+
+const CHAIN_ID: &str = "bench-1";
+const PROPOSER: &str = "BENCHPROPOSER";
+
+/// The relayers signing the generated txs, in turn
+const RELAYERS: &[&str] = &[
+    "bench1v9k8q6rpqqqqqqqqqqqqqqqqqqqqqqqqpwtdc7",
+    "bench1vfexzan0qqqqqqqqqqqqqqqqqqqqqqqqy6d84u",
+    "bench1vd5xzunvd9jsqqqqqqqqqqqqqqqqqqqqp0zxya",
+    "bench1v3jkcarpqqqqqqqqqqqqqqqqqqqqqqqqsnsu5r",
+];
+
+/// Options of the `bench` command
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Number of blocks to generate
+    pub blocks: u64,
+
+    /// Number of blocks to generate per second, or as many as possible if zero
+    pub rate: f64,
+
+    /// Number of txs per block
+    pub txs: usize,
+
+    /// Number of packets relayed per tx
+    pub packets: usize,
+
+    /// Ratio of the packets which are relayed again by another relayer, ie. frontrun
+    pub frontruns: f64,
+
+    /// Where to create the database, defaults to a file in the temporary directory
+    pub database: Option<PathBuf>,
+}
+
+impl Options {
+    fn interval(&self) -> Option<Duration> {
+        (self.rate > 0.0).then(|| Duration::from_secs_f64(1.0 / self.rate))
+    }
+}
+
+/// Generate the blocks, process them and print a report
+pub async fn run(config: &Config, options: Options) -> Result<()> {
+    let path = options
+        .database
+        .clone()
+        .unwrap_or_else(|| env::temp_dir().join(format!("chainpulse-bench-{}.db", process::id())));
+
+    if path.exists() {
+        return Err(format!("refusing to overwrite existing database {}", path.display()).into());
+    }
+
+    info!("Running the benchmark against {}", path.display());
+
+    let pool = db::connect(&path).await?;
+    db::setup(&pool).await?;
+
+    let (metrics, _) = Metrics::new(config.memo);
+    let decoders = Decoders::new();
+    let plugins = Plugins::load(&config.plugins)?;
+    let chain_id = chain::Id::try_from(CHAIN_ID)?;
+
+    let mut generator = Generator::new(options.packets, options.frontruns);
+    let mut latencies = Vec::with_capacity(options.blocks as usize);
+    let mut packets = 0;
+
+    let mut ticker = options.interval().map(|interval| {
+        let mut ticker = time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        ticker
+    });
+
+    let start = Instant::now();
+
+    for height in 1..=options.blocks {
+        if let Some(ticker) = &mut ticker {
+            ticker.tick().await;
+        }
+
+        let txs = generator.block(options.txs);
+
+        let block_start = Instant::now();
+
+        let processed = collect::process_txs(
+            &pool,
+            &chain_id,
+            Height::try_from(height)?,
+            Time::now(),
+            PROPOSER,
+            txs,
+            config.memo,
+            &metrics,
+            &decoders,
+            &plugins,
+            &mut BlockTrace::new(height),
+        )
+        .await?;
+
+        latencies.push(block_start.elapsed());
+        packets += processed.packets;
+    }
+
+    let elapsed = start.elapsed();
+
+    pool.close().await;
+    remove_database(&path);
+
+    let report = Report {
+        blocks: options.blocks,
+        txs: options.blocks * options.txs as u64,
+        packets: packets as u64,
+        frontruns: generator.frontruns,
+        elapsed,
+        latencies,
+        interval: options.interval(),
+    };
+
+    print!("{report}");
+
+    Ok(())
+}
+
+fn remove_database(path: &Path) {
+    for suffix in ["", "-wal", "-shm"] {
+        let mut file = path.as_os_str().to_owned();
+        file.push(suffix);
+
+        let _ = std::fs::remove_file(file);
+    }
+}
+
+/// Generates txs relaying packets over a single channel, some of which were already relayed
+pub(crate) struct Generator {
+    packets: usize,
+    frontruns_ratio: f64,
+    frontruns_debt: f64,
+    frontruns: u64,
+    next_sequence: u64,
+    txs: u64,
+}
+
+impl Generator {
+    pub(crate) fn new(packets: usize, frontruns_ratio: f64) -> Self {
+        Self {
+            packets,
+            frontruns_ratio: frontruns_ratio.clamp(0.0, 1.0),
+            frontruns_debt: 0.0,
+            frontruns: 0,
+            next_sequence: 1,
+            txs: 0,
+        }
+    }
+
+    pub(crate) fn block(&mut self, txs: usize) -> Vec<Vec<u8>> {
+        (0..txs).map(|_| self.tx().encode_to_vec()).collect()
+    }
+
+    fn tx(&mut self) -> Tx {
+        let signer = RELAYERS[self.txs as usize % RELAYERS.len()];
+        self.txs += 1;
+
+        // The packets relayed by the previous txs, starting with the last one, to relay again
+        let mut replay = self.next_sequence - 1;
+
+        let messages = (0..self.packets)
+            .map(|_| {
+                // Spread the frontruns evenly, by relaying again packets of the previous txs
+                self.frontruns_debt += self.frontruns_ratio;
+
+                let sequence = if self.frontruns_debt >= 1.0 && replay > 0 {
+                    self.frontruns_debt -= 1.0;
+                    self.frontruns += 1;
+                    replay -= 1;
+                    replay + 1
+                } else {
+                    self.next_sequence += 1;
+                    self.next_sequence - 1
+                };
+
+                recv_packet(sequence, signer)
+            })
+            .collect();
+
+        Tx {
+            body: Some(TxBody {
+                messages,
+                memo: "chainpulse bench".to_string(),
+                ..TxBody::default()
+            }),
+            auth_info: Some(AuthInfo {
+                signer_infos: vec![SignerInfo {
+                    sequence: self.txs,
+                    ..SignerInfo::default()
+                }],
+                fee: Some(Fee {
+                    amount: vec![Coin {
+                        denom: "ubench".to_string(),
+                        amount: "5000".to_string(),
+                    }],
+                    gas_limit: 200_000,
+                    ..Fee::default()
+                }),
+                ..AuthInfo::default()
+            }),
+            signatures: vec![],
+        }
+    }
+}
+
+fn recv_packet(sequence: u64, signer: &str) -> Any {
+    let data = r#"{"denom":"transfer/channel-0/ubench","amount":"1000","sender":"bench1sender","receiver":"bench1receiver"}"#;
+
+    let msg = MsgRecvPacket {
+        packet: Some(Packet {
+            sequence,
+            source_port: "transfer".to_string(),
+            source_channel: "channel-0".to_string(),
+            destination_port: "transfer".to_string(),
+            destination_channel: "channel-1".to_string(),
+            data: data.as_bytes().to_vec(),
+            timeout_height: None,
+            timeout_timestamp: 0,
+        }),
+        proof_commitment: vec![],
+        proof_height: None,
+        signer: signer.to_string(),
+    };
+
+    Any {
+        type_url: "/ibc.core.channel.v1.MsgRecvPacket".to_string(),
+        value: msg.encode_to_vec(),
+    }
+}
+
+struct Report {
+    blocks: u64,
+    txs: u64,
+    packets: u64,
+    frontruns: u64,
+    elapsed: Duration,
+    latencies: Vec<Duration>,
+    interval: Option<Duration>,
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secs = self.elapsed.as_secs_f64().max(f64::EPSILON);
+
+        let mut latencies = self.latencies.clone();
+        latencies.sort();
+
+        writeln!(
+            f,
+            "Processed {} blocks, {} txs and {} packets ({} frontrun) in {:.2?}",
+            self.blocks, self.txs, self.packets, self.frontruns, self.elapsed
+        )?;
+
+        writeln!(
+            f,
+            "Throughput: {:.1} blocks/s, {:.1} txs/s, {:.1} packets/s",
+            self.blocks as f64 / secs,
+            self.txs as f64 / secs,
+            self.packets as f64 / secs
+        )?;
+
+        let mut line = String::from("Block latency:");
+        for (name, p) in [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("max", 1.0)] {
+            let _ = write!(line, " {name} {:.2?}", percentile(&latencies, p));
+        }
+        writeln!(f, "{line}")?;
+
+        if let Some(interval) = self.interval {
+            let late = latencies.iter().filter(|l| **l > interval).count();
+            writeln!(
+                f,
+                "Blocks processed slower than the {interval:.2?} interval: {late}"
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The nearest-rank percentile of sorted durations
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let sorted = (1..=100).map(Duration::from_millis).collect::<Vec<_>>();
+
+        assert_eq!(percentile(&sorted, 0.5), Duration::from_millis(50));
+        assert_eq!(percentile(&sorted, 0.99), Duration::from_millis(99));
+        assert_eq!(percentile(&sorted, 1.0), Duration::from_millis(100));
+        assert_eq!(percentile(&sorted, 0.0), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 0.5), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_generator() {
+        let mut generator = Generator::new(5, 0.2);
+        let txs = generator.block(10);
+
+        assert_eq!(txs.len(), 10);
+        assert_eq!(generator.frontruns, 10);
+        assert_eq!(generator.next_sequence, 41);
+
+        let tx = Tx::decode(txs[0].as_slice()).unwrap();
+        let msgs = tx.body.unwrap().messages;
+        assert_eq!(msgs.len(), 5);
+
+        let msg = Decoders::new().decode(msgs[0].clone()).unwrap();
+        assert_eq!(msg.packet().unwrap().sequence, 1);
+        assert_eq!(msg.signer(), Some(RELAYERS[0]));
+    }
+}
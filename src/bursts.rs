@@ -0,0 +1,206 @@
+//! Detection of bursts of frontruns, ie. when a signer suddenly loses many more packets
+//! to other relayers than it usually does.
+//!
+//! For each signer, both the ratio of its packets which were uneffected and the rate at which
+//! it loses packets to frontrunners over a recent window are compared to the ones over a longer
+//! baseline period preceding it, and a burst is reported once either exceeds its baseline by a given factor.
+
+use std::collections::BTreeSet;
+
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tokio::time;
+use tracing::{error, info, warn};
+
+use crate::{config::FrontrunBursts, metrics::Metrics, Result};
+
+/// Smallest uneffected ratio assumed for the baseline, so that a signer which never lost
+/// a packet does not enter a burst on the first one it loses
+const MIN_BASELINE_RATIO: f64 = 0.01;
+
+/// The packets relayed by a signer over a period
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct Counts {
+    total: u64,
+    uneffected: u64,
+}
+
+/// What deviates from the baseline of a signer
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    /// The ratio of uneffected packets
+    UneffectedRatio,
+
+    /// The number of packets lost to frontrunners per hour
+    FrontrunRate,
+}
+
+impl Kind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::UneffectedRatio => "uneffected_ratio",
+            Self::FrontrunRate => "frontrun_rate",
+        }
+    }
+}
+
+/// Periodically look for bursts of frontruns, reporting each burst once when it starts
+pub async fn run(pool: SqlitePool, metrics: Metrics, config: FrontrunBursts) {
+    let mut active = BTreeSet::new();
+
+    loop {
+        if let Err(e) = update(&pool, &metrics, &config, &mut active).await {
+            error!("Failed to detect frontrun bursts: {e}");
+        }
+
+        time::sleep(config.interval()).await;
+    }
+}
+
+async fn update(
+    pool: &SqlitePool,
+    metrics: &Metrics,
+    config: &FrontrunBursts,
+    active: &mut BTreeSet<(chain::Id, String, Kind)>,
+) -> Result<()> {
+    let query = r#"
+        SELECT txs.chain, packets.signer,
+            SUM(packets.block_time >= datetime('now', ?)),
+            SUM(packets.block_time >= datetime('now', ?) AND NOT packets.effected),
+            SUM(packets.block_time < datetime('now', ?)),
+            SUM(packets.block_time < datetime('now', ?) AND NOT packets.effected)
+        FROM packets JOIN txs ON packets.tx_id = txs.id
+        WHERE packets.block_time >= datetime('now', ?) AND packets.signer IS NOT NULL
+        GROUP BY txs.chain, packets.signer
+    "#;
+
+    let window = format!("-{} seconds", config.window);
+    let baseline = format!("-{} seconds", config.window + config.baseline);
+
+    let rows: Vec<(String, String, i64, i64, i64, i64)> = sqlx::query_as(query)
+        .bind(&window)
+        .bind(&window)
+        .bind(&window)
+        .bind(&window)
+        .bind(&baseline)
+        .fetch_all(pool)
+        .await?;
+
+    let mut bursts = BTreeSet::new();
+
+    metrics.reset_ibc_frontrun_burst_factor();
+
+    for (chain, signer, total, uneffected, baseline_total, baseline_uneffected) in rows {
+        let Ok(chain_id) = chain::Id::try_from(chain) else {
+            continue;
+        };
+
+        let recent = Counts {
+            total: total as u64,
+            uneffected: uneffected as u64,
+        };
+
+        let baseline = Counts {
+            total: baseline_total as u64,
+            uneffected: baseline_uneffected as u64,
+        };
+
+        for (kind, factor) in factors(recent, baseline, config) {
+            metrics.ibc_frontrun_burst_factor(&chain_id, &signer, kind.as_str(), factor);
+
+            if factor < config.factor || recent.uneffected < config.min_losses {
+                continue;
+            }
+
+            let key = (chain_id.clone(), signer.clone(), kind);
+
+            if !active.contains(&key) {
+                warn!(
+                    chain = %chain_id,
+                    "Frontrun burst for {signer}: its {} is {factor:.1} times its baseline, \
+                     with {} of its {} packets lost in the last {}s",
+                    kind.as_str().replace('_', " "),
+                    recent.uneffected,
+                    recent.total,
+                    config.window,
+                );
+
+                metrics.ibc_frontrun_bursts(&chain_id, &signer, kind.as_str());
+            }
+
+            bursts.insert(key);
+        }
+    }
+
+    for (chain_id, signer, kind) in active.difference(&bursts) {
+        info!(chain = %chain_id, "Frontrun burst for {signer} ({}) is over", kind.as_str());
+    }
+
+    *active = bursts;
+
+    Ok(())
+}
+
+/// How many times the uneffected ratio and the frontrun rate of a signer over the recent window
+/// exceed the ones over the baseline period
+fn factors(recent: Counts, baseline: Counts, config: &FrontrunBursts) -> [(Kind, f64); 2] {
+    let ratio = |counts: Counts| counts.uneffected as f64 / counts.total.max(1) as f64;
+
+    let recent_ratio = ratio(recent);
+    let baseline_ratio = ratio(baseline).max(MIN_BASELINE_RATIO);
+
+    // Assume at least one loss over the baseline period, for the same reason
+    let hours = |secs: u64| secs.max(1) as f64 / 3600.0;
+    let recent_rate = recent.uneffected as f64 / hours(config.window);
+    let baseline_rate = baseline.uneffected.max(1) as f64 / hours(config.baseline);
+
+    [
+        (Kind::UneffectedRatio, recent_ratio / baseline_ratio),
+        (Kind::FrontrunRate, recent_rate / baseline_rate),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> FrontrunBursts {
+        FrontrunBursts {
+            window: 3600,
+            baseline: 7 * 86400,
+            factor: 3.0,
+            min_losses: 10,
+            interval: 60,
+        }
+    }
+
+    fn counts(total: u64, uneffected: u64) -> Counts {
+        Counts { total, uneffected }
+    }
+
+    #[test]
+    fn test_factors_steady() {
+        // Losing 10% of 100 packets per hour, as usual
+        let [(_, ratio), (_, rate)] = factors(counts(100, 10), counts(16800, 1680), &config());
+
+        assert!((ratio - 1.0).abs() < 1e-9, "{ratio}");
+        assert!((rate - 1.0).abs() < 1e-9, "{rate}");
+    }
+
+    #[test]
+    fn test_factors_burst() {
+        // Suddenly losing half of the packets
+        let [(_, ratio), (_, rate)] = factors(counts(100, 50), counts(16800, 1680), &config());
+
+        assert!((ratio - 5.0).abs() < 1e-9, "{ratio}");
+        assert!((rate - 5.0).abs() < 1e-9, "{rate}");
+    }
+
+    #[test]
+    fn test_factors_no_baseline() {
+        let [(kind, ratio), _] = factors(counts(10, 1), counts(0, 0), &config());
+
+        assert_eq!(kind, Kind::UneffectedRatio);
+        assert!((ratio - 10.0).abs() < 1e-9, "{ratio}");
+    }
+}
@@ -0,0 +1,194 @@
+//! Capture of the NewBlock events received and the blocks fetched by a collector, so that they can be
+//! fed back through the same processing later on with `chainpulse replay`, eg. to reproduce a decoding bug
+//! reported against a specific chain.
+//!
+//! The records are appended to a file as JSON lines, in the order in which they were received.
+//! A block and the results of its txs are captured separately, as they are fetched, so that a block
+//! which fails to be processed is captured nonetheless.
+
+use std::{
+    borrow::Cow,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use tendermint::{abci::response::DeliverTx, Block, Time};
+use tendermint_rpc::dialect::{self, v0_37::Event};
+use tracing::warn;
+
+use crate::Result;
+
+/// A captured event, block or results of the txs of a block
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record<'a> {
+    /// A NewBlock event was received
+    NewBlock { height: u64, time: Time },
+
+    /// A block was fetched
+    Block { block: Cow<'a, Block> },
+
+    /// The results of the txs of a block were fetched
+    TxsResults {
+        height: u64,
+        txs_results: Vec<dialect::DeliverTx<Event>>,
+    },
+}
+
+impl Record<'_> {
+    pub fn txs_results(height: u64, txs_results: &[DeliverTx]) -> Self {
+        let txs_results = txs_results
+            .iter()
+            .map(|result| dialect::DeliverTx {
+                code: result.code,
+                data: result.data.clone(),
+                log: result.log.clone(),
+                info: result.info.clone(),
+                gas_wanted: result.gas_wanted,
+                gas_used: result.gas_used,
+                events: result.events.iter().cloned().map(Event::from).collect(),
+                codespace: result.codespace.clone(),
+            })
+            .collect();
+
+        Self::TxsResults {
+            height,
+            txs_results,
+        }
+    }
+}
+
+/// Appends the records of a chain to a capture file
+#[derive(Clone, Debug)]
+pub struct Capture {
+    file: Arc<Mutex<File>>,
+}
+
+impl Capture {
+    /// Open the capture file, appending to it if it already exists
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Record a NewBlock event
+    pub fn new_block(&self, height: u64, time: Time) {
+        self.write(&Record::NewBlock { height, time });
+    }
+
+    /// Record a fetched block
+    pub fn block(&self, block: &Block) {
+        self.write(&Record::Block {
+            block: Cow::Borrowed(block),
+        });
+    }
+
+    /// Record the fetched results of the txs of a block
+    pub fn txs_results(&self, height: u64, txs_results: &[DeliverTx]) {
+        self.write(&Record::txs_results(height, txs_results));
+    }
+
+    /// Append a record, as a single write so that the lines of concurrent writers do not interleave.
+    /// Failing to capture must not stop the collection, so errors are only logged.
+    fn write(&self, record: &Record) {
+        let mut line = match serde_json::to_string(record) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to serialize captured record: {e}");
+                return;
+            }
+        };
+
+        line.push('\n');
+
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            warn!("Failed to write to the capture file: {e}");
+        }
+    }
+}
+
+/// Read the records of a capture file, in order
+pub fn read(path: &Path) -> Result<impl Iterator<Item = Result<Record<'static>>>> {
+    let reader = BufReader::new(File::open(path)?);
+
+    let records = reader
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|(index, line)| -> Result<Record<'static>> {
+            let record = serde_json::from_str(&line?)
+                .map_err(|e| format!("invalid record on line {}: {e}", index + 1))?;
+
+            Ok(record)
+        });
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use tendermint::abci::{Code, Event as AbciEvent, EventAttributeIndexExt};
+
+    use super::*;
+
+    const BLOCK: &str = r#"{
+        "header": {
+            "version": { "block": "11", "app": "1" },
+            "chain_id": "capture-1",
+            "height": "1",
+            "time": "2023-10-02T13:37:00Z",
+            "last_block_id": null,
+            "last_commit_hash": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+            "data_hash": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+            "validators_hash": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+            "next_validators_hash": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+            "consensus_hash": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+            "app_hash": "",
+            "last_results_hash": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+            "evidence_hash": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+            "proposer_address": "DD8A65495B6240145764A74E78CF203D51510371"
+        },
+        "data": { "txs": ["Y2hhaW5wdWxzZQ=="] },
+        "evidence": { "evidence": [] },
+        "last_commit": null
+    }"#;
+
+    #[test]
+    fn test_record_roundtrip() {
+        let block: Block = serde_json::from_str(BLOCK).unwrap();
+
+        let results = vec![DeliverTx {
+            code: Code::from(5),
+            log: "insufficient funds".to_string(),
+            events: vec![AbciEvent::new(
+                "send_packet",
+                [("packet_sequence", "42").index()],
+            )],
+            codespace: "sdk".to_string(),
+            ..DeliverTx::default()
+        }];
+
+        let records = [
+            Record::NewBlock {
+                height: 1,
+                time: block.header.time,
+            },
+            Record::Block {
+                block: Cow::Owned(block),
+            },
+            Record::txs_results(1, &results),
+        ];
+
+        for record in records {
+            let line = serde_json::to_string(&record).unwrap();
+            assert!(!line.contains('\n'));
+            assert_eq!(serde_json::from_str::<Record>(&line).unwrap(), record);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Inventory of the channels on which traffic was seen, either relayed packets or sent ones,
+//! along with when it was first and last seen and the signers which relayed the most packets on them.
+//!
+//! The channels are reported per chain on which the traffic was seen, so that a channel between two
+//! monitored chains shows once for each of them.
+
+use std::collections::HashMap;
+
+use sqlx::SqlitePool;
+
+use crate::{
+    db::{ChannelRelayerRow, ChannelRow},
+    Result,
+};
+
+/// Number of relayers reported per channel
+pub const TOP_RELAYERS: u32 = 3;
+
+/// The channels on which traffic was seen, optionally only on the given chain
+pub async fn inventory(pool: &SqlitePool, chain: Option<&str>) -> Result<Vec<ChannelRow>> {
+    let query = r#"
+        WITH activity AS (
+            SELECT chain, src_port, src_channel, dst_port, dst_channel,
+                block_time AS at, 1 AS relayed, effected, 0 AS sent
+            FROM packets
+            UNION ALL
+            SELECT chain, src_port, src_channel, dst_port, dst_channel,
+                first_seen_at AS at, 0 AS relayed, 0 AS effected, 1 AS sent
+            FROM sent_packets
+        )
+        SELECT chain, src_port, src_channel, dst_port, dst_channel,
+            MIN(at) AS first_seen_at, MAX(at) AS last_activity_at,
+            SUM(relayed) AS packets, SUM(effected) AS effected_packets, SUM(sent) AS sent_packets
+        FROM activity
+        WHERE ?1 IS NULL OR chain = ?1
+        GROUP BY chain, src_port, src_channel, dst_port, dst_channel
+        ORDER BY chain, src_channel, dst_channel
+    "#;
+
+    let mut channels: Vec<ChannelRow> = sqlx::query_as(query).bind(chain).fetch_all(pool).await?;
+
+    let query = r#"
+        SELECT chain, src_port, src_channel, dst_port, dst_channel, signer, packets, effected_packets
+        FROM (
+            SELECT chain, src_port, src_channel, dst_port, dst_channel, signer,
+                COUNT(*) AS packets, SUM(effected) AS effected_packets,
+                ROW_NUMBER() OVER (
+                    PARTITION BY chain, src_port, src_channel, dst_port, dst_channel
+                    ORDER BY SUM(effected) DESC, COUNT(*) DESC, signer
+                ) AS position
+            FROM packets
+            WHERE signer IS NOT NULL AND (?1 IS NULL OR chain = ?1)
+            GROUP BY chain, src_port, src_channel, dst_port, dst_channel, signer
+        )
+        WHERE position <= ?2
+        ORDER BY position
+    "#;
+
+    let relayers: Vec<ChannelRelayerRow> = sqlx::query_as(query)
+        .bind(chain)
+        .bind(TOP_RELAYERS)
+        .fetch_all(pool)
+        .await?;
+
+    let mut by_channel: HashMap<_, Vec<ChannelRelayerRow>> = HashMap::new();
+
+    for relayer in relayers {
+        let key = (
+            relayer.chain.clone(),
+            relayer.src_port.clone(),
+            relayer.src_channel.clone(),
+            relayer.dst_port.clone(),
+            relayer.dst_channel.clone(),
+        );
+
+        by_channel.entry(key).or_default().push(relayer);
+    }
+
+    for channel in &mut channels {
+        let key = (
+            channel.chain.clone(),
+            channel.src_port.clone(),
+            channel.src_channel.clone(),
+            channel.dst_port.clone(),
+            channel.dst_channel.clone(),
+        );
+
+        channel.top_relayers = by_channel.remove(&key).unwrap_or_default();
+    }
+
+    Ok(channels)
+}
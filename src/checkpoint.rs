@@ -0,0 +1,69 @@
+//! Coordination of the checkpoints of the write-ahead log, for the database to be followed by
+//! external replication tools, eg. Litestream, or by read-only analytics processes.
+//!
+//! SQLite normally checkpoints the write-ahead log on its own whenever it grows past a thousand pages,
+//! which may truncate it under the feet of a tool shipping it elsewhere. In this mode, the automatic
+//! checkpoints are disabled, and the log is only checkpointed periodically, in passive mode, which
+//! never waits on the readers, and consistent snapshots of the database can be taken on demand.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tokio::time;
+use tracing::{debug, error, info};
+
+use crate::Result;
+
+/// A snapshot of the database, written by [`snapshot`]
+#[derive(Clone, Debug, Serialize)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+}
+
+/// Periodically checkpoint the write-ahead log
+pub async fn run(pool: SqlitePool, interval: Duration) {
+    loop {
+        time::sleep(interval).await;
+
+        if let Err(e) = checkpoint(&pool).await {
+            error!("Failed to checkpoint the write-ahead log: {e}");
+        }
+    }
+}
+
+/// Checkpoint as much of the write-ahead log as possible without waiting on the readers
+async fn checkpoint(pool: &SqlitePool) -> Result<()> {
+    let (busy, log, checkpointed): (i64, i64, i64) =
+        sqlx::query_as("PRAGMA wal_checkpoint(PASSIVE)")
+            .fetch_one(pool)
+            .await?;
+
+    debug!("Checkpointed {checkpointed} of {log} frames of the write-ahead log (busy: {busy})");
+
+    Ok(())
+}
+
+/// Write a consistent snapshot of the database to a new file of the given directory
+pub async fn snapshot(pool: &SqlitePool, dir: &Path) -> Result<Snapshot> {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = dir.join(format!("chainpulse-{millis}.db"));
+
+    tokio::fs::create_dir_all(dir).await?;
+
+    // Runs in a single read transaction, so the snapshot is consistent even while blocks are written
+    sqlx::query("VACUUM INTO ?")
+        .bind(path.to_string_lossy().into_owned())
+        .execute(pool)
+        .await?;
+
+    let size_bytes = tokio::fs::metadata(&path).await?.len();
+
+    info!("Wrote a snapshot of the database to {}", path.display());
+
+    Ok(Snapshot { path, size_bytes })
+}
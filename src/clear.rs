@@ -0,0 +1,196 @@
+//! Ready-to-run Hermes commands to clear the packets which are stuck on a channel,
+//! from the packets sent from the monitored chains which were neither acknowledged nor timed out.
+//!
+//! `hermes clear packets` relays both the packets not yet received and the acknowledgements
+//! not yet relayed back, so a single command per channel covers the packets in either state.
+//! The commands are served at `/api/v1/packets/pending/clear` and printed by the `clear-commands` command.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::{api::Scope, config::Config, db, Result};
+
+/// Options of the `clear-commands` command
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Only suggest commands for the packets sent from the given chain
+    pub chain: Option<String>,
+
+    /// Only suggest commands for the packets sent or received over the given channel
+    pub channel: Option<String>,
+
+    /// Only consider the packets sent at least this many seconds ago
+    pub min_age: Option<u64>,
+}
+
+/// A command clearing the stuck packets of a channel
+#[derive(Clone, Debug, Serialize)]
+pub struct Suggestion {
+    /// The chain the packets were sent from
+    pub chain: String,
+    pub port: String,
+    pub channel: String,
+
+    /// The sequences of the stuck packets
+    pub sequences: Vec<u64>,
+
+    /// The time in seconds since the oldest of the packets was sent
+    pub oldest_age_seconds: i64,
+
+    /// The Hermes command clearing the packets
+    pub command: String,
+}
+
+/// Format sequences as expected by the `--packet-sequences` option of Hermes, collapsing consecutive ones into ranges,
+/// eg. `1,10..20`
+pub fn sequence_ranges(sequences: &[u64]) -> String {
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+
+    for &sequence in sequences {
+        match ranges.last_mut() {
+            Some((_, end)) if sequence == *end + 1 => *end = sequence,
+            Some((_, end)) if sequence == *end => {}
+            _ => ranges.push((sequence, sequence)),
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{start}..{end}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The Hermes command clearing the given packets sent on a channel
+pub fn command(chain: &str, port: &str, channel: &str, sequences: &[u64]) -> String {
+    format!(
+        "hermes clear packets --chain {chain} --port {port} --channel {channel} --packet-sequences {}",
+        sequence_ranges(sequences)
+    )
+}
+
+/// Suggest a command for each channel with packets sent at least `min_age` seconds ago
+/// which were neither acknowledged nor timed out, within the given scope, optionally only for the given chain or channel.
+/// The commands are run on the channel the packets were sent over, so that is the channel which must be in scope.
+pub async fn suggestions(
+    pool: &SqlitePool,
+    scope: Scope<'_>,
+    chain: Option<&str>,
+    channel: Option<&str>,
+    min_age: u64,
+) -> Result<Vec<Suggestion>> {
+    let query = format!(
+        r#"
+        SELECT chain, src_port, src_channel, sequence,
+            CAST((julianday('now') - julianday(first_seen_at)) * 86400 AS INTEGER)
+        FROM sent_packets
+        WHERE   acknowledged_at IS NULL
+            AND timed_out_at IS NULL
+            AND (resolved_at IS NULL OR received_at IS NOT NULL)
+            AND first_seen_at < datetime('now', ?1)
+            AND (?2 IS NULL OR chain = ?2)
+            AND (?3 IS NULL OR src_channel = ?3 OR dst_channel = ?3)
+            AND {}
+        ORDER BY chain, src_port, src_channel, sequence
+        "#,
+        Scope::filter(4, "chain", &["src_channel"])
+    );
+
+    let rows: Vec<(String, String, String, i64, i64)> = sqlx::query_as(&query)
+        .bind(format!("-{min_age} seconds"))
+        .bind(chain)
+        .bind(channel)
+        .bind(scope.chains()?)
+        .bind(scope.channels()?)
+        .fetch_all(pool)
+        .await?;
+
+    let mut channels: BTreeMap<_, (Vec<u64>, i64)> = BTreeMap::new();
+
+    for (chain, port, channel, sequence, age) in rows {
+        let (sequences, oldest_age) = channels.entry((chain, port, channel)).or_default();
+        sequences.push(sequence as u64);
+        *oldest_age = (*oldest_age).max(age);
+    }
+
+    let suggestions = channels
+        .into_iter()
+        .map(
+            |((chain, port, channel), (sequences, oldest_age_seconds))| Suggestion {
+                command: command(&chain, &port, &channel, &sequences),
+                chain,
+                port,
+                channel,
+                sequences,
+                oldest_age_seconds,
+            },
+        )
+        .collect();
+
+    Ok(suggestions)
+}
+
+/// Print the commands clearing the stuck packets of each channel
+pub async fn run(config: &Config, options: Options) -> Result<()> {
+    let pool = db::connect(&config.database.path).await?;
+
+    let min_age = options
+        .min_age
+        .unwrap_or(config.stuck_packets.native_min_age);
+
+    let suggestions = suggestions(
+        &pool,
+        Scope::default(),
+        options.chain.as_deref(),
+        options.channel.as_deref(),
+        min_age,
+    )
+    .await?;
+
+    if suggestions.is_empty() {
+        println!("No packets pending for more than {min_age}s");
+    }
+
+    for suggestion in suggestions {
+        println!(
+            "# {} {}/{}: {} packets pending, the oldest for {}s",
+            suggestion.chain,
+            suggestion.port,
+            suggestion.channel,
+            suggestion.sequences.len(),
+            suggestion.oldest_age_seconds,
+        );
+        println!("{}", suggestion.command);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_ranges() {
+        assert_eq!(sequence_ranges(&[]), "");
+        assert_eq!(sequence_ranges(&[7]), "7");
+        assert_eq!(sequence_ranges(&[1, 10, 11, 12, 20]), "1,10..12,20");
+        assert_eq!(sequence_ranges(&[3, 4, 4, 5]), "3..5");
+    }
+
+    #[test]
+    fn test_command() {
+        assert_eq!(
+            command("osmosis-1", "transfer", "channel-0", &[1, 2, 3, 8]),
+            "hermes clear packets --chain osmosis-1 --port transfer --channel channel-0 --packet-sequences 1..3,8"
+        );
+    }
+}
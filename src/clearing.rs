@@ -0,0 +1,127 @@
+//! Percentiles of the time it takes for the packets sent on each channel to be received on their destination chain,
+//! over sliding windows, computed from the database.
+//!
+//! Histograms are hard to compare across chains whose block times differ by an order of magnitude,
+//! as most of their samples fall in a few buckets, so the percentiles are computed exactly instead.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tokio::time;
+use tracing::error;
+
+use crate::{metrics::Metrics, Result};
+
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The windows over which the percentiles are computed, with their label and SQLite modifier
+const WINDOWS: &[(&str, &str)] = &[("1h", "-1 hours"), ("24h", "-24 hours")];
+
+/// The percentiles computed, with their label
+const QUANTILES: &[(&str, f64)] = &[("0.5", 0.5), ("0.95", 0.95), ("0.99", 0.99)];
+
+/// The value below which the given fraction of the sorted values fall, with the nearest-rank method
+pub fn quantile(sorted: &[f64], q: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Periodically compute the percentiles of the clearing time of the packets sent on each channel
+pub async fn run(pool: SqlitePool, metrics: Metrics) {
+    loop {
+        if let Err(e) = update(&pool, &metrics).await {
+            error!("Failed to compute the clearing times: {e}");
+        }
+
+        time::sleep(UPDATE_INTERVAL).await;
+    }
+}
+
+async fn update(pool: &SqlitePool, metrics: &Metrics) -> Result<()> {
+    let query = r#"
+        SELECT chain, src_channel, dst_channel,
+            (julianday(received_at) - julianday(first_seen_at)) * 86400
+        FROM sent_packets
+        WHERE received_at >= datetime('now', ?)
+    "#;
+
+    let mut percentiles = Vec::new();
+
+    for (window, modifier) in WINDOWS {
+        let rows: Vec<(String, String, String, f64)> = sqlx::query_as(query)
+            .bind(*modifier)
+            .fetch_all(pool)
+            .await?;
+
+        let mut channels: BTreeMap<_, Vec<f64>> = BTreeMap::new();
+
+        for (chain, src_channel, dst_channel, seconds) in rows {
+            channels
+                .entry((chain, src_channel, dst_channel))
+                .or_default()
+                .push(seconds.max(0.0));
+        }
+
+        for ((chain, src_channel, dst_channel), mut times) in channels {
+            let Ok(chain_id) = chain::Id::try_from(chain) else {
+                continue;
+            };
+
+            times.sort_by(f64::total_cmp);
+
+            for (label, q) in QUANTILES {
+                if let Some(value) = quantile(&times, *q) {
+                    percentiles.push((
+                        chain_id.clone(),
+                        src_channel.clone(),
+                        dst_channel.clone(),
+                        *label,
+                        *window,
+                        value,
+                    ));
+                }
+            }
+        }
+    }
+
+    // Only reset the gauge once all windows have been computed,
+    // so that channels without any packet received are removed
+    metrics.reset_ibc_packet_clearing_time();
+
+    for (chain_id, src_channel, dst_channel, quantile, window, value) in percentiles {
+        metrics.ibc_packet_clearing_time(
+            &chain_id,
+            &src_channel,
+            &dst_channel,
+            quantile,
+            window,
+            value,
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantile() {
+        assert_eq!(quantile(&[], 0.5), None);
+        assert_eq!(quantile(&[7.0], 0.99), Some(7.0));
+
+        let times: Vec<f64> = (1..=100).map(f64::from).collect();
+
+        assert_eq!(quantile(&times, 0.5), Some(50.0));
+        assert_eq!(quantile(&times, 0.95), Some(95.0));
+        assert_eq!(quantile(&times, 0.99), Some(99.0));
+        assert_eq!(quantile(&times, 0.0), Some(1.0));
+        assert_eq!(quantile(&times, 1.0), Some(100.0));
+    }
+}
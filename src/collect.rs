@@ -1,94 +1,390 @@
-use std::time::Duration;
+use std::{collections::VecDeque, sync::Arc, time::Duration};
 
 use futures::StreamExt;
-use ibc_proto::cosmos::tx::v1beta1::Tx;
+use ibc_proto::{
+    cosmos::tx::v1beta1::{Fee, Tx},
+    google::protobuf::Any,
+    ibc::core::channel::v1::Packet,
+};
 use prost::Message;
 use sqlx::SqlitePool;
 use tendermint::{
-    block::Height,
+    abci::response::DeliverTx,
+    block::{Block, Height},
     chain::{self, Id as ChainId},
     crypto::Sha256,
+    Time,
 };
 use tendermint_rpc::{
-    client::CompatMode,
-    event::{Event, EventData},
-    Client, SubscriptionClient, WebSocketClient, WebSocketClientUrl,
+    client::CompatMode, error::ErrorDetail, event::EventData, Client, SubscriptionClient,
+    WebSocketClient, WebSocketClientUrl,
+};
+use tokio::{
+    sync::Semaphore,
+    time::{self, Instant},
 };
-use tokio::time;
-use tracing::{error, info, warn, Instrument};
+use tracing::{debug, error, info, warn, Instrument};
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
 type Pool = SqlitePool;
 
 use crate::{
-    db::{PacketRow, TxRow},
+    capture::Capture,
+    config::{CircuitBreaker, Endpoint, Keepalive, Memo},
+    db::{self, PacketRow, TxRow},
+    dead_letters, discovery, failures,
+    flows::{self, Flow, Kind},
+    health::{self, Health},
+    hops,
+    ignored::Sampler,
+    memo,
     metrics::Metrics,
-    msg::Msg,
+    msg::{Decoders, Msg},
+    pending::{self, SendPacket},
+    plugins::{PacketEvent, Plugins},
+    queue::RetryQueue,
+    roles, sequences, signer,
+    state::{Collectors, PauseHandle},
+    trace::{BlockTrace, MsgTrace, Outcome as MsgOutcome, PacketTrace},
+    transfer::{self, Ack, Token, TransferData},
 };
 
 const NEWBLOCK_TIMEOUT: Duration = Duration::from_secs(60);
 const DISCONNECT_AFTER_BLOCKS: usize = 100;
 
-#[derive(Copy, Clone, Debug, thiserror::Error)]
+/// Maximum number of dropped blocks to fetch when a gap in NewBlock events is detected
+const MAX_DROPPED_BLOCKS: u64 = 100;
+
+/// Maximum number of blocks taken out of the retry queue on each new block
+const MAX_RETRIED_BLOCKS: usize = 10;
+
+/// The capacity the buffer the txs are encoded into keeps between two txs,
+/// so that a single huge tx does not keep its memory allocated for the rest of the block
+const MAX_ENCODE_BUFFER_CAPACITY: usize = 1024 * 1024;
+
+#[derive(Clone, Debug, thiserror::Error)]
 pub enum Outcome {
     #[error("Timeout after {0:?} waiting for a NewBlock event")]
     Timeout(Duration),
 
     #[error("Disconnecting after {0} blocks")]
     BlockElapsed(usize),
+
+    #[error("Collection paused")]
+    Paused,
+
+    #[error("Missed {0} keepalive pongs in a row")]
+    KeepaliveTimeout(u32),
+
+    #[error("Endpoint reports chain {0}, refusing to collect")]
+    ChainIdMismatch(chain::Id),
+
+    #[error("Endpoint speaks CometBFT {0}, switching compat mode")]
+    CompatModeMismatch(CompatMode),
+
+    #[error("Failed to decode the events of the endpoint, falling back to CometBFT {0}")]
+    CompatModeFallback(CompatMode),
 }
 
+/// How the compat mode in use to talk to an endpoint was picked
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum CompatSource {
+    /// Configured, or matching the version reported by the node
+    #[default]
+    Version,
+
+    /// Fallen back to after failing to decode the events in the other compat mode
+    Fallback,
+
+    /// Fallen back to, and then successfully decoded the events, so it is kept
+    /// even if the node reports a version matching the other compat mode
+    Decoded,
+}
+
+impl CompatSource {
+    /// Whether to switch to the compat mode matching the version reported by the node.
+    /// Once fallen back, the reported version is not trusted anymore, as the events already
+    /// failed to decode in its compat mode, eg. because the node misreports its version.
+    fn follows_version(self) -> bool {
+        self == Self::Version
+    }
+
+    /// How the compat mode was picked, once the events were successfully decoded in it
+    fn decoded(self) -> Self {
+        match self {
+            Self::Version => Self::Version,
+            Self::Fallback | Self::Decoded => Self::Decoded,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     chain_id: chain::Id,
-    compat_mode: CompatMode,
-    ws_url: WebSocketClientUrl,
+    mut endpoint: Endpoint,
+    breaker: CircuitBreaker,
+    keepalive: Keepalive,
+    memo: Memo,
     db: Pool,
     metrics: Metrics,
+    collectors: Collectors,
+    decoders: Decoders,
+    plugins: Plugins,
+    queue: RetryQueue,
+    health: Health,
+    sampler: Sampler,
 ) -> Result<()> {
+    let mut pause = collectors.pause_handle(&chain_id);
+
+    let capture = match &endpoint.capture {
+        Some(path) => {
+            info!("Capturing events and blocks to {}", path.display());
+            Some(Capture::open(path)?)
+        }
+        None => None,
+    };
+
+    // Shared across reconnections, so that the blocks still processed from a previous connection count as well
+    let in_flight = Arc::new(Semaphore::new(endpoint.max_in_flight_blocks()));
+
+    let urls = endpoint.urls();
+    let mut exclude = None;
+    let mut reconnects = VecDeque::new();
+    let mut compat_source = CompatSource::default();
+
+    // The endpoints which reported another chain, and are thus not connected to again
+    let mut mismatched: Vec<WebSocketClientUrl> = Vec::new();
+
     loop {
-        let task = collect(&chain_id, compat_mode, &ws_url, &db, &metrics);
+        if pause.is_paused() {
+            info!("Collection paused, waiting to be resumed...");
+            pause.resumed().await;
+            info!("Collection resumed");
+        }
+
+        // Prefer the healthiest endpoint, other than the one which just tripped the circuit breaker
+        let candidates = urls
+            .iter()
+            .filter(|url| !mismatched.contains(*url))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let ws_url = health.best(&chain_id, &candidates, exclude.take().as_ref());
+
+        let task = collect(
+            &chain_id,
+            &endpoint,
+            &ws_url,
+            keepalive,
+            memo,
+            &db,
+            &metrics,
+            &collectors,
+            &decoders,
+            &plugins,
+            &queue,
+            &sampler,
+            capture.as_ref(),
+            &in_flight,
+            &mut pause,
+            &mut compat_source,
+        );
 
         match task.await {
+            Ok(Outcome::Paused) => {
+                warn!("{}", Outcome::Paused);
+                continue;
+            }
+            Ok(Outcome::ChainIdMismatch(network)) => {
+                error!("{}", Outcome::ChainIdMismatch(network));
+                mismatched.push(ws_url);
+
+                if urls.iter().all(|url| mismatched.contains(url)) {
+                    collectors.chain_id_mismatch(&chain_id);
+
+                    return Err(format!(
+                        "Every endpoint of {chain_id} reports another chain, stopping the collector"
+                    )
+                    .into());
+                }
+
+                // Another endpoint is tried right away, this is not a reconnection to a failing one
+                warn!("Switching to another endpoint");
+                continue;
+            }
+            Ok(Outcome::CompatModeMismatch(compat_mode)) => {
+                warn!("{}", Outcome::CompatModeMismatch(compat_mode));
+                endpoint.comet_version = compat_mode;
+                compat_source = CompatSource::Version;
+            }
+            Ok(Outcome::CompatModeFallback(compat_mode)) => {
+                warn!("{}", Outcome::CompatModeFallback(compat_mode));
+                endpoint.comet_version = compat_mode;
+                compat_source = CompatSource::Fallback;
+            }
             Ok(outcome) => warn!("{outcome}"),
             Err(e) => {
                 metrics.chainpulse_errors(&chain_id);
+                collectors.error(&chain_id, &e);
 
                 error!("{e}")
             }
         }
 
+        collectors.disconnected(&chain_id);
+
         metrics.chainpulse_reconnects(&chain_id);
+        collectors.reconnect(&chain_id);
+
+        // Open the circuit if the endpoint keeps failing, instead of reconnecting every few seconds
+        let now = Instant::now();
+        reconnects.push_back(now);
+
+        while let Some(first) = reconnects.front() {
+            if now.duration_since(*first) <= breaker.window() {
+                break;
+            }
+
+            reconnects.pop_front();
+        }
+
+        if reconnects.len() >= breaker.max_reconnects {
+            reconnects.clear();
+            metrics.chainpulse_circuit_breaks(&chain_id);
+
+            if urls.len() > 1 {
+                warn!("Too many reconnects to {ws_url}, switching endpoints");
+                exclude = Some(ws_url);
+            } else {
+                warn!(
+                    "Too many reconnects, backing off for {:?}...",
+                    breaker.backoff()
+                );
+
+                metrics.chainpulse_circuit_open(&chain_id, true);
+                time::sleep(breaker.backoff()).await;
+                metrics.chainpulse_circuit_open(&chain_id, false);
+
+                continue;
+            }
+        }
 
         info!("Reconnecting in 5 seconds...");
         time::sleep(Duration::from_secs(5)).await;
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn collect(
     chain_id: &chain::Id,
-    compat_mode: CompatMode,
+    endpoint: &Endpoint,
     ws_url: &WebSocketClientUrl,
+    keepalive: Keepalive,
+    memo: Memo,
     db: &Pool,
     metrics: &Metrics,
+    collectors: &Collectors,
+    decoders: &Decoders,
+    plugins: &Plugins,
+    queue: &RetryQueue,
+    sampler: &Sampler,
+    capture: Option<&Capture>,
+    in_flight: &Arc<Semaphore>,
+    pause: &mut PauseHandle,
+    compat_source: &mut CompatSource,
 ) -> Result<Outcome> {
     info!("Connecting to {ws_url}...");
+    collectors.connecting(chain_id, &ws_url.to_string());
+
     let (client, driver) = WebSocketClient::builder(ws_url.clone())
-        .compat_mode(compat_mode)
+        .compat_mode(endpoint.comet_version)
         .build()
         .await?;
 
     tokio::spawn(driver.run());
 
+    let host = health::endpoint_host(ws_url);
+
+    // Make sure we are not recording the blocks of another chain
+    let status = client.status().await?;
+    let network = status.node_info.network;
+
+    if network != *chain_id {
+        metrics.chainpulse_chain_id_mismatches(chain_id, &host, &network);
+        collectors.error(
+            chain_id,
+            &format!("Endpoint {host} reports chain {network}"),
+        );
+
+        let _ = client.close();
+        return Ok(Outcome::ChainIdMismatch(network));
+    }
+
+    // Switch to the compat mode matching the version of the node, if it differs from the configured one,
+    // unless the current one was fallen back to, eg. because the node misreports its version
+    if let Ok(compat_mode) = CompatMode::from_version(status.node_info.version.clone()) {
+        if compat_mode != endpoint.comet_version && compat_source.follows_version() {
+            let _ = client.close();
+            return Ok(Outcome::CompatModeMismatch(compat_mode));
+        }
+    }
+
+    metrics.chainpulse_compat_mode(chain_id, endpoint.comet_version);
+
+    metrics.chainpulse_node_info(
+        chain_id,
+        status.node_info.moniker.as_ref(),
+        &status.node_info.version.to_string(),
+        &host,
+    );
+
     info!("Subscribing to NewBlock events...");
-    let mut subscription = client.subscribe(queries::new_block()).await?;
+    let mut subscription = client.subscribe(endpoint.subscription_query()?).await?;
 
     info!("Waiting for new blocks...");
+    collectors.connected(chain_id);
 
     let mut count: usize = 0;
 
+    // The height and time of the highest block received on this connection
+    let mut last_block: Option<(u64, Time)> = None;
+
+    // Only time out if no event was received at all within the timeout, regardless of keepalive pings
+    let mut deadline = Instant::now() + NEWBLOCK_TIMEOUT;
+
+    let mut keepalive_interval =
+        time::interval_at(Instant::now() + keepalive.interval(), keepalive.interval());
+    let mut missed_pongs = 0;
+
     loop {
-        let next_event = time::timeout(NEWBLOCK_TIMEOUT, subscription.next()).await;
+        let next_event = tokio::select! {
+            next_event = time::timeout_at(deadline, subscription.next()) => next_event,
+            _ = keepalive_interval.tick(), if keepalive.is_enabled() => {
+                let pong = time::timeout(keepalive.timeout(), client.health()).await;
+
+                if matches!(pong, Ok(Ok(_))) {
+                    missed_pongs = 0;
+                    continue;
+                }
+
+                missed_pongs += 1;
+                metrics.chainpulse_missed_pongs(chain_id);
+                warn!("Missed keepalive pong ({missed_pongs}/{})", keepalive.max_missed);
+
+                if missed_pongs >= keepalive.max_missed {
+                    let _ = client.close();
+                    return Ok(Outcome::KeepaliveTimeout(missed_pongs));
+                }
+
+                continue;
+            }
+            _ = pause.paused() => {
+                let _ = client.close();
+                return Ok(Outcome::Paused);
+            }
+        };
+
         let next_event = match next_event {
             Ok(next_event) => next_event,
             Err(_) => {
@@ -97,29 +393,211 @@ async fn collect(
             }
         };
 
+        deadline = Instant::now() + NEWBLOCK_TIMEOUT;
+
         count += 1;
 
-        let Some(Ok(event)) = next_event else {
+        let event = match next_event {
+            Some(Ok(event)) => event,
+            Some(Err(e)) if is_protocol_mismatch(&e) => {
+                warn!("Failed to decode event, the compat mode may be wrong: {e}");
+
+                let _ = client.close();
+                return Ok(Outcome::CompatModeFallback(other_compat_mode(
+                    endpoint.comet_version,
+                )));
+            }
+            _ => continue,
+        };
+
+        let EventData::NewBlock {
+            block: Some(block), ..
+        } = event.data
+        else {
             continue;
         };
 
-        let (chain_id, client, pool, metrics) = (
-            chain_id.clone(),
-            client.clone(),
-            db.clone(),
-            metrics.clone(),
-        );
+        if *compat_source == CompatSource::Fallback {
+            info!(
+                "Decoded the events in compat mode {}, keeping it",
+                endpoint.comet_version
+            );
+            *compat_source = compat_source.decoded();
+        }
+
+        let height = block.header.height.value();
+        info!("New block at height {height}");
+
+        if let Some(capture) = capture {
+            capture.new_block(height, block.header.time);
+        }
+
+        let now = Time::now();
+
+        // Signed, so that clock skew shows when the block is ahead of the local clock
+        let drift = match now.duration_since(block.header.time) {
+            Ok(delay) => {
+                metrics.chainpulse_event_delay(chain_id, &host, delay);
+                delay.as_secs_f64()
+            }
+            Err(_) => block
+                .header
+                .time
+                .duration_since(now)
+                .map_or(0.0, |ahead| -ahead.as_secs_f64()),
+        };
 
-        tokio::spawn(
-            async move {
-                if let Err(e) = on_new_block(client, pool, event, &metrics).await {
-                    metrics.chainpulse_errors(&chain_id);
+        metrics.chainpulse_block_drift(chain_id, &host, drift);
 
-                    error!("{e}");
+        // Only the interval between consecutive blocks is observed, blocks replayed after a reconnect
+        // or delivered out of order are ignored
+        match last_block {
+            Some((last_height, last_time)) if height > last_height => {
+                if height == last_height + 1 {
+                    if let Ok(interval) = block.header.time.duration_since(last_time) {
+                        metrics.chain_block_interval(chain_id, interval);
+                    }
                 }
+
+                last_block = Some((height, block.header.time));
             }
-            .in_current_span(),
-        );
+            Some(_) => (),
+            None => last_block = Some((height, block.header.time)),
+        }
+
+        let mut heights = vec![height];
+
+        // WebSocket subscriptions may drop events under load, which shows as gaps in heights
+        if let Some(previous) = collectors.new_block(chain_id, height) {
+            if height > previous + 1 {
+                let dropped = height - previous - 1;
+
+                warn!("Dropped {dropped} NewBlock events between heights {previous} and {height}");
+                metrics.chainpulse_dropped_events(chain_id, dropped);
+
+                if endpoint.fetch_dropped_blocks {
+                    let first = (previous + 1).max(height.saturating_sub(MAX_DROPPED_BLOCKS));
+                    heights = (first..=height).collect();
+                }
+            }
+        }
+
+        // Retry the blocks which previously could not be written to the database
+        let retried = queue.take(chain_id, MAX_RETRIED_BLOCKS).await;
+        if !retried.is_empty() {
+            info!("Retrying {} blocks from the retry queue", retried.len());
+            heights.extend(retried);
+            metrics.chainpulse_retry_queue_size(chain_id, queue.len(chain_id));
+        }
+
+        let pending = heights.len();
+
+        for (index, height) in heights.into_iter().enumerate() {
+            if !collectors.claim_block(chain_id, height) {
+                debug!("Skipping block at height {height}, which was already processed");
+                continue;
+            }
+
+            // Wait for a slot before spawning, leaving the next events in the subscription meanwhile
+            metrics.chainpulse_pending_blocks(chain_id, pending - index);
+            let permit = in_flight.clone().acquire_owned().await?;
+            let max_in_flight = endpoint.max_in_flight_blocks();
+            metrics.chainpulse_in_flight_blocks(
+                chain_id,
+                max_in_flight - in_flight.available_permits(),
+            );
+
+            let (
+                chain_id,
+                host,
+                client,
+                pool,
+                metrics,
+                collectors,
+                decoders,
+                plugins,
+                queue,
+                sampler,
+                capture,
+                in_flight,
+            ) = (
+                chain_id.clone(),
+                host.clone(),
+                client.clone(),
+                db.clone(),
+                metrics.clone(),
+                collectors.clone(),
+                decoders.clone(),
+                plugins.clone(),
+                queue.clone(),
+                sampler.clone(),
+                capture.cloned(),
+                in_flight.clone(),
+            );
+
+            tokio::spawn(
+                async move {
+                    let mut trace = BlockTrace::new(height);
+
+                    let result = on_new_block(
+                        client,
+                        &host,
+                        pool.clone(),
+                        &chain_id,
+                        height,
+                        memo,
+                        &metrics,
+                        &decoders,
+                        &plugins,
+                        capture.as_ref(),
+                        &mut trace,
+                    )
+                    .await;
+
+                    match &result {
+                        Ok(()) => {
+                            let ignored = &trace.ignored;
+                            let sampled = sampler.record(&pool, &chain_id, height, ignored).await;
+
+                            if let Err(e) = sampled {
+                                warn!("Failed to record the ignored messages: {e}");
+                            }
+                        }
+                        Err(e) => trace.error = Some(e.to_string()),
+                    }
+
+                    collectors.trace_block(&chain_id, trace);
+
+                    drop(permit);
+                    metrics.chainpulse_in_flight_blocks(
+                        &chain_id,
+                        max_in_flight - in_flight.available_permits(),
+                    );
+
+                    if let Err(e) = result {
+                        collectors.release_block(&chain_id, height);
+
+                        metrics.chainpulse_errors(&chain_id);
+                        collectors.error(&chain_id, &e);
+
+                        error!("{e}");
+
+                        if db::is_transient(e.as_ref()) {
+                            if queue.push(&chain_id, height).await {
+                                info!("Queued block at height {height} to be retried");
+                            } else {
+                                warn!("Retry queue is full, dropping block at height {height}");
+                            }
+
+                            metrics.chainpulse_retry_queue_size(&chain_id, queue.len(&chain_id));
+                        }
+                    }
+                }
+                .in_current_span(),
+            );
+        }
+
+        metrics.chainpulse_pending_blocks(chain_id, 0);
 
         if count >= DISCONNECT_AFTER_BLOCKS {
             return Ok(Outcome::BlockElapsed(count));
@@ -127,65 +605,510 @@ async fn collect(
     }
 }
 
+/// Whether an error is typical of a response which does not have the shape expected in the current compat mode
+fn is_protocol_mismatch(e: &tendermint_rpc::Error) -> bool {
+    matches!(
+        e.detail(),
+        ErrorDetail::Serde(_)
+            | ErrorDetail::Parse(_)
+            | ErrorDetail::MalformedJson(_)
+            | ErrorDetail::UnrecognizedEventType(_)
+    )
+}
+
+fn other_compat_mode(compat_mode: CompatMode) -> CompatMode {
+    match compat_mode {
+        CompatMode::V0_34 => CompatMode::V0_37,
+        CompatMode::V0_37 => CompatMode::V0_34,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn on_new_block(
     client: WebSocketClient,
+    endpoint: &str,
     db: Pool,
-    event: Event,
+    chain_id: &chain::Id,
+    height: u64,
+    memo: Memo,
     metrics: &Metrics,
+    decoders: &Decoders,
+    plugins: &Plugins,
+    capture: Option<&Capture>,
+    trace: &mut BlockTrace,
 ) -> Result<()> {
-    let EventData::NewBlock {
-        block: Some(block), ..
-    } = event.data
-    else {
-        return Ok(());
-    };
+    let height = Height::try_from(height)?;
+
+    let start = Instant::now();
+    let block = client.block(height).await?.block;
+    metrics.chainpulse_rpc_latency(chain_id, endpoint, "block", start.elapsed());
+
+    // Captured before being processed, so that a block which fails to be processed can be replayed
+    if let Some(capture) = capture {
+        capture.block(&block);
+    }
+
+    // The txs of the block are released as they are processed, before fetching the results of the block
+    let processed = process_block(
+        &db, chain_id, block, memo, metrics, decoders, plugins, trace,
+    )
+    .await?;
+
+    let start = Instant::now();
+    let results = client.block_results(height).await?;
+    metrics.chainpulse_rpc_latency(chain_id, endpoint, "block_results", start.elapsed());
+
+    let txs_results = results.txs_results.unwrap_or_default();
 
+    if let Some(capture) = capture {
+        capture.txs_results(height.value(), &txs_results);
+    }
+
+    process_results(&db, chain_id, &processed, &txs_results, metrics).await
+}
+
+/// Record the IBC packets relayed in a block, consuming its txs
+#[allow(clippy::too_many_arguments)]
+pub async fn process_block(
+    db: &Pool,
+    chain_id: &chain::Id,
+    mut block: Block,
+    memo: Memo,
+    metrics: &Metrics,
+    decoders: &Decoders,
+    plugins: &Plugins,
+    trace: &mut BlockTrace,
+) -> Result<ProcessedTxs> {
     let height = block.header.height;
-    let chain_id = block.header.chain_id;
+    let time = block.header.time;
+    let proposer = block.header.proposer_address.to_string();
+
+    let block_size: usize = block.data.iter().map(|tx| tx.len()).sum();
+    metrics.chain_block_txs(chain_id, block.data.len());
+    metrics.chain_block_size_bytes(chain_id, block_size);
+
+    let processed = process_txs(
+        db,
+        chain_id,
+        height,
+        time,
+        &proposer,
+        std::mem::take(&mut block.data),
+        memo,
+        metrics,
+        decoders,
+        plugins,
+        trace,
+    )
+    .await?;
+
+    metrics.ibc_packets_per_block(chain_id, processed.packets);
+
+    Ok(processed)
+}
+
+/// Record the failed relay txs and the packets sent in a block, from the results of its txs
+pub async fn process_results(
+    db: &Pool,
+    chain_id: &chain::Id,
+    processed: &ProcessedTxs,
+    txs_results: &[DeliverTx],
+    metrics: &Metrics,
+) -> Result<()> {
+    let results_size: usize = txs_results.iter().map(result_size).sum();
+    metrics.chainpulse_block_peak_memory_bytes(chain_id, processed.peak_memory.max(results_size));
+
+    for (result, relayer) in txs_results.iter().zip(&processed.relayers) {
+        let Some(signer) = relayer else {
+            continue;
+        };
+
+        if result.code.is_err() {
+            debug!(
+                "    Relay tx by {signer} failed with code {} ({}): {}",
+                result.code.value(),
+                result.codespace,
+                result.log
+            );
+
+            metrics.ibc_failed_relay_txs(chain_id, signer, &result.codespace, result.code.value());
+        }
+
+        // The packets received in the relay tx which were forwarded to another chain
+        for link in hops::links(&result.events) {
+            db::timed(
+                metrics,
+                chain_id,
+                "record_hop",
+                db::retry_busy(metrics, chain_id, || hops::record(db, chain_id, &link)),
+            )
+            .await?;
+        }
+    }
+
+    let events = txs_results.iter().flat_map(|tx| &tx.events);
+    let block_time = processed.block_time.to_rfc3339();
 
-    info!("New block at height {}", block.header.height);
+    for event in events {
+        if let Some(packet) = SendPacket::from_event(event) {
+            db::timed(
+                metrics,
+                chain_id,
+                "record_send",
+                db::retry_busy(metrics, chain_id, || {
+                    pending::record_send(db, chain_id, &packet)
+                }),
+            )
+            .await?;
+
+            if let Some(data) = TransferData::decode(&packet.data) {
+                let flow = Flow {
+                    kind: Kind::Send,
+                    channel: &packet.src_channel,
+                    counterparty_channel: &packet.dst_channel,
+                    sequence: packet.sequence,
+                };
+
+                db::timed(
+                    metrics,
+                    chain_id,
+                    "record_flow",
+                    db::retry_busy(metrics, chain_id, || {
+                        flows::record(db, chain_id, &flow, &data.tokens, &block_time)
+                    }),
+                )
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// What was found in the txs of a block
+pub struct ProcessedTxs {
+    /// The number of IBC packets relayed in the block
+    pub packets: usize,
+
+    /// The signer of each relay tx of the block, by index, or `None` for other txs
+    pub relayers: Vec<Option<String>>,
+
+    /// An estimate of the peak memory held while processing the txs, in bytes
+    pub peak_memory: usize,
+
+    /// The time of the block
+    pub block_time: Time,
+}
+
+/// An estimate of the memory held by the result of a tx, in bytes
+fn result_size(result: &DeliverTx) -> usize {
+    let events: usize = result
+        .events
+        .iter()
+        .flat_map(|event| {
+            let attributes = event
+                .attributes
+                .iter()
+                .map(|attribute| attribute.key.len() + attribute.value.len());
+
+            std::iter::once(event.kind.len()).chain(attributes)
+        })
+        .sum();
+
+    result.data.len() + result.log.len() + result.info.len() + result.codespace.len() + events
+}
+
+/// Decode the txs of a block one after the other, and record and count the IBC packets they relay.
+/// Each tx is released once processed, so that at most one tx of the block is held decoded at any time.
+#[allow(clippy::too_many_arguments)]
+pub async fn process_txs(
+    db: &Pool,
+    chain_id: &chain::Id,
+    height: Height,
+    time: Time,
+    proposer: &str,
+    txs: Vec<Vec<u8>>,
+    memo: Memo,
+    metrics: &Metrics,
+    decoders: &Decoders,
+    plugins: &Plugins,
+    trace: &mut BlockTrace,
+) -> Result<ProcessedTxs> {
+    let mut packets = 0;
+    trace.txs = txs.len();
+
+    // The signer of each relay tx of the block, by index
+    let mut relayers = Vec::with_capacity(txs.len());
+
+    // The size of the raw txs not processed yet
+    let mut remaining: usize = txs.iter().map(Vec::len).sum();
+    let mut peak_memory = remaining;
+
+    // The txs are encoded again to be hashed, into a buffer reused across the txs of the block
+    let mut buffer = Vec::new();
+
+    for (tx_index, raw) in txs.into_iter().enumerate() {
+        metrics.chainpulse_txs(chain_id);
+
+        let tx_size = raw.len();
+        let tx = Tx::decode(raw.as_slice())?;
+
+        peak_memory = peak_memory.max(remaining + tx.encoded_len());
+        remaining -= tx_size;
+        drop(raw);
 
-    let block = client.block(height).await?;
+        // Only encoded once the raw tx is released, so that the tx is not held three times
+        buffer.clear();
+        tx.encode(&mut buffer)?;
+        peak_memory = peak_memory.max(remaining + tx.encoded_len() + buffer.capacity());
 
-    for tx in &block.block.data {
-        metrics.chainpulse_txs(&chain_id);
+        let tx_row = db::timed(
+            metrics,
+            chain_id,
+            "insert_tx",
+            db::retry_busy(metrics, chain_id, || {
+                insert_tx(
+                    db, chain_id, height, time, tx_index, proposer, &tx, &buffer, memo,
+                )
+            }),
+        )
+        .await?;
 
-        let tx = Tx::decode(tx.as_slice())?;
-        let tx_row = insert_tx(&db, &chain_id, height, &tx).await?;
+        buffer.shrink_to(MAX_ENCODE_BUFFER_CAPACITY);
 
         let msgs = tx.body.ok_or("missing tx body")?.messages;
+        let mut is_relay = false;
+        let mut signer = None;
 
-        for msg in msgs {
+        for (msg_index, msg) in msgs.into_iter().enumerate() {
             let type_url = msg.type_url.clone();
 
-            if let Ok(msg) = Msg::decode(msg) {
-                if msg.is_ibc() {
-                    info!("    {msg}");
+            let mut msg_trace = MsgTrace {
+                tx_index,
+                tx_hash: tx_row.hash.clone(),
+                tx_id: tx_row.id,
+                type_url: type_url.clone(),
+                summary: None,
+                signer: None,
+                packet: None,
+                outcome: MsgOutcome::NotRelevant,
+            };
+
+            // The raw message is kept along with the decoded one, to be recorded as a dead letter if it fails
+            let (decoded, raw_msg) = match decoders.try_decode(&msg) {
+                Some(result) => (result, Some(msg)),
+                None => (Ok(Msg::Other(msg)), None),
+            };
+
+            let mut msg = match decoded {
+                Ok(msg) => msg,
+                Err(e) => {
+                    warn!("    Failed to decode {type_url}, recording it as a dead letter: {e}");
+
+                    // Only the messages of the types with a decoder can fail to be decoded
+                    if let Some(raw_msg) = &raw_msg {
+                        record_dead_letter(
+                            db,
+                            metrics,
+                            chain_id,
+                            &tx_row,
+                            msg_index,
+                            raw_msg,
+                            &e.to_string(),
+                        )
+                        .await?;
+                    }
+
+                    msg_trace.outcome = MsgOutcome::DecodeFailed {
+                        error: e.to_string(),
+                    };
+                    trace.msgs.push(msg_trace);
+                    continue;
+                }
+            };
+
+            if !msg.is_ibc() {
+                trace.ignore(&type_url, "other", &tx_row.hash);
+                continue;
+            }
+
+            // Malformed signers would otherwise end up as metric labels and database rows
+            if let Some(value) = msg.signer_mut() {
+                match signer::normalize(value) {
+                    Ok(normalized) => *value = normalized,
+                    Err(e) => {
+                        warn!("    Skipping {type_url}: {e}");
+                        metrics.chainpulse_invalid_signers(chain_id);
+
+                        msg_trace.outcome = MsgOutcome::InvalidSigner {
+                            error: e.to_string(),
+                        };
+                        trace.msgs.push(msg_trace);
+                        continue;
+                    }
+                }
+            }
+
+            let summary = msg.to_string();
+            info!("    {summary}");
+
+            msg_trace.summary = Some(summary);
+            msg_trace.signer = msg.signer().map(str::to_string);
+            msg_trace.packet = msg.packet().map(|packet| PacketTrace {
+                sequence: packet.sequence,
+                src_port: packet.source_port.clone(),
+                src_channel: packet.source_channel.clone(),
+                dst_port: packet.destination_port.clone(),
+                dst_channel: packet.destination_channel.clone(),
+            });
+
+            if signer.is_none() {
+                signer = msg.signer().map(str::to_string);
+            }
+
+            if let Msg::SubmitQueryResult(result) = &msg {
+                metrics.ibc_icq_query_results(chain_id, &result.client_id, &result.sender);
+            }
+
+            if msg.is_relevant() {
+                is_relay = true;
+
+                if msg.packet().is_some() {
+                    packets += 1;
+                }
+
+                // Traced even if it fails, so that the error can be related to the message
+                let outcome =
+                    process_msg(db, chain_id, &tx_row, &type_url, msg, metrics, plugins).await;
+
+                match outcome {
+                    Ok(outcome) => msg_trace.outcome = outcome,
+
+                    // The block is retried as a whole, see `RetryQueue`
+                    Err(e) if db::is_transient(e.as_ref()) => {
+                        trace.msgs.push(msg_trace);
+                        return Err(e);
+                    }
+
+                    Err(e) => {
+                        warn!(
+                            "    Failed to process {type_url}, recording it as a dead letter: {e}"
+                        );
+
+                        if let Some(raw_msg) = &raw_msg {
+                            record_dead_letter(
+                                db,
+                                metrics,
+                                chain_id,
+                                &tx_row,
+                                msg_index,
+                                raw_msg,
+                                &e.to_string(),
+                            )
+                            .await?;
+                        }
 
-                    if msg.is_relevant() {
-                        process_msg(&db, &chain_id, &tx_row, &type_url, msg, metrics).await?;
+                        msg_trace.outcome = MsgOutcome::DeadLetter {
+                            error: e.to_string(),
+                        };
                     }
                 }
+            } else {
+                let reason = if matches!(msg, Msg::Other(_)) {
+                    "other"
+                } else {
+                    "not_relevant"
+                };
+
+                trace.ignore(&type_url, reason, &tx_row.hash);
             }
+
+            trace.msgs.push(msg_trace);
+        }
+
+        if is_relay {
+            metrics.ibc_relay_tx_size_bytes(chain_id, tx_size);
+
+            let fee = tx
+                .auth_info
+                .as_ref()
+                .and_then(|auth_info| auth_info.fee.as_ref());
+
+            if let (Some(signer), Some(fee)) = (&signer, fee) {
+                record_gas_prices(chain_id, signer, fee, metrics);
+            }
+        }
+
+        relayers.push(signer.clone().filter(|_| is_relay));
+
+        let sequence = tx
+            .auth_info
+            .as_ref()
+            .and_then(|auth_info| auth_info.signer_infos.first())
+            .map(|signer_info| signer_info.sequence);
+
+        if let (Some(signer), Some(sequence)) = (&signer, sequence) {
+            db::timed(
+                metrics,
+                chain_id,
+                "record_sequence",
+                db::retry_busy(metrics, chain_id, || {
+                    sequences::record(db, chain_id, signer, sequence, height.value())
+                }),
+            )
+            .await?;
         }
     }
 
-    Ok(())
+    Ok(ProcessedTxs {
+        packets,
+        relayers,
+        peak_memory,
+        block_time: time,
+    })
 }
 
-async fn process_msg(
+pub async fn process_msg(
     pool: &Pool,
     chain_id: &ChainId,
     tx_row: &TxRow,
     type_url: &str,
     msg: Msg,
     metrics: &Metrics,
-) -> Result<()> {
+    plugins: &Plugins,
+) -> Result<MsgOutcome> {
     let Some(packet) = msg.packet() else {
-        return Ok(());
+        return Ok(MsgOutcome::NotRelevant);
     };
 
-    metrics.chainpulse_packets(chain_id);
+    // The packet was already recorded, and counted either by a previous run or when populating the metrics
+    let recorded = db::timed(
+        metrics,
+        chain_id,
+        "is_recorded",
+        is_recorded(pool, tx_row, type_url, &packet),
+    )
+    .await?;
+
+    if recorded {
+        tracing::debug!(
+            "    Packet #{} in tx {} was already recorded",
+            packet.sequence,
+            tx_row.id
+        );
+
+        return Ok(MsgOutcome::AlreadyRecorded);
+    }
+
+    if let Some(stage) = pending::Stage::of(&msg) {
+        db::timed(
+            metrics,
+            chain_id,
+            "resolve_pending",
+            db::retry_busy(metrics, chain_id, || pending::resolve(pool, &packet, stage)),
+        )
+        .await?;
+    }
 
     tracing::debug!(
         "    Packet #{} in tx {} ({}) - {}",
@@ -195,33 +1118,58 @@ async fn process_msg(
         tx_row.memo
     );
 
-    let query = r#"
-        SELECT * FROM packets
-        WHERE   src_channel = ? 
-            AND src_port = ? 
-            AND dst_channel = ? 
-            AND dst_port = ? 
-            AND sequence = ?
-            AND msg_type_url = ?
-            LIMIT 1
-    "#;
+    let existing = db::timed(
+        metrics,
+        chain_id,
+        "find_packet",
+        find_packet(pool, chain_id, type_url, &packet),
+    )
+    .await?;
 
-    let existing: Option<PacketRow> = sqlx::query_as(query)
-        .bind(&packet.source_channel)
-        .bind(&packet.source_port)
-        .bind(&packet.destination_channel)
-        .bind(&packet.destination_port)
-        .bind(packet.sequence as i64)
-        .bind(type_url)
-        .fetch_optional(pool)
-        .await?;
+    let effected_tx: Option<TxRow> = match &existing {
+        Some(existing) => Some(
+            db::timed(
+                metrics,
+                chain_id,
+                "find_tx",
+                sqlx::query_as(FIND_TX_QUERY)
+                    .persistent(true)
+                    .bind(existing.tx_id)
+                    .fetch_one(pool),
+            )
+            .await?,
+        ),
+        None => None,
+    };
 
-    if let Some(existing) = &existing {
-        let effected_tx: TxRow = sqlx::query_as("SELECT * FROM txs WHERE id = ? LIMIT 1")
-            .bind(existing.tx_id)
-            .fetch_one(pool)
-            .await?;
+    // Everything about the packet is recorded before the packet itself, and only counted once the packet is,
+    // so that a block retried after a failure does not count its packets again
+    let mut effects = Effects::default();
+
+    if existing.is_none() {
+        if let Some(ack) = msg.acknowledgement() {
+            effects.failed_denoms =
+                record_transfer_failure(pool, chain_id, tx_row, &packet, ack, metrics).await?;
+        }
+
+        record_transfer_flow(pool, chain_id, tx_row, &msg, &packet, metrics).await?;
+        effects.chain_pair = chain_pair(pool, chain_id, &msg, &packet, metrics).await?;
+        effects.backup = is_backup_relay(pool, chain_id, &msg, &packet, metrics).await?;
+    }
 
+    db::timed(
+        metrics,
+        chain_id,
+        "insert_packet",
+        db::retry_busy(metrics, chain_id, || {
+            insert_packet(pool, tx_row, type_url, &msg, &packet, existing.as_ref())
+        }),
+    )
+    .await?;
+
+    metrics.chainpulse_packets(chain_id);
+
+    let outcome = if let (Some(existing), Some(effected_tx)) = (&existing, effected_tx) {
         tracing::debug!(
             "        Frontrun by tx {} ({}) - {}",
             existing.tx_id,
@@ -239,6 +1187,8 @@ async fn process_msg(
             &tx_row.memo,
         );
 
+        metrics.ibc_relayer_id_packets(chain_id, msg.signer().unwrap_or(""), false);
+
         metrics.ibc_frontrun_counter(
             chain_id,
             &packet.source_channel,
@@ -250,6 +1200,26 @@ async fn process_msg(
             &tx_row.memo,
             &effected_tx.memo,
         );
+
+        metrics.ibc_frontrun_by_proposer(
+            chain_id,
+            tx_row.proposer.as_deref().unwrap_or(""),
+            msg.signer().unwrap_or(""),
+            &existing.signer,
+        );
+
+        metrics.ibc_frontrun_inclusion_delay(
+            chain_id,
+            msg.signer().unwrap_or(""),
+            tx_row,
+            &effected_tx,
+        );
+
+        MsgOutcome::Frontrun {
+            effected_tx: existing.tx_id,
+            effected_tx_hash: effected_tx.hash,
+            effected_signer: existing.signer.clone(),
+        }
     } else {
         metrics.ibc_effected_packets(
             chain_id,
@@ -260,17 +1230,150 @@ async fn process_msg(
             msg.signer().unwrap_or(""),
             &tx_row.memo,
         );
+
+        metrics.ibc_relayer_id_packets(chain_id, msg.signer().unwrap_or(""), true);
+
+        if matches!(msg, Msg::RecvPacket(_) | Msg::RecvPacketV2(_)) {
+            record_transfer_volume(chain_id, &packet, metrics);
+        }
+
+        effects.count(chain_id, &msg, &packet, metrics);
+
+        MsgOutcome::Effected
+    };
+
+    let event = PacketEvent {
+        chain_id: chain_id.as_str(),
+        type_url,
+        sequence: packet.sequence,
+        src_channel: &packet.source_channel,
+        src_port: &packet.source_port,
+        dst_channel: &packet.destination_channel,
+        dst_port: &packet.destination_port,
+        signer: msg.signer().unwrap_or(""),
+        memo: &tx_row.memo,
+        effected: existing.is_none(),
+        tx_hash: &tx_row.hash,
+    };
+
+    for (plugin, output) in plugins.on_packet(&event) {
+        for derived in output.events {
+            metrics.ibc_plugin_events(chain_id, &plugin, &derived.name, &derived.value);
+        }
     }
 
-    let query = r#"
-        INSERT OR IGNORE INTO packets
-            (tx_id, sequence, src_channel, src_port, dst_channel, dst_port,
-            msg_type_url, signer, effected, effected_signer, effected_tx, created_at)
-        VALUES
-            (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, datetime('now'))
-    "#;
+    Ok(outcome)
+}
 
-    sqlx::query(query)
+/// What was found about an effected packet before recording it, to be counted once it is recorded
+#[derive(Debug, Default)]
+struct Effects {
+    /// The denominations of the tokens of the transfer, if it failed
+    failed_denoms: Vec<String>,
+
+    /// The source and destination chains of the packet, and the kind of relay, if the counterparty chain is known
+    chain_pair: Option<(String, String, &'static str)>,
+
+    /// Whether the packet was effected by one of the backup relayers of its channel
+    backup: bool,
+}
+
+impl Effects {
+    fn count(self, chain_id: &ChainId, msg: &Msg, packet: &Packet, metrics: &Metrics) {
+        for denom in &self.failed_denoms {
+            metrics.ibc_transfer_failures(
+                chain_id,
+                &packet.source_channel,
+                &packet.destination_channel,
+                denom,
+            );
+        }
+
+        if let Some((src_chain, dst_chain, kind)) = &self.chain_pair {
+            metrics.ibc_chain_pair_packets(src_chain, dst_chain, kind);
+        }
+
+        if let (true, Some(signer)) = (self.backup, msg.signer()) {
+            metrics.ibc_backup_relayed_packets(
+                chain_id,
+                &packet.source_channel,
+                &packet.destination_channel,
+                signer,
+            );
+        }
+    }
+}
+
+// The queries of the hot path, which runs for every packet, are kept as constants
+// and marked as persistent, so that they are prepared once per connection and then
+// reused from its statement cache instead of being parsed again.
+
+const FIND_PACKET_QUERY: &str = r#"
+    SELECT * FROM packets
+    WHERE   chain = ?
+        AND src_channel = ?
+        AND src_port = ?
+        AND dst_channel = ?
+        AND dst_port = ?
+        AND sequence = ?
+        AND msg_type_url = ?
+        LIMIT 1
+"#;
+
+const FIND_TX_QUERY: &str = "SELECT * FROM txs WHERE id = ? LIMIT 1";
+
+const INSERT_PACKET_QUERY: &str = r#"
+    INSERT OR IGNORE INTO packets
+        (tx_id, sequence, src_channel, src_port, dst_channel, dst_port,
+        msg_type_url, signer, effected, effected_signer, effected_tx, chain, block_time, created_at)
+    VALUES
+        (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, datetime(?), datetime('now'))
+"#;
+
+const IS_RECORDED_QUERY: &str = r#"
+    SELECT 1 FROM packets
+    WHERE   tx_id = ?
+        AND src_channel = ?
+        AND src_port = ?
+        AND dst_channel = ?
+        AND dst_port = ?
+        AND sequence = ?
+        AND msg_type_url = ?
+        LIMIT 1
+"#;
+
+/// Find the packet relayed first on the chain, if any, in which case the packet being processed was frontrun
+async fn find_packet(
+    pool: &Pool,
+    chain_id: &ChainId,
+    type_url: &str,
+    packet: &Packet,
+) -> Result<Option<PacketRow>> {
+    let existing = sqlx::query_as(FIND_PACKET_QUERY)
+        .persistent(true)
+        .bind(chain_id.as_str())
+        .bind(&packet.source_channel)
+        .bind(&packet.source_port)
+        .bind(&packet.destination_channel)
+        .bind(&packet.destination_port)
+        .bind(packet.sequence as i64)
+        .bind(type_url)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(existing)
+}
+
+async fn insert_packet(
+    pool: &Pool,
+    tx_row: &TxRow,
+    type_url: &str,
+    msg: &Msg,
+    packet: &Packet,
+    existing: Option<&PacketRow>,
+) -> Result<()> {
+    sqlx::query(INSERT_PACKET_QUERY)
+        .persistent(true)
         .bind(tx_row.id)
         .bind(packet.sequence as i64)
         .bind(&packet.source_channel)
@@ -280,22 +1383,306 @@ async fn process_msg(
         .bind(type_url)
         .bind(msg.signer())
         .bind(existing.is_none())
-        .bind(existing.as_ref().map(|row| &row.signer))
-        .bind(existing.as_ref().map(|row| row.tx_id))
+        .bind(existing.map(|row| &row.signer))
+        .bind(existing.map(|row| row.tx_id))
+        .bind(&tx_row.chain)
+        .bind(tx_row.block_time)
         .execute(pool)
         .await?;
 
     Ok(())
 }
 
-async fn insert_tx(db: &Pool, chain_id: &ChainId, height: Height, tx: &Tx) -> Result<TxRow> {
+async fn is_recorded(pool: &Pool, tx_row: &TxRow, type_url: &str, packet: &Packet) -> Result<bool> {
+    let recorded: Option<(i64,)> = sqlx::query_as(IS_RECORDED_QUERY)
+        .persistent(true)
+        .bind(tx_row.id)
+        .bind(&packet.source_channel)
+        .bind(&packet.source_port)
+        .bind(&packet.destination_channel)
+        .bind(&packet.destination_port)
+        .bind(packet.sequence as i64)
+        .bind(type_url)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(recorded.is_some())
+}
+
+/// Record the effective gas price paid by a relayer, ie. the fee it paid per unit of gas requested, in each fee denomination
+fn record_gas_prices(chain_id: &ChainId, signer: &str, fee: &Fee, metrics: &Metrics) {
+    if fee.gas_limit == 0 {
+        return;
+    }
+
+    for coin in &fee.amount {
+        let Ok(amount) = coin.amount.parse::<f64>() else {
+            continue;
+        };
+
+        let price = amount / fee.gas_limit as f64;
+        metrics.ibc_relay_gas_price(chain_id, signer, &coin.denom, price);
+    }
+}
+
+fn record_transfer_volume(chain_id: &ChainId, packet: &Packet, metrics: &Metrics) {
+    let Some(data) = TransferData::decode(&packet.data) else {
+        return;
+    };
+
+    for token in data.tokens {
+        let Ok(amount) = token.amount.parse::<f64>() else {
+            continue;
+        };
+
+        metrics.ibc_transfer_volume(
+            chain_id,
+            &packet.source_channel,
+            &packet.destination_channel,
+            &token.denom,
+            amount,
+        );
+    }
+}
+
+/// Record the failure of a transfer, returning the denominations of its tokens if it failed
+async fn record_transfer_failure(
+    pool: &Pool,
+    chain_id: &ChainId,
+    tx_row: &TxRow,
+    packet: &Packet,
+    ack: &[u8],
+    metrics: &Metrics,
+) -> Result<Vec<String>> {
+    let Some(Ack::Error(error)) = Ack::decode(ack) else {
+        return Ok(Vec::new());
+    };
+
+    let Some(data) = TransferData::decode(&packet.data) else {
+        return Ok(Vec::new());
+    };
+
+    debug!(
+        "        Transfer #{} on {} failed: {error}",
+        packet.sequence, packet.source_channel
+    );
+
+    db::timed(
+        metrics,
+        chain_id,
+        "record_failure",
+        db::retry_busy(metrics, chain_id, || {
+            failures::record(pool, chain_id, tx_row, packet, &data, &error)
+        }),
+    )
+    .await?;
+
+    Ok(data.tokens.into_iter().map(|token| token.denom).collect())
+}
+
+/// Record the tokens received over a channel, or refunded after a failed or timed out transfer
+async fn record_transfer_flow(
+    pool: &Pool,
+    chain_id: &ChainId,
+    tx_row: &TxRow,
+    msg: &Msg,
+    packet: &Packet,
+    metrics: &Metrics,
+) -> Result<()> {
+    let Some(data) = TransferData::decode(&packet.data) else {
+        return Ok(());
+    };
+
+    let is_error_ack = matches!(
+        msg.acknowledgement().and_then(Ack::decode),
+        Some(Ack::Error(_))
+    );
+
+    let (flow, tokens) = match msg {
+        Msg::RecvPacket(_) | Msg::RecvPacketV2(_) => {
+            let flow = Flow {
+                kind: Kind::Recv,
+                channel: &packet.destination_channel,
+                counterparty_channel: &packet.source_channel,
+                sequence: packet.sequence,
+            };
+
+            let tokens = data
+                .tokens
+                .iter()
+                .map(|token| Token {
+                    denom: transfer::received_denom(packet, &token.denom),
+                    amount: token.amount.clone(),
+                })
+                .collect();
+
+            (flow, tokens)
+        }
+
+        Msg::Timeout(_) | Msg::TimeoutV2(_) => (refund(packet), data.tokens),
+        _ if is_error_ack => (refund(packet), data.tokens),
+        _ => return Ok(()),
+    };
+
+    // The txs recorded before their block time was stored have it filled in with their insertion time
+    let block_time = tx_row.block_time.unwrap_or(tx_row.created_at).to_string();
+
+    db::timed(
+        metrics,
+        chain_id,
+        "record_flow",
+        db::retry_busy(metrics, chain_id, || {
+            flows::record(pool, chain_id, &flow, &tokens, &block_time)
+        }),
+    )
+    .await
+}
+
+/// The source and destination chains of the packet, along with the kind of relay, to count the traffic between them.
+/// One of them is the chain the packet was relayed to and the other the counterparty of its channel there,
+/// or the chain tracked by its client for IBC v2 packets.
+async fn chain_pair(
+    pool: &Pool,
+    chain_id: &ChainId,
+    msg: &Msg,
+    packet: &Packet,
+    metrics: &Metrics,
+) -> Result<Option<(String, String, &'static str)>> {
+    // IBC v2 packets are sent between clients, which take the place of the channels
+    let (kind, port, channel, v2) = match msg {
+        Msg::RecvPacket(_) => (
+            "recv",
+            &packet.destination_port,
+            &packet.destination_channel,
+            false,
+        ),
+        Msg::Acknowledgement(_) => ("ack", &packet.source_port, &packet.source_channel, false),
+        Msg::Timeout(_) => (
+            "timeout",
+            &packet.source_port,
+            &packet.source_channel,
+            false,
+        ),
+        Msg::RecvPacketV2(_) => (
+            "recv",
+            &packet.destination_port,
+            &packet.destination_channel,
+            true,
+        ),
+        Msg::AcknowledgementV2(_) => ("ack", &packet.source_port, &packet.source_channel, true),
+        Msg::TimeoutV2(_) => ("timeout", &packet.source_port, &packet.source_channel, true),
+        _ => return Ok(None),
+    };
+
+    let counterparty_chain = if v2 {
+        db::timed(
+            metrics,
+            chain_id,
+            "client_chain",
+            discovery::client_chain(pool, chain_id, channel),
+        )
+        .await?
+    } else {
+        db::timed(
+            metrics,
+            chain_id,
+            "counterparty_chain",
+            discovery::counterparty_chain(pool, chain_id, port, channel),
+        )
+        .await?
+    };
+
+    let Some(counterparty_chain) = counterparty_chain else {
+        return Ok(None);
+    };
+
+    if kind == "recv" {
+        Ok(Some((counterparty_chain, chain_id.to_string(), kind)))
+    } else {
+        Ok(Some((chain_id.to_string(), counterparty_chain, kind)))
+    }
+}
+
+/// Whether the packet was effected by one of the backup relayers of its channel
+async fn is_backup_relay(
+    pool: &Pool,
+    chain_id: &ChainId,
+    msg: &Msg,
+    packet: &Packet,
+    metrics: &Metrics,
+) -> Result<bool> {
+    let Some(signer) = msg.signer() else {
+        return Ok(false);
+    };
+
+    let is_backup = db::timed(
+        metrics,
+        chain_id,
+        "relayer_role",
+        roles::is_backup(
+            pool,
+            chain_id,
+            &packet.source_channel,
+            &packet.destination_channel,
+            signer,
+        ),
+    )
+    .await?;
+
+    Ok(is_backup)
+}
+
+fn refund(packet: &Packet) -> Flow<'_> {
+    Flow {
+        kind: Kind::Refund,
+        channel: &packet.source_channel,
+        counterparty_channel: &packet.destination_channel,
+        sequence: packet.sequence,
+    }
+}
+
+/// Record a message which failed to be decoded or processed as a dead letter, to be processed again once fixed
+#[allow(clippy::too_many_arguments)]
+async fn record_dead_letter(
+    db: &Pool,
+    metrics: &Metrics,
+    chain_id: &ChainId,
+    tx_row: &TxRow,
+    msg_index: usize,
+    msg: &Any,
+    error: &str,
+) -> Result<()> {
+    metrics.chainpulse_dead_letters(chain_id);
+
+    db::timed(
+        metrics,
+        chain_id,
+        "record_dead_letter",
+        db::retry_busy(metrics, chain_id, || {
+            dead_letters::record(db, tx_row, msg_index, msg, error)
+        }),
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn insert_tx(
+    db: &Pool,
+    chain_id: &ChainId,
+    height: Height,
+    time: Time,
+    tx_index: usize,
+    proposer: &str,
+    tx: &Tx,
+    bytes: &[u8],
+    memo: Memo,
+) -> Result<TxRow> {
     let query = r#"
-        INSERT OR IGNORE INTO txs (chain, height, hash, memo, created_at)
-        VALUES (?, ?, ?, ?, datetime('now'))
+        INSERT OR IGNORE INTO txs (chain, height, hash, memo, tx_index, proposer, block_time, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, datetime(?), datetime('now'))
     "#;
 
-    let bytes = tx.encode_to_vec();
-    let hash = tendermint::crypto::default::Sha256::digest(&bytes);
+    let hash = tendermint::crypto::default::Sha256::digest(bytes);
     let hash = subtle_encoding::hex::encode_upper(hash);
     let hash = String::from_utf8_lossy(&hash);
 
@@ -304,7 +1691,7 @@ async fn insert_tx(db: &Pool, chain_id: &ChainId, height: Height, tx: &Tx) -> Re
     let memo = tx
         .body
         .as_ref()
-        .map(|body| body.memo.to_string())
+        .map(|body| memo::sanitize(&body.memo, &memo))
         .unwrap_or_default();
 
     sqlx::query(query)
@@ -312,6 +1699,9 @@ async fn insert_tx(db: &Pool, chain_id: &ChainId, height: Height, tx: &Tx) -> Re
         .bind(height)
         .bind(&hash)
         .bind(memo)
+        .bind(tx_index as i64)
+        .bind(proposer)
+        .bind(time.to_rfc3339())
         .execute(db)
         .await?;
 
@@ -326,10 +1716,42 @@ async fn insert_tx(db: &Pool, chain_id: &ChainId, height: Height, tx: &Tx) -> Re
     Ok(tx)
 }
 
-mod queries {
-    use tendermint_rpc::query::{EventType, Query};
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compat_source_misreported_version() {
+        // The node reports 0.37, but its events only decode in 0.34
+        let reported = CompatMode::V0_37;
+        let (mut compat_mode, mut source) = (CompatMode::V0_34, CompatSource::default());
+
+        // The configured compat mode is switched to the reported one
+        assert!(compat_mode != reported && source.follows_version());
+        (compat_mode, source) = (reported, CompatSource::Version);
+        assert!(source.follows_version());
+
+        // The events fail to decode, so the collector falls back to the other one
+        (compat_mode, source) = (other_compat_mode(compat_mode), CompatSource::Fallback);
+        assert_eq!(compat_mode, CompatMode::V0_34);
+
+        // On reconnect, the reported version does not switch it back
+        assert!(compat_mode != reported && !source.follows_version());
+
+        // The events decode, so the fallback is kept for good
+        source = source.decoded();
+        assert_eq!(source, CompatSource::Decoded);
+        assert!(!source.follows_version());
+    }
+
+    #[test]
+    fn test_compat_source_decoded() {
+        assert_eq!(CompatSource::Version.decoded(), CompatSource::Version);
+        assert_eq!(CompatSource::Fallback.decoded(), CompatSource::Decoded);
+        assert_eq!(CompatSource::Decoded.decoded(), CompatSource::Decoded);
 
-    pub fn new_block() -> Query {
-        Query::from(EventType::NewBlock)
+        assert!(CompatSource::Version.follows_version());
+        assert!(!CompatSource::Fallback.follows_version());
+        assert!(!CompatSource::Decoded.follows_version());
     }
 }
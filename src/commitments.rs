@@ -0,0 +1,376 @@
+//! Stuck packets found by querying the chains directly, without depending on any third party:
+//! the packets sent on a channel whose commitment is still stored on the source chain,
+//! and which the destination chain reports as not received yet.
+//!
+//! Both ends of a channel must be monitored, and the channel discovered, for its packets to be checked.
+//! A commitment does not tell when its packet was sent, so a packet only counts as stuck once it was
+//! found not received for at least `native_min_age` seconds, over consecutive polls.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use ibc_proto::{
+    cosmos::base::query::v1beta1::PageRequest,
+    ibc::core::channel::v1::{
+        QueryPacketCommitmentsRequest, QueryPacketCommitmentsResponse,
+        QueryUnreceivedPacketsRequest, QueryUnreceivedPacketsResponse,
+    },
+};
+use sqlx::SqlitePool;
+use tendermint_rpc::WebSocketClient;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::{
+    config::{Chains, StuckPackets},
+    query::abci_query,
+    status::Stuck,
+    Result,
+};
+
+/// Maximum number of commitments fetched per query
+const PAGE_LIMIT: u64 = 1000;
+
+/// A channel of a chain, identified by its chain, port and channel
+type ChannelKey = (String, String, String);
+
+/// A connection to a chain, along with the task driving it
+type Client = (WebSocketClient, JoinHandle<()>);
+
+/// The state of the RPC source kept across polls
+#[derive(Default)]
+pub struct Tracker {
+    /// Since when each packet has been found not received
+    first_seen: HashMap<ChannelKey, HashMap<u64, Instant>>,
+
+    /// A single connection per chain, shared by all of its channels and reused across polls
+    clients: BTreeMap<String, Client>,
+}
+
+impl Tracker {
+    /// Record the packets found not received on the channels checked in the latest poll.
+    /// The channels which could not be checked keep their packets until the next poll which checks them.
+    fn update(&mut self, unreceived: HashMap<ChannelKey, Vec<u64>>, now: Instant) {
+        for (key, sequences) in unreceived {
+            let previous = self.first_seen.remove(&key).unwrap_or_default();
+            self.first_seen
+                .insert(key, aged(&previous, &sequences, now));
+        }
+    }
+
+    /// Forget the channels which are no longer open between monitored chains
+    fn retain(&mut self, channels: &HashSet<ChannelKey>) {
+        self.first_seen.retain(|key, _| channels.contains(key));
+    }
+
+    /// The connection to the given chain, connecting to it unless already connected
+    async fn client(&mut self, chains: &Chains, chain_id: &str) -> Result<&WebSocketClient> {
+        // The driver stops when the connection is closed, in which case the client is of no use anymore
+        if self
+            .clients
+            .get(chain_id)
+            .map_or(false, |(_, driver)| driver.is_finished())
+        {
+            self.disconnect(chain_id);
+        }
+
+        if !self.clients.contains_key(chain_id) {
+            let endpoint = chains
+                .endpoints
+                .iter()
+                .find(|(id, _)| id.as_str() == chain_id)
+                .map(|(_, endpoint)| endpoint)
+                .ok_or_else(|| format!("{chain_id} is not monitored"))?;
+
+            let (client, driver) = WebSocketClient::builder(endpoint.url.clone())
+                .compat_mode(endpoint.comet_version)
+                .build()
+                .await?;
+
+            let driver = tokio::spawn(async move {
+                let _ = driver.run().await;
+            });
+
+            self.clients.insert(chain_id.to_string(), (client, driver));
+        }
+
+        Ok(&self.clients[chain_id].0)
+    }
+
+    /// Close the connection to the given chain, if any, to connect again on the next poll
+    fn disconnect(&mut self, chain_id: &str) {
+        if let Some((client, driver)) = self.clients.remove(chain_id) {
+            let _ = client.close();
+            driver.abort();
+        }
+    }
+
+    /// The number of packets of a channel found not received for at least `min_age`
+    fn stuck(&self, key: &ChannelKey, now: Instant, min_age: Duration) -> i64 {
+        self.first_seen.get(key).map_or(0, |sequences| {
+            sequences
+                .values()
+                .filter(|first_seen| now.duration_since(**first_seen) >= min_age)
+                .count() as i64
+        })
+    }
+}
+
+/// Since when each of the packets not received was first found not received,
+/// carried over from the previous poll for the packets which were already
+fn aged(
+    previous: &HashMap<u64, Instant>,
+    unreceived: &[u64],
+    now: Instant,
+) -> HashMap<u64, Instant> {
+    unreceived
+        .iter()
+        .map(|sequence| (*sequence, previous.get(sequence).copied().unwrap_or(now)))
+        .collect()
+}
+
+/// An open channel between two monitored chains
+struct Channel {
+    chain: String,
+    port: String,
+    channel: String,
+    counterparty_chain: String,
+    counterparty_port: String,
+    counterparty_channel: String,
+}
+
+/// Find the channels between monitored chains on which packets have not been received for at least `native_min_age`,
+/// returning the channels checked, by chain and channel, along with them
+pub async fn stuck(
+    chains: &Chains,
+    pool: &SqlitePool,
+    config: &StuckPackets,
+    tracker: &mut Tracker,
+) -> Result<(HashSet<(String, String)>, Vec<Stuck>)> {
+    let query = r#"
+        SELECT chain, port, channel, counterparty_chain, counterparty_port, counterparty_channel
+        FROM channels
+        WHERE state = 'open' AND counterparty_chain IS NOT NULL
+        ORDER BY chain, port, channel
+    "#;
+
+    let rows: Vec<(String, String, String, String, String, String)> =
+        sqlx::query_as(query).fetch_all(pool).await?;
+
+    let is_monitored = |chain: &str| chains.endpoints.keys().any(|id| id.as_str() == chain);
+
+    let channels = rows
+        .into_iter()
+        .filter(|(chain, _, _, counterparty_chain, _, _)| {
+            is_monitored(chain) && is_monitored(counterparty_chain)
+        })
+        .map(
+            |(
+                chain,
+                port,
+                channel,
+                counterparty_chain,
+                counterparty_port,
+                counterparty_channel,
+            )| {
+                Channel {
+                    chain,
+                    port,
+                    channel,
+                    counterparty_chain,
+                    counterparty_port,
+                    counterparty_channel,
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+
+    let keys = channels
+        .iter()
+        .map(|channel| {
+            (
+                channel.chain.clone(),
+                channel.port.clone(),
+                channel.channel.clone(),
+            )
+        })
+        .collect::<HashSet<_>>();
+
+    tracker.retain(&keys);
+
+    // The chains which are not at either end of a channel between monitored chains are not connected to
+    let linked = channels
+        .iter()
+        .flat_map(|channel| [channel.chain.as_str(), channel.counterparty_chain.as_str()])
+        .collect::<HashSet<_>>();
+
+    let unlinked = tracker
+        .clients
+        .keys()
+        .filter(|chain| !linked.contains(chain.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for chain in unlinked {
+        tracker.disconnect(&chain);
+    }
+
+    let mut unreceived = HashMap::new();
+    let mut checked = Vec::new();
+
+    for channel in &channels {
+        match unreceived_packets(chains, tracker, channel).await {
+            Ok(sequences) => {
+                let key = (
+                    channel.chain.clone(),
+                    channel.port.clone(),
+                    channel.channel.clone(),
+                );
+
+                unreceived.insert(key, sequences);
+                checked.push(channel);
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to query the packets of {} [{}]: {e}",
+                    channel.chain, channel.channel
+                );
+
+                // Either connection may be broken, so both are opened again on the next poll
+                tracker.disconnect(&channel.chain);
+                tracker.disconnect(&channel.counterparty_chain);
+            }
+        }
+    }
+
+    let now = Instant::now();
+    tracker.update(unreceived, now);
+
+    let min_age = Duration::from_secs(config.native_min_age);
+    let mut stuck = Vec::new();
+
+    for channel in &checked {
+        let key = (
+            channel.chain.clone(),
+            channel.port.clone(),
+            channel.channel.clone(),
+        );
+
+        let size_queue = tracker.stuck(&key, now, min_age);
+
+        if size_queue >= config.min_queue_size {
+            stuck.push(Stuck {
+                src_chain: channel.chain.clone(),
+                dst_chain: channel.counterparty_chain.clone(),
+                src_channel: channel.channel.clone(),
+                dst_channel: channel.counterparty_channel.clone(),
+                direction: "outgoing",
+                size_queue,
+            });
+        }
+    }
+
+    let checked = checked
+        .iter()
+        .map(|channel| (channel.chain.clone(), channel.channel.clone()))
+        .collect();
+
+    Ok((checked, stuck))
+}
+
+/// The sequences of the packets committed on the source chain which the destination chain has not received
+async fn unreceived_packets(
+    chains: &Chains,
+    tracker: &mut Tracker,
+    channel: &Channel,
+) -> Result<Vec<u64>> {
+    let src = tracker.client(chains, &channel.chain).await?;
+    let sequences = commitments(src, &channel.port, &channel.channel).await?;
+
+    if sequences.is_empty() {
+        return Ok(sequences);
+    }
+
+    let request = QueryUnreceivedPacketsRequest {
+        port_id: channel.counterparty_port.clone(),
+        channel_id: channel.counterparty_channel.clone(),
+        packet_commitment_sequences: sequences,
+    };
+
+    let dst = tracker.client(chains, &channel.counterparty_chain).await?;
+
+    let response: QueryUnreceivedPacketsResponse =
+        abci_query(dst, "/ibc.core.channel.v1.Query/UnreceivedPackets", request).await?;
+
+    Ok(response.sequences)
+}
+
+/// The sequences of the packets sent on a channel whose commitment is still stored, following the pagination
+async fn commitments(client: &WebSocketClient, port: &str, channel: &str) -> Result<Vec<u64>> {
+    let mut sequences = Vec::new();
+    let mut key = Vec::new();
+
+    loop {
+        let request = QueryPacketCommitmentsRequest {
+            port_id: port.to_string(),
+            channel_id: channel.to_string(),
+            pagination: Some(PageRequest {
+                key,
+                limit: PAGE_LIMIT,
+                ..Default::default()
+            }),
+        };
+
+        let response: QueryPacketCommitmentsResponse = abci_query(
+            client,
+            "/ibc.core.channel.v1.Query/PacketCommitments",
+            request,
+        )
+        .await?;
+
+        sequences.extend(response.commitments.iter().map(|state| state.sequence));
+
+        match response.pagination {
+            Some(page) if !page.next_key.is_empty() => key = page.next_key,
+            _ => break,
+        }
+    }
+
+    Ok(sequences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker() {
+        let key = (
+            "osmosis-1".to_string(),
+            "transfer".to_string(),
+            "channel-0".to_string(),
+        );
+
+        let start = Instant::now();
+        let min_age = Duration::from_secs(60);
+        let mut tracker = Tracker::default();
+
+        tracker.update(HashMap::from([(key.clone(), vec![1, 2, 3])]), start);
+        assert_eq!(tracker.stuck(&key, start, min_age), 0);
+
+        // Packet 1 was received, packet 4 was sent since
+        let later = start + Duration::from_secs(90);
+        tracker.update(HashMap::from([(key.clone(), vec![2, 3, 4])]), later);
+        assert_eq!(tracker.stuck(&key, later, min_age), 2);
+
+        // The channel could not be checked, so its packets are kept
+        tracker.update(HashMap::new(), later);
+        assert_eq!(tracker.stuck(&key, later, min_age), 2);
+
+        // The channel was closed, so its packets are forgotten
+        tracker.retain(&HashSet::new());
+        assert_eq!(tracker.stuck(&key, later, min_age), 0);
+    }
+}
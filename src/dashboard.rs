@@ -0,0 +1,152 @@
+use serde_json::{json, Value};
+
+use crate::{config::Config, Result};
+
+/// Generate a Grafana dashboard for the chains in the given configuration.
+///
+/// The dashboard has a `chain_id` variable listing the configured chains,
+/// and a `datasource` variable to pick the Prometheus instance scraping Chain Pulse.
+pub fn dashboard(config: &Config) -> Value {
+    let chains = config
+        .chains
+        .endpoints
+        .keys()
+        .map(|chain_id| chain_id.to_string())
+        .collect::<Vec<_>>();
+
+    let mut panels = vec![
+        timeseries(
+            "Effected packets by signer",
+            r#"sum by (signer) (rate(ibc_effected_packets{chain_id=~"$chain_id"}[5m]))"#,
+            "{{signer}}",
+        ),
+        timeseries(
+            "Uneffected packets by signer",
+            r#"sum by (signer) (rate(ibc_uneffected_packets{chain_id=~"$chain_id"}[5m]))"#,
+            "{{signer}}",
+        ),
+        timeseries(
+            "Frontruns by signer",
+            r#"sum by (signer, frontrunned_by) (rate(ibc_frontrun_counter{chain_id=~"$chain_id"}[5m]))"#,
+            "{{signer}} frontrun by {{frontrunned_by}}",
+        ),
+        timeseries(
+            "Share of effected packets by relayer, across chains",
+            r#"sum by (relayer_id) (rate(ibc_relayer_id_packets{outcome="effected"}[5m])) / scalar(sum(rate(ibc_relayer_id_packets{outcome="effected"}[5m])))"#,
+            "{{relayer_id}}",
+        ),
+        timeseries(
+            "Effected packets by channel",
+            r#"sum by (src_channel, dst_channel) (rate(ibc_effected_packets{chain_id=~"$chain_id"}[5m]))"#,
+            "{{src_channel}} -> {{dst_channel}}",
+        ),
+        timeseries(
+            "Txs processed",
+            r#"sum by (chain_id) (rate(chainpulse_txs{chain_id=~"$chain_id"}[5m]))"#,
+            "{{chain_id}}",
+        ),
+        timeseries(
+            "Packets processed",
+            r#"sum by (chain_id) (rate(chainpulse_packets{chain_id=~"$chain_id"}[5m]))"#,
+            "{{chain_id}}",
+        ),
+        timeseries(
+            "Block interval (p90)",
+            r#"histogram_quantile(0.9, sum by (chain_id, le) (rate(chain_block_interval_seconds_bucket{chain_id=~"$chain_id"}[5m])))"#,
+            "{{chain_id}}",
+        ),
+        timeseries(
+            "Reconnects",
+            r#"sum by (chain_id) (increase(chainpulse_reconnects{chain_id=~"$chain_id"}[15m]))"#,
+            "{{chain_id}}",
+        ),
+        timeseries(
+            "Timeouts",
+            r#"sum by (chain_id) (increase(chainpulse_timeouts{chain_id=~"$chain_id"}[15m]))"#,
+            "{{chain_id}}",
+        ),
+        timeseries(
+            "Errors",
+            r#"sum by (chain_id) (increase(chainpulse_errors{chain_id=~"$chain_id"}[15m]))"#,
+            "{{chain_id}}",
+        ),
+    ];
+
+    if config.metrics.stuck_packets {
+        panels.push(timeseries(
+            "Stuck packets",
+            r#"ibc_stuck_packets{src_chain=~"$chain_id"} or ibc_stuck_packets{dst_chain=~"$chain_id"}"#,
+            "{{src_chain}} {{src_channel}} -> {{dst_chain}}",
+        ));
+    }
+
+    // Lay out the panels on a grid, two panels per row
+    for (i, panel) in panels.iter_mut().enumerate() {
+        panel["id"] = json!(i + 1);
+        panel["gridPos"] = json!({
+            "h": 8,
+            "w": 12,
+            "x": (i % 2) * 12,
+            "y": (i / 2) * 8,
+        });
+    }
+
+    let options = chains
+        .iter()
+        .map(|chain| json!({ "text": chain, "value": chain, "selected": false }))
+        .collect::<Vec<_>>();
+
+    json!({
+        "title": "Chain Pulse",
+        "uid": "chainpulse",
+        "tags": ["chainpulse", "ibc"],
+        "timezone": "browser",
+        "schemaVersion": 38,
+        "refresh": "1m",
+        "time": { "from": "now-6h", "to": "now" },
+        "templating": {
+            "list": [
+                {
+                    "name": "datasource",
+                    "label": "Data source",
+                    "type": "datasource",
+                    "query": "prometheus",
+                },
+                {
+                    "name": "chain_id",
+                    "label": "Chain",
+                    "type": "custom",
+                    "query": chains.join(","),
+                    "multi": true,
+                    "includeAll": true,
+                    "current": { "text": "All", "value": "$__all" },
+                    "options": options,
+                },
+            ]
+        },
+        "panels": panels,
+    })
+}
+
+fn timeseries(title: &str, expr: &str, legend: &str) -> Value {
+    json!({
+        "title": title,
+        "type": "timeseries",
+        "datasource": { "type": "prometheus", "uid": "${datasource}" },
+        "targets": [
+            {
+                "refId": "A",
+                "datasource": { "type": "prometheus", "uid": "${datasource}" },
+                "expr": expr,
+                "legendFormat": legend,
+            }
+        ],
+    })
+}
+
+pub fn print_dashboard(config: &Config) -> Result<()> {
+    let dashboard = dashboard(config);
+    println!("{}", serde_json::to_string_pretty(&dashboard)?);
+
+    Ok(())
+}
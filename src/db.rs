@@ -1,9 +1,31 @@
-use std::path::Path;
+use std::{
+    future::Future,
+    path::Path,
+    time::{Duration, Instant},
+};
 
-use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    SqlitePool,
+};
+use tendermint::chain;
 use time::PrimitiveDateTime;
+use tracing::warn;
 
-use crate::Result;
+use crate::{config, metrics::Metrics, Result};
+
+/// Number of times an operation is retried while the database is busy or locked
+const BUSY_RETRIES: u32 = 5;
+
+/// Delay before the first retry of an operation, doubled on every retry
+const BUSY_BACKOFF: Duration = Duration::from_millis(100);
+
+// https://www.sqlite.org/rescode.html
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
+
+/// Number of prepared statements cached per connection, enough for all the queries of the collectors
+const STATEMENT_CACHE_CAPACITY: usize = 256;
 
 #[derive(Clone, Debug, sqlx::FromRow)]
 pub struct TxRow {
@@ -13,6 +35,9 @@ pub struct TxRow {
     pub hash: String,
     pub memo: String,
     pub created_at: PrimitiveDateTime,
+    pub proposer: Option<String>,
+    pub tx_index: Option<i64>,
+    pub block_time: Option<PrimitiveDateTime>,
 }
 
 #[derive(Clone, Debug, sqlx::FromRow)]
@@ -30,25 +55,389 @@ pub struct PacketRow {
     pub effected_signer: Option<String>,
     pub effected_tx: Option<i64>,
     pub created_at: PrimitiveDateTime,
+    pub chain: Option<String>,
+    pub block_time: Option<PrimitiveDateTime>,
+}
+
+/// An observation of a channel on which packets were stuck
+#[derive(Clone, Debug, sqlx::FromRow, serde::Serialize)]
+pub struct StuckRow {
+    pub id: i64,
+    pub observed_at: String,
+    pub src_chain: String,
+    pub dst_chain: String,
+    pub src_channel: String,
+    pub dst_channel: String,
+    pub direction: String,
+    pub size_queue: i64,
+    pub source: String,
+}
+
+/// A transfer which failed on its destination chain, as reported by its error acknowledgement
+#[derive(Clone, Debug, sqlx::FromRow, serde::Serialize)]
+pub struct TransferFailureRow {
+    pub id: i64,
+    pub chain: String,
+    pub src_channel: String,
+    pub dst_channel: String,
+    pub sequence: i64,
+    pub denom: String,
+    pub amount: String,
+    pub sender: String,
+    pub receiver: String,
+    pub error: String,
+    pub tx_hash: String,
+    pub created_at: String,
+}
+
+/// A packet along with its tx, as exported to CSV
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct PacketExportRow {
+    pub chain: String,
+    pub height: i64,
+    pub tx_hash: String,
+    pub block_time: String,
+    pub created_at: String,
+    pub msg_type_url: String,
+    pub src_port: String,
+    pub src_channel: String,
+    pub dst_port: String,
+    pub dst_channel: String,
+    pub sequence: i64,
+    pub signer: Option<String>,
+    pub effected: bool,
+    pub effected_signer: Option<String>,
+    pub memo: String,
+}
+
+/// A packet along with the height of its tx and its stored classification, as checked by `chainpulse reclassify`
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct ClassificationRow {
+    pub id: i64,
+    pub tx_id: i64,
+    pub height: i64,
+    pub sequence: i64,
+    pub src_channel: String,
+    pub src_port: String,
+    pub dst_channel: String,
+    pub dst_port: String,
+    pub msg_type_url: String,
+    pub signer: Option<String>,
+    pub effected: bool,
+    pub effected_tx: Option<i64>,
+}
+
+/// The number of packets relayed over an hour on a channel by a signer, with a given outcome
+#[derive(Clone, Debug, sqlx::FromRow, serde::Serialize)]
+pub struct HourlyPacketsRow {
+    pub chain: String,
+    pub hour: String,
+    pub src_channel: String,
+    pub dst_channel: String,
+    pub signer: String,
+    pub effected: bool,
+    pub packets: i64,
+}
+
+/// The traffic seen on a channel of a chain, along with its most active relayers
+#[derive(Clone, Debug, sqlx::FromRow, serde::Serialize)]
+pub struct ChannelRow {
+    pub chain: String,
+    pub src_port: String,
+    pub src_channel: String,
+    pub dst_port: String,
+    pub dst_channel: String,
+    pub first_seen_at: String,
+    pub last_activity_at: String,
+    pub packets: i64,
+    pub effected_packets: i64,
+    pub sent_packets: i64,
+    #[sqlx(skip)]
+    pub top_relayers: Vec<ChannelRelayerRow>,
+}
+
+/// The packets relayed on a channel of a chain by a signer
+#[derive(Clone, Debug, sqlx::FromRow, serde::Serialize)]
+pub struct ChannelRelayerRow {
+    #[serde(skip)]
+    pub chain: String,
+    #[serde(skip)]
+    pub src_port: String,
+    #[serde(skip)]
+    pub src_channel: String,
+    #[serde(skip)]
+    pub dst_port: String,
+    #[serde(skip)]
+    pub dst_channel: String,
+    pub signer: String,
+    pub packets: i64,
+    pub effected_packets: i64,
+}
+
+/// A message which failed to be processed, along with its raw bytes to process it again later on
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct DeadLetterRow {
+    pub id: i64,
+    pub chain: String,
+    pub tx_id: i64,
+    pub msg_index: i64,
+    pub type_url: String,
+    pub value: Vec<u8>,
+    pub error: String,
+    pub attempts: i64,
+    pub created_at: PrimitiveDateTime,
+    pub updated_at: PrimitiveDateTime,
+}
+
+/// A tx whose memo matched a search
+#[derive(Clone, Debug, sqlx::FromRow, serde::Serialize)]
+pub struct MemoMatch {
+    pub id: i64,
+    pub chain: String,
+    pub height: i64,
+    pub hash: String,
+    pub memo: String,
+    pub block_time: String,
+    pub created_at: String,
+    pub packets: i64,
+}
+
+/// A packet sent from a monitored chain whose lifecycle is not finished yet,
+/// ie. which was neither acknowledged nor timed out
+#[derive(Clone, Debug, sqlx::FromRow, serde::Serialize)]
+pub struct PendingPacketRow {
+    pub chain: String,
+    pub src_port: String,
+    pub src_channel: String,
+    pub dst_port: String,
+    pub dst_channel: String,
+    pub sequence: i64,
+
+    /// Either `sent`, or `received` once the packet was relayed to its destination chain
+    pub state: String,
+
+    pub sent_at: String,
+    pub received_at: Option<String>,
+
+    /// The time in seconds since the packet was sent
+    pub age_seconds: i64,
+}
+
+/// A sampled message which was ignored by the collectors, see `ignored`
+#[derive(Clone, Debug, sqlx::FromRow, serde::Serialize)]
+pub struct IgnoredMsgRow {
+    pub chain: String,
+    pub height: i64,
+    pub tx_hash: String,
+    pub type_url: String,
+
+    /// Either `other` or `not_relevant`
+    pub reason: String,
+
+    pub created_at: String,
+}
+
+/// A packet relayed on a hop of the journey of a packet, along with its tx
+#[derive(Clone, Debug, sqlx::FromRow, serde::Serialize)]
+pub struct HopRelayRow {
+    pub chain: Option<String>,
+    pub dst_channel: String,
+    pub msg_type_url: String,
+    pub tx_hash: String,
+    pub height: i64,
+    pub signer: Option<String>,
+    pub effected: bool,
+    pub block_time: String,
+
+    /// The time in seconds between the packet being sent and relayed, if known
+    pub latency_seconds: Option<f64>,
+}
+
+/// A packet along with its tx, as served at `/api/v1/packets`
+#[derive(Clone, Debug, sqlx::FromRow, serde::Serialize)]
+pub struct PacketApiRow {
+    pub id: i64,
+    pub chain: String,
+    pub height: i64,
+    pub tx_hash: String,
+    pub msg_type_url: String,
+    pub src_port: String,
+    pub src_channel: String,
+    pub dst_port: String,
+    pub dst_channel: String,
+    pub sequence: i64,
+    pub signer: Option<String>,
+    pub effected: bool,
+    pub effected_signer: Option<String>,
+    pub memo: String,
+    pub block_time: String,
+    pub created_at: String,
+}
+
+/// A tx, as served at `/api/v1/txs/{hash}`
+#[derive(Clone, Debug, sqlx::FromRow, serde::Serialize)]
+pub struct TxApiRow {
+    pub id: i64,
+    pub chain: String,
+    pub height: i64,
+    pub hash: String,
+    pub memo: String,
+    pub tx_index: Option<i64>,
+    pub proposer: Option<String>,
+    pub block_time: String,
+    pub created_at: String,
+}
+
+/// A packet which was relayed again after another relayer had already relayed it, as served at `/api/v1/frontruns`
+#[derive(Clone, Debug, sqlx::FromRow, serde::Serialize)]
+pub struct FrontrunRow {
+    pub id: i64,
+    pub chain: String,
+    pub height: i64,
+    pub tx_hash: String,
+    pub msg_type_url: String,
+    pub src_channel: String,
+    pub dst_channel: String,
+    pub sequence: i64,
+
+    /// The relayer which was frontrun
+    pub signer: Option<String>,
+
+    /// The relayer which relayed the packet first, and its tx, if recorded
+    pub frontrunner: Option<String>,
+    pub frontrunner_tx_hash: Option<String>,
+
+    pub block_time: String,
+}
+
+/// Whether an error is a transient database error, eg. because the database is busy or locked,
+/// in which case the write can be retried later. Other database errors, eg. constraint violations,
+/// would fail again on every retry.
+pub fn is_transient(e: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    is_busy(e)
+        || matches!(
+            e.downcast_ref::<sqlx::Error>(),
+            Some(sqlx::Error::PoolTimedOut | sqlx::Error::Io(_))
+        )
+}
+
+/// Whether an error is caused by the database being busy or locked by another connection
+pub fn is_busy(e: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    let Some(sqlx::Error::Database(e)) = e.downcast_ref::<sqlx::Error>() else {
+        return false;
+    };
+
+    // Extended result codes carry the primary result code in their least significant byte
+    let code = e
+        .code()
+        .and_then(|code| code.parse::<i32>().ok())
+        .unwrap_or_default();
+
+    matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED)
+}
+
+/// Run a database operation, recording its latency under the given name
+pub async fn timed<T>(
+    metrics: &Metrics,
+    chain_id: &chain::Id,
+    operation: &str,
+    f: impl Future<Output = T>,
+) -> T {
+    metrics.chainpulse_pending_db_operations(chain_id, 1);
+
+    let start = Instant::now();
+    let result = f.await;
+    metrics.chainpulse_db_latency(chain_id, operation, start.elapsed());
+
+    metrics.chainpulse_pending_db_operations(chain_id, -1);
+    result
+}
+
+/// Run a database operation, retrying it with an exponential backoff while the database is busy or locked
+pub async fn retry_busy<T, F, Fut>(metrics: &Metrics, chain_id: &chain::Id, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backoff = BUSY_BACKOFF;
+
+    for _ in 0..BUSY_RETRIES {
+        match f().await {
+            Err(e) if is_busy(e.as_ref()) => {
+                metrics.chainpulse_db_busy(chain_id);
+                warn!("Database is busy, retrying in {backoff:?}...");
+
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+
+    let result = f().await;
+
+    if let Err(e) = &result {
+        if is_busy(e.as_ref()) {
+            metrics.chainpulse_db_busy(chain_id);
+        }
+    }
+
+    result
+}
+
+/// Open the configured database, either in memory or from its file
+pub async fn open(config: &config::Database) -> Result<SqlitePool> {
+    if config.in_memory {
+        connect_in_memory().await
+    } else if config.checkpoint.is_some() {
+        // The write-ahead log is then only checkpointed by `checkpoint::run`
+        let options = connect_options(&config.path).pragma("wal_autocheckpoint", "0");
+        let pool = SqlitePool::connect_with(options).await?;
+
+        Ok(pool)
+    } else {
+        connect(&config.path).await
+    }
 }
 
 pub async fn connect(path: &Path) -> Result<SqlitePool> {
-    let options = SqliteConnectOptions::new()
+    let pool = SqlitePool::connect_with(connect_options(path)).await?;
+
+    Ok(pool)
+}
+
+fn connect_options(path: &Path) -> SqliteConnectOptions {
+    SqliteConnectOptions::new()
         .filename(path)
         .create_if_missing(true)
-        .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal);
+        .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
+        .statement_cache_capacity(STATEMENT_CACHE_CAPACITY)
+}
 
-    let pool = SqlitePool::connect_with(options).await?;
+/// Open a database which only lives in memory, shared by all the connections of the pool
+pub async fn connect_in_memory() -> Result<SqlitePool> {
+    let options = "sqlite::memory:"
+        .parse::<SqliteConnectOptions>()?
+        .statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+
+    // The database is dropped along with its last connection, so keep one open at all times
+    let pool = SqlitePoolOptions::new()
+        .min_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(options)
+        .await?;
 
     Ok(pool)
 }
 
-pub async fn setup(pool: &SqlitePool) {
-    create_tables(pool).await;
-    create_indexes(pool).await;
+pub async fn setup(pool: &SqlitePool) -> Result<()> {
+    create_tables(pool).await?;
+
+    Ok(())
 }
 
-pub async fn create_tables(pool: &SqlitePool) {
+pub async fn create_tables(pool: &SqlitePool) -> Result<()> {
     const TABLES: &[&str] = &[
         r#"
         CREATE TABLE IF NOT EXISTS txs (
@@ -76,23 +465,170 @@ pub async fn create_tables(pool: &SqlitePool) {
             created_at          TEXT    NOT NULL
         );
         "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS sent_packets (
+            id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+            chain               TEXT    NOT NULL,
+            sequence            INTEGER NOT NULL,
+            src_channel         TEXT    NOT NULL,
+            src_port            TEXT    NOT NULL,
+            dst_channel         TEXT    NOT NULL,
+            dst_port            TEXT    NOT NULL,
+            first_seen_at       TEXT    NOT NULL,
+            resolved_at         TEXT
+        );
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS stuck_history (
+            id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+            observed_at         TEXT    NOT NULL,
+            src_chain           TEXT    NOT NULL,
+            dst_chain           TEXT    NOT NULL,
+            src_channel         TEXT    NOT NULL,
+            dst_channel         TEXT    NOT NULL,
+            direction           TEXT    NOT NULL,
+            size_queue          INTEGER NOT NULL,
+            source              TEXT    NOT NULL
+        );
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS transfer_failures (
+            id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+            chain               TEXT    NOT NULL,
+            src_channel         TEXT    NOT NULL,
+            dst_channel         TEXT    NOT NULL,
+            sequence            INTEGER NOT NULL,
+            denom               TEXT    NOT NULL,
+            amount              TEXT    NOT NULL,
+            sender              TEXT    NOT NULL,
+            receiver            TEXT    NOT NULL,
+            error               TEXT    NOT NULL,
+            tx_hash             TEXT    NOT NULL,
+            created_at          TEXT    NOT NULL
+        );
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS signer_sequences (
+            id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+            chain               TEXT    NOT NULL,
+            signer              TEXT    NOT NULL,
+            sequence            INTEGER NOT NULL,
+            height              INTEGER NOT NULL,
+            updated_at          TEXT    NOT NULL
+        );
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS dead_letters (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            chain       TEXT    NOT NULL,
+            tx_id       INTEGER NOT NULL REFERENCES txs (id),
+            msg_index   INTEGER NOT NULL,
+            type_url    TEXT    NOT NULL,
+            value       BLOB    NOT NULL,
+            error       TEXT    NOT NULL,
+            attempts    INTEGER NOT NULL,
+            created_at  TEXT    NOT NULL,
+            updated_at  TEXT    NOT NULL
+        );
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS channels (
+            id                   INTEGER PRIMARY KEY AUTOINCREMENT,
+            chain                TEXT    NOT NULL,
+            port                 TEXT    NOT NULL,
+            channel              TEXT    NOT NULL,
+            counterparty_port    TEXT    NOT NULL,
+            counterparty_channel TEXT    NOT NULL,
+            connection           TEXT    NOT NULL,
+            state                TEXT    NOT NULL,
+            discovered_at        TEXT    NOT NULL,
+            updated_at           TEXT    NOT NULL
+        );
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS transfer_flows (
+            id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+            chain               TEXT    NOT NULL,
+            channel             TEXT    NOT NULL,
+            counterparty_channel TEXT   NOT NULL,
+            kind                TEXT    NOT NULL,
+            sequence            INTEGER NOT NULL,
+            denom               TEXT    NOT NULL,
+            amount              TEXT    NOT NULL,
+            created_at          TEXT    NOT NULL
+        );
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS packet_hops (
+            id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+            chain               TEXT    NOT NULL,
+            src_channel         TEXT    NOT NULL,
+            dst_channel         TEXT    NOT NULL,
+            sequence            INTEGER NOT NULL,
+            next_src_channel    TEXT    NOT NULL,
+            next_dst_channel    TEXT    NOT NULL,
+            next_sequence       INTEGER NOT NULL,
+            created_at          TEXT    NOT NULL
+        );
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS relayer_roles (
+            id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+            chain               TEXT    NOT NULL,
+            src_channel         TEXT    NOT NULL,
+            dst_channel         TEXT    NOT NULL,
+            signer              TEXT    NOT NULL,
+            role                TEXT    NOT NULL,
+            share               REAL    NOT NULL,
+            updated_at          TEXT    NOT NULL
+        );
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS ignored_msgs (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            chain       TEXT    NOT NULL,
+            height      INTEGER NOT NULL,
+            tx_hash     TEXT    NOT NULL,
+            type_url    TEXT    NOT NULL,
+            reason      TEXT    NOT NULL,
+            created_at  TEXT    NOT NULL
+        );
+        "#,
     ];
 
     for table in TABLES {
-        sqlx::query(table).execute(pool).await.unwrap();
+        sqlx::query(table).execute(pool).await?;
     }
 
-    const MIGRATIONS: &[&str] =
-        &["ALTER TABLE packets ADD COLUMN effected_tx INTEGER REFERENCES txs (id);"];
+    const MIGRATIONS: &[&str] = &[
+        "ALTER TABLE packets ADD COLUMN effected_tx INTEGER REFERENCES txs (id);",
+        "ALTER TABLE txs ADD COLUMN proposer TEXT;",
+        "ALTER TABLE txs ADD COLUMN tx_index INTEGER;",
+        "ALTER TABLE packets ADD COLUMN chain TEXT;",
+        "ALTER TABLE channels ADD COLUMN client TEXT;",
+        "ALTER TABLE channels ADD COLUMN counterparty_chain TEXT;",
+        "ALTER TABLE txs ADD COLUMN block_time TEXT;",
+        "ALTER TABLE packets ADD COLUMN block_time TEXT;",
+        "ALTER TABLE sent_packets ADD COLUMN received_at TEXT;",
+        "ALTER TABLE sent_packets ADD COLUMN acknowledged_at TEXT;",
+        "ALTER TABLE sent_packets ADD COLUMN timed_out_at TEXT;",
+        "ALTER TABLE transfer_flows ADD COLUMN block_time TEXT;",
+    ];
 
     for migration in MIGRATIONS {
         run_migration(pool, migration).await;
     }
 
-    create_indexes(pool).await;
+    create_indexes(pool).await?;
+    backfill_packets_chain(pool).await?;
+    backfill_block_time(pool).await?;
+    create_search_index(pool).await?;
+    create_hourly_packets(pool).await?;
+
+    Ok(())
 }
 
-async fn create_indexes(pool: &SqlitePool) {
+async fn create_indexes(pool: &SqlitePool) -> Result<()> {
     const INDEXES: &[&str] = &[
         "CREATE UNIQUE INDEX IF NOT EXISTS txs_unique          ON txs (chain, hash);",
         "CREATE        INDEX IF NOT EXISTS txs_chain           ON txs (chain);",
@@ -100,17 +636,195 @@ async fn create_indexes(pool: &SqlitePool) {
         "CREATE        INDEX IF NOT EXISTS txs_memo            ON txs (memo);",
         "CREATE        INDEX IF NOT EXISTS txs_height          ON txs (height);",
         "CREATE        INDEX IF NOT EXISTS txs_created_at      ON txs (created_at);",
+        "CREATE        INDEX IF NOT EXISTS txs_proposer        ON txs (proposer);",
+        "CREATE        INDEX IF NOT EXISTS txs_block_time      ON txs (block_time);",
         "CREATE        INDEX IF NOT EXISTS packets_tx_id       ON packets(tx_id);",
         "CREATE        INDEX IF NOT EXISTS packets_signer      ON packets (signer);",
         "CREATE        INDEX IF NOT EXISTS packets_src_channel ON packets (src_channel);",
         "CREATE        INDEX IF NOT EXISTS packets_dst_channel ON packets (dst_channel);",
         "CREATE        INDEX IF NOT EXISTS packets_effected    ON packets (effected);",
         "CREATE        INDEX IF NOT EXISTS packets_effected_tx ON packets (effected_tx);",
+        "CREATE        INDEX IF NOT EXISTS packets_block_time  ON packets (block_time);",
+        "CREATE        INDEX IF NOT EXISTS packets_lookup      ON packets (chain, src_channel, dst_channel, sequence, msg_type_url);",
+        "CREATE UNIQUE INDEX IF NOT EXISTS sent_packets_unique ON sent_packets (chain, src_channel, src_port, sequence);",
+        "CREATE        INDEX IF NOT EXISTS sent_packets_packet ON sent_packets (src_channel, src_port, dst_channel, dst_port, sequence);",
+        "CREATE        INDEX IF NOT EXISTS sent_packets_resolved_at ON sent_packets (resolved_at);",
+        "CREATE        INDEX IF NOT EXISTS sent_packets_received_at ON sent_packets (received_at);",
+        "CREATE        INDEX IF NOT EXISTS stuck_history_observed_at ON stuck_history (observed_at);",
+        "CREATE        INDEX IF NOT EXISTS stuck_history_channel ON stuck_history (src_chain, src_channel);",
+        "CREATE UNIQUE INDEX IF NOT EXISTS transfer_failures_unique ON transfer_failures (chain, src_channel, sequence, denom);",
+        "CREATE        INDEX IF NOT EXISTS transfer_failures_created_at ON transfer_failures (created_at);",
+        "CREATE UNIQUE INDEX IF NOT EXISTS signer_sequences_unique ON signer_sequences (chain, signer);",
+        "CREATE UNIQUE INDEX IF NOT EXISTS channels_unique ON channels (chain, port, channel);",
+        "CREATE UNIQUE INDEX IF NOT EXISTS dead_letters_unique ON dead_letters (tx_id, msg_index);",
+        "CREATE UNIQUE INDEX IF NOT EXISTS transfer_flows_unique ON transfer_flows (chain, channel, kind, sequence, denom);",
+        "CREATE        INDEX IF NOT EXISTS transfer_flows_created_at ON transfer_flows (created_at);",
+        "CREATE        INDEX IF NOT EXISTS transfer_flows_block_time ON transfer_flows (block_time);",
+        "CREATE UNIQUE INDEX IF NOT EXISTS packet_hops_unique ON packet_hops (chain, dst_channel, sequence);",
+        "CREATE        INDEX IF NOT EXISTS packet_hops_created_at ON packet_hops (created_at);",
+        "CREATE UNIQUE INDEX IF NOT EXISTS relayer_roles_unique ON relayer_roles (chain, src_channel, dst_channel, signer);",
+        "CREATE        INDEX IF NOT EXISTS ignored_msgs_created_at ON ignored_msgs (created_at);",
     ];
 
     for index in INDEXES {
-        sqlx::query(index).execute(pool).await.unwrap();
+        sqlx::query(index).execute(pool).await?;
+    }
+
+    Ok(())
+}
+
+/// Fill in the chain of the packets recorded before it was stored along with them.
+/// Only the packets without a chain are updated, which are found through the `packets_lookup` index.
+async fn backfill_packets_chain(pool: &SqlitePool) -> Result<()> {
+    let query = r#"
+        UPDATE packets SET chain = (SELECT chain FROM txs WHERE txs.id = packets.tx_id)
+        WHERE chain IS NULL
+    "#;
+
+    let result = sqlx::query(query).execute(pool).await?;
+
+    if result.rows_affected() > 0 {
+        tracing::info!(
+            "Filled in the chain of {} existing packets",
+            result.rows_affected()
+        );
+    }
+
+    Ok(())
+}
+
+/// Fill in the block time of the txs, packets and transfer flows recorded before it was stored along with them.
+/// Their block time is unknown, so the time at which they were inserted is used as the best approximation.
+async fn backfill_block_time(pool: &SqlitePool) -> Result<()> {
+    const QUERIES: &[&str] = &[
+        "UPDATE txs SET block_time = created_at WHERE block_time IS NULL",
+        "UPDATE packets SET block_time = created_at WHERE block_time IS NULL",
+        "UPDATE transfer_flows SET block_time = created_at WHERE block_time IS NULL",
+    ];
+
+    let mut rows = 0;
+
+    for query in QUERIES {
+        rows += sqlx::query(query).execute(pool).await?.rows_affected();
+    }
+
+    if rows > 0 {
+        tracing::info!(
+            "Filled in the block time of {rows} existing txs, packets and transfer flows"
+        );
+    }
+
+    Ok(())
+}
+
+/// Create the full-text index over the memos of the txs, kept up to date by triggers,
+/// and populate it from the existing txs when it is first created.
+async fn create_search_index(pool: &SqlitePool) -> Result<()> {
+    let exists: Option<(String,)> = sqlx::query_as(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'txs_memo_fts'",
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    const STATEMENTS: &[&str] = &[
+        r#"
+        CREATE VIRTUAL TABLE IF NOT EXISTS txs_memo_fts USING fts5 (
+            memo,
+            content = 'txs',
+            content_rowid = 'id'
+        );
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS txs_memo_fts_insert AFTER INSERT ON txs BEGIN
+            INSERT INTO txs_memo_fts (rowid, memo) VALUES (new.id, new.memo);
+        END;
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS txs_memo_fts_delete AFTER DELETE ON txs BEGIN
+            INSERT INTO txs_memo_fts (txs_memo_fts, rowid, memo) VALUES ('delete', old.id, old.memo);
+        END;
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS txs_memo_fts_update AFTER UPDATE OF memo ON txs BEGIN
+            INSERT INTO txs_memo_fts (txs_memo_fts, rowid, memo) VALUES ('delete', old.id, old.memo);
+            INSERT INTO txs_memo_fts (rowid, memo) VALUES (new.id, new.memo);
+        END;
+        "#,
+    ];
+
+    for statement in STATEMENTS {
+        sqlx::query(statement).execute(pool).await?;
+    }
+
+    if exists.is_none() {
+        tracing::info!("Building the full-text index over the memos of the existing txs...");
+
+        sqlx::query("INSERT INTO txs_memo_fts (txs_memo_fts) VALUES ('rebuild');")
+            .execute(pool)
+            .await?;
     }
+
+    Ok(())
+}
+
+/// Create the table of the number of packets per hour, chain, channel, signer and outcome,
+/// kept up to date by a trigger as packets are inserted, and populate it from the existing
+/// packets when it is first created.
+async fn create_hourly_packets(pool: &SqlitePool) -> Result<()> {
+    let exists: Option<(String,)> = sqlx::query_as(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'packets_hourly'",
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    const STATEMENTS: &[&str] = &[
+        r#"
+        CREATE TABLE IF NOT EXISTS packets_hourly (
+            id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+            chain               TEXT    NOT NULL,
+            hour                TEXT    NOT NULL,
+            src_channel         TEXT    NOT NULL,
+            dst_channel         TEXT    NOT NULL,
+            signer              TEXT    NOT NULL,
+            effected            BOOL    NOT NULL,
+            packets             INTEGER NOT NULL
+        );
+        "#,
+        "CREATE UNIQUE INDEX IF NOT EXISTS packets_hourly_unique ON packets_hourly (chain, hour, src_channel, dst_channel, signer, effected);",
+        "CREATE        INDEX IF NOT EXISTS packets_hourly_hour ON packets_hourly (hour);",
+        r#"
+        CREATE TRIGGER IF NOT EXISTS packets_hourly_insert AFTER INSERT ON packets BEGIN
+            INSERT INTO packets_hourly (chain, hour, src_channel, dst_channel, signer, effected, packets)
+            SELECT txs.chain, strftime('%Y-%m-%d %H:00:00', new.block_time), new.src_channel,
+                new.dst_channel, COALESCE(new.signer, ''), new.effected, 1
+            FROM txs WHERE txs.id = new.tx_id
+            ON CONFLICT (chain, hour, src_channel, dst_channel, signer, effected)
+                DO UPDATE SET packets = packets + 1;
+        END;
+        "#,
+    ];
+
+    for statement in STATEMENTS {
+        sqlx::query(statement).execute(pool).await?;
+    }
+
+    if exists.is_none() {
+        tracing::info!("Aggregating the existing packets per hour...");
+
+        let query = r#"
+            INSERT INTO packets_hourly (chain, hour, src_channel, dst_channel, signer, effected, packets)
+            SELECT txs.chain, strftime('%Y-%m-%d %H:00:00', packets.block_time), packets.src_channel,
+                packets.dst_channel, COALESCE(packets.signer, ''), packets.effected, COUNT(*)
+            FROM packets JOIN txs ON packets.tx_id = txs.id
+            GROUP BY 1, 2, 3, 4, 5, 6
+            ON CONFLICT (chain, hour, src_channel, dst_channel, signer, effected)
+                DO UPDATE SET packets = packets + excluded.packets;
+        "#;
+
+        sqlx::query(query).execute(pool).await?;
+    }
+
+    Ok(())
 }
 
 async fn run_migration(pool: &SqlitePool, migration: &str) {
@@ -0,0 +1,158 @@
+//! Dead letters, ie. the IBC messages which failed to be decoded or processed, eg. because of a bug in the
+//! decoding or processing of some message type, recorded along with their raw bytes in the `dead_letters` table
+//! instead of failing the whole block, so that they can be processed again once fixed.
+//!
+//! The `replay-dead-letters` command processes them again, removing the ones which succeed
+//! and recording the latest error of the ones which fail again.
+
+use ibc_proto::google::protobuf::Any;
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tracing::{info, warn};
+
+use crate::{
+    collect,
+    config::Config,
+    db::{self, DeadLetterRow, TxRow},
+    metrics::Metrics,
+    msg::Decoders,
+    plugins::Plugins,
+    signer,
+    trace::Outcome,
+    Result,
+};
+
+/// Options of the `replay-dead-letters` command
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Only replay the dead letters of the given chain
+    pub chain: Option<String>,
+}
+
+/// Record a message of a tx which failed to be processed, or the latest error if it already was
+pub async fn record(
+    pool: &SqlitePool,
+    tx_row: &TxRow,
+    msg_index: usize,
+    msg: &Any,
+    error: &str,
+) -> Result<()> {
+    let query = r#"
+        INSERT INTO dead_letters
+            (chain, tx_id, msg_index, type_url, value, error, attempts, created_at, updated_at)
+        VALUES
+            (?, ?, ?, ?, ?, ?, 1, datetime('now'), datetime('now'))
+        ON CONFLICT (tx_id, msg_index) DO UPDATE SET
+            error = excluded.error,
+            attempts = attempts + 1,
+            updated_at = excluded.updated_at
+    "#;
+
+    sqlx::query(query)
+        .bind(&tx_row.chain)
+        .bind(tx_row.id)
+        .bind(msg_index as i64)
+        .bind(&msg.type_url)
+        .bind(&msg.value)
+        .bind(error)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Process the dead letters again, in the order in which they were recorded
+pub async fn run(config: &Config, options: Options) -> Result<()> {
+    let pool = db::connect(&config.database.path).await?;
+    db::setup(&pool).await?;
+
+    let (metrics, _registries) = Metrics::new(config.memo);
+    let decoders = Decoders::new();
+    let plugins = Plugins::load(&config.plugins)?;
+
+    let rows: Vec<DeadLetterRow> =
+        sqlx::query_as("SELECT * FROM dead_letters WHERE (?1 IS NULL OR chain = ?1) ORDER BY id")
+            .bind(options.chain.as_deref())
+            .fetch_all(&pool)
+            .await?;
+
+    info!("Replaying {} dead letters...", rows.len());
+
+    let (mut replayed, mut failed) = (0, 0);
+
+    for row in rows {
+        match replay(&pool, &row, &metrics, &decoders, &plugins).await {
+            Ok(outcome) => {
+                replayed += 1;
+                info!(
+                    "Replayed {} of tx {} on {}: {outcome:?}",
+                    row.type_url, row.tx_id, row.chain
+                );
+
+                sqlx::query("DELETE FROM dead_letters WHERE id = ?")
+                    .bind(row.id)
+                    .execute(&pool)
+                    .await?;
+            }
+            Err(e) => {
+                failed += 1;
+                warn!(
+                    "Failed to replay {} of tx {} on {}: {e}",
+                    row.type_url, row.tx_id, row.chain
+                );
+
+                sqlx::query(
+                    "UPDATE dead_letters SET error = ?, attempts = attempts + 1, updated_at = datetime('now') WHERE id = ?",
+                )
+                .bind(e.to_string())
+                .bind(row.id)
+                .execute(&pool)
+                .await?;
+            }
+        }
+    }
+
+    println!("{replayed} dead letters replayed, {failed} failed again");
+
+    if failed > 0 {
+        return Err(format!("{failed} dead letters failed to be replayed").into());
+    }
+
+    Ok(())
+}
+
+/// Decode and process a dead letter again, as when it was first collected
+async fn replay(
+    pool: &SqlitePool,
+    row: &DeadLetterRow,
+    metrics: &Metrics,
+    decoders: &Decoders,
+    plugins: &Plugins,
+) -> Result<Outcome> {
+    let chain_id = chain::Id::try_from(row.chain.clone())?;
+
+    let tx_row: TxRow = sqlx::query_as("SELECT * FROM txs WHERE id = ?")
+        .bind(row.tx_id)
+        .fetch_one(pool)
+        .await?;
+
+    let mut msg = decoders.decode(Any {
+        type_url: row.type_url.clone(),
+        value: row.value.clone(),
+    })?;
+
+    if let Some(value) = msg.signer_mut() {
+        *value = signer::normalize(value)?;
+    }
+
+    collect::process_msg(
+        pool,
+        &chain_id,
+        &tx_row,
+        &row.type_url,
+        msg,
+        metrics,
+        plugins,
+    )
+    .await
+}
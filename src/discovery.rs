@@ -0,0 +1,446 @@
+//! Discovery of the channels of the monitored chains.
+//!
+//! The channels of each chain are queried from the IBC module and recorded in the `channels` table,
+//! so that channels on which no traffic ever flows are known as well, and the time since traffic
+//! was last seen on each open channel is exported, to detect channels which have fallen silent.
+//!
+//! The client of the connection of each channel is resolved as well, along with the chain it tracks,
+//! and exported with the rest of the topology of the channel as `ibc_channel_info`.
+//! The time at which each Tendermint client expires, ie. the time of its latest consensus state
+//! plus its trusting period, is exported as `ibc_client_expiry_timestamp_seconds`.
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use ibc_proto::{
+    cosmos::base::query::v1beta1::PageRequest,
+    google::protobuf::Any,
+    ibc::{
+        core::{
+            channel::v1::{IdentifiedChannel, QueryChannelsRequest, QueryChannelsResponse, State},
+            client::v1::{QueryConsensusStateRequest, QueryConsensusStateResponse},
+            connection::v1::{
+                QueryConnectionClientStateRequest, QueryConnectionClientStateResponse,
+            },
+        },
+        lightclients::tendermint::v1::{ClientState, ConsensusState},
+    },
+};
+use prost::Message;
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tendermint_rpc::WebSocketClient;
+use tokio::time;
+use tracing::{error, info, warn};
+
+use crate::{
+    config::{Chains, ChannelDiscovery, Endpoint},
+    metrics::Metrics,
+    query::abci_query,
+    Result,
+};
+
+/// Interval between two computations of the idle time of the channels
+const UPDATE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Maximum number of channels fetched per query
+const PAGE_LIMIT: u64 = 500;
+
+const TENDERMINT_CLIENT_STATE: &str = "/ibc.lightclients.tendermint.v1.ClientState";
+const TENDERMINT_CONSENSUS_STATE: &str = "/ibc.lightclients.tendermint.v1.ConsensusState";
+
+/// The client of a connection, along with the chain it tracks
+#[derive(Clone, Debug, Default)]
+struct Client {
+    id: String,
+    chain: String,
+
+    /// Unix time at which the client expires, only known for Tendermint clients which are not frozen
+    expires_at: Option<i64>,
+}
+
+/// Discover the channels of each chain on startup and then periodically,
+/// and periodically export the idle time of the open ones
+pub async fn run(chains: Chains, config: ChannelDiscovery, pool: SqlitePool, metrics: Metrics) {
+    let mut discovered_at: Option<Instant> = None;
+
+    loop {
+        if discovered_at.map_or(true, |at| at.elapsed() >= config.interval()) {
+            for (chain_id, endpoint) in &chains.endpoints {
+                if let Err(e) = discover(chain_id, endpoint, &pool, &metrics).await {
+                    error!("Failed to discover the channels of {chain_id}: {e}");
+                }
+            }
+
+            discovered_at = Some(Instant::now());
+        }
+
+        if let Err(e) = update(&pool, &metrics).await {
+            error!("Failed to compute the idle time of the channels: {e}");
+        }
+
+        time::sleep(UPDATE_INTERVAL).await;
+    }
+}
+
+/// Query the channels of a chain and record them
+async fn discover(
+    chain_id: &chain::Id,
+    endpoint: &Endpoint,
+    pool: &SqlitePool,
+    metrics: &Metrics,
+) -> Result<()> {
+    let (client, driver) = WebSocketClient::builder(endpoint.url.clone())
+        .compat_mode(endpoint.comet_version)
+        .build()
+        .await?;
+
+    let driver = tokio::spawn(driver.run());
+
+    let result = channels(&client).await;
+
+    // The clients are resolved once per connection, shared by all of its channels
+    let mut clients = BTreeMap::new();
+
+    if let Ok(channels) = &result {
+        for channel in channels {
+            let Some(connection) = channel.connection_hops.first() else {
+                continue;
+            };
+
+            if clients.contains_key(connection) {
+                continue;
+            }
+
+            let resolved = match connection_client(&client, connection).await {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    warn!("Failed to resolve the client of {connection} on {chain_id}: {e}");
+                    Client::default()
+                }
+            };
+
+            if let Some(expires_at) = resolved.expires_at {
+                metrics.ibc_client_expiry(chain_id, &resolved.id, &resolved.chain, expires_at);
+            }
+
+            clients.insert(connection.clone(), resolved);
+        }
+    }
+
+    let _ = client.close();
+    let _ = driver.await;
+
+    let channels = result?;
+
+    let query = r#"
+        INSERT INTO channels
+            (chain, port, channel, counterparty_port, counterparty_channel,
+            connection, state, client, counterparty_chain, discovered_at, updated_at)
+        VALUES
+            (?, ?, ?, ?, ?, ?, ?, ?, ?, datetime('now'), datetime('now'))
+        ON CONFLICT (chain, port, channel) DO UPDATE SET
+            counterparty_port = excluded.counterparty_port,
+            counterparty_channel = excluded.counterparty_channel,
+            connection = excluded.connection,
+            state = excluded.state,
+            client = COALESCE(excluded.client, client),
+            counterparty_chain = COALESCE(excluded.counterparty_chain, counterparty_chain),
+            updated_at = excluded.updated_at
+    "#;
+
+    let mut tx = pool.begin().await?;
+
+    for channel in &channels {
+        let counterparty = channel.counterparty.clone().unwrap_or_default();
+        let connection = channel.connection_hops.first().map_or("", String::as_str);
+
+        // Left untouched if the client could not be resolved this time
+        let client = clients
+            .get(connection)
+            .filter(|client| !client.id.is_empty());
+
+        sqlx::query(query)
+            .bind(chain_id.as_str())
+            .bind(&channel.port_id)
+            .bind(&channel.channel_id)
+            .bind(&counterparty.port_id)
+            .bind(&counterparty.channel_id)
+            .bind(connection)
+            .bind(state_name(channel.state))
+            .bind(client.map(|client| &client.id))
+            .bind(client.map(|client| &client.chain))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    info!("Discovered {} channels on {chain_id}", channels.len());
+
+    Ok(())
+}
+
+/// Query all the channels of a chain via ABCI, following the pagination
+async fn channels(client: &WebSocketClient) -> Result<Vec<IdentifiedChannel>> {
+    let mut channels = Vec::new();
+    let mut key = Vec::new();
+
+    loop {
+        let request = QueryChannelsRequest {
+            pagination: Some(PageRequest {
+                key,
+                limit: PAGE_LIMIT,
+                ..Default::default()
+            }),
+        };
+
+        let response: QueryChannelsResponse =
+            abci_query(client, "/ibc.core.channel.v1.Query/Channels", request).await?;
+
+        channels.extend(response.channels);
+
+        match response.pagination {
+            Some(page) if !page.next_key.is_empty() => key = page.next_key,
+            _ => break,
+        }
+    }
+
+    Ok(channels)
+}
+
+/// Query the client of a connection, and the chain it tracks
+async fn connection_client(client: &WebSocketClient, connection: &str) -> Result<Client> {
+    let request = QueryConnectionClientStateRequest {
+        connection_id: connection.to_string(),
+    };
+
+    let response: QueryConnectionClientStateResponse = abci_query(
+        client,
+        "/ibc.core.connection.v1.Query/ConnectionClientState",
+        request,
+    )
+    .await?;
+
+    let state = response.identified_client_state.unwrap_or_default();
+
+    // The chain tracked by a client, and when it expires, are only known for Tendermint clients
+    let Some(tendermint) = tendermint_client_state(state.client_state.as_ref()) else {
+        return Ok(Client {
+            id: state.client_id,
+            ..Client::default()
+        });
+    };
+
+    let expires_at = match client_expiry(client, &state.client_id, &tendermint).await {
+        Ok(expires_at) => expires_at,
+        Err(e) => {
+            warn!("Failed to query the expiry of {}: {e}", state.client_id);
+            None
+        }
+    };
+
+    Ok(Client {
+        id: state.client_id,
+        chain: tendermint.chain_id,
+        expires_at,
+    })
+}
+
+/// Query the latest consensus state of a Tendermint client, and compute when the client expires from its trusting period.
+/// Frozen clients never expire.
+async fn client_expiry(
+    client: &WebSocketClient,
+    client_id: &str,
+    state: &ClientState,
+) -> Result<Option<i64>> {
+    if state.frozen_height.as_ref().map_or(false, |height| {
+        height.revision_number != 0 || height.revision_height != 0
+    }) {
+        return Ok(None);
+    }
+
+    let (Some(height), Some(trusting_period)) = (&state.latest_height, &state.trusting_period)
+    else {
+        return Ok(None);
+    };
+
+    let request = QueryConsensusStateRequest {
+        client_id: client_id.to_string(),
+        revision_number: height.revision_number,
+        revision_height: height.revision_height,
+        latest_height: false,
+    };
+
+    let response: QueryConsensusStateResponse =
+        abci_query(client, "/ibc.core.client.v1.Query/ConsensusState", request).await?;
+
+    let consensus_state = response
+        .consensus_state
+        .filter(|state| state.type_url == TENDERMINT_CONSENSUS_STATE)
+        .map(|state| ConsensusState::decode(state.value.as_slice()))
+        .transpose()?;
+
+    let timestamp = consensus_state.and_then(|state| state.timestamp);
+
+    Ok(timestamp.map(|timestamp| timestamp.seconds + trusting_period.seconds))
+}
+
+/// The state of a client, only decoded for Tendermint clients
+fn tendermint_client_state(state: Option<&Any>) -> Option<ClientState> {
+    let state = state.filter(|state| state.type_url == TENDERMINT_CLIENT_STATE)?;
+
+    ClientState::decode(state.value.as_slice()).ok()
+}
+
+/// The name of the state of a channel, as recorded in the `channels` table
+fn state_name(state: i32) -> &'static str {
+    match State::from_i32(state) {
+        Some(State::Open) => "open",
+        Some(State::Closed) => "closed",
+        Some(State::Init) => "init",
+        Some(State::Tryopen) => "tryopen",
+        Some(State::UninitializedUnspecified) | None => "unknown",
+    }
+}
+
+/// The chain at the other end of a channel, if the channel was discovered and its client resolved
+pub async fn counterparty_chain(
+    pool: &SqlitePool,
+    chain_id: &chain::Id,
+    port: &str,
+    channel: &str,
+) -> Result<Option<String>> {
+    let row: Option<(Option<String>,)> = sqlx::query_as(
+        "SELECT counterparty_chain FROM channels WHERE chain = ? AND port = ? AND channel = ? LIMIT 1",
+    )
+    .bind(chain_id.as_str())
+    .bind(port)
+    .bind(channel)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.and_then(|(counterparty_chain,)| counterparty_chain))
+}
+
+/// The chain tracked by a client, if a channel over that client was discovered and its client resolved.
+/// IBC v2 packets are sent between clients rather than channels.
+pub async fn client_chain(
+    pool: &SqlitePool,
+    chain_id: &chain::Id,
+    client: &str,
+) -> Result<Option<String>> {
+    let row: Option<(String,)> = sqlx::query_as(
+        "SELECT counterparty_chain FROM channels WHERE chain = ? AND client = ? AND counterparty_chain IS NOT NULL LIMIT 1",
+    )
+    .bind(chain_id.as_str())
+    .bind(client)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|(counterparty_chain,)| counterparty_chain))
+}
+
+/// Export the time since traffic was last seen on each open channel, either a packet relayed
+/// on the chain, in either direction, or a packet sent from it
+async fn update(pool: &SqlitePool, metrics: &Metrics) -> Result<()> {
+    let query = r#"
+        SELECT chain, port, channel, counterparty_channel,
+            CAST((julianday('now') - julianday(COALESCE(NULLIF(MAX(
+                COALESCE((SELECT MAX(block_time) FROM packets
+                    WHERE packets.chain = channels.chain AND packets.src_channel = channels.channel
+                        AND packets.src_port = channels.port), ''),
+                COALESCE((SELECT MAX(block_time) FROM packets
+                    WHERE packets.chain = channels.chain AND packets.dst_channel = channels.channel
+                        AND packets.dst_port = channels.port), ''),
+                COALESCE((SELECT MAX(first_seen_at) FROM sent_packets
+                    WHERE sent_packets.chain = channels.chain AND sent_packets.src_channel = channels.channel
+                        AND sent_packets.src_port = channels.port), '')
+            ), ''), discovered_at))) * 86400 AS INTEGER)
+        FROM channels
+        WHERE state = 'open'
+    "#;
+
+    let rows: Vec<(String, String, String, String, i64)> =
+        sqlx::query_as(query).fetch_all(pool).await?;
+
+    // Reset the gauge so that channels which are no longer open are removed
+    metrics.reset_ibc_channel_idle();
+
+    for (chain, port, channel, counterparty_channel, idle) in rows {
+        let Ok(chain_id) = chain::Id::try_from(chain) else {
+            continue;
+        };
+
+        metrics.ibc_channel_idle(&chain_id, &port, &channel, &counterparty_channel, idle);
+    }
+
+    let query = r#"
+        SELECT chain, channel, port, COALESCE(counterparty_chain, ''), counterparty_channel,
+            connection, COALESCE(client, '')
+        FROM channels
+    "#;
+
+    let rows: Vec<(String, String, String, String, String, String, String)> =
+        sqlx::query_as(query).fetch_all(pool).await?;
+
+    metrics.reset_ibc_channel_info();
+
+    for (chain, channel, port, counterparty_chain, counterparty_channel, connection, client) in rows
+    {
+        let Ok(chain_id) = chain::Id::try_from(chain) else {
+            continue;
+        };
+
+        metrics.ibc_channel_info(
+            &chain_id,
+            &channel,
+            &port,
+            &counterparty_chain,
+            &counterparty_channel,
+            &connection,
+            &client,
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_name() {
+        assert_eq!(state_name(State::Open as i32), "open");
+        assert_eq!(state_name(State::Closed as i32), "closed");
+        assert_eq!(state_name(42), "unknown");
+    }
+
+    #[test]
+    fn test_tracked_chain() {
+        let state = Any {
+            type_url: TENDERMINT_CLIENT_STATE.to_string(),
+            value: ClientState {
+                chain_id: "osmosis-1".to_string(),
+                ..Default::default()
+            }
+            .encode_to_vec(),
+        };
+
+        let tracked_chain =
+            |state: Option<&Any>| tendermint_client_state(state).map(|state| state.chain_id);
+
+        assert_eq!(tracked_chain(Some(&state)).as_deref(), Some("osmosis-1"));
+
+        let other = Any {
+            type_url: "/ibc.lightclients.solomachine.v3.ClientState".to_string(),
+            ..state
+        };
+
+        assert_eq!(tracked_chain(Some(&other)), None);
+        assert_eq!(tracked_chain(None), None);
+    }
+}
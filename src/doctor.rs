@@ -0,0 +1,257 @@
+//! Startup diagnostics, to check that the configured endpoints, database and metrics port
+//! are usable before deploying chainpulse.
+
+use std::{fmt::Display, future::Future, net::SocketAddr, path::Path, time::Duration};
+
+use futures::future;
+use tendermint::chain;
+use tendermint_rpc::{client::CompatMode, Client, WebSocketClient, WebSocketClientUrl};
+use tokio::time;
+
+use crate::{config::Config, db, Result};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The outcome of a single check
+#[derive(Clone, Debug)]
+struct Check {
+    name: &'static str,
+    target: String,
+    result: std::result::Result<String, String>,
+}
+
+impl Check {
+    fn new(
+        name: &'static str,
+        target: &str,
+        result: std::result::Result<String, impl Display>,
+    ) -> Self {
+        Self {
+            name,
+            target: target.to_string(),
+            result: result.map_err(|e| e.to_string()),
+        }
+    }
+
+    fn pass(name: &'static str, target: &str, details: &str) -> Self {
+        Self::new(name, target, Ok::<_, String>(details.to_string()))
+    }
+}
+
+/// Run every check, print a table of their outcomes, and fail if any of them failed
+pub async fn run(config: &Config) -> Result<()> {
+    let endpoints = config
+        .chains
+        .endpoints
+        .iter()
+        .flat_map(|(chain_id, endpoint)| {
+            endpoint
+                .urls()
+                .into_iter()
+                .map(move |url| check_endpoint(chain_id, endpoint.comet_version, url))
+        });
+
+    let mut checks = future::join_all(endpoints)
+        .await
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    // An in-memory database has no file to check
+    if !config.database.in_memory {
+        checks.push(check_database(&config.database.path).await);
+    }
+
+    if config.metrics.enabled {
+        checks.push(check_port(config.metrics.port));
+    }
+
+    print!("{}", table(&checks));
+
+    let failed = checks.iter().filter(|check| check.result.is_err()).count();
+
+    if failed > 0 {
+        return Err(format!("{failed} of {} checks failed", checks.len()).into());
+    }
+
+    Ok(())
+}
+
+/// Print the endpoints and subscription queries of the configured chains,
+/// the configuration having been validated when loaded
+pub fn check_config(config: &Config) -> Result<()> {
+    for (chain_id, endpoint) in &config.chains.endpoints {
+        let query = endpoint.subscription_query()?;
+
+        for url in endpoint.urls() {
+            println!("{chain_id}: {url} ({query})");
+        }
+    }
+
+    println!("Configuration is valid");
+
+    Ok(())
+}
+
+/// Connect to an endpoint, query its status, fetch its latest block, and check its version
+async fn check_endpoint(
+    chain_id: &chain::Id,
+    compat_mode: CompatMode,
+    url: WebSocketClientUrl,
+) -> Vec<Check> {
+    let target = format!("{chain_id} ({url})");
+    let mut checks = Vec::new();
+
+    let connect = with_timeout(
+        WebSocketClient::builder(url)
+            .compat_mode(compat_mode)
+            .build(),
+    )
+    .await;
+
+    let (client, driver) = match connect {
+        Ok(connected) => connected,
+        Err(e) => {
+            checks.push(Check::new("WebSocket connect", &target, Err(e)));
+            return checks;
+        }
+    };
+
+    checks.push(Check::pass("WebSocket connect", &target, "connected"));
+
+    let driver = tokio::spawn(driver.run());
+
+    match with_timeout(client.status()).await {
+        Ok(status) => {
+            let network = status.node_info.network;
+            let version = status.node_info.version;
+
+            let chain = if network == *chain_id {
+                Ok(format!("reports chain {network}"))
+            } else {
+                Err(format!("reports chain {network} instead of {chain_id}"))
+            };
+
+            checks.push(Check::new("Status query", &target, chain));
+
+            let compat = match CompatMode::from_version(version.clone()) {
+                Ok(mode) if mode == compat_mode => Ok(format!("node runs {version}")),
+                Ok(mode) => Err(format!(
+                    "node runs {version}, expected comet_version = \"{}\"",
+                    mode.to_string().trim_start_matches('v')
+                )),
+                Err(e) => Err(e.to_string()),
+            };
+
+            checks.push(Check::new("Compat mode", &target, compat));
+
+            let height = status.sync_info.latest_block_height;
+            let block = with_timeout(client.block(height))
+                .await
+                .map(|block| format!("fetched block {}", block.block.header.height));
+
+            checks.push(Check::new("Block fetch", &target, block));
+        }
+        Err(e) => checks.push(Check::new("Status query", &target, Err(e))),
+    }
+
+    let _ = client.close();
+    let _ = driver.await;
+
+    checks
+}
+
+/// Check that the database can be opened and written to, without leaving any trace
+async fn check_database(path: &Path) -> Check {
+    let result = async {
+        let pool = db::connect(path).await?;
+
+        let mut tx = pool.begin().await?;
+        sqlx::query("CREATE TABLE chainpulse_doctor (id INTEGER)")
+            .execute(&mut *tx)
+            .await?;
+        tx.rollback().await?;
+
+        pool.close().await;
+
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>("writable".to_string())
+    }
+    .await;
+
+    Check::new("Database", &path.display().to_string(), result)
+}
+
+/// Check that the metrics server will be able to listen on its port
+fn check_port(port: u16) -> Check {
+    let result = std::net::TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port)))
+        .map(|_| "available".to_string());
+
+    Check::new("Metrics port", &port.to_string(), result)
+}
+
+async fn with_timeout<T, E: Display>(
+    f: impl Future<Output = std::result::Result<T, E>>,
+) -> std::result::Result<T, String> {
+    match time::timeout(TIMEOUT, f).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err(format!("timeout after {TIMEOUT:?}")),
+    }
+}
+
+/// Format the checks as a table, with one row per check
+fn table(checks: &[Check]) -> String {
+    let rows = checks
+        .iter()
+        .map(|check| {
+            let (status, details) = match &check.result {
+                Ok(details) => ("PASS", details.as_str()),
+                Err(details) => ("FAIL", details.as_str()),
+            };
+
+            [check.name, check.target.as_str(), status, details]
+        })
+        .collect::<Vec<_>>();
+
+    let header = ["CHECK", "TARGET", "RESULT", "DETAILS"];
+
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    std::iter::once(header)
+        .chain(rows)
+        .map(|row| {
+            let line = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+
+            format!("{}\n", line.trim_end())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table() {
+        let checks = [
+            Check::pass("Database", "chainpulse.db", "writable"),
+            Check::new("Metrics port", "3000", Err("address in use")),
+        ];
+
+        assert_eq!(
+            table(&checks),
+            "CHECK         TARGET         RESULT  DETAILS\n\
+             Database      chainpulse.db  PASS    writable\n\
+             Metrics port  3000           FAIL    address in use\n"
+        );
+    }
+}
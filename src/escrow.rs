@@ -0,0 +1,174 @@
+//! Monitoring of the balances of the escrow accounts of transfer channels.
+//!
+//! Tokens sent over a channel are locked in its escrow account until they come back,
+//! so an unexpected drop of an escrow balance is an early warning of a bridge accounting bug or exploit.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use ibc_proto::cosmos::{
+    bank::v1beta1::{QueryAllBalancesRequest, QueryAllBalancesResponse},
+    base::query::v1beta1::PageRequest,
+};
+use tendermint::{
+    chain,
+    crypto::{default::Sha256, Sha256 as _},
+};
+use tendermint_rpc::WebSocketClient;
+use tokio::time;
+use tracing::{error, warn};
+
+use crate::{
+    config::{Chains, Endpoint, Escrow},
+    metrics::Metrics,
+    query::abci_query,
+    Result,
+};
+
+const TRANSFER_PORT: &str = "transfer";
+
+/// Version of ICS-20 used to derive the escrow addresses
+const ICS20_VERSION: &str = "ics20-1";
+
+/// Maximum number of balances fetched per query
+const PAGE_LIMIT: u64 = 500;
+
+/// The address of the escrow account of a channel, following ADR-028,
+/// ie. the first 20 bytes of `sha256("ics20-1\0{port}/{channel}")`
+pub fn address(prefix: &str, port: &str, channel: &str) -> String {
+    let preimage = format!("{ICS20_VERSION}\0{port}/{channel}");
+    let hash = Sha256::digest(preimage.as_bytes());
+
+    subtle_encoding::bech32::encode(prefix, &hash[..20])
+}
+
+/// Periodically query the balances of the escrow accounts of the configured channels
+pub async fn run(chains: Chains, config: Escrow, metrics: Metrics) {
+    let chains = chains
+        .endpoints
+        .into_iter()
+        .filter(|(chain_id, endpoint)| {
+            if !endpoint.escrow_channels.is_empty() && endpoint.bech32_prefix.is_none() {
+                warn!(
+                    "Not monitoring the escrow accounts of {chain_id}: `bech32_prefix` is not set"
+                );
+            }
+
+            endpoint.bech32_prefix.is_some() && !endpoint.escrow_channels.is_empty()
+        })
+        .collect::<Vec<_>>();
+
+    // The denominations last seen in each escrow account, whose balance is reported
+    // as zero once they disappear from it, as the bank module omits empty balances
+    let mut seen = BTreeMap::<(chain::Id, String), BTreeSet<String>>::new();
+
+    loop {
+        for (chain_id, endpoint) in &chains {
+            if let Err(e) = update(chain_id, endpoint, &metrics, &mut seen).await {
+                error!("Failed to query the escrow balances on {chain_id}: {e}");
+            }
+        }
+
+        time::sleep(config.interval()).await;
+    }
+}
+
+async fn update(
+    chain_id: &chain::Id,
+    endpoint: &Endpoint,
+    metrics: &Metrics,
+    seen: &mut BTreeMap<(chain::Id, String), BTreeSet<String>>,
+) -> Result<()> {
+    let prefix = endpoint.bech32_prefix.as_deref().unwrap_or_default();
+
+    let (client, driver) = WebSocketClient::builder(endpoint.url.clone())
+        .compat_mode(endpoint.comet_version)
+        .build()
+        .await?;
+
+    let driver = tokio::spawn(driver.run());
+
+    let mut result = Ok(());
+
+    for channel in &endpoint.escrow_channels {
+        let address = address(prefix, TRANSFER_PORT, channel);
+
+        let balances = match balances(&client, &address).await {
+            Ok(balances) => balances,
+            Err(e) => {
+                result = Err(e);
+                continue;
+            }
+        };
+
+        let previous = seen
+            .insert(
+                (chain_id.clone(), channel.clone()),
+                balances.keys().cloned().collect(),
+            )
+            .unwrap_or_default();
+
+        for denom in previous.iter().filter(|d| !balances.contains_key(*d)) {
+            metrics.ibc_escrow_balance(chain_id, channel, denom, 0.0);
+        }
+
+        for (denom, amount) in &balances {
+            metrics.ibc_escrow_balance(chain_id, channel, denom, *amount);
+        }
+    }
+
+    let _ = client.close();
+    let _ = driver.await;
+
+    result
+}
+
+/// Query all the balances of an account via ABCI, following the pagination
+async fn balances(client: &WebSocketClient, address: &str) -> Result<BTreeMap<String, f64>> {
+    let mut balances = BTreeMap::new();
+    let mut key = Vec::new();
+
+    loop {
+        let request = QueryAllBalancesRequest {
+            address: address.to_string(),
+            pagination: Some(PageRequest {
+                key,
+                limit: PAGE_LIMIT,
+                ..Default::default()
+            }),
+            resolve_denom: false,
+        };
+
+        let response: QueryAllBalancesResponse =
+            abci_query(client, "/cosmos.bank.v1beta1.Query/AllBalances", request).await?;
+
+        for coin in response.balances {
+            if let Ok(amount) = coin.amount.parse::<f64>() {
+                balances.insert(coin.denom, amount);
+            }
+        }
+
+        match response.pagination {
+            Some(page) if !page.next_key.is_empty() => key = page.next_key,
+            _ => break,
+        }
+    }
+
+    Ok(balances)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address() {
+        assert_eq!(
+            address("cosmos", "transfer", "channel-141"),
+            "cosmos1x54ltnyg88k0ejmk8ytwrhd3ltm84xehrnlslf"
+        );
+        assert_eq!(
+            address("osmo", "transfer", "channel-0"),
+            "osmo1a53udazy8ayufvy0s434pfwjcedzqv347h34au"
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! Export of the recorded packets as CSV, streamed row by row so that large extracts
+//! can be downloaded without loading them in memory.
+//!
+//! The rows are sent in chunks over a bounded channel, so that reading from the database
+//! waits for the client to consume what was already sent.
+
+use std::io;
+
+use futures::{channel::mpsc, SinkExt, TryStreamExt};
+use sqlx::SqlitePool;
+
+use crate::{db::PacketExportRow, Result};
+
+/// Number of chunks buffered between the database and the client
+pub const BUFFERED_CHUNKS: usize = 16;
+
+/// Size above which the buffered rows are sent as a chunk
+const CHUNK_SIZE: usize = 16 * 1024;
+
+const HEADER: [&str; 15] = [
+    "chain",
+    "height",
+    "tx_hash",
+    "block_time",
+    "created_at",
+    "msg_type_url",
+    "src_port",
+    "src_channel",
+    "dst_port",
+    "dst_channel",
+    "sequence",
+    "signer",
+    "effected",
+    "effected_signer",
+    "memo",
+];
+
+pub type Chunk = std::result::Result<String, io::Error>;
+
+fn fields(row: &PacketExportRow) -> [String; 15] {
+    [
+        row.chain.clone(),
+        row.height.to_string(),
+        row.tx_hash.clone(),
+        row.block_time.clone(),
+        row.created_at.clone(),
+        row.msg_type_url.clone(),
+        row.src_port.clone(),
+        row.src_channel.clone(),
+        row.dst_port.clone(),
+        row.dst_channel.clone(),
+        row.sequence.to_string(),
+        row.signer.clone().unwrap_or_default(),
+        row.effected.to_string(),
+        row.effected_signer.clone().unwrap_or_default(),
+        row.memo.clone(),
+    ]
+}
+
+/// Stream the packets of a chain, or of all chains, recorded between two dates as CSV chunks,
+/// keeping only the ones which are `visible`. Stops early if the receiver is dropped.
+pub async fn packets_csv(
+    pool: &SqlitePool,
+    chain: Option<&str>,
+    from: Option<&str>,
+    to: Option<&str>,
+    visible: impl Fn(&PacketExportRow) -> bool,
+    mut sender: mpsc::Sender<Chunk>,
+) -> Result<()> {
+    let query = r#"
+        SELECT txs.chain, txs.height, txs.hash AS tx_hash, packets.block_time, packets.created_at,
+            packets.msg_type_url, packets.src_port, packets.src_channel,
+            packets.dst_port, packets.dst_channel, packets.sequence,
+            packets.signer, packets.effected, packets.effected_signer, txs.memo
+        FROM packets JOIN txs ON packets.tx_id = txs.id
+        WHERE   (?1 IS NULL OR txs.chain = ?1)
+            AND (?2 IS NULL OR packets.block_time >= datetime(?2))
+            AND (?3 IS NULL OR packets.block_time < datetime(?3))
+        ORDER BY packets.id
+    "#;
+
+    let mut rows = sqlx::query_as::<_, PacketExportRow>(query)
+        .bind(chain)
+        .bind(from)
+        .bind(to)
+        .fetch(pool);
+
+    let mut chunk = csv_line(&HEADER);
+
+    loop {
+        let row = match rows.try_next().await {
+            Ok(row) => row,
+            Err(e) => {
+                // Abort the response, so that the client does not mistake it for a complete extract
+                let _ = sender.send(Err(io::Error::other("database error"))).await;
+                return Err(e.into());
+            }
+        };
+
+        let Some(row) = row else {
+            break;
+        };
+
+        if !visible(&row) {
+            continue;
+        }
+
+        chunk.push_str(&csv_line(&fields(&row)));
+
+        if chunk.len() >= CHUNK_SIZE && sender.send(Ok(std::mem::take(&mut chunk))).await.is_err() {
+            return Ok(());
+        }
+    }
+
+    if !chunk.is_empty() {
+        let _ = sender.send(Ok(chunk)).await;
+    }
+
+    Ok(())
+}
+
+/// Format a CSV line, quoting the fields which contain a separator, a quote or a line break
+fn csv_line<S: AsRef<str>>(fields: &[S]) -> String {
+    let mut line = fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+
+            if field.contains(|c: char| matches!(c, ',' | '"' | '\n' | '\r')) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+
+    line.push_str("\r\n");
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_line() {
+        assert_eq!(csv_line(&["a", "b", ""]), "a,b,\r\n");
+        assert_eq!(
+            csv_line(&["relayed by \"hermes\", v1.7", "line\nbreak"]),
+            "\"relayed by \"\"hermes\"\", v1.7\",\"line\nbreak\"\r\n"
+        );
+    }
+
+    #[test]
+    fn test_header() {
+        assert_eq!(
+            csv_line(&HEADER),
+            "chain,height,tx_hash,block_time,created_at,msg_type_url,src_port,src_channel,dst_port,\
+             dst_channel,sequence,signer,effected,effected_signer,memo\r\n"
+        );
+    }
+}
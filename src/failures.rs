@@ -0,0 +1,86 @@
+//! Transfers which failed on their destination chain, as reported by their error acknowledgements.
+//!
+//! Such failures happen at the application level, eg. because the receiver is blocked
+//! or the memo of the transfer is invalid, and thus never show in the queues of the channels.
+
+use sqlx::SqlitePool;
+use tendermint::chain;
+
+use ibc_proto::ibc::core::channel::v1::Packet;
+
+use crate::{
+    api::{self, Scope},
+    db::{TransferFailureRow, TxRow},
+    transfer::TransferData,
+    Result,
+};
+
+/// Record the failure of a transfer packet, one row per token it carried
+pub async fn record(
+    pool: &SqlitePool,
+    chain_id: &chain::Id,
+    tx_row: &TxRow,
+    packet: &Packet,
+    data: &TransferData,
+    error: &str,
+) -> Result<()> {
+    let query = r#"
+        INSERT OR IGNORE INTO transfer_failures
+            (chain, src_channel, dst_channel, sequence, denom, amount,
+            sender, receiver, error, tx_hash, created_at)
+        VALUES
+            (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, datetime('now'))
+    "#;
+
+    for token in &data.tokens {
+        sqlx::query(query)
+            .bind(chain_id.as_str())
+            .bind(&packet.source_channel)
+            .bind(&packet.destination_channel)
+            .bind(packet.sequence as i64)
+            .bind(&token.denom)
+            .bind(&token.amount)
+            .bind(&data.sender)
+            .bind(&data.receiver)
+            .bind(error)
+            .bind(&tx_row.hash)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Fetch the most recent failures within the given scope, optionally only for the given chain and channel
+pub async fn recent(
+    pool: &SqlitePool,
+    scope: Scope<'_>,
+    chain: Option<&str>,
+    channel: Option<&str>,
+    limit: Option<u32>,
+) -> Result<Vec<TransferFailureRow>> {
+    let query = format!(
+        r#"
+        SELECT * FROM transfer_failures
+        WHERE   (?1 IS NULL OR chain = ?1)
+            AND (?2 IS NULL OR src_channel = ?2 OR dst_channel = ?2)
+            AND {}
+        ORDER BY id DESC
+        LIMIT ?3
+        "#,
+        Scope::filter(4, "chain", &["src_channel", "dst_channel"])
+    );
+
+    let limit = api::limit(limit);
+
+    let rows = sqlx::query_as(&query)
+        .bind(chain)
+        .bind(channel)
+        .bind(limit)
+        .bind(scope.chains()?)
+        .bind(scope.channels()?)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows)
+}
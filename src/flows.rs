@@ -0,0 +1,207 @@
+//! Net flow of tokens through each channel, ie. the amount of tokens received over the channel
+//! minus the amount sent over it, over sliding windows.
+//!
+//! Large one-way flows, eg. a channel being drained of a token, often precede incident reports.
+
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tokio::time;
+use tracing::error;
+
+use crate::{config::NetFlow, metrics::Metrics, transfer::Token, Result};
+
+/// How tokens moved over a channel of a monitored chain
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// Tokens sent from the chain over the channel
+    Send,
+
+    /// Tokens received by the chain over the channel
+    Recv,
+
+    /// Tokens refunded to their sender after the transfer failed or timed out
+    Refund,
+}
+
+impl Kind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Send => "send",
+            Self::Recv => "recv",
+            Self::Refund => "refund",
+        }
+    }
+}
+
+/// A transfer of tokens over a channel of a monitored chain
+#[derive(Clone, Debug)]
+pub struct Flow<'a> {
+    pub kind: Kind,
+    pub channel: &'a str,
+    pub counterparty_channel: &'a str,
+    pub sequence: u64,
+}
+
+/// Record the tokens moved by a transfer, with their denomination on the monitored chain.
+/// The time of the block of the transfer can be in any format understood by SQLite's `datetime`.
+pub async fn record(
+    pool: &SqlitePool,
+    chain_id: &chain::Id,
+    flow: &Flow<'_>,
+    tokens: &[Token],
+    block_time: &str,
+) -> Result<()> {
+    let query = r#"
+        INSERT OR IGNORE INTO transfer_flows
+            (chain, channel, counterparty_channel, kind, sequence, denom, amount, block_time, created_at)
+        VALUES
+            (?, ?, ?, ?, ?, ?, ?, datetime(?), datetime('now'))
+    "#;
+
+    for token in tokens {
+        sqlx::query(query)
+            .bind(chain_id.as_str())
+            .bind(flow.channel)
+            .bind(flow.counterparty_channel)
+            .bind(flow.kind.as_str())
+            .bind(flow.sequence as i64)
+            .bind(&token.denom)
+            .bind(&token.amount)
+            .bind(block_time)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Periodically compute the net flow of tokens through each channel over the configured windows
+pub async fn run(pool: SqlitePool, metrics: Metrics, config: NetFlow) {
+    loop {
+        if let Err(e) = update(&pool, &metrics, &config.windows).await {
+            error!("Failed to compute net flows: {e}");
+        }
+
+        time::sleep(config.interval()).await;
+    }
+}
+
+async fn update(pool: &SqlitePool, metrics: &Metrics, windows: &[u64]) -> Result<()> {
+    let mut flows = Vec::new();
+
+    for window in windows {
+        let rows = net_flows(pool, *window).await?;
+
+        for (chain, channel, counterparty_channel, denom, net) in rows {
+            let Ok(chain_id) = chain::Id::try_from(chain) else {
+                continue;
+            };
+
+            let window = window_label(*window);
+            flows.push((chain_id, channel, counterparty_channel, denom, window, net));
+        }
+    }
+
+    // Only reset the gauges once all windows have been computed,
+    // so that channels without transfers in a window are removed.
+    metrics.reset_ibc_transfer_net_flow();
+
+    for (chain_id, channel, counterparty_channel, denom, window, net) in flows {
+        metrics.ibc_transfer_net_flow(
+            &chain_id,
+            &channel,
+            &counterparty_channel,
+            &denom,
+            &window,
+            net,
+        );
+    }
+
+    Ok(())
+}
+
+/// The net flow of each denomination through each channel, over the blocks of the last `window` seconds
+async fn net_flows(
+    pool: &SqlitePool,
+    window: u64,
+) -> Result<Vec<(String, String, String, String, f64)>> {
+    let query = r#"
+        SELECT chain, channel, counterparty_channel, denom,
+            SUM(CASE kind WHEN 'send' THEN -CAST(amount AS REAL) ELSE CAST(amount AS REAL) END)
+        FROM transfer_flows
+        WHERE block_time >= datetime('now', ?)
+        GROUP BY chain, channel, counterparty_channel, denom
+    "#;
+
+    let rows = sqlx::query_as(query)
+        .bind(format!("-{window} seconds"))
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows)
+}
+
+/// The label of a window, in the largest unit which divides it, eg. `24h` or `90m`
+fn window_label(secs: u64) -> String {
+    match secs {
+        0 => "0s".to_string(),
+        s if s % 86400 == 0 && s >= 7 * 86400 => format!("{}d", s / 86400),
+        s if s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{s}s"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_label() {
+        assert_eq!(window_label(3600), "1h");
+        assert_eq!(window_label(86400), "24h");
+        assert_eq!(window_label(7 * 86400), "7d");
+        assert_eq!(window_label(5400), "90m");
+        assert_eq!(window_label(90), "90s");
+    }
+
+    #[tokio::test]
+    async fn test_net_flows_by_block_time() {
+        let pool = crate::db::connect_in_memory().await.unwrap();
+        crate::db::setup(&pool).await.unwrap();
+
+        let chain_id = chain::Id::try_from("osmosis-1").unwrap();
+        let token = |amount: &str| Token {
+            denom: "uosmo".to_string(),
+            amount: amount.to_string(),
+        };
+
+        let flow = |kind, sequence| Flow {
+            kind,
+            channel: "channel-0",
+            counterparty_channel: "channel-141",
+            sequence,
+        };
+
+        record(
+            &pool,
+            &chain_id,
+            &flow(Kind::Send, 1),
+            &[token("100")],
+            "now",
+        )
+        .await
+        .unwrap();
+
+        // Recorded now, but from a block replayed long after it was produced
+        let old = "2020-01-01T00:00:00Z";
+        record(&pool, &chain_id, &flow(Kind::Recv, 2), &[token("50")], old)
+            .await
+            .unwrap();
+
+        let flows = net_flows(&pool, 3600).await.unwrap();
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[0].3, "uosmo");
+        assert_eq!(flows[0].4, -100.0);
+    }
+}
@@ -0,0 +1,195 @@
+//! Health scoring of the endpoints of chains for which multiple URLs are configured.
+//!
+//! Each endpoint is periodically probed for its latest height, and scored from its latency,
+//! its lag behind the most advanced endpoint of the chain, and whether the probe succeeded.
+//! The collector then prefers the healthiest endpoint whenever it (re)connects.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::future;
+use tendermint::chain;
+use tendermint_rpc::{client::CompatMode, Client, Url, WebSocketClient, WebSocketClientUrl};
+use tokio::time::{self, Instant};
+use tracing::{debug, warn};
+
+use crate::{config::Endpoint, metrics::Metrics, Result};
+
+const PROBE_INTERVAL: Duration = Duration::from_secs(60);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Weight of the latest probe in the score of an endpoint
+const SMOOTHING: f64 = 0.3;
+
+/// The health scores of the endpoints of each chain, between 0 (unhealthy) and 1 (healthy)
+#[derive(Clone, Debug, Default)]
+pub struct Health {
+    scores: Arc<Mutex<BTreeMap<chain::Id, BTreeMap<WebSocketClientUrl, f64>>>>,
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The healthiest of the given endpoints, other than the excluded one.
+    /// Endpoints which have not been scored yet are considered healthy,
+    /// and ties are broken by the order of the endpoints in the configuration.
+    pub fn best(
+        &self,
+        chain_id: &chain::Id,
+        urls: &[WebSocketClientUrl],
+        exclude: Option<&WebSocketClientUrl>,
+    ) -> WebSocketClientUrl {
+        let scores = self.scores.lock().unwrap();
+        let scores = scores.get(chain_id);
+
+        let score = |url: &WebSocketClientUrl| {
+            scores
+                .and_then(|scores| scores.get(url))
+                .copied()
+                .unwrap_or(1.0)
+        };
+
+        let mut best = None;
+
+        for url in urls.iter().filter(|url| Some(*url) != exclude) {
+            match best {
+                Some((_, best_score)) if best_score >= score(url) => (),
+                _ => best = Some((url, score(url))),
+            }
+        }
+
+        best.map(|(url, _)| url)
+            .or(urls.first())
+            .cloned()
+            .expect("at least one endpoint")
+    }
+
+    fn update(&self, chain_id: &chain::Id, url: &WebSocketClientUrl, probe: f64) -> f64 {
+        let mut scores = self.scores.lock().unwrap();
+        let score = scores
+            .entry(chain_id.clone())
+            .or_default()
+            .entry(url.clone())
+            .or_insert(probe);
+
+        *score = SMOOTHING * probe + (1.0 - SMOOTHING) * *score;
+        *score
+    }
+}
+
+/// The host of an endpoint, used to label the metrics pertaining to it
+pub fn endpoint_host(url: &WebSocketClientUrl) -> String {
+    Url::from(url.clone()).host().to_string()
+}
+
+/// Periodically probe the endpoints of a chain and update their health scores
+pub async fn run(chain_id: chain::Id, endpoint: Endpoint, health: Health, metrics: Metrics) {
+    let urls = endpoint.urls();
+
+    loop {
+        let probes =
+            future::join_all(urls.iter().map(|url| probe(endpoint.comet_version, url))).await;
+
+        let max_height = probes
+            .iter()
+            .filter_map(|probe| probe.as_ref().ok())
+            .map(|(height, _)| *height)
+            .max()
+            .unwrap_or(0);
+
+        for (url, probe) in urls.iter().zip(probes) {
+            let host = endpoint_host(url);
+
+            let probe_score = match probe {
+                Ok((height, latency)) => {
+                    let lag = max_height.saturating_sub(height);
+
+                    metrics.chainpulse_endpoint_latency(&chain_id, &host, latency);
+                    metrics.chainpulse_endpoint_height_lag(&chain_id, &host, lag);
+
+                    score(latency, lag)
+                }
+                Err(e) => {
+                    warn!("Failed to probe endpoint {host}: {e}");
+                    0.0
+                }
+            };
+
+            let score = health.update(&chain_id, url, probe_score);
+            metrics.chainpulse_endpoint_health(&chain_id, &host, score);
+
+            debug!("Endpoint {host} has a health score of {score:.2}");
+        }
+
+        time::sleep(PROBE_INTERVAL).await;
+    }
+}
+
+/// Fetch the latest height of an endpoint, along with how long it took
+async fn probe(compat_mode: CompatMode, url: &WebSocketClientUrl) -> Result<(u64, Duration)> {
+    let start = Instant::now();
+
+    let (client, driver) = WebSocketClient::builder(url.clone())
+        .compat_mode(compat_mode)
+        .build()
+        .await?;
+
+    let driver = tokio::spawn(driver.run());
+
+    let status = time::timeout(PROBE_TIMEOUT, client.status()).await;
+    let latency = start.elapsed();
+
+    let _ = client.close();
+    let _ = driver.await;
+
+    let status = status.map_err(|_| "timeout")??;
+
+    Ok((status.sync_info.latest_block_height.value(), latency))
+}
+
+/// The score of a single probe, between 0 and 1, which decreases with its latency
+/// and with the number of blocks it lags behind the most advanced endpoint
+fn score(latency: Duration, lag: u64) -> f64 {
+    1.0 / (1.0 + latency.as_secs_f64()) / (1.0 + lag as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> WebSocketClientUrl {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_score() {
+        assert_eq!(score(Duration::ZERO, 0), 1.0);
+        assert_eq!(score(Duration::from_secs(1), 0), 0.5);
+        assert_eq!(score(Duration::ZERO, 1), 0.5);
+        assert!(score(Duration::from_secs(1), 1) < score(Duration::from_millis(100), 1));
+    }
+
+    #[test]
+    fn test_best() {
+        let chain_id: chain::Id = "osmosis-1".parse().unwrap();
+        let (a, b) = (
+            url("wss://a.example/websocket"),
+            url("wss://b.example/websocket"),
+        );
+        let urls = [a.clone(), b.clone()];
+
+        let health = Health::new();
+        assert_eq!(health.best(&chain_id, &urls, None), a);
+        assert_eq!(health.best(&chain_id, &urls, Some(&a)), b);
+
+        health.update(&chain_id, &a, 0.1);
+        health.update(&chain_id, &b, 0.9);
+        assert_eq!(health.best(&chain_id, &urls, None), b);
+        assert_eq!(health.best(&chain_id, &urls, Some(&b)), a);
+    }
+}
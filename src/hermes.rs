@@ -0,0 +1,480 @@
+//! Integration with Hermes relayer instances, via their telemetry endpoint.
+//!
+//! The accounts of each instance are found in its `wallet_balance` metric, and the paths it serves
+//! in its `backlog_size` metric. The signers observed on-chain are then mapped to the instance they
+//! belong to, and the packets relayed by someone else on the paths an instance serves are counted as missed.
+//!
+//! The balances of the accounts and the backlogs of the paths are re-exported along with the on-chain view,
+//! ie. the backlog of each path as seen by Chain Pulse, as well as any other series selected in the configuration.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tokio::time;
+use tracing::{debug, error, warn};
+
+use crate::{config::Hermes, metrics::Metrics, Result};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A path served by a relayer instance, identified by the chain and channel the packets are sent from,
+/// and the chain they are sent to
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Path {
+    pub src_chain: String,
+    pub src_channel: String,
+    pub dst_chain: String,
+}
+
+/// A series selected to be re-exported, along with its chain, channel and counterparty labels, if any
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Selected {
+    pub series: String,
+    pub chain: String,
+    pub channel: String,
+    pub counterparty: String,
+}
+
+/// What a relayer instance reports about itself
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Instance {
+    /// The accounts of the instance, per chain
+    pub signers: BTreeSet<(String, String)>,
+
+    /// The paths served by the instance
+    pub paths: BTreeSet<Path>,
+
+    /// The balances of the accounts of the instance, per chain, account and denomination
+    pub balances: BTreeMap<(String, String, String), f64>,
+
+    /// The number of packets pending on each path served by the instance
+    pub backlogs: BTreeMap<Path, f64>,
+
+    /// The values of the selected series, summed over their other labels
+    pub selected: BTreeMap<Selected, f64>,
+}
+
+impl Instance {
+    /// Gather what the instance reports from its telemetry, along with the values of the given series
+    pub fn from_telemetry(samples: &[Sample], series: &[String]) -> Self {
+        let mut instance = Self::default();
+
+        for sample in samples {
+            match sample.name.as_str() {
+                "wallet_balance" => {
+                    if let (Some(chain), Some(account)) =
+                        (sample.label("chain"), sample.label("account"))
+                    {
+                        instance
+                            .signers
+                            .insert((chain.to_string(), account.to_string()));
+
+                        let denom = sample.label("denom").unwrap_or_default();
+                        instance.balances.insert(
+                            (chain.to_string(), account.to_string(), denom.to_string()),
+                            sample.value,
+                        );
+                    }
+                }
+                "backlog_size" => {
+                    if let (Some(chain), Some(channel), Some(counterparty)) = (
+                        sample.label("chain"),
+                        sample.label("channel"),
+                        sample.label("counterparty"),
+                    ) {
+                        let path = Path {
+                            src_chain: chain.to_string(),
+                            src_channel: channel.to_string(),
+                            dst_chain: counterparty.to_string(),
+                        };
+
+                        instance.paths.insert(path.clone());
+                        *instance.backlogs.entry(path).or_default() += sample.value;
+                    }
+                }
+                _ => {}
+            }
+
+            if series.contains(&sample.name) {
+                let label = |name| sample.label(name).unwrap_or_default().to_string();
+
+                let selected = Selected {
+                    series: sample.name.clone(),
+                    chain: label("chain"),
+                    channel: label("channel"),
+                    counterparty: label("counterparty"),
+                };
+
+                *instance.selected.entry(selected).or_default() += sample.value;
+            }
+        }
+
+        instance
+    }
+}
+
+/// Periodically fetch the telemetry of the configured Hermes instances, export the signers
+/// of each instance, and count the packets relayed by others on the paths they serve
+pub async fn run(instances: Vec<Hermes>, pool: SqlitePool, metrics: Metrics) {
+    // Only the packets relayed from now on are checked
+    let mut cursor = match last_packet_id(&pool).await {
+        Ok(id) => id,
+        Err(e) => {
+            error!("Failed to fetch the last packet, not checking missed packets: {e}");
+            i64::MAX
+        }
+    };
+
+    let mut known = BTreeMap::new();
+
+    loop {
+        for hermes in &instances {
+            match fetch(hermes).await {
+                Ok(instance) => {
+                    debug!(
+                        "Hermes instance {} has {} accounts and serves {} paths",
+                        hermes.name,
+                        instance.signers.len(),
+                        instance.paths.len()
+                    );
+
+                    metrics.chainpulse_hermes_up(&hermes.name, true);
+                    known.insert(hermes.name.clone(), instance);
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to fetch the telemetry of Hermes instance {}: {e}",
+                        hermes.name
+                    );
+                    metrics.chainpulse_hermes_up(&hermes.name, false);
+                }
+            }
+        }
+
+        metrics.reset_ibc_relayer_signer_info();
+        metrics.reset_ibc_relayer_telemetry();
+
+        for (name, instance) in &known {
+            for (chain, signer) in &instance.signers {
+                if let Ok(chain_id) = chain::Id::try_from(chain.as_str()) {
+                    metrics.ibc_relayer_signer_info(&chain_id, signer, name);
+                }
+            }
+
+            export_telemetry(&metrics, name, instance);
+        }
+
+        if let Err(e) = export_backlogs(&pool, &metrics, &known).await {
+            error!("Failed to compare the backlogs of the Hermes instances: {e}");
+        }
+
+        match check_missed(&pool, &metrics, &known, cursor).await {
+            Ok(last) => cursor = last,
+            Err(e) => error!("Failed to check for packets missed by Hermes: {e}"),
+        }
+
+        time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn fetch(hermes: &Hermes) -> Result<Instance> {
+    let body = reqwest::get(hermes.url.as_str())
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    Ok(Instance::from_telemetry(&parse(&body), &hermes.series))
+}
+
+/// Re-export the balances of the accounts, the backlogs and the selected series reported by an instance
+fn export_telemetry(metrics: &Metrics, name: &str, instance: &Instance) {
+    for ((chain, account, denom), balance) in &instance.balances {
+        if let Ok(chain_id) = chain::Id::try_from(chain.as_str()) {
+            metrics.ibc_relayer_wallet_balance(name, &chain_id, account, denom, *balance);
+        }
+    }
+
+    for (path, backlog) in &instance.backlogs {
+        if let Ok(chain_id) = chain::Id::try_from(path.src_chain.as_str()) {
+            metrics.ibc_relayer_backlog(
+                name,
+                &chain_id,
+                &path.src_channel,
+                &path.dst_chain,
+                "hermes",
+                *backlog,
+            );
+        }
+    }
+
+    for (selected, value) in &instance.selected {
+        metrics.ibc_relayer_telemetry(
+            name,
+            &selected.series,
+            &selected.chain,
+            &selected.channel,
+            &selected.counterparty,
+            *value,
+        );
+    }
+}
+
+/// Export the backlog of each path served by an instance as seen on-chain, ie. the packets sent
+/// on the path which were not relayed yet, next to the backlog reported by the instance
+async fn export_backlogs(
+    pool: &SqlitePool,
+    metrics: &Metrics,
+    instances: &BTreeMap<String, Instance>,
+) -> Result<()> {
+    let query = r#"
+        SELECT chain, src_channel, COUNT(*)
+        FROM sent_packets
+        WHERE resolved_at IS NULL
+        GROUP BY chain, src_channel
+    "#;
+
+    let rows: Vec<(String, String, i64)> = sqlx::query_as(query).fetch_all(pool).await?;
+    let pending: BTreeMap<_, _> = rows
+        .into_iter()
+        .map(|(chain, channel, count)| ((chain, channel), count))
+        .collect();
+
+    for (name, instance) in instances {
+        for path in instance.backlogs.keys() {
+            let Ok(chain_id) = chain::Id::try_from(path.src_chain.as_str()) else {
+                continue;
+            };
+
+            let count = pending
+                .get(&(path.src_chain.clone(), path.src_channel.clone()))
+                .copied()
+                .unwrap_or(0);
+
+            metrics.ibc_relayer_backlog(
+                name,
+                &chain_id,
+                &path.src_channel,
+                &path.dst_chain,
+                "chainpulse",
+                count as f64,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn last_packet_id(pool: &SqlitePool) -> Result<i64> {
+    let (id,): (i64,) = sqlx::query_as("SELECT COALESCE(MAX(id), 0) FROM packets")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(id)
+}
+
+/// Count the packets relayed since the cursor on paths served by an instance, by a signer which is not
+/// one of its accounts, returning the new cursor
+async fn check_missed(
+    pool: &SqlitePool,
+    metrics: &Metrics,
+    instances: &BTreeMap<String, Instance>,
+    cursor: i64,
+) -> Result<i64> {
+    let query = r#"
+        SELECT packets.id, txs.chain, packets.src_channel, packets.dst_channel,
+            packets.msg_type_url, packets.signer
+        FROM packets JOIN txs ON packets.tx_id = txs.id
+        WHERE packets.id > ? AND packets.effected
+        ORDER BY packets.id
+    "#;
+
+    let rows: Vec<(i64, String, String, String, String, Option<String>)> =
+        sqlx::query_as(query).bind(cursor).fetch_all(pool).await?;
+
+    let mut last = cursor;
+
+    for (id, chain, src_channel, dst_channel, type_url, signer) in rows {
+        last = id;
+
+        let signer = signer.unwrap_or_default();
+        let is_recv = type_url.ends_with("MsgRecvPacket");
+
+        for (name, instance) in instances {
+            let serves = instance.paths.iter().any(|path| {
+                path.src_channel == src_channel
+                    && if is_recv {
+                        path.dst_chain == chain
+                    } else {
+                        path.src_chain == chain
+                    }
+            });
+
+            if !serves || instance.signers.contains(&(chain.clone(), signer.clone())) {
+                continue;
+            }
+
+            if let Ok(chain_id) = chain::Id::try_from(chain.as_str()) {
+                metrics.ibc_relayer_missed_packets(&chain_id, &src_channel, &dst_channel, name);
+            }
+        }
+    }
+
+    Ok(last)
+}
+
+/// A sample of the Prometheus text exposition format
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+}
+
+impl Sample {
+    pub fn label(&self, name: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(label, _)| label == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse the samples of the Prometheus text exposition format, skipping the malformed lines
+pub fn parse(text: &str) -> Vec<Sample> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(parse_line)
+        .collect()
+}
+
+fn parse_line(line: &str) -> Option<Sample> {
+    let name_end = line.find(|c: char| c == '{' || c.is_whitespace())?;
+    let (name, mut rest) = line.split_at(name_end);
+
+    let mut labels = Vec::new();
+
+    if let Some(mut inner) = rest.strip_prefix('{') {
+        loop {
+            inner = inner.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+
+            if let Some(after) = inner.strip_prefix('}') {
+                rest = after;
+                break;
+            }
+
+            let (label, after) = inner.split_once('=')?;
+            let (value, after) = parse_quoted(after.trim_start())?;
+
+            labels.push((label.trim().to_string(), value));
+            inner = after;
+        }
+    }
+
+    let value = rest.split_whitespace().next()?.parse().ok()?;
+
+    Some(Sample {
+        name: name.to_string(),
+        labels,
+        value,
+    })
+}
+
+/// Parse a quoted label value, returning it unescaped along with the rest of the input
+fn parse_quoted(input: &str) -> Option<(String, &str)> {
+    let input = input.strip_prefix('"')?;
+
+    let mut value = String::new();
+    let mut chars = input.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                c => value.push(c),
+            },
+            '"' => return Some((value, &input[i + 1..])),
+            c => value.push(c),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TELEMETRY: &str = r#"
+# HELP wallet_balance The balance of each wallet Hermes uses per chain
+# TYPE wallet_balance gauge
+wallet_balance{account="osmo1hermes",chain="osmosis-1",denom="uosmo",otel_scope_name="hermes"} 1234.5
+wallet_balance{account="cosmos1hermes",chain="cosmoshub-4",denom="uatom"} 42
+# TYPE backlog_size gauge
+backlog_size{chain="osmosis-1",channel="channel-0",counterparty="cosmoshub-4",port="transfer"} 3
+backlog_size{chain="cosmoshub-4",channel="channel-141",counterparty="osmosis-1",port="transfer",} 0
+tx_latency_submitted_count 10
+"#;
+
+    #[test]
+    fn test_parse() {
+        let samples = parse(TELEMETRY);
+
+        assert_eq!(samples.len(), 5);
+        assert_eq!(samples[0].name, "wallet_balance");
+        assert_eq!(samples[0].label("account"), Some("osmo1hermes"));
+        assert_eq!(samples[0].label("otel_scope_name"), Some("hermes"));
+        assert_eq!(samples[0].value, 1234.5);
+        assert_eq!(samples[3].label("channel"), Some("channel-141"));
+        assert_eq!(samples[4].name, "tx_latency_submitted_count");
+        assert!(samples[4].labels.is_empty());
+        assert_eq!(samples[4].value, 10.0);
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        let samples = parse(r#"memo{text="a \"quoted\", value\n"} 1"#);
+
+        assert_eq!(samples[0].label("text"), Some("a \"quoted\", value\n"));
+    }
+
+    #[test]
+    fn test_instance() {
+        let instance = Instance::from_telemetry(&parse(TELEMETRY), &["backlog_size".to_string()]);
+
+        assert!(instance
+            .signers
+            .contains(&("osmosis-1".to_string(), "osmo1hermes".to_string())));
+        assert_eq!(instance.signers.len(), 2);
+        assert!(instance.paths.contains(&Path {
+            src_chain: "osmosis-1".to_string(),
+            src_channel: "channel-0".to_string(),
+            dst_chain: "cosmoshub-4".to_string(),
+        }));
+        assert_eq!(instance.paths.len(), 2);
+
+        assert_eq!(
+            instance.balances.get(&(
+                "osmosis-1".to_string(),
+                "osmo1hermes".to_string(),
+                "uosmo".to_string()
+            )),
+            Some(&1234.5)
+        );
+        assert_eq!(
+            instance.selected.get(&Selected {
+                series: "backlog_size".to_string(),
+                chain: "osmosis-1".to_string(),
+                channel: "channel-0".to_string(),
+                counterparty: "cosmoshub-4".to_string(),
+            }),
+            Some(&3.0)
+        );
+        assert_eq!(instance.selected.len(), 2);
+    }
+}
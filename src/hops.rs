@@ -0,0 +1,315 @@
+//! Reconstruction of the journey of a packet across chains, hop by hop, served at `/api/v1/trace`.
+//!
+//! A transfer received on a chain is forwarded when its forwarding info or packet-forward-middleware memo
+//! names a channel to forward it through, in which case the chain sends a new packet over that channel
+//! in the same tx. Such links between the packets received and sent are found in the events of the relay txs,
+//! and recorded in the `packet_hops` table, so that the journey can be followed from the chain the packet
+//! was sent from, through every monitored chain which forwarded it.
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tendermint::{abci, chain};
+
+use crate::{db::HopRelayRow, pending::SendPacket, transfer::TransferData, Result};
+
+/// Maximum number of hops followed, in case the links would form a cycle
+const MAX_HOPS: usize = 8;
+
+/// A packet received on a chain, and the packet the chain sent to forward it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    pub src_channel: String,
+    pub dst_channel: String,
+    pub sequence: u64,
+    pub next_src_channel: String,
+    pub next_dst_channel: String,
+    pub next_sequence: u64,
+}
+
+/// A hop of the journey of a packet
+#[derive(Clone, Debug, Serialize)]
+pub struct Hop {
+    pub src_chain: String,
+    pub src_channel: String,
+    pub dst_chain: Option<String>,
+    pub dst_channel: Option<String>,
+    pub sequence: i64,
+
+    /// When the packet was sent: the block time of the relay which was forwarded on the previous hop,
+    /// or when the packet was first seen if it was sent from a monitored chain
+    pub sent_at: Option<String>,
+
+    /// The relay of the packet to its destination chain, if seen
+    pub recv: Option<HopRelayRow>,
+
+    /// The relay of the acknowledgement or timeout of the packet back to its source chain, if seen
+    pub ack: Option<HopRelayRow>,
+}
+
+/// Find the packets forwarded in a tx, from its events, in which each `send_packet` event
+/// forwarding a packet follows the `recv_packet` event of that packet
+pub fn links(events: &[abci::Event]) -> Vec<Link> {
+    let mut links = Vec::new();
+
+    // The last packet received which is to be forwarded, along with the channel it is to be forwarded through
+    let mut pending = None;
+
+    for event in events {
+        if let Some(received) = SendPacket::from_recv_event(event) {
+            pending = TransferData::decode(&received.data)
+                .and_then(|data| data.next_hop())
+                .map(|hop| (received, hop));
+        } else if let Some(sent) = SendPacket::from_event(event) {
+            match pending.take() {
+                Some((received, hop))
+                    if hop.port_id == sent.src_port && hop.channel_id == sent.src_channel =>
+                {
+                    links.push(Link {
+                        src_channel: received.src_channel,
+                        dst_channel: received.dst_channel,
+                        sequence: received.sequence,
+                        next_src_channel: sent.src_channel,
+                        next_dst_channel: sent.dst_channel,
+                        next_sequence: sent.sequence,
+                    });
+                }
+                other => pending = other,
+            }
+        }
+    }
+
+    links
+}
+
+/// Record that a packet received on a chain was forwarded
+pub async fn record(pool: &SqlitePool, chain_id: &chain::Id, link: &Link) -> Result<()> {
+    let query = r#"
+        INSERT OR IGNORE INTO packet_hops
+            (chain, src_channel, dst_channel, sequence,
+            next_src_channel, next_dst_channel, next_sequence, created_at)
+        VALUES
+            (?, ?, ?, ?, ?, ?, ?, datetime('now'))
+    "#;
+
+    sqlx::query(query)
+        .bind(chain_id.as_str())
+        .bind(&link.src_channel)
+        .bind(&link.dst_channel)
+        .bind(link.sequence as i64)
+        .bind(&link.next_src_channel)
+        .bind(&link.next_dst_channel)
+        .bind(link.next_sequence as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Follow a packet sent from a chain over a channel, from hop to hop, as far as it was seen.
+/// Returns no hops if nothing is known about the packet.
+pub async fn trace(
+    pool: &SqlitePool,
+    src_chain: &str,
+    src_channel: &str,
+    sequence: i64,
+) -> Result<Vec<Hop>> {
+    let mut hops: Vec<Hop> = Vec::new();
+
+    let (mut src_chain, mut src_channel) = (src_chain.to_string(), src_channel.to_string());
+    let mut sequence = sequence;
+
+    while hops.len() < MAX_HOPS {
+        let counterparty: Option<(Option<String>, String)> = sqlx::query_as(
+            "SELECT counterparty_chain, counterparty_channel FROM channels WHERE chain = ? AND channel = ? LIMIT 1",
+        )
+        .bind(&src_chain)
+        .bind(&src_channel)
+        .fetch_optional(pool)
+        .await?;
+
+        let sent: Option<(String, String)> = sqlx::query_as(
+            "SELECT dst_channel, first_seen_at FROM sent_packets WHERE chain = ? AND src_channel = ? AND sequence = ? LIMIT 1",
+        )
+        .bind(&src_chain)
+        .bind(&src_channel)
+        .bind(sequence)
+        .fetch_optional(pool)
+        .await?;
+
+        let (counterparty_chain, counterparty_channel) = counterparty.unzip();
+        let counterparty_chain = counterparty_chain.flatten();
+        let dst_channel = sent
+            .as_ref()
+            .map(|(dst_channel, _)| dst_channel.clone())
+            .or(counterparty_channel);
+
+        // A forwarded packet is sent in the tx relaying the packet of the previous hop
+        let sent_at = match hops.last() {
+            Some(previous) => previous.recv.as_ref().map(|recv| recv.block_time.clone()),
+            None => sent.map(|(_, first_seen_at)| first_seen_at),
+        };
+
+        let recv = relay(
+            pool,
+            counterparty_chain.as_deref(),
+            &src_channel,
+            dst_channel.as_deref(),
+            sequence,
+            &["%MsgRecvPacket"],
+            sent_at.as_deref(),
+        )
+        .await?;
+
+        let ack = relay(
+            pool,
+            Some(&src_chain),
+            &src_channel,
+            dst_channel.as_deref(),
+            sequence,
+            &["%MsgAcknowledgement", "%MsgTimeout"],
+            sent_at.as_deref(),
+        )
+        .await?;
+
+        if hops.is_empty() && sent_at.is_none() && recv.is_none() && ack.is_none() {
+            break;
+        }
+
+        let dst_chain = recv
+            .as_ref()
+            .and_then(|recv| recv.chain.clone())
+            .or(counterparty_chain);
+        let dst_channel = recv
+            .as_ref()
+            .map(|recv| recv.dst_channel.clone())
+            .or(dst_channel);
+
+        let next = match (&dst_chain, &dst_channel) {
+            (Some(dst_chain), Some(dst_channel)) => {
+                sqlx::query_as::<_, (String, i64)>(
+                    "SELECT next_src_channel, next_sequence FROM packet_hops WHERE chain = ? AND dst_channel = ? AND sequence = ? LIMIT 1",
+                )
+                .bind(dst_chain)
+                .bind(dst_channel)
+                .bind(sequence)
+                .fetch_optional(pool)
+                .await?
+            }
+            _ => None,
+        };
+
+        hops.push(Hop {
+            src_chain: src_chain.clone(),
+            src_channel: src_channel.clone(),
+            dst_chain: dst_chain.clone(),
+            dst_channel,
+            sequence,
+            sent_at,
+            recv,
+            ack,
+        });
+
+        match (dst_chain, next) {
+            (Some(dst_chain), Some((next_src_channel, next_sequence))) => {
+                (src_chain, src_channel, sequence) = (dst_chain, next_src_channel, next_sequence);
+            }
+            _ => break,
+        }
+    }
+
+    Ok(hops)
+}
+
+/// Find the relay of a packet with one of the given message types, preferring the one which was effected
+async fn relay(
+    pool: &SqlitePool,
+    chain: Option<&str>,
+    src_channel: &str,
+    dst_channel: Option<&str>,
+    sequence: i64,
+    type_urls: &[&str],
+    sent_at: Option<&str>,
+) -> Result<Option<HopRelayRow>> {
+    let query = r#"
+        SELECT packets.chain, packets.dst_channel, packets.msg_type_url, txs.hash AS tx_hash, txs.height,
+            packets.signer, packets.effected, COALESCE(packets.block_time, packets.created_at) AS block_time,
+            (julianday(packets.block_time) - julianday(?1)) * 86400.0 AS latency_seconds
+        FROM packets JOIN txs ON packets.tx_id = txs.id
+        WHERE   packets.src_channel = ?2
+            AND packets.sequence = ?3
+            AND (?4 IS NULL OR packets.dst_channel = ?4)
+            AND (?5 IS NULL OR packets.chain = ?5)
+            AND (packets.msg_type_url LIKE ?6 OR packets.msg_type_url LIKE ?7)
+        ORDER BY packets.effected DESC, packets.id
+        LIMIT 1
+    "#;
+
+    let row = sqlx::query_as(query)
+        .bind(sent_at)
+        .bind(src_channel)
+        .bind(sequence)
+        .bind(dst_channel)
+        .bind(chain)
+        .bind(type_urls[0])
+        .bind(type_urls.last().copied())
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: &str, channel: (&str, &str), sequence: u64, memo: &str) -> abci::Event {
+        let data = serde_json::json!({
+            "denom": "uatom",
+            "amount": "1",
+            "sender": "cosmos1...",
+            "receiver": "osmo1...",
+            "memo": memo,
+        });
+
+        abci::Event::new(
+            kind,
+            [
+                ("packet_sequence", sequence.to_string()),
+                ("packet_src_channel", channel.0.to_string()),
+                ("packet_src_port", "transfer".to_string()),
+                ("packet_dst_channel", channel.1.to_string()),
+                ("packet_dst_port", "transfer".to_string()),
+                ("packet_data", data.to_string()),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_links() {
+        let forward =
+            r#"{"forward": {"receiver": "juno1...", "port": "transfer", "channel": "channel-42"}}"#;
+
+        let events = [
+            event("recv_packet", ("channel-141", "channel-0"), 7, forward),
+            event("send_packet", ("channel-42", "channel-1"), 3, ""),
+            // Not forwarded, so the packet sent next is unrelated
+            event("recv_packet", ("channel-141", "channel-0"), 8, ""),
+            event("send_packet", ("channel-42", "channel-1"), 4, ""),
+            // Forwarded over another channel than the one the packet is sent over
+            event("recv_packet", ("channel-141", "channel-0"), 9, forward),
+            event("send_packet", ("channel-5", "channel-6"), 5, ""),
+        ];
+
+        assert_eq!(
+            links(&events),
+            [Link {
+                src_channel: "channel-141".to_string(),
+                dst_channel: "channel-0".to_string(),
+                sequence: 7,
+                next_src_channel: "channel-42".to_string(),
+                next_dst_channel: "channel-1".to_string(),
+                next_sequence: 3,
+            }]
+        );
+    }
+}
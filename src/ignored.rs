@@ -0,0 +1,216 @@
+//! Sampled examples of the messages ignored by the collectors, ie. which could not be decoded into one of the
+//! supported messages (`other`) or which do not relay any packet (`not_relevant`), to discover the new message
+//! types worth supporting.
+//!
+//! Every message would be too many rows, so only the first `samples_per_hour` messages of each type
+//! are recorded per chain and per hour, in the `ignored_msgs` table, which is pruned after `retention` seconds.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tokio::time;
+use tracing::{error, info};
+
+use crate::{
+    api::{self, Scope},
+    config::IgnoredMsgs,
+    db::IgnoredMsgRow,
+    trace::IgnoredMsg,
+    Result,
+};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// The number of messages of each type sampled on each chain over the current hour, shared by the collectors
+#[derive(Clone, Debug, Default)]
+pub struct Sampler {
+    per_hour: u32,
+    counts: Arc<Mutex<Counts>>,
+}
+
+/// The counts of the current hour, cleared when the hour rolls over,
+/// so that only the types seen during the current hour are kept
+#[derive(Debug, Default)]
+struct Counts {
+    hour: u64,
+    by_type: HashMap<(String, String), u32>,
+}
+
+impl Sampler {
+    pub fn new(per_hour: u32) -> Self {
+        Self {
+            per_hour,
+            ..Self::default()
+        }
+    }
+
+    /// Whether a message of the given type should be sampled during the given hour
+    pub fn sample(&self, chain_id: &str, type_url: &str, hour: u64) -> bool {
+        let mut counts = self.counts.lock().unwrap();
+
+        if counts.hour != hour {
+            counts.hour = hour;
+            counts.by_type.clear();
+        }
+
+        let count = counts
+            .by_type
+            .entry((chain_id.to_string(), type_url.to_string()))
+            .or_default();
+
+        if *count >= self.per_hour {
+            return false;
+        }
+
+        *count += 1;
+        true
+    }
+
+    /// Record the samples among the messages ignored in a block
+    pub async fn record(
+        &self,
+        pool: &SqlitePool,
+        chain_id: &chain::Id,
+        height: u64,
+        msgs: &[IgnoredMsg],
+    ) -> Result<()> {
+        if self.per_hour == 0 {
+            return Ok(());
+        }
+
+        let hour = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() / 3600);
+
+        for msg in msgs {
+            if !self.sample(chain_id.as_str(), &msg.type_url, hour) {
+                continue;
+            }
+
+            let query = r#"
+                INSERT INTO ignored_msgs (chain, height, tx_hash, type_url, reason, created_at)
+                VALUES (?, ?, ?, ?, ?, datetime('now'))
+            "#;
+
+            sqlx::query(query)
+                .bind(chain_id.as_str())
+                .bind(height as i64)
+                .bind(&msg.tx_hash)
+                .bind(&msg.type_url)
+                .bind(msg.reason)
+                .execute(pool)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Fetch the sampled messages within the given scope, most recent first, optionally only the ones of the given chain or type.
+/// The messages do not relay any packet, so the scope only restricts their chain.
+pub async fn samples(
+    pool: &SqlitePool,
+    scope: Scope<'_>,
+    chain: Option<&str>,
+    type_url: Option<&str>,
+    limit: Option<u32>,
+) -> Result<Vec<IgnoredMsgRow>> {
+    let query = format!(
+        r#"
+        SELECT chain, height, tx_hash, type_url, reason, created_at
+        FROM ignored_msgs
+        WHERE   (?1 IS NULL OR chain = ?1)
+            AND (?2 IS NULL OR type_url = ?2)
+            AND {}
+        ORDER BY id DESC
+        LIMIT ?3
+        "#,
+        Scope::filter(4, "chain", &[])
+    );
+
+    let limit = api::limit(limit);
+
+    let rows = sqlx::query_as(&query)
+        .bind(chain)
+        .bind(type_url)
+        .bind(limit)
+        .bind(scope.chains()?)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows)
+}
+
+/// Periodically prune the samples older than the retention period
+pub async fn run(pool: SqlitePool, config: IgnoredMsgs) {
+    let mut interval = time::interval(PRUNE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let result = sqlx::query("DELETE FROM ignored_msgs WHERE created_at < datetime('now', ?)")
+            .bind(format!("-{} seconds", config.retention))
+            .execute(&pool)
+            .await;
+
+        match result {
+            Ok(result) if result.rows_affected() > 0 => {
+                info!("Pruned {} ignored message samples", result.rows_affected());
+            }
+            Ok(_) => {}
+            Err(e) => error!("Failed to prune the ignored message samples: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        let sampler = Sampler::new(2);
+
+        assert!(sampler.sample(
+            "osmosis-1",
+            "/osmosis.gamm.v1beta1.MsgSwapExactAmountIn",
+            10
+        ));
+        assert!(sampler.sample(
+            "osmosis-1",
+            "/osmosis.gamm.v1beta1.MsgSwapExactAmountIn",
+            10
+        ));
+        assert!(!sampler.sample(
+            "osmosis-1",
+            "/osmosis.gamm.v1beta1.MsgSwapExactAmountIn",
+            10
+        ));
+
+        // Sampled separately per type and chain
+        assert!(sampler.sample("osmosis-1", "/cosmos.bank.v1beta1.MsgSend", 10));
+        assert!(sampler.sample(
+            "cosmoshub-4",
+            "/osmosis.gamm.v1beta1.MsgSwapExactAmountIn",
+            10
+        ));
+
+        // And again on the next hour
+        assert!(sampler.sample(
+            "osmosis-1",
+            "/osmosis.gamm.v1beta1.MsgSwapExactAmountIn",
+            11
+        ));
+
+        // Only the types seen during the current hour are kept
+        assert_eq!(sampler.counts.lock().unwrap().by_type.len(), 1);
+
+        let disabled = Sampler::new(0);
+        assert!(!disabled.sample("osmosis-1", "/cosmos.bank.v1beta1.MsgSend", 10));
+    }
+}
@@ -0,0 +1,236 @@
+//! Import of the txs of a chain exported from an indexer, eg. Numia's BigQuery datasets or a SubQuery project,
+//! to backfill the history from before chainpulse was deployed, through the same processing as the collectors.
+//!
+//! The export is a JSON lines file with one tx per line, ordered by height and then by index in the block:
+//!
+//! ```json
+//! {"height": 12345678, "time": "2023-10-02T13:37:00Z", "index": 0, "tx": "CpMBCpABChwvY29zbW9z..."}
+//! ```
+//!
+//! where `tx` is the raw tx encoded in base64, `index` defaults to the order of the lines,
+//! and `proposer`, the address of the proposer of the block, is optional.
+//! The results of the txs are not imported, so only the packets relayed to the chain are backfilled,
+//! along with the txs relaying them, which is enough for the frontrun and relayer market share history.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::PathBuf,
+};
+
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use tendermint::{block::Height, chain, Time};
+use tracing::info;
+
+use crate::{
+    collect, config::Config, db, metrics::Metrics, msg::Decoders, plugins::Plugins,
+    trace::BlockTrace, Result,
+};
+
+/// Options of the `import` command
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The chain the txs were exported from
+    pub chain: chain::Id,
+
+    /// The export to import, or `-` to read it from stdin
+    pub file: PathBuf,
+
+    /// Only import the heights below this one, defaults to the first height recorded for the chain
+    pub until: Option<u64>,
+}
+
+/// A tx as exported from the indexer
+#[derive(Clone, Debug, Deserialize)]
+pub struct IndexedTx {
+    pub height: u64,
+
+    /// The time of the block, in RFC 3339 format
+    pub time: String,
+
+    /// The index of the tx in the block
+    #[serde(default)]
+    pub index: Option<usize>,
+
+    /// The raw tx, encoded in base64
+    pub tx: String,
+
+    /// The address of the proposer of the block
+    #[serde(default)]
+    pub proposer: String,
+}
+
+/// The txs of a block, in order
+struct Block {
+    height: u64,
+    time: String,
+    proposer: String,
+    txs: Vec<(usize, Vec<u8>)>,
+}
+
+/// Import the txs of the export into the database, one block after the other
+pub async fn run(config: &Config, options: Options) -> Result<()> {
+    let pool = db::connect(&config.database.path).await?;
+    db::setup(&pool).await?;
+
+    let until = match options.until {
+        Some(until) => Some(until),
+        None => first_height(&pool, &options.chain).await?,
+    };
+
+    match until {
+        Some(until) => info!(
+            "Importing the txs of {} below height {until}",
+            options.chain
+        ),
+        None => info!("Importing the txs of {}", options.chain),
+    }
+
+    let reader: Box<dyn BufRead> = if options.file.as_os_str() == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(&options.file)?))
+    };
+
+    let (metrics, _registries) = Metrics::new(config.memo);
+    let decoders = Decoders::new();
+    let plugins = Plugins::load(&config.plugins)?;
+
+    let (mut blocks, mut txs) = (0, 0);
+    let mut block: Option<Block> = None;
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let indexed: IndexedTx = serde_json::from_str(&line)
+            .map_err(|e| format!("invalid tx on line {}: {e}", line_number + 1))?;
+
+        if until.map_or(false, |until| indexed.height >= until) {
+            continue;
+        }
+
+        let raw = subtle_encoding::base64::decode(&indexed.tx)
+            .map_err(|e| format!("invalid tx on line {}: {e}", line_number + 1))?;
+
+        let current = block.as_ref().map(|block| block.height);
+
+        if current.map_or(false, |height| height > indexed.height) {
+            return Err(format!(
+                "the txs must be ordered by height, found height {} after {} on line {}",
+                indexed.height,
+                current.unwrap_or_default(),
+                line_number + 1
+            )
+            .into());
+        }
+
+        if current == Some(indexed.height) {
+            if let Some(block) = &mut block {
+                let index = indexed.index.unwrap_or(block.txs.len());
+                block.txs.push((index, raw));
+            }
+
+            continue;
+        }
+
+        if let Some(previous) = block.take() {
+            txs += previous.txs.len();
+            import(
+                &pool,
+                config,
+                &options.chain,
+                previous,
+                &metrics,
+                &decoders,
+                &plugins,
+            )
+            .await?;
+            blocks += 1;
+        }
+
+        block = Some(Block {
+            height: indexed.height,
+            time: indexed.time,
+            proposer: indexed.proposer,
+            txs: vec![(indexed.index.unwrap_or(0), raw)],
+        });
+    }
+
+    if let Some(last) = block {
+        txs += last.txs.len();
+        import(
+            &pool,
+            config,
+            &options.chain,
+            last,
+            &metrics,
+            &decoders,
+            &plugins,
+        )
+        .await?;
+        blocks += 1;
+    }
+
+    pool.close().await;
+
+    info!(
+        "Imported {txs} txs from {blocks} blocks of {}",
+        options.chain
+    );
+
+    Ok(())
+}
+
+/// The first height recorded for the chain, if any
+async fn first_height(pool: &SqlitePool, chain_id: &chain::Id) -> Result<Option<u64>> {
+    let (height,): (Option<i64>,) = sqlx::query_as("SELECT MIN(height) FROM txs WHERE chain = ?")
+        .bind(chain_id.as_str())
+        .fetch_one(pool)
+        .await?;
+
+    Ok(height.map(|height| height as u64))
+}
+
+async fn import(
+    pool: &SqlitePool,
+    config: &Config,
+    chain_id: &chain::Id,
+    mut block: Block,
+    metrics: &Metrics,
+    decoders: &Decoders,
+    plugins: &Plugins,
+) -> Result<()> {
+    let height = Height::try_from(block.height)
+        .map_err(|e| format!("invalid height {}: {e}", block.height))?;
+
+    let time = Time::parse_from_rfc3339(&block.time)
+        .map_err(|e| format!("invalid time of block {}: {e}", block.height))?;
+
+    info!("Importing block {height} of {chain_id}");
+
+    block.txs.sort_by_key(|(index, _)| *index);
+    let txs = block.txs.into_iter().map(|(_, raw)| raw).collect();
+
+    collect::process_txs(
+        pool,
+        chain_id,
+        height,
+        time,
+        &block.proposer,
+        txs,
+        config.memo,
+        metrics,
+        decoders,
+        plugins,
+        &mut BlockTrace::new(block.height),
+    )
+    .await
+    .map_err(|e| format!("failed to import block {height} of {chain_id}: {e}"))?;
+
+    Ok(())
+}
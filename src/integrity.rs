@@ -0,0 +1,125 @@
+//! Periodic checks of the integrity of the database, to detect corruption early, eg. after a power loss,
+//! instead of when a query eventually fails on a damaged page.
+//!
+//! Both the structure of the database, with `PRAGMA integrity_check` (or `quick_check`), and its foreign keys,
+//! with `PRAGMA foreign_key_check`, are checked. The checks run in a read transaction, which does not block
+//! the writes of the collectors, and their results are exported as metrics and served at `/health`.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tendermint::Time;
+use tokio::time;
+use tracing::{error, info};
+
+use crate::{config::IntegrityCheck, metrics::Metrics, Result};
+
+/// Maximum number of problems reported by the integrity check
+const MAX_ERRORS: u32 = 100;
+
+/// The result of the last check of the integrity of the database
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    /// When the check completed
+    pub checked_at: String,
+
+    /// The problems found in the structure of the database, at most 100
+    pub integrity_errors: Vec<String>,
+
+    /// The number of rows referencing a row which does not exist
+    pub foreign_key_errors: usize,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.integrity_errors.is_empty() && self.foreign_key_errors == 0
+    }
+}
+
+/// The result of the last check of the integrity of the database, shared with the server
+#[derive(Clone, Debug, Default)]
+pub struct Integrity(Arc<Mutex<Option<Report>>>);
+
+impl Integrity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The result of the last check, if the database was checked already
+    pub fn last(&self) -> Option<Report> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn set(&self, report: Report) {
+        *self.0.lock().unwrap() = Some(report);
+    }
+}
+
+/// Periodically check the integrity of the database, starting right away
+pub async fn run(pool: SqlitePool, config: IntegrityCheck, metrics: Metrics, integrity: Integrity) {
+    loop {
+        match check(&pool, config.quick).await {
+            Ok(report) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs());
+
+                metrics.chainpulse_db_integrity("integrity", report.integrity_errors.len(), now);
+                metrics.chainpulse_db_integrity("foreign_key", report.foreign_key_errors, now);
+
+                if report.is_ok() {
+                    info!("The integrity of the database was checked, no problems found");
+                } else {
+                    error!(
+                        "The database is corrupted: {} problems found, {} rows with broken foreign keys",
+                        report.integrity_errors.len(),
+                        report.foreign_key_errors
+                    );
+
+                    for problem in &report.integrity_errors {
+                        error!("    {problem}");
+                    }
+                }
+
+                integrity.set(report);
+            }
+            Err(e) => error!("Failed to check the integrity of the database: {e}"),
+        }
+
+        time::sleep(config.interval()).await;
+    }
+}
+
+async fn check(pool: &SqlitePool, quick: bool) -> Result<Report> {
+    let pragma = if quick {
+        "quick_check"
+    } else {
+        "integrity_check"
+    };
+
+    let rows: Vec<(String,)> = sqlx::query_as(&format!("PRAGMA {pragma}({MAX_ERRORS})"))
+        .fetch_all(pool)
+        .await?;
+
+    // A single `ok` row is returned when no problems are found
+    let integrity_errors = rows
+        .into_iter()
+        .map(|(problem,)| problem)
+        .filter(|problem| problem != "ok")
+        .collect();
+
+    let foreign_key_errors = sqlx::query("PRAGMA foreign_key_check")
+        .fetch_all(pool)
+        .await?
+        .len();
+
+    Ok(Report {
+        checked_at: Time::now().to_rfc3339(),
+        integrity_errors,
+        foreign_key_errors,
+    })
+}
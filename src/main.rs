@@ -1,21 +1,82 @@
+pub mod aggregates;
+pub mod alerts;
+pub mod anomalies;
+pub mod api;
+pub mod bench;
+pub mod bursts;
+pub mod capture;
+pub mod channels;
+pub mod checkpoint;
+pub mod clear;
+pub mod clearing;
 pub mod collect;
+pub mod commitments;
 pub mod config;
+pub mod dashboard;
 pub mod db;
+pub mod dead_letters;
+pub mod discovery;
+pub mod doctor;
+pub mod escrow;
+pub mod export;
+pub mod failures;
+pub mod flows;
+pub mod health;
+pub mod hermes;
+pub mod hops;
+pub mod ignored;
+pub mod import;
+pub mod integrity;
+pub mod memo;
 pub mod metrics;
 pub mod msg;
+pub mod pending;
+pub mod plugins;
 pub mod populate;
+pub mod quality;
+pub mod query;
+pub mod queue;
+pub mod ratios;
+pub mod reclassify;
+pub mod relabel;
+pub mod replay;
+pub mod retention;
+pub mod roles;
+pub mod search;
+pub mod sequences;
+pub mod server;
+pub mod shutdown;
+pub mod signer;
+pub mod simulate;
+pub mod slo;
+pub mod software;
+pub mod startup;
+pub mod state;
 pub mod status;
+pub mod stuck_value;
+pub mod top;
+pub mod trace;
+pub mod transfer;
 
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use clap::Parser;
 use futures::future;
 use sqlx::SqlitePool;
 use tendermint::chain;
-use tracing::{error, error_span, info, Instrument};
+use tracing::{error, error_span, info, warn, Instrument};
 
-use crate::config::{Config, Endpoint};
+use crate::config::{CircuitBreaker, Config, Endpoint, Keepalive, Memo};
+use crate::health::Health;
+use crate::ignored::Sampler;
+use crate::integrity::Integrity;
 use crate::metrics::Metrics;
+use crate::msg::Decoders;
+use crate::plugins::Plugins;
+use crate::queue::RetryQueue;
+use crate::relabel::Relabeler;
+use crate::server::AppState;
+use crate::state::Collectors;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
@@ -23,43 +84,526 @@ pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + S
 #[derive(clap::Parser)]
 struct App {
     /// Path to the configuration file
-    #[clap(short, long = "config", default_value = "chainpulse.toml")]
+    #[clap(
+        short,
+        long = "config",
+        default_value = "chainpulse.toml",
+        global = true
+    )]
     config: PathBuf,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Print Prometheus alerting rules for the configured chains
+    GenAlertRules,
+
+    /// Print a Grafana dashboard for the configured chains
+    GenDashboard,
+
+    /// Check that the configuration is valid, without connecting to anything
+    CheckConfig,
+
+    /// Check that the configured endpoints, database and metrics port are usable
+    Doctor,
+
+    /// Show a live leaderboard of the relayers of each chain, from the database
+    Top {
+        /// Only consider the packets relayed over the last given minutes
+        #[clap(long, default_value_t = 15)]
+        minutes: u64,
+
+        /// How often to refresh the leaderboard, in seconds
+        #[clap(long, default_value_t = 5)]
+        interval: u64,
+
+        /// How many relayers to show per chain
+        #[clap(long, default_value_t = 10)]
+        limit: usize,
+
+        /// Only show the given chain
+        #[clap(long)]
+        chain: Option<String>,
+    },
+
+    /// Feed synthetic blocks through the processing pipeline and report its throughput and latency
+    Bench {
+        /// Number of blocks to generate
+        #[clap(long, default_value_t = 1000)]
+        blocks: u64,
+
+        /// Number of blocks to generate per second, or as many as possible if 0
+        #[clap(long, default_value_t = 0.0)]
+        rate: f64,
+
+        /// Number of txs per block
+        #[clap(long, default_value_t = 10)]
+        txs: usize,
+
+        /// Number of packets relayed per tx
+        #[clap(long, default_value_t = 5)]
+        packets: usize,
+
+        /// Ratio of the packets which are frontrun, ie. relayed again by another relayer
+        #[clap(long, default_value_t = 0.2)]
+        frontruns: f64,
+
+        /// Where to create the database of the benchmark, which must not exist and is removed once done,
+        /// defaults to a file in the temporary directory
+        #[clap(long)]
+        database: Option<PathBuf>,
+    },
+
+    /// Serve a simulated chain over WebSocket, producing blocks of IBC txs, for testing
+    Simulate {
+        /// The port to listen on
+        #[clap(long, default_value_t = 26657)]
+        port: u16,
+
+        /// The chain reported by the simulated node
+        #[clap(long, default_value = "simulated-1")]
+        chain_id: String,
+
+        /// The CometBFT version reported by the simulated node, eg. 0.34.27 or 0.37.2
+        #[clap(long, default_value = "0.37.2")]
+        version: String,
+
+        /// How often to produce a block, in milliseconds
+        #[clap(long, default_value_t = 1000)]
+        interval: u64,
+
+        /// Number of txs per block
+        #[clap(long, default_value_t = 10)]
+        txs: usize,
+
+        /// Number of packets relayed per tx
+        #[clap(long, default_value_t = 5)]
+        packets: usize,
+
+        /// Ratio of the packets which are frontrun, ie. relayed again by another relayer
+        #[clap(long, default_value_t = 0.2)]
+        frontruns: f64,
+
+        /// Close the connections after every given number of blocks, or never if 0
+        #[clap(long, default_value_t = 0)]
+        disconnect_every: u64,
+    },
+
+    /// Feed the events and blocks of a capture file through the processing pipeline
+    Replay {
+        /// The capture file to replay, as written by a chain with the `capture` field set
+        file: PathBuf,
+
+        /// Where to create the database the replayed blocks are recorded to, which must not exist
+        #[clap(long)]
+        database: PathBuf,
+
+        /// Print the resulting metrics once done
+        #[clap(long)]
+        print_metrics: bool,
+    },
+
+    /// Backfill the database with the txs of a chain exported from an indexer, as JSON lines ordered by height
+    Import {
+        /// The chain the txs were exported from
+        #[clap(long)]
+        chain_id: String,
+
+        /// The export to import, or `-` to read it from stdin
+        file: PathBuf,
+
+        /// Only import the heights below this one, defaults to the first height recorded for the chain
+        #[clap(long)]
+        until: Option<u64>,
+    },
+
+    /// Classify the recorded packets again from scratch and diff the result against the stored classification
+    Reclassify {
+        /// Only check the given chain
+        #[clap(long)]
+        chain: Option<String>,
+
+        /// How many differences to print per chain
+        #[clap(long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Process again the messages which failed to be processed and were recorded as dead letters
+    ReplayDeadLetters {
+        /// Only replay the dead letters of the given chain
+        #[clap(long)]
+        chain: Option<String>,
+    },
+
+    /// Print the Hermes commands clearing the packets stuck on each channel, from the database
+    ClearCommands {
+        /// Only consider the packets sent from the given chain
+        #[clap(long)]
+        chain: Option<String>,
+
+        /// Only consider the packets sent or received over the given channel
+        #[clap(long)]
+        channel: Option<String>,
+
+        /// Only consider the packets sent at least this many seconds ago,
+        /// defaults to the `native_min_age` of the stuck packets
+        #[clap(long)]
+        min_age: Option<u64>,
+    },
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
     setup_tracing();
-    setup_ctrlc_handler();
 
     let app = App::parse();
     let config = Config::load(&app.config)?;
 
-    let (metrics, registry) = Metrics::new();
+    if let Some(command) = app.command {
+        return match command {
+            Command::GenAlertRules => alerts::print_rules(&config),
+            Command::GenDashboard => dashboard::print_dashboard(&config),
+            Command::CheckConfig => doctor::check_config(&config),
+            Command::Doctor => doctor::run(&config).await,
+            Command::Top {
+                minutes,
+                interval,
+                limit,
+                chain,
+            } => {
+                let options = top::Options {
+                    minutes,
+                    interval: Duration::from_secs(interval.max(1)),
+                    limit,
+                    chain,
+                };
 
-    if config.metrics.enabled {
+                top::run(&config, options).await
+            }
+            Command::Bench {
+                blocks,
+                rate,
+                txs,
+                packets,
+                frontruns,
+                database,
+            } => {
+                let options = bench::Options {
+                    blocks,
+                    rate,
+                    txs,
+                    packets,
+                    frontruns,
+                    database,
+                };
+
+                bench::run(&config, options).await
+            }
+            Command::Simulate {
+                port,
+                chain_id,
+                version,
+                interval,
+                txs,
+                packets,
+                frontruns,
+                disconnect_every,
+            } => {
+                let options = simulate::Options {
+                    port,
+                    chain_id: chain::Id::try_from(chain_id)?,
+                    version,
+                    interval: Duration::from_millis(interval.max(1)),
+                    txs,
+                    packets,
+                    frontruns,
+                    disconnect_every,
+                };
+
+                simulate::run(options).await
+            }
+            Command::Replay {
+                file,
+                database,
+                print_metrics,
+            } => {
+                let options = replay::Options {
+                    file,
+                    database,
+                    print_metrics,
+                };
+
+                replay::run(&config, options).await
+            }
+            Command::Import {
+                chain_id,
+                file,
+                until,
+            } => {
+                let options = import::Options {
+                    chain: chain::Id::try_from(chain_id)?,
+                    file,
+                    until,
+                };
+
+                import::run(&config, options).await
+            }
+            Command::Reclassify { chain, limit } => {
+                let options = reclassify::Options { chain, limit };
+
+                reclassify::run(&config, options).await
+            }
+            Command::ReplayDeadLetters { chain } => {
+                let options = dead_letters::Options { chain };
+
+                dead_letters::run(&config, options).await
+            }
+            Command::ClearCommands {
+                chain,
+                channel,
+                min_age,
+            } => {
+                let options = clear::Options {
+                    chain,
+                    channel,
+                    min_age,
+                };
+
+                clear::run(&config, options).await
+            }
+        };
+    }
+
+    let (metrics, registries) = Metrics::new(config.memo);
+    let collectors = Collectors::new();
+    let decoders = Decoders::new();
+    let plugins = Plugins::load(&config.plugins)?;
+    let health = Health::new();
+    let integrity = Integrity::new();
+    let queue = RetryQueue::open(&config.retry_queue)?;
+    let sampler = Sampler::new(config.ignored_msgs.samples_per_hour);
+
+    let startup = startup::State::new();
+
+    // Nothing may touch the database before it is set up, so everything else starts afterwards
+    let pool = startup::open_database(&config.database, config.startup, &startup).await?;
+
+    if config.ignored_msgs.samples_per_hour > 0 {
         tokio::spawn(
-            metrics::run(config.metrics.port, registry).instrument(error_span!("metrics")),
+            ignored::run(pool.clone(), config.ignored_msgs).instrument(error_span!("ignored")),
         );
     }
 
+    if config.database.in_memory {
+        info!(
+            "Keeping the database in memory, retaining the last {:?}",
+            config.database.window()
+        );
+
+        tokio::spawn(
+            retention::run(
+                pool.clone(),
+                config.database.window(),
+                config.database.keep.clone(),
+            )
+            .instrument(error_span!("retention")),
+        );
+    }
+
+    if let Some(checkpoint) = &config.database.checkpoint {
+        if config.database.in_memory {
+            warn!("Ignoring the checkpoint settings of the database, which is kept in memory");
+        } else {
+            info!(
+                "Checkpointing the write-ahead log of the database every {:?}",
+                checkpoint.interval()
+            );
+
+            tokio::spawn(
+                checkpoint::run(pool.clone(), checkpoint.interval())
+                    .instrument(error_span!("checkpoint")),
+            );
+        }
+    }
+
+    if let Some(integrity_check) = &config.database.integrity_check {
+        info!(
+            "Checking the integrity of the database every {:?}",
+            integrity_check.interval()
+        );
+
+        tokio::spawn(
+            integrity::run(
+                pool.clone(),
+                integrity_check.clone(),
+                metrics.clone(),
+                integrity.clone(),
+            )
+            .instrument(error_span!("integrity")),
+        );
+    }
+
+    if config.metrics.enabled {
+        let state = AppState {
+            registries,
+            relabeler: Relabeler::new(config.metrics.relabel.clone()),
+            chains: Arc::new(config.chains.endpoints.keys().cloned().collect()),
+            collectors: collectors.clone(),
+            pool: pool.clone(),
+            admin: config.admin.clone(),
+            snapshot_dir: config
+                .database
+                .checkpoint
+                .as_ref()
+                .filter(|_| !config.database.in_memory)
+                .and_then(|checkpoint| checkpoint.snapshot_dir.clone()),
+            api_keys: Arc::new(config.api_keys.clone()),
+            integrity,
+            startup: startup.clone(),
+            stuck_min_age: config.stuck_packets.native_min_age,
+        };
+
+        let (port, ready) = (config.metrics.port, startup.clone());
+        let wait_for_populate = config.startup.wait_for_populate;
+
+        let task = async move {
+            if wait_for_populate {
+                info!("Waiting for the metrics to be populated before serving them");
+                ready.ready().await;
+            }
+
+            server::run(port, state).await
+        };
+
+        tokio::spawn(task.instrument(error_span!("metrics")));
+    }
+
     if config.metrics.stuck_packets {
         info!("Monitoring packets stuck on IBC channels");
 
         tokio::spawn(
-            status::run(config.chains.clone(), metrics.clone()).instrument(error_span!("status")),
+            status::run(
+                config.chains.clone(),
+                config.stuck_packets,
+                pool.clone(),
+                metrics.clone(),
+            )
+            .instrument(error_span!("status")),
         );
     }
 
-    let pool = db::connect(&config.database.path).await?;
-    db::setup(&pool).await;
+    if config.metrics.enabled {
+        tokio::spawn(ratios::run(pool.clone(), metrics.clone()).instrument(error_span!("ratios")));
+        tokio::spawn(
+            clearing::run(pool.clone(), metrics.clone()).instrument(error_span!("clearing")),
+        );
+        tokio::spawn(
+            quality::run(config.chains.clone(), pool.clone(), metrics.clone())
+                .instrument(error_span!("quality")),
+        );
+        tokio::spawn(
+            anomalies::run(pool.clone(), metrics.clone(), config.anomalies)
+                .instrument(error_span!("anomalies")),
+        );
+        tokio::spawn(
+            bursts::run(pool.clone(), metrics.clone(), config.frontrun_bursts)
+                .instrument(error_span!("bursts")),
+        );
+        tokio::spawn(
+            roles::run(pool.clone(), metrics.clone(), config.relayer_roles)
+                .instrument(error_span!("roles")),
+        );
+        tokio::spawn(
+            software::run(pool.clone(), metrics.clone()).instrument(error_span!("software")),
+        );
+        tokio::spawn(
+            escrow::run(config.chains.clone(), config.escrow, metrics.clone())
+                .instrument(error_span!("escrow")),
+        );
+        tokio::spawn(
+            stuck_value::run(
+                pool.clone(),
+                metrics.clone(),
+                config.stuck_value.clone(),
+                config.stuck_packets.native_min_age,
+            )
+            .instrument(error_span!("stuck_value")),
+        );
+        tokio::spawn(
+            discovery::run(
+                config.chains.clone(),
+                config.channel_discovery,
+                pool.clone(),
+                metrics.clone(),
+            )
+            .instrument(error_span!("discovery")),
+        );
+        tokio::spawn(
+            flows::run(pool.clone(), metrics.clone(), config.net_flow.clone())
+                .instrument(error_span!("flows")),
+        );
+        tokio::spawn(
+            sequences::run(config.chains.clone(), pool.clone(), metrics.clone())
+                .instrument(error_span!("sequences")),
+        );
+        tokio::spawn(
+            pending::run(pool.clone(), metrics.clone(), config.operators.clone())
+                .instrument(error_span!("pending")),
+        );
+    }
+
+    if config.metrics.enabled && !config.slos.is_empty() {
+        tokio::spawn(
+            slo::run(pool.clone(), metrics.clone(), config.slos.clone())
+                .instrument(error_span!("slo")),
+        );
+    }
+
+    if config.metrics.enabled && !config.hermes.is_empty() {
+        info!(
+            "Mapping signers to {} Hermes instances",
+            config.hermes.len()
+        );
+
+        tokio::spawn(
+            hermes::run(config.hermes.clone(), pool.clone(), metrics.clone())
+                .instrument(error_span!("hermes")),
+        );
+    }
 
     if config.metrics.enabled && config.metrics.populate_on_start {
         info!("Populating metrics on start");
+        startup.set(startup::Phase::Populating);
+
+        // Populate from the packets recorded so far, concurrently with the collection of new ones
+        let cutoff = populate::cutoff(&pool).await?;
+        let mut tasks = Vec::new();
 
         for chain_id in config.chains.endpoints.keys() {
-            populate::run(chain_id, &pool, &metrics).await?;
+            let span = error_span!("populate", chain = %chain_id);
+            let (chain_id, pool, metrics) = (chain_id.clone(), pool.clone(), metrics.clone());
+
+            let task = async move {
+                if let Err(e) = populate::run(&chain_id, &pool, &metrics, cutoff).await {
+                    error!("Failed to populate metrics: {e}");
+                }
+            };
+
+            tasks.push(tokio::spawn(task.instrument(span)));
         }
+
+        let startup = startup.clone();
+
+        tokio::spawn(async move {
+            future::join_all(tasks).await;
+            info!("Metrics populated, chainpulse is ready");
+            startup.set(startup::Phase::Ready);
+        });
+    } else {
+        startup.set(startup::Phase::Ready);
     }
 
     let handles = config
@@ -69,24 +613,70 @@ async fn main() -> Result<()> {
         .map(|(chain_id, endpoint)| {
             metrics.chainpulse_chains();
 
+            if !endpoint.backup_urls.is_empty() {
+                let span = error_span!("health", chain = %chain_id);
+                let task = health::run(
+                    chain_id.clone(),
+                    endpoint.clone(),
+                    health.clone(),
+                    metrics.clone(),
+                );
+                tokio::spawn(task.instrument(span));
+            }
+
             let span = error_span!("collect", chain = %chain_id);
-            let task = collect(chain_id, endpoint, pool.clone(), metrics.clone()).instrument(span);
+            let task = collect(
+                chain_id,
+                endpoint,
+                config.circuit_breaker,
+                config.keepalive,
+                config.memo,
+                pool.clone(),
+                metrics.clone(),
+                collectors.clone(),
+                decoders.clone(),
+                plugins.clone(),
+                queue.clone(),
+                health.clone(),
+                sampler.clone(),
+            )
+            .instrument(span);
             tokio::spawn(task)
         })
         .collect::<Vec<_>>();
 
-    future::join_all(handles).await;
+    tokio::select! {
+        _ = future::join_all(handles) => {}
+        signal = shutdown::signal() => {
+            info!("{} received, shutting down", signal?);
+        }
+    }
+
+    // Wait for the queries in flight to complete, and let SQLite checkpoint the write-ahead log on close
+    pool.close().await;
 
     Ok(())
 }
 
-async fn collect(chain_id: chain::Id, endpoint: Endpoint, pool: SqlitePool, metrics: Metrics) {
+#[allow(clippy::too_many_arguments)]
+async fn collect(
+    chain_id: chain::Id,
+    endpoint: Endpoint,
+    breaker: CircuitBreaker,
+    keepalive: Keepalive,
+    memo: Memo,
+    pool: SqlitePool,
+    metrics: Metrics,
+    collectors: Collectors,
+    decoders: Decoders,
+    plugins: Plugins,
+    queue: RetryQueue,
+    health: Health,
+    sampler: Sampler,
+) {
     let result = collect::run(
-        chain_id,
-        endpoint.comet_version,
-        endpoint.url,
-        pool,
-        metrics,
+        chain_id, endpoint, breaker, keepalive, memo, pool, metrics, collectors, decoders, plugins,
+        queue, health, sampler,
     )
     .await;
 
@@ -105,16 +695,11 @@ fn setup_tracing() {
         .or_else(|_| EnvFilter::try_new("chainpulse=info"))
         .unwrap();
 
-    tracing_subscriber::registry()
-        .with(filter_layer)
-        .with(fmt_layer)
-        .init();
-}
+    // The filter only applies to the logs, so that the console still receives the runtime events
+    let registry = tracing_subscriber::registry().with(fmt_layer.with_filter(filter_layer));
+
+    #[cfg(feature = "console")]
+    let registry = registry.with(console_subscriber::spawn());
 
-fn setup_ctrlc_handler() {
-    ctrlc::set_handler(move || {
-        info!("Ctrl-C received, shutting down");
-        std::process::exit(0);
-    })
-    .expect("Error setting Ctrl-C handler");
+    registry.init();
 }
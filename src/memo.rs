@@ -0,0 +1,54 @@
+//! Sanitization of the memos of txs, which are stored in the database and used as metric labels.
+//!
+//! Memos are arbitrary user input, and may contain newlines, control characters
+//! or megabytes of JSON, which break the Prometheus exposition format and bloat the database.
+
+use crate::config::Memo;
+
+/// Sanitize a memo according to the configuration, replacing control characters
+/// which are whitespace (eg. newlines) with a space, stripping the other ones,
+/// and truncating it to the maximum length, in characters.
+pub fn sanitize(memo: &str, config: &Memo) -> String {
+    let chars = memo.chars().filter_map(|c| {
+        if !config.strip_control_chars || !c.is_control() {
+            Some(c)
+        } else if c.is_whitespace() {
+            Some(' ')
+        } else {
+            None
+        }
+    });
+
+    if config.max_length > 0 {
+        chars.take(config.max_length).collect()
+    } else {
+        chars.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_length: usize, strip_control_chars: bool) -> Memo {
+        Memo {
+            max_length,
+            strip_control_chars,
+        }
+    }
+
+    #[test]
+    fn test_strip_control_chars() {
+        let memo = "relayed\nby\thermes\u{0}\u{1b}[31m";
+
+        assert_eq!(sanitize(memo, &config(0, true)), "relayed by hermes[31m");
+        assert_eq!(sanitize(memo, &config(0, false)), memo);
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(sanitize("hermes", &config(3, true)), "her");
+        assert_eq!(sanitize("hermes", &config(0, true)), "hermes");
+        assert_eq!(sanitize("héllo wörld", &config(7, true)), "héllo w");
+    }
+}
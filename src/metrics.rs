@@ -1,20 +1,85 @@
-use std::net::SocketAddr;
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
-use axum::{extract::State, routing::get, Router, Server};
+use prometheus::proto::{Metric, MetricFamily};
 use prometheus::{
-    register_int_counter_vec_with_registry, register_int_gauge_vec_with_registry, Encoder,
-    IntCounterVec, IntGaugeVec, Registry, TextEncoder,
+    exponential_buckets, register_counter_vec_with_registry, register_gauge_vec_with_registry,
+    register_histogram_vec_with_registry, register_int_counter_vec_with_registry,
+    register_int_gauge_vec_with_registry, CounterVec as FloatCounterVec, Encoder,
+    GaugeVec as FloatGaugeVec, HistogramVec, IntCounterVec, IntGaugeVec, Registry, TextEncoder,
 };
 use tendermint::chain;
-use tracing::info;
+use tendermint_rpc::client::CompatMode;
+
+use crate::{config::Memo, db::TxRow, memo, relabel::Relabeler, signer};
 
 type GaugeVec = IntGaugeVec;
 type CounterVec = IntCounterVec;
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
-
+/// The metrics, each recorded in the registry of the chain it pertains to,
+/// or in the global registry if it does not pertain to a single monitored chain
 #[derive(Clone)]
 pub struct Metrics {
+    /// The number of packets relayed between two chains, from the chain the packets were sent from
+    /// Labels: ['src_chain', 'dst_chain', 'kind']
+    ibc_chain_pair_packets: CounterVec,
+
+    /// A series selected from the telemetry of a Hermes relayer instance, summed over its other labels
+    /// Labels: ['relayer', 'series', 'chain_id', 'channel', 'counterparty']
+    ibc_relayer_telemetry: FloatGaugeVec,
+
+    /// The number of stuck packets on an IBC channel
+    /// Labels: ['src_chain', 'dst_chain', 'src_channel', 'dst_channel', 'direction', 'source']
+    ibc_stuck_packets: GaugeVec,
+
+    /// Whether an IBC channel is reported as stuck by a stuck packets source but not by another one covering it
+    /// Labels: ['src_chain', 'src_channel', 'source']
+    ibc_stuck_packets_disagreement: GaugeVec,
+
+    /// The number of chains being monitored
+    chainpulse_chains: GaugeVec,
+
+    /// Whether the telemetry of a Hermes instance could be fetched
+    /// Labels: ['relayer']
+    chainpulse_hermes_up: GaugeVec,
+
+    /// The duration in seconds of the fetches of the stuck packets feed
+    /// Labels: ['source', 'outcome']
+    chainpulse_status_fetch_duration_seconds: HistogramVec,
+
+    /// The Unix timestamp of the last successful fetch of the stuck packets feed
+    /// Labels: ['source']
+    chainpulse_status_last_success_timestamp_seconds: GaugeVec,
+
+    /// The number of channels of the monitored chains covered by the last fetch of the stuck packets feed
+    /// Labels: ['source']
+    chainpulse_status_channels: GaugeVec,
+
+    /// The number of problems found by the last check of the integrity of the database
+    /// Labels: ['check']
+    chainpulse_db_integrity_errors: GaugeVec,
+
+    /// The Unix timestamp of the last check of the integrity of the database
+    /// Labels: ['check']
+    chainpulse_db_integrity_last_check_timestamp_seconds: GaugeVec,
+
+    /// The metrics of each chain, created along with its registry the first time one of them is recorded
+    chains: Chains,
+
+    /// How memos are sanitized before being used as labels
+    memo: Memo,
+}
+
+/// The metrics of each chain, shared between the metrics and the registries
+type Chains = Arc<RwLock<BTreeMap<chain::Id, Arc<ChainMetrics>>>>;
+
+/// The metrics pertaining to a single chain, registered in the registry of that chain
+struct ChainMetrics {
+    registry: Registry,
+
     /// The number of IBC packets that are effected
     /// Labels: ['chain_id', 'src_channel', 'src_port', 'dst_channel', 'dst_port', 'signer', 'memo']
     ibc_effected_packets: CounterVec,
@@ -27,12 +92,183 @@ pub struct Metrics {
     /// Labels: ['chain_id', 'src_channel', 'src_port', 'dst_channel', 'dst_port', 'signer', 'frontrunned_by', 'memo', 'effected_memo']
     ibc_frontrun_counter: CounterVec,
 
-    /// The number of stuck packets on an IBC channel
-    /// Labels: ['src_chain', 'dst_chain', 'src_channel']
-    ibc_stuck_packets: GaugeVec,
+    /// The number of times a signer gets frontrun, by proposer of the block including the frontrun tx
+    /// Labels: ['chain_id', 'proposer', 'signer', 'frontrunned_by']
+    ibc_frontrun_by_proposer: CounterVec,
 
-    /// The number of chains being monitored
-    chainpulse_chains: GaugeVec,
+    /// The number of blocks between the effected tx and the frontrun tx
+    /// Labels: ['chain_id', 'signer']
+    ibc_frontrun_height_delta: HistogramVec,
+
+    /// The number of txs between the effected tx and the frontrun tx, when included in the same block
+    /// Labels: ['chain_id', 'signer']
+    ibc_frontrun_index_delta: HistogramVec,
+
+    /// The ratio of effected packets per signer over a sliding window
+    /// Labels: ['chain_id', 'signer', 'window']
+    ibc_effected_ratio: FloatGaugeVec,
+
+    /// A percentile of the time in seconds for the packets sent on a channel to be received, over a sliding window
+    /// Labels: ['chain_id', 'src_channel', 'dst_channel', 'quantile', 'window']
+    ibc_packet_clearing_time_seconds: FloatGaugeVec,
+
+    /// The age in seconds of the oldest packet sent on a channel which has not been relayed yet
+    /// Labels: ['chain_id', 'src_channel', 'dst_channel']
+    ibc_oldest_unrelayed_packet_age_seconds: GaugeVec,
+
+    /// The number of packets not yet relayed on the channels an operator is responsible for
+    /// Labels: ['operator', 'chain_id', 'src_channel', 'dst_channel']
+    ibc_operator_pending_packets: GaugeVec,
+
+    /// The ratio of the packets sent on a channel which met the latency of an objective, over a sliding window
+    /// Labels: ['slo', 'chain_id', 'channel', 'window']
+    ibc_slo_sli: FloatGaugeVec,
+
+    /// The rate at which the error budget of an objective is burning, over a sliding window
+    /// Labels: ['slo', 'chain_id', 'channel', 'window']
+    ibc_slo_burn_rate: FloatGaugeVec,
+
+    /// The fraction of the error budget of an objective which remains over its period
+    /// Labels: ['slo', 'chain_id', 'channel']
+    ibc_slo_error_budget_remaining: FloatGaugeVec,
+
+    /// The number of interchain query results submitted to Neutron
+    /// Labels: ['chain_id', 'client_id', 'signer']
+    ibc_icq_query_results: CounterVec,
+
+    /// The amount of tokens received via ICS-20 transfers, per denomination
+    /// Labels: ['chain_id', 'src_channel', 'dst_channel', 'denom']
+    ibc_transfer_volume: FloatCounterVec,
+
+    /// The number of ICS-20 transfers which failed on their destination chain, per denomination
+    /// Labels: ['chain_id', 'src_channel', 'dst_channel', 'denom']
+    ibc_transfer_failures: CounterVec,
+
+    /// The amount of tokens received over a channel minus the amount sent over it, over a sliding window
+    /// Labels: ['chain_id', 'channel', 'counterparty_channel', 'denom', 'window']
+    ibc_transfer_net_flow: FloatGaugeVec,
+
+    /// The balance of the escrow account of a transfer channel, per denomination
+    /// Labels: ['chain_id', 'channel', 'denom']
+    ibc_escrow_balance: FloatGaugeVec,
+
+    /// The time in seconds since traffic was last seen on an open channel, or since it was discovered if never
+    /// Labels: ['chain_id', 'port', 'channel', 'counterparty_channel']
+    ibc_channel_idle_seconds: GaugeVec,
+
+    /// The topology of each discovered channel, always 1
+    /// Labels: ['chain_id', 'channel', 'port', 'counterparty_chain', 'counterparty_channel', 'connection', 'client']
+    ibc_channel_info: GaugeVec,
+
+    /// The Unix time at which a Tendermint client of a discovered channel expires if not updated, from its trusting period
+    /// Labels: ['chain_id', 'client', 'counterparty_chain']
+    ibc_client_expiry_timestamp_seconds: GaugeVec,
+
+    /// The baseline number of packets relayed on a channel per bucket, ie. their moving average
+    /// Labels: ['chain_id', 'src_channel', 'dst_channel']
+    ibc_channel_traffic_baseline: FloatGaugeVec,
+
+    /// The number of standard deviations the traffic of a channel lies above or below its baseline
+    /// Labels: ['chain_id', 'src_channel', 'dst_channel']
+    ibc_channel_traffic_anomaly: FloatGaugeVec,
+
+    /// How many times the uneffected ratio or frontrun rate of a signer exceeds its baseline
+    /// Labels: ['chain_id', 'signer', 'kind']
+    ibc_frontrun_burst_factor: FloatGaugeVec,
+
+    /// The number of bursts of frontruns detected for a signer
+    /// Labels: ['chain_id', 'signer', 'kind']
+    ibc_frontrun_bursts: CounterVec,
+
+    /// The signers of the Hermes relayer instances, always 1
+    /// Labels: ['chain_id', 'signer', 'relayer']
+    ibc_relayer_signer_info: GaugeVec,
+
+    /// The balance of an account of a Hermes relayer instance, as reported by its telemetry
+    /// Labels: ['relayer', 'chain_id', 'signer', 'denom']
+    ibc_relayer_wallet_balance: FloatGaugeVec,
+
+    /// The number of packets pending on a path served by a Hermes relayer instance,
+    /// as reported by the instance or as seen on-chain
+    /// Labels: ['relayer', 'chain_id', 'src_channel', 'dst_chain', 'source']
+    ibc_relayer_backlog: FloatGaugeVec,
+
+    /// The number of packets relayed by a relayer operator, identified by the public key hash
+    /// of its signers, which is the same across chains
+    /// Labels: ['chain_id', 'relayer_id', 'outcome']
+    ibc_relayer_id_packets: CounterVec,
+
+    /// The signers of each relayer operator, always 1
+    /// Labels: ['chain_id', 'signer', 'relayer_id']
+    ibc_relayer_id_info: GaugeVec,
+
+    /// The number of packets relayed by someone else on the paths a Hermes instance serves
+    /// Labels: ['chain_id', 'src_channel', 'dst_channel', 'relayer']
+    ibc_relayer_missed_packets: CounterVec,
+
+    /// The share of the packets effected on a channel by a signer over the baseline period, labelled with its role
+    /// Labels: ['chain_id', 'src_channel', 'dst_channel', 'signer', 'role']
+    ibc_relayer_role: FloatGaugeVec,
+
+    /// The number of packets effected on a channel by one of its backup relayers
+    /// Labels: ['chain_id', 'src_channel', 'dst_channel', 'signer']
+    ibc_backup_relayed_packets: CounterVec,
+
+    /// The number of relayers active over the last day per software and version, as advertised in their memos
+    /// Labels: ['chain_id', 'software', 'version']
+    ibc_relayer_software: GaugeVec,
+
+    /// The account sequence of a relayer address
+    /// Labels: ['chain_id', 'address']
+    ibc_relayer_account_sequence: GaugeVec,
+
+    /// The number of txs landed by a relayer address since the last one collected
+    /// Labels: ['chain_id', 'address']
+    ibc_relayer_sequence_gap: GaugeVec,
+
+    /// The number of events derived from packets by plugins
+    /// Labels: ['chain_id', 'plugin', 'event', 'value']
+    ibc_plugin_events: CounterVec,
+
+    /// The amount of tokens of the ICS-20 transfers stuck on a channel, per denomination
+    /// Labels: ['src_chain', 'src_channel', 'dst_channel', 'denom']
+    ibc_stuck_value: FloatGaugeVec,
+
+    /// The value in USD of the ICS-20 transfers stuck on a channel, of the denominations with a known price
+    /// Labels: ['src_chain', 'src_channel', 'dst_channel']
+    ibc_stuck_value_usd: FloatGaugeVec,
+
+    /// The number of IBC packets in each processed block
+    /// Labels: ['chain_id']
+    ibc_packets_per_block: HistogramVec,
+
+    /// The size in bytes of txs relaying IBC packets
+    /// Labels: ['chain_id']
+    ibc_relay_tx_size_bytes: HistogramVec,
+
+    /// The number of txs relaying IBC packets which failed
+    /// Labels: ['chain_id', 'signer', 'codespace', 'code']
+    ibc_failed_relay_txs: CounterVec,
+
+    /// The effective gas price paid by relayers, ie. the fee paid per unit of gas requested
+    /// Labels: ['chain_id', 'signer', 'denom']
+    ibc_relay_gas_price: HistogramVec,
+
+    /// The number of txs in the last processed block
+    /// Labels: ['chain_id']
+    chain_block_txs: GaugeVec,
+
+    /// The total size in bytes of the txs in the last processed block
+    /// Labels: ['chain_id']
+    chain_block_size_bytes: GaugeVec,
+
+    /// The time in seconds between consecutive blocks, according to their header time
+    /// Labels: ['chain_id']
+    chain_block_interval_seconds: HistogramVec,
+
+    /// An estimate of the peak memory held while processing the last block, in bytes
+    /// Labels: ['chain_id']
+    chainpulse_block_peak_memory_bytes: GaugeVec,
 
     /// The number of txs processed
     /// Labels: ['chain_id']
@@ -53,10 +289,103 @@ pub struct Metrics {
     /// The number of times we encountered an error
     /// Labels: ['chain_id']
     chainpulse_errors: CounterVec,
+
+    /// The number of keepalive pings on the WebSocket which did not get a pong in time
+    /// Labels: ['chain_id']
+    chainpulse_missed_pongs: CounterVec,
+
+    /// The number of NewBlock events which were dropped by the WebSocket subscription,
+    /// as detected from gaps in the heights of the blocks received
+    /// Labels: ['chain_id']
+    chainpulse_dropped_events: CounterVec,
+
+    /// The number of IBC messages which were skipped because their signer is not a valid address
+    /// Labels: ['chain_id']
+    chainpulse_invalid_signers: CounterVec,
+
+    /// The number of IBC messages which failed to be processed and were recorded as dead letters
+    /// Labels: ['chain_id']
+    chainpulse_dead_letters: CounterVec,
+
+    /// The number of times an endpoint reported another chain than the one it is configured for
+    /// Labels: ['chain_id', 'endpoint', 'reported_chain_id']
+    chainpulse_chain_id_mismatches: CounterVec,
+
+    /// Information about the node each chain is collected from, always 1
+    /// Labels: ['chain_id', 'moniker', 'version', 'endpoint']
+    chainpulse_node_info: GaugeVec,
+
+    /// The number of blocks waiting in the retry queue to be written to the database
+    /// Labels: ['chain_id']
+    chainpulse_retry_queue_size: GaugeVec,
+
+    /// The number of blocks being processed concurrently
+    /// Labels: ['chain_id']
+    chainpulse_in_flight_blocks: GaugeVec,
+
+    /// The number of blocks waiting for a slot to be processed
+    /// Labels: ['chain_id']
+    chainpulse_pending_blocks: GaugeVec,
+
+    /// The number of database operations in progress or waiting for a connection
+    /// Labels: ['chain_id']
+    chainpulse_pending_db_operations: GaugeVec,
+
+    /// The number of acknowledgements and timeouts relayed for packets never seen being sent, over a sliding window
+    /// Labels: ['chain_id', 'src_channel', 'dst_channel', 'window']
+    chainpulse_unmatched_acks: GaugeVec,
+
+    /// The number of packets received from a monitored chain which were never seen being sent, over a sliding window
+    /// Labels: ['chain_id', 'src_chain', 'src_channel', 'dst_channel', 'window']
+    chainpulse_orphan_receives: GaugeVec,
+
+    /// The number of database operations which failed because the database was busy or locked
+    /// Labels: ['chain_id']
+    chainpulse_db_busy_total: CounterVec,
+
+    /// The compat mode in use to talk to the endpoint of each chain, 1 for the current one and 0 for the other
+    /// Labels: ['chain_id', 'version']
+    chainpulse_compat_mode: GaugeVec,
+
+    /// The number of times the circuit breaker opened after too many reconnects
+    /// Labels: ['chain_id']
+    chainpulse_circuit_breaks: CounterVec,
+
+    /// Whether the circuit breaker is currently open, ie. the collector is backing off
+    /// Labels: ['chain_id']
+    chainpulse_circuit_open: GaugeVec,
+
+    /// The health score of an endpoint, between 0 (unhealthy) and 1 (healthy)
+    /// Labels: ['chain_id', 'endpoint']
+    chainpulse_endpoint_health: FloatGaugeVec,
+
+    /// The latency in seconds of the last probe of an endpoint
+    /// Labels: ['chain_id', 'endpoint']
+    chainpulse_endpoint_latency_seconds: FloatGaugeVec,
+
+    /// The number of blocks an endpoint lags behind the most advanced endpoint of the chain
+    /// Labels: ['chain_id', 'endpoint']
+    chainpulse_endpoint_height_lag: GaugeVec,
+
+    /// The delay in seconds between the time of a block and the delivery of its NewBlock event
+    /// Labels: ['chain_id', 'endpoint']
+    chainpulse_event_delay_seconds: HistogramVec,
+
+    /// The local time minus the header time of the last block received, negative when the block is ahead of the local clock
+    /// Labels: ['chain_id', 'endpoint']
+    chainpulse_block_drift_seconds: FloatGaugeVec,
+
+    /// The latency in seconds of RPC calls
+    /// Labels: ['chain_id', 'endpoint', 'method']
+    chainpulse_rpc_latency_seconds: HistogramVec,
+
+    /// The latency in seconds of database operations
+    /// Labels: ['chain_id', 'operation']
+    chainpulse_db_latency_seconds: HistogramVec,
 }
 
-impl Metrics {
-    pub fn new() -> (Self, Registry) {
+impl ChainMetrics {
+    fn new() -> Self {
         let registry = Registry::new();
 
         let ibc_effected_packets = register_int_counter_vec_with_registry!(
@@ -109,222 +438,2155 @@ impl Metrics {
         )
         .unwrap();
 
-        let ibc_stuck_packets = register_int_gauge_vec_with_registry!(
-            "ibc_stuck_packets",
-            "The number of packets stuck on an IBC channel",
-            &["src_chain", "dst_chain", "src_channel"],
+        let ibc_frontrun_by_proposer = register_int_counter_vec_with_registry!(
+            "ibc_frontrun_by_proposer",
+            "The number of times a signer gets frontrun, by proposer of the block including the frontrun tx",
+            &["chain_id", "proposer", "signer", "frontrunned_by"],
             registry
         )
         .unwrap();
 
-        let chainpulse_chains = register_int_gauge_vec_with_registry!(
-            "chainpulse_chains",
-            "The number of chains being monitored",
-            &[],
+        let ibc_frontrun_height_delta = register_histogram_vec_with_registry!(
+            "ibc_frontrun_height_delta",
+            "The number of blocks between the effected tx and the frontrun tx",
+            &["chain_id", "signer"],
+            vec![0.0, 1.0, 2.0, 3.0, 5.0, 10.0, 20.0, 50.0, 100.0],
             registry
         )
         .unwrap();
 
-        let chainpulse_txs = register_int_counter_vec_with_registry!(
-            "chainpulse_txs",
-            "The number of txs processed",
-            &["chain_id"],
+        let ibc_frontrun_index_delta = register_histogram_vec_with_registry!(
+            "ibc_frontrun_index_delta",
+            "The number of txs between the effected tx and the frontrun tx, when included in the same block",
+            &["chain_id", "signer"],
+            vec![1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0],
             registry
         )
         .unwrap();
 
-        let chainpulse_packets = register_int_counter_vec_with_registry!(
-            "chainpulse_packets",
-            "The number of packets processed",
-            &["chain_id"],
+        let ibc_effected_ratio = register_gauge_vec_with_registry!(
+            "ibc_effected_ratio",
+            "The ratio of effected packets per signer over a sliding window",
+            &["chain_id", "signer", "window"],
             registry
         )
         .unwrap();
 
-        let chainpulse_reconnects = register_int_counter_vec_with_registry!(
-            "chainpulse_reconnects",
-            "The number of times we had to reconnect to the WebSocket",
-            &["chain_id"],
+        let ibc_packet_clearing_time_seconds = register_gauge_vec_with_registry!(
+            "ibc_packet_clearing_time_seconds",
+            "A percentile of the time in seconds for the packets sent on a channel to be received, over a sliding window",
+            &["chain_id", "src_channel", "dst_channel", "quantile", "window"],
             registry
         )
         .unwrap();
 
-        let chainpulse_timeouts = register_int_counter_vec_with_registry!(
-            "chainpulse_timeouts",
-            "The number of times the WebSocket connection timed out",
-            &["chain_id"],
+        let ibc_oldest_unrelayed_packet_age_seconds = register_int_gauge_vec_with_registry!(
+            "ibc_oldest_unrelayed_packet_age_seconds",
+            "The age in seconds of the oldest packet sent on a channel which has not been relayed yet",
+            &["chain_id", "src_channel", "dst_channel"],
             registry
         )
         .unwrap();
 
-        let chainpulse_errors = register_int_counter_vec_with_registry!(
-            "chainpulse_errors",
-            "The number of times an error was encountered",
-            &["chain_id"],
+        let ibc_operator_pending_packets = register_int_gauge_vec_with_registry!(
+            "ibc_operator_pending_packets",
+            "The number of packets not yet relayed on the channels an operator is responsible for",
+            &["operator", "chain_id", "src_channel", "dst_channel"],
             registry
         )
         .unwrap();
 
-        (
-            Self {
-                ibc_effected_packets,
-                ibc_uneffected_packets,
-                ibc_frontrun_counter,
-                ibc_stuck_packets,
-                chainpulse_chains,
-                chainpulse_txs,
-                chainpulse_packets,
-                chainpulse_reconnects,
-                chainpulse_timeouts,
-                chainpulse_errors,
-            },
-            registry,
+        let ibc_slo_sli = register_gauge_vec_with_registry!(
+            "ibc_slo_sli",
+            "The ratio of the packets sent on a channel which met the latency of an objective, over a sliding window",
+            &["slo", "chain_id", "channel", "window"],
+            registry
         )
-    }
-
-    #[allow(clippy::too_many_arguments)]
-    pub fn ibc_effected_packets(
-        &self,
-        chain_id: &chain::Id,
-        src_channel: &str,
-        src_port: &str,
-        dst_channel: &str,
-        dst_port: &str,
-        signer: &str,
-        memo: &str,
-    ) {
-        self.ibc_effected_packets
-            .with_label_values(&[
-                chain_id.as_ref(),
-                src_channel,
-                src_port,
-                dst_channel,
-                dst_port,
-                signer,
-                memo,
-            ])
-            .inc();
-    }
+        .unwrap();
 
-    #[allow(clippy::too_many_arguments)]
-    pub fn ibc_uneffected_packets(
-        &self,
-        chain_id: &chain::Id,
-        src_channel: &str,
-        src_port: &str,
-        dst_channel: &str,
-        dst_port: &str,
-        signer: &str,
-        memo: &str,
-    ) {
-        self.ibc_uneffected_packets
-            .with_label_values(&[
-                chain_id.as_ref(),
-                src_channel,
-                src_port,
-                dst_channel,
-                dst_port,
-                signer,
-                memo,
-            ])
-            .inc();
-    }
+        let ibc_slo_burn_rate = register_gauge_vec_with_registry!(
+            "ibc_slo_burn_rate",
+            "The rate at which the error budget of an objective is burning, over a sliding window",
+            &["slo", "chain_id", "channel", "window"],
+            registry
+        )
+        .unwrap();
 
-    #[allow(clippy::too_many_arguments)]
-    pub fn ibc_frontrun_counter(
-        &self,
-        chain_id: &chain::Id,
-        src_channel: &str,
-        src_port: &str,
-        dst_channel: &str,
-        dst_port: &str,
-        signer: &str,
-        frontrunned_by: &str,
-        memo: &str,
-        effected_memo: &str,
-    ) {
-        self.ibc_frontrun_counter
-            .with_label_values(&[
-                chain_id.as_ref(),
-                src_channel,
-                src_port,
-                dst_channel,
-                dst_port,
-                signer,
-                frontrunned_by,
-                memo,
-                effected_memo,
-            ])
-            .inc();
-    }
+        let ibc_slo_error_budget_remaining = register_gauge_vec_with_registry!(
+            "ibc_slo_error_budget_remaining",
+            "The fraction of the error budget of an objective which remains over its period",
+            &["slo", "chain_id", "channel"],
+            registry
+        )
+        .unwrap();
 
-    pub fn ibc_stuck_packets(
-        &self,
-        src_chain: &str,
-        dst_chain: &str,
-        src_channel: &str,
-        value: i64,
-    ) {
-        self.ibc_stuck_packets
-            .with_label_values(&[src_chain, dst_chain, src_channel])
-            .set(value);
-    }
+        let ibc_icq_query_results = register_int_counter_vec_with_registry!(
+            "ibc_icq_query_results",
+            "The number of interchain query results submitted to Neutron",
+            &["chain_id", "client_id", "signer"],
+            registry
+        )
+        .unwrap();
 
-    pub fn chainpulse_chains(&self) {
-        self.chainpulse_chains.with_label_values(&[]).inc();
-    }
+        let ibc_transfer_volume = register_counter_vec_with_registry!(
+            "ibc_transfer_volume",
+            "The amount of tokens received via ICS-20 transfers, per denomination",
+            &["chain_id", "src_channel", "dst_channel", "denom"],
+            registry
+        )
+        .unwrap();
 
-    pub fn chainpulse_txs(&self, chain_id: &chain::Id) {
-        self.chainpulse_txs
-            .with_label_values(&[chain_id.as_ref()])
-            .inc();
-    }
+        let ibc_transfer_failures = register_int_counter_vec_with_registry!(
+            "ibc_transfer_failures",
+            "The number of ICS-20 transfers which failed on their destination chain, per denomination",
+            &["chain_id", "src_channel", "dst_channel", "denom"],
+            registry
+        )
+        .unwrap();
 
-    pub fn chainpulse_packets(&self, chain_id: &chain::Id) {
-        self.chainpulse_packets
-            .with_label_values(&[chain_id.as_ref()])
-            .inc();
-    }
+        let ibc_transfer_net_flow = register_gauge_vec_with_registry!(
+            "ibc_transfer_net_flow",
+            "The amount of tokens received over a channel minus the amount sent over it, over a sliding window",
+            &["chain_id", "channel", "counterparty_channel", "denom", "window"],
+            registry
+        )
+        .unwrap();
 
-    pub fn chainpulse_reconnects(&self, chain_id: &chain::Id) {
-        self.chainpulse_reconnects
+        let ibc_escrow_balance = register_gauge_vec_with_registry!(
+            "ibc_escrow_balance",
+            "The balance of the escrow account of a transfer channel, per denomination",
+            &["chain_id", "channel", "denom"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_channel_idle_seconds = register_int_gauge_vec_with_registry!(
+            "ibc_channel_idle_seconds",
+            "The time in seconds since traffic was last seen on an open channel, or since it was discovered if never",
+            &["chain_id", "port", "channel", "counterparty_channel"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_channel_info = register_int_gauge_vec_with_registry!(
+            "ibc_channel_info",
+            "The topology of each discovered channel, always 1",
+            &[
+                "chain_id",
+                "channel",
+                "port",
+                "counterparty_chain",
+                "counterparty_channel",
+                "connection",
+                "client"
+            ],
+            registry
+        )
+        .unwrap();
+
+        let ibc_client_expiry_timestamp_seconds = register_int_gauge_vec_with_registry!(
+            "ibc_client_expiry_timestamp_seconds",
+            "The Unix time at which a Tendermint client of a discovered channel expires if not updated, from its trusting period",
+            &["chain_id", "client", "counterparty_chain"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_channel_traffic_baseline = register_gauge_vec_with_registry!(
+            "ibc_channel_traffic_baseline",
+            "The baseline number of packets relayed on a channel per bucket, ie. their moving average",
+            &["chain_id", "src_channel", "dst_channel"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_channel_traffic_anomaly = register_gauge_vec_with_registry!(
+            "ibc_channel_traffic_anomaly",
+            "The number of standard deviations the traffic of a channel lies above or below its baseline",
+            &["chain_id", "src_channel", "dst_channel"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_frontrun_burst_factor = register_gauge_vec_with_registry!(
+            "ibc_frontrun_burst_factor",
+            "How many times the uneffected ratio or frontrun rate of a signer exceeds its baseline",
+            &["chain_id", "signer", "kind"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_frontrun_bursts = register_int_counter_vec_with_registry!(
+            "ibc_frontrun_bursts",
+            "The number of bursts of frontruns detected for a signer",
+            &["chain_id", "signer", "kind"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_relayer_signer_info = register_int_gauge_vec_with_registry!(
+            "ibc_relayer_signer_info",
+            "The signers of the Hermes relayer instances, always 1",
+            &["chain_id", "signer", "relayer"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_relayer_wallet_balance = register_gauge_vec_with_registry!(
+            "ibc_relayer_wallet_balance",
+            "The balance of an account of a Hermes relayer instance, as reported by its telemetry",
+            &["relayer", "chain_id", "signer", "denom"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_relayer_backlog = register_gauge_vec_with_registry!(
+            "ibc_relayer_backlog",
+            "The number of packets pending on a path served by a Hermes relayer instance, as reported by the instance or as seen on-chain",
+            &["relayer", "chain_id", "src_channel", "dst_chain", "source"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_relayer_id_packets = register_int_counter_vec_with_registry!(
+            "ibc_relayer_id_packets",
+            "The number of packets relayed by a relayer operator, identified by the public key hash of its signers",
+            &["chain_id", "relayer_id", "outcome"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_relayer_id_info = register_int_gauge_vec_with_registry!(
+            "ibc_relayer_id_info",
+            "The signers of each relayer operator, always 1",
+            &["chain_id", "signer", "relayer_id"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_relayer_missed_packets = register_int_counter_vec_with_registry!(
+            "ibc_relayer_missed_packets",
+            "The number of packets relayed by someone else on the paths a Hermes instance serves",
+            &["chain_id", "src_channel", "dst_channel", "relayer"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_relayer_role = register_gauge_vec_with_registry!(
+            "ibc_relayer_role",
+            "The share of the packets effected on a channel by a signer over the baseline period, labelled with its role",
+            &["chain_id", "src_channel", "dst_channel", "signer", "role"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_backup_relayed_packets = register_int_counter_vec_with_registry!(
+            "ibc_backup_relayed_packets",
+            "The number of packets effected on a channel by one of its backup relayers",
+            &["chain_id", "src_channel", "dst_channel", "signer"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_relayer_software = register_int_gauge_vec_with_registry!(
+            "ibc_relayer_software",
+            "The number of relayers active over the last day per software and version, as advertised in their memos",
+            &["chain_id", "software", "version"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_relayer_account_sequence = register_int_gauge_vec_with_registry!(
+            "ibc_relayer_account_sequence",
+            "The account sequence of a relayer address",
+            &["chain_id", "address"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_relayer_sequence_gap = register_int_gauge_vec_with_registry!(
+            "ibc_relayer_sequence_gap",
+            "The number of txs landed by a relayer address since the last one collected",
+            &["chain_id", "address"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_plugin_events = register_int_counter_vec_with_registry!(
+            "ibc_plugin_events",
+            "The number of events derived from packets by plugins",
+            &["chain_id", "plugin", "event", "value"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_stuck_value = register_gauge_vec_with_registry!(
+            "ibc_stuck_value",
+            "The amount of tokens of the ICS-20 transfers stuck on a channel, per denomination",
+            &["src_chain", "src_channel", "dst_channel", "denom"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_stuck_value_usd = register_gauge_vec_with_registry!(
+            "ibc_stuck_value_usd",
+            "The value in USD of the ICS-20 transfers stuck on a channel, of the denominations with a known price",
+            &["src_chain", "src_channel", "dst_channel"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_packets_per_block = register_histogram_vec_with_registry!(
+            "ibc_packets_per_block",
+            "The number of IBC packets in each processed block",
+            &["chain_id"],
+            vec![0.0, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0],
+            registry
+        )
+        .unwrap();
+
+        let ibc_relay_tx_size_bytes = register_histogram_vec_with_registry!(
+            "ibc_relay_tx_size_bytes",
+            "The size in bytes of txs relaying IBC packets",
+            &["chain_id"],
+            exponential_buckets(256.0, 2.0, 13).unwrap(),
+            registry
+        )
+        .unwrap();
+
+        let ibc_failed_relay_txs = register_int_counter_vec_with_registry!(
+            "ibc_failed_relay_txs",
+            "The number of txs relaying IBC packets which failed",
+            &["chain_id", "signer", "codespace", "code"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_relay_gas_price = register_histogram_vec_with_registry!(
+            "ibc_relay_gas_price",
+            "The effective gas price paid by relayers, ie. the fee paid per unit of gas requested",
+            &["chain_id", "signer", "denom"],
+            // Two buckets per decade, from 0.0001 to 1e9, to cover the gas prices of all chains
+            exponential_buckets(0.0001, 10f64.sqrt(), 27).unwrap(),
+            registry
+        )
+        .unwrap();
+
+        let chain_block_txs = register_int_gauge_vec_with_registry!(
+            "chain_block_txs",
+            "The number of txs in the last processed block",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chain_block_size_bytes = register_int_gauge_vec_with_registry!(
+            "chain_block_size_bytes",
+            "The total size in bytes of the txs in the last processed block",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chain_block_interval_seconds = register_histogram_vec_with_registry!(
+            "chain_block_interval_seconds",
+            "The time in seconds between consecutive blocks, according to their header time",
+            &["chain_id"],
+            vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 8.0, 10.0, 15.0, 20.0, 30.0, 60.0],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_block_peak_memory_bytes = register_int_gauge_vec_with_registry!(
+            "chainpulse_block_peak_memory_bytes",
+            "An estimate of the peak memory held while processing the last block, in bytes",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_txs = register_int_counter_vec_with_registry!(
+            "chainpulse_txs",
+            "The number of txs processed",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_packets = register_int_counter_vec_with_registry!(
+            "chainpulse_packets",
+            "The number of packets processed",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_reconnects = register_int_counter_vec_with_registry!(
+            "chainpulse_reconnects",
+            "The number of times we had to reconnect to the WebSocket",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_timeouts = register_int_counter_vec_with_registry!(
+            "chainpulse_timeouts",
+            "The number of times the WebSocket connection timed out",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_errors = register_int_counter_vec_with_registry!(
+            "chainpulse_errors",
+            "The number of times an error was encountered",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_missed_pongs = register_int_counter_vec_with_registry!(
+            "chainpulse_missed_pongs",
+            "The number of keepalive pings on the WebSocket which did not get a pong in time",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_dropped_events = register_int_counter_vec_with_registry!(
+            "chainpulse_dropped_events",
+            "The number of NewBlock events which were dropped by the WebSocket subscription",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_invalid_signers = register_int_counter_vec_with_registry!(
+            "chainpulse_invalid_signers",
+            "The number of IBC messages which were skipped because their signer is not a valid address",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_dead_letters = register_int_counter_vec_with_registry!(
+            "chainpulse_dead_letters",
+            "The number of IBC messages which failed to be processed and were recorded as dead letters",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_chain_id_mismatches = register_int_counter_vec_with_registry!(
+            "chainpulse_chain_id_mismatches",
+            "The number of times an endpoint reported another chain than the one it is configured for",
+            &["chain_id", "endpoint", "reported_chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_node_info = register_int_gauge_vec_with_registry!(
+            "chainpulse_node_info",
+            "Information about the node each chain is collected from, always 1",
+            &["chain_id", "moniker", "version", "endpoint"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_retry_queue_size = register_int_gauge_vec_with_registry!(
+            "chainpulse_retry_queue_size",
+            "The number of blocks waiting in the retry queue to be written to the database",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_in_flight_blocks = register_int_gauge_vec_with_registry!(
+            "chainpulse_in_flight_blocks",
+            "The number of blocks being processed concurrently",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_pending_blocks = register_int_gauge_vec_with_registry!(
+            "chainpulse_pending_blocks",
+            "The number of blocks waiting for a slot to be processed",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_pending_db_operations = register_int_gauge_vec_with_registry!(
+            "chainpulse_pending_db_operations",
+            "The number of database operations in progress or waiting for a connection",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_unmatched_acks = register_int_gauge_vec_with_registry!(
+            "chainpulse_unmatched_acks",
+            "The number of acknowledgements and timeouts relayed for packets never seen being sent, over a sliding window",
+            &["chain_id", "src_channel", "dst_channel", "window"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_orphan_receives = register_int_gauge_vec_with_registry!(
+            "chainpulse_orphan_receives",
+            "The number of packets received from a monitored chain which were never seen being sent, over a sliding window",
+            &["chain_id", "src_chain", "src_channel", "dst_channel", "window"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_db_busy_total = register_int_counter_vec_with_registry!(
+            "chainpulse_db_busy_total",
+            "The number of database operations which failed because the database was busy or locked",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_compat_mode = register_int_gauge_vec_with_registry!(
+            "chainpulse_compat_mode",
+            "The compat mode in use to talk to the endpoint of each chain",
+            &["chain_id", "version"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_circuit_breaks = register_int_counter_vec_with_registry!(
+            "chainpulse_circuit_breaks",
+            "The number of times the circuit breaker opened after too many reconnects",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_circuit_open = register_int_gauge_vec_with_registry!(
+            "chainpulse_circuit_open",
+            "Whether the circuit breaker is currently open, ie. the collector is backing off",
+            &["chain_id"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_endpoint_health = register_gauge_vec_with_registry!(
+            "chainpulse_endpoint_health",
+            "The health score of an endpoint, between 0 (unhealthy) and 1 (healthy)",
+            &["chain_id", "endpoint"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_endpoint_latency_seconds = register_gauge_vec_with_registry!(
+            "chainpulse_endpoint_latency_seconds",
+            "The latency in seconds of the last probe of an endpoint",
+            &["chain_id", "endpoint"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_endpoint_height_lag = register_int_gauge_vec_with_registry!(
+            "chainpulse_endpoint_height_lag",
+            "The number of blocks an endpoint lags behind the most advanced endpoint of the chain",
+            &["chain_id", "endpoint"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_event_delay_seconds = register_histogram_vec_with_registry!(
+            "chainpulse_event_delay_seconds",
+            "The delay in seconds between the time of a block and the delivery of its NewBlock event",
+            &["chain_id", "endpoint"],
+            vec![0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_block_drift_seconds = register_gauge_vec_with_registry!(
+            "chainpulse_block_drift_seconds",
+            "The local time minus the header time of the last block received, negative when the block is ahead of the local clock",
+            &["chain_id", "endpoint"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_rpc_latency_seconds = register_histogram_vec_with_registry!(
+            "chainpulse_rpc_latency_seconds",
+            "The latency in seconds of RPC calls",
+            &["chain_id", "endpoint", "method"],
+            vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_db_latency_seconds = register_histogram_vec_with_registry!(
+            "chainpulse_db_latency_seconds",
+            "The latency in seconds of database operations",
+            &["chain_id", "operation"],
+            vec![0.0001, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0],
+            registry
+        )
+        .unwrap();
+
+        Self {
+            registry,
+            ibc_effected_packets,
+            ibc_uneffected_packets,
+            ibc_frontrun_counter,
+            ibc_frontrun_by_proposer,
+            ibc_frontrun_height_delta,
+            ibc_frontrun_index_delta,
+            ibc_effected_ratio,
+            ibc_packet_clearing_time_seconds,
+            ibc_oldest_unrelayed_packet_age_seconds,
+            ibc_operator_pending_packets,
+            ibc_slo_sli,
+            ibc_slo_burn_rate,
+            ibc_slo_error_budget_remaining,
+            ibc_icq_query_results,
+            ibc_transfer_volume,
+            ibc_transfer_failures,
+            ibc_transfer_net_flow,
+            ibc_escrow_balance,
+            ibc_channel_idle_seconds,
+            ibc_channel_info,
+            ibc_client_expiry_timestamp_seconds,
+            ibc_channel_traffic_baseline,
+            ibc_channel_traffic_anomaly,
+            ibc_frontrun_burst_factor,
+            ibc_frontrun_bursts,
+            ibc_relayer_signer_info,
+            ibc_relayer_wallet_balance,
+            ibc_relayer_backlog,
+            ibc_relayer_id_packets,
+            ibc_relayer_id_info,
+            ibc_relayer_missed_packets,
+            ibc_relayer_role,
+            ibc_backup_relayed_packets,
+            ibc_relayer_software,
+            ibc_relayer_account_sequence,
+            ibc_relayer_sequence_gap,
+            ibc_plugin_events,
+            ibc_stuck_value,
+            ibc_stuck_value_usd,
+            ibc_packets_per_block,
+            ibc_relay_tx_size_bytes,
+            ibc_failed_relay_txs,
+            ibc_relay_gas_price,
+            chain_block_txs,
+            chain_block_size_bytes,
+            chain_block_interval_seconds,
+            chainpulse_block_peak_memory_bytes,
+            chainpulse_txs,
+            chainpulse_packets,
+            chainpulse_reconnects,
+            chainpulse_timeouts,
+            chainpulse_errors,
+            chainpulse_missed_pongs,
+            chainpulse_dropped_events,
+            chainpulse_invalid_signers,
+            chainpulse_dead_letters,
+            chainpulse_chain_id_mismatches,
+            chainpulse_node_info,
+            chainpulse_retry_queue_size,
+            chainpulse_in_flight_blocks,
+            chainpulse_pending_blocks,
+            chainpulse_pending_db_operations,
+            chainpulse_unmatched_acks,
+            chainpulse_orphan_receives,
+            chainpulse_db_busy_total,
+            chainpulse_compat_mode,
+            chainpulse_circuit_breaks,
+            chainpulse_circuit_open,
+            chainpulse_endpoint_health,
+            chainpulse_endpoint_latency_seconds,
+            chainpulse_endpoint_height_lag,
+            chainpulse_event_delay_seconds,
+            chainpulse_block_drift_seconds,
+            chainpulse_rpc_latency_seconds,
+            chainpulse_db_latency_seconds,
+        }
+    }
+}
+
+/// The registries the metrics are recorded in, one for each chain along with the global one
+#[derive(Clone)]
+pub struct Registries {
+    global: Registry,
+    chains: Chains,
+}
+
+impl Registries {
+    /// Gather the metrics of all the chains, along with the global ones
+    pub fn gather(&self) -> Vec<MetricFamily> {
+        let chains = self.chains.read().unwrap();
+
+        let gathered = chains
+            .values()
+            .map(|chain| chain.registry.gather())
+            .chain(std::iter::once(self.global.gather()));
+
+        merge(gathered)
+    }
+
+    /// Gather the metrics of the given chain, without the global ones
+    pub fn gather_chain(&self, chain_id: &chain::Id) -> Vec<MetricFamily> {
+        let chains = self.chains.read().unwrap();
+
+        chains
+            .get(chain_id)
+            .map(|chain| chain.registry.gather())
+            .unwrap_or_default()
+    }
+}
+
+/// Merge the families of the same name gathered from several registries, sorted by name
+fn merge(gathered: impl IntoIterator<Item = Vec<MetricFamily>>) -> Vec<MetricFamily> {
+    let mut families: BTreeMap<String, MetricFamily> = BTreeMap::new();
+
+    for mut family in gathered.into_iter().flatten() {
+        match families.entry(family.get_name().to_string()) {
+            Entry::Occupied(mut entry) => entry.get_mut().mut_metric().extend(family.take_metric()),
+            Entry::Vacant(entry) => {
+                entry.insert(family);
+            }
+        }
+    }
+
+    families.into_values().collect()
+}
+
+impl Metrics {
+    pub fn new(memo: Memo) -> (Self, Registries) {
+        let registry = Registry::new();
+
+        let ibc_chain_pair_packets = register_int_counter_vec_with_registry!(
+            "ibc_chain_pair_packets",
+            "The number of packets relayed between two chains, from the chain the packets were sent from",
+            &["src_chain", "dst_chain", "kind"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_relayer_telemetry = register_gauge_vec_with_registry!(
+            "ibc_relayer_telemetry",
+            "A series selected from the telemetry of a Hermes relayer instance, summed over its other labels",
+            &["relayer", "series", "chain_id", "channel", "counterparty"],
+            registry
+        )
+        .unwrap();
+
+        let ibc_stuck_packets = register_int_gauge_vec_with_registry!(
+            "ibc_stuck_packets",
+            "The number of packets stuck on an IBC channel",
+            &[
+                "src_chain",
+                "dst_chain",
+                "src_channel",
+                "dst_channel",
+                "direction",
+                "source"
+            ],
+            registry
+        )
+        .unwrap();
+
+        let ibc_stuck_packets_disagreement = register_int_gauge_vec_with_registry!(
+            "ibc_stuck_packets_disagreement",
+            "Whether an IBC channel is reported as stuck by a stuck packets source but not by another one covering it",
+            &["src_chain", "src_channel", "source"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_chains = register_int_gauge_vec_with_registry!(
+            "chainpulse_chains",
+            "The number of chains being monitored",
+            &[],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_hermes_up = register_int_gauge_vec_with_registry!(
+            "chainpulse_hermes_up",
+            "Whether the telemetry of a Hermes instance could be fetched",
+            &["relayer"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_status_fetch_duration_seconds = register_histogram_vec_with_registry!(
+            "chainpulse_status_fetch_duration_seconds",
+            "The duration in seconds of the fetches of the stuck packets feed",
+            &["source", "outcome"],
+            vec![0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_status_last_success_timestamp_seconds =
+            register_int_gauge_vec_with_registry!(
+                "chainpulse_status_last_success_timestamp_seconds",
+                "The Unix timestamp of the last successful fetch of the stuck packets feed",
+                &["source"],
+                registry
+            )
+            .unwrap();
+
+        let chainpulse_status_channels = register_int_gauge_vec_with_registry!(
+            "chainpulse_status_channels",
+            "The number of channels of the monitored chains covered by the last fetch of the stuck packets feed",
+            &["source"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_db_integrity_errors = register_int_gauge_vec_with_registry!(
+            "chainpulse_db_integrity_errors",
+            "The number of problems found by the last check of the integrity of the database",
+            &["check"],
+            registry
+        )
+        .unwrap();
+
+        let chainpulse_db_integrity_last_check_timestamp_seconds =
+            register_int_gauge_vec_with_registry!(
+                "chainpulse_db_integrity_last_check_timestamp_seconds",
+                "The Unix timestamp of the last check of the integrity of the database",
+                &["check"],
+                registry
+            )
+            .unwrap();
+
+        let chains = Chains::default();
+
+        (
+            Self {
+                ibc_chain_pair_packets,
+                ibc_relayer_telemetry,
+                ibc_stuck_packets,
+                ibc_stuck_packets_disagreement,
+                chainpulse_chains,
+                chainpulse_hermes_up,
+                chainpulse_status_fetch_duration_seconds,
+                chainpulse_status_last_success_timestamp_seconds,
+                chainpulse_status_channels,
+                chainpulse_db_integrity_errors,
+                chainpulse_db_integrity_last_check_timestamp_seconds,
+                chains: chains.clone(),
+                memo,
+            },
+            Registries {
+                global: registry,
+                chains,
+            },
+        )
+    }
+
+    /// The metrics of the given chain, created along with its registry if none was recorded for it yet
+    fn chain(&self, chain_id: &chain::Id) -> Arc<ChainMetrics> {
+        if let Some(chain) = self.chains.read().unwrap().get(chain_id) {
+            return Arc::clone(chain);
+        }
+
+        let mut chains = self.chains.write().unwrap();
+        let chain = chains
+            .entry(chain_id.clone())
+            .or_insert_with(|| Arc::new(ChainMetrics::new()));
+
+        Arc::clone(chain)
+    }
+
+    /// The metrics of all the chains, eg. to reset them
+    fn chains(&self) -> Vec<Arc<ChainMetrics>> {
+        self.chains.read().unwrap().values().cloned().collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn ibc_effected_packets(
+        &self,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        src_port: &str,
+        dst_channel: &str,
+        dst_port: &str,
+        signer: &str,
+        memo: &str,
+    ) {
+        let chain = self.chain(chain_id);
+
+        let memo = memo::sanitize(memo, &self.memo);
+
+        chain
+            .ibc_effected_packets
+            .with_label_values(&[
+                chain_id.as_ref(),
+                src_channel,
+                src_port,
+                dst_channel,
+                dst_port,
+                signer,
+                &memo,
+            ])
+            .inc();
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn ibc_uneffected_packets(
+        &self,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        src_port: &str,
+        dst_channel: &str,
+        dst_port: &str,
+        signer: &str,
+        memo: &str,
+    ) {
+        let chain = self.chain(chain_id);
+
+        let memo = memo::sanitize(memo, &self.memo);
+
+        chain
+            .ibc_uneffected_packets
+            .with_label_values(&[
+                chain_id.as_ref(),
+                src_channel,
+                src_port,
+                dst_channel,
+                dst_port,
+                signer,
+                &memo,
+            ])
+            .inc();
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn ibc_frontrun_counter(
+        &self,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        src_port: &str,
+        dst_channel: &str,
+        dst_port: &str,
+        signer: &str,
+        frontrunned_by: &str,
+        memo: &str,
+        effected_memo: &str,
+    ) {
+        let chain = self.chain(chain_id);
+
+        let memo = memo::sanitize(memo, &self.memo);
+        let effected_memo = memo::sanitize(effected_memo, &self.memo);
+
+        chain
+            .ibc_frontrun_counter
+            .with_label_values(&[
+                chain_id.as_ref(),
+                src_channel,
+                src_port,
+                dst_channel,
+                dst_port,
+                signer,
+                frontrunned_by,
+                &memo,
+                &effected_memo,
+            ])
+            .inc();
+    }
+
+    pub fn ibc_frontrun_by_proposer(
+        &self,
+        chain_id: &chain::Id,
+        proposer: &str,
+        signer: &str,
+        frontrunned_by: &str,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_frontrun_by_proposer
+            .with_label_values(&[chain_id.as_ref(), proposer, signer, frontrunned_by])
+            .inc();
+    }
+
+    /// Record how far behind the effected tx the frontrun tx was included
+    pub fn ibc_frontrun_inclusion_delay(
+        &self,
+        chain_id: &chain::Id,
+        signer: &str,
+        frontrun_tx: &TxRow,
+        effected_tx: &TxRow,
+    ) {
+        let chain = self.chain(chain_id);
+
+        let height_delta = frontrun_tx.height - effected_tx.height;
+
+        chain
+            .ibc_frontrun_height_delta
+            .with_label_values(&[chain_id.as_ref(), signer])
+            .observe(height_delta as f64);
+
+        if height_delta != 0 {
+            return;
+        }
+
+        if let (Some(frontrun_index), Some(effected_index)) =
+            (frontrun_tx.tx_index, effected_tx.tx_index)
+        {
+            chain
+                .ibc_frontrun_index_delta
+                .with_label_values(&[chain_id.as_ref(), signer])
+                .observe((frontrun_index - effected_index) as f64);
+        }
+    }
+
+    pub fn ibc_effected_ratio(&self, chain_id: &chain::Id, signer: &str, window: &str, ratio: f64) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_effected_ratio
+            .with_label_values(&[chain_id.as_ref(), signer, window])
+            .set(ratio);
+    }
+
+    pub fn reset_ibc_effected_ratio(&self) {
+        for chain in self.chains() {
+            chain.ibc_effected_ratio.reset();
+        }
+    }
+
+    pub fn ibc_slo_sli(
+        &self,
+        slo: &str,
+        chain_id: &chain::Id,
+        channel: &str,
+        window: &str,
+        sli: f64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_slo_sli
+            .with_label_values(&[slo, chain_id.as_ref(), channel, window])
+            .set(sli);
+    }
+
+    pub fn ibc_slo_burn_rate(
+        &self,
+        slo: &str,
+        chain_id: &chain::Id,
+        channel: &str,
+        window: &str,
+        burn_rate: f64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_slo_burn_rate
+            .with_label_values(&[slo, chain_id.as_ref(), channel, window])
+            .set(burn_rate);
+    }
+
+    pub fn ibc_slo_error_budget_remaining(
+        &self,
+        slo: &str,
+        chain_id: &chain::Id,
+        channel: &str,
+        remaining: f64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_slo_error_budget_remaining
+            .with_label_values(&[slo, chain_id.as_ref(), channel])
+            .set(remaining);
+    }
+
+    pub fn reset_ibc_slo(&self) {
+        for chain in self.chains() {
+            chain.ibc_slo_sli.reset();
+            chain.ibc_slo_burn_rate.reset();
+            chain.ibc_slo_error_budget_remaining.reset();
+        }
+    }
+
+    pub fn ibc_packet_clearing_time(
+        &self,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        dst_channel: &str,
+        quantile: &str,
+        window: &str,
+        seconds: f64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_packet_clearing_time_seconds
+            .with_label_values(&[
+                chain_id.as_ref(),
+                src_channel,
+                dst_channel,
+                quantile,
+                window,
+            ])
+            .set(seconds);
+    }
+
+    pub fn reset_ibc_packet_clearing_time(&self) {
+        for chain in self.chains() {
+            chain.ibc_packet_clearing_time_seconds.reset();
+        }
+    }
+
+    pub fn ibc_oldest_unrelayed_packet_age(
+        &self,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        dst_channel: &str,
+        age: i64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_oldest_unrelayed_packet_age_seconds
+            .with_label_values(&[chain_id.as_ref(), src_channel, dst_channel])
+            .set(age);
+    }
+
+    pub fn reset_ibc_oldest_unrelayed_packet_age(&self) {
+        for chain in self.chains() {
+            chain.ibc_oldest_unrelayed_packet_age_seconds.reset();
+        }
+    }
+
+    pub fn ibc_operator_pending_packets(
+        &self,
+        operator: &str,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        dst_channel: &str,
+        count: i64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_operator_pending_packets
+            .with_label_values(&[operator, chain_id.as_ref(), src_channel, dst_channel])
+            .set(count);
+    }
+
+    pub fn reset_ibc_operator_pending_packets(&self) {
+        for chain in self.chains() {
+            chain.ibc_operator_pending_packets.reset();
+        }
+    }
+
+    pub fn ibc_icq_query_results(&self, chain_id: &chain::Id, client_id: &str, signer: &str) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_icq_query_results
+            .with_label_values(&[chain_id.as_ref(), client_id, signer])
+            .inc();
+    }
+
+    pub fn ibc_transfer_volume(
+        &self,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        dst_channel: &str,
+        denom: &str,
+        amount: f64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_transfer_volume
+            .with_label_values(&[chain_id.as_ref(), src_channel, dst_channel, denom])
+            .inc_by(amount);
+    }
+
+    pub fn ibc_chain_pair_packets(&self, src_chain: &str, dst_chain: &str, kind: &str) {
+        self.ibc_chain_pair_packets
+            .with_label_values(&[src_chain, dst_chain, kind])
+            .inc();
+    }
+
+    pub fn ibc_transfer_failures(
+        &self,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        dst_channel: &str,
+        denom: &str,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_transfer_failures
+            .with_label_values(&[chain_id.as_ref(), src_channel, dst_channel, denom])
+            .inc();
+    }
+
+    pub fn ibc_transfer_net_flow(
+        &self,
+        chain_id: &chain::Id,
+        channel: &str,
+        counterparty_channel: &str,
+        denom: &str,
+        window: &str,
+        net: f64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_transfer_net_flow
+            .with_label_values(&[
+                chain_id.as_ref(),
+                channel,
+                counterparty_channel,
+                denom,
+                window,
+            ])
+            .set(net);
+    }
+
+    pub fn reset_ibc_transfer_net_flow(&self) {
+        for chain in self.chains() {
+            chain.ibc_transfer_net_flow.reset();
+        }
+    }
+
+    pub fn ibc_escrow_balance(
+        &self,
+        chain_id: &chain::Id,
+        channel: &str,
+        denom: &str,
+        amount: f64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_escrow_balance
+            .with_label_values(&[chain_id.as_ref(), channel, denom])
+            .set(amount);
+    }
+
+    pub fn ibc_channel_idle(
+        &self,
+        chain_id: &chain::Id,
+        port: &str,
+        channel: &str,
+        counterparty_channel: &str,
+        idle: i64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_channel_idle_seconds
+            .with_label_values(&[chain_id.as_ref(), port, channel, counterparty_channel])
+            .set(idle);
+    }
+
+    pub fn reset_ibc_channel_idle(&self) {
+        for chain in self.chains() {
+            chain.ibc_channel_idle_seconds.reset();
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn ibc_channel_info(
+        &self,
+        chain_id: &chain::Id,
+        channel: &str,
+        port: &str,
+        counterparty_chain: &str,
+        counterparty_channel: &str,
+        connection: &str,
+        client: &str,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_channel_info
+            .with_label_values(&[
+                chain_id.as_ref(),
+                channel,
+                port,
+                counterparty_chain,
+                counterparty_channel,
+                connection,
+                client,
+            ])
+            .set(1);
+    }
+
+    pub fn reset_ibc_channel_info(&self) {
+        for chain in self.chains() {
+            chain.ibc_channel_info.reset();
+        }
+    }
+
+    pub fn ibc_client_expiry(
+        &self,
+        chain_id: &chain::Id,
+        client: &str,
+        counterparty_chain: &str,
+        expires_at: i64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_client_expiry_timestamp_seconds
+            .with_label_values(&[chain_id.as_ref(), client, counterparty_chain])
+            .set(expires_at);
+    }
+
+    pub fn ibc_channel_traffic(
+        &self,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        dst_channel: &str,
+        baseline: f64,
+        score: f64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        let labels = [chain_id.as_ref(), src_channel, dst_channel];
+
+        chain
+            .ibc_channel_traffic_baseline
+            .with_label_values(&labels)
+            .set(baseline);
+
+        chain
+            .ibc_channel_traffic_anomaly
+            .with_label_values(&labels)
+            .set(score);
+    }
+
+    pub fn reset_ibc_channel_traffic(&self) {
+        for chain in self.chains() {
+            chain.ibc_channel_traffic_baseline.reset();
+            chain.ibc_channel_traffic_anomaly.reset();
+        }
+    }
+
+    pub fn ibc_frontrun_burst_factor(
+        &self,
+        chain_id: &chain::Id,
+        signer: &str,
+        kind: &str,
+        factor: f64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_frontrun_burst_factor
+            .with_label_values(&[chain_id.as_ref(), signer, kind])
+            .set(factor);
+    }
+
+    pub fn reset_ibc_frontrun_burst_factor(&self) {
+        for chain in self.chains() {
+            chain.ibc_frontrun_burst_factor.reset();
+        }
+    }
+
+    pub fn ibc_frontrun_bursts(&self, chain_id: &chain::Id, signer: &str, kind: &str) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_frontrun_bursts
+            .with_label_values(&[chain_id.as_ref(), signer, kind])
+            .inc();
+    }
+
+    pub fn ibc_relayer_signer_info(&self, chain_id: &chain::Id, signer: &str, relayer: &str) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_relayer_signer_info
+            .with_label_values(&[chain_id.as_ref(), signer, relayer])
+            .set(1);
+    }
+
+    pub fn reset_ibc_relayer_signer_info(&self) {
+        for chain in self.chains() {
+            chain.ibc_relayer_signer_info.reset();
+        }
+    }
+
+    pub fn ibc_relayer_wallet_balance(
+        &self,
+        relayer: &str,
+        chain_id: &chain::Id,
+        signer: &str,
+        denom: &str,
+        balance: f64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_relayer_wallet_balance
+            .with_label_values(&[relayer, chain_id.as_ref(), signer, denom])
+            .set(balance);
+    }
+
+    pub fn ibc_relayer_backlog(
+        &self,
+        relayer: &str,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        dst_chain: &str,
+        source: &str,
+        backlog: f64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_relayer_backlog
+            .with_label_values(&[relayer, chain_id.as_ref(), src_channel, dst_chain, source])
+            .set(backlog);
+    }
+
+    pub fn ibc_relayer_telemetry(
+        &self,
+        relayer: &str,
+        series: &str,
+        chain: &str,
+        channel: &str,
+        counterparty: &str,
+        value: f64,
+    ) {
+        self.ibc_relayer_telemetry
+            .with_label_values(&[relayer, series, chain, channel, counterparty])
+            .set(value);
+    }
+
+    /// Reset the series re-exported from the telemetry of the Hermes instances
+    pub fn reset_ibc_relayer_telemetry(&self) {
+        self.ibc_relayer_telemetry.reset();
+
+        for chain in self.chains() {
+            chain.ibc_relayer_wallet_balance.reset();
+            chain.ibc_relayer_backlog.reset();
+        }
+    }
+
+    /// Count a packet relayed by the operator of the given signer, if it is a valid address
+    pub fn ibc_relayer_id_packets(&self, chain_id: &chain::Id, signer: &str, effected: bool) {
+        let chain = self.chain(chain_id);
+
+        let Some(relayer_id) = signer::relayer_id(signer) else {
+            return;
+        };
+
+        let outcome = if effected { "effected" } else { "uneffected" };
+
+        chain
+            .ibc_relayer_id_packets
+            .with_label_values(&[chain_id.as_ref(), &relayer_id, outcome])
+            .inc();
+
+        chain
+            .ibc_relayer_id_info
+            .with_label_values(&[chain_id.as_ref(), signer, &relayer_id])
+            .set(1);
+    }
+
+    pub fn ibc_relayer_missed_packets(
+        &self,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        dst_channel: &str,
+        relayer: &str,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_relayer_missed_packets
+            .with_label_values(&[chain_id.as_ref(), src_channel, dst_channel, relayer])
+            .inc();
+    }
+
+    pub fn ibc_relayer_role(
+        &self,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        dst_channel: &str,
+        signer: &str,
+        role: &str,
+        share: f64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_relayer_role
+            .with_label_values(&[chain_id.as_ref(), src_channel, dst_channel, signer, role])
+            .set(share);
+    }
+
+    pub fn reset_ibc_relayer_role(&self) {
+        for chain in self.chains() {
+            chain.ibc_relayer_role.reset();
+        }
+    }
+
+    pub fn ibc_backup_relayed_packets(
+        &self,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        dst_channel: &str,
+        signer: &str,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_backup_relayed_packets
+            .with_label_values(&[chain_id.as_ref(), src_channel, dst_channel, signer])
+            .inc();
+    }
+
+    pub fn ibc_relayer_software(
+        &self,
+        chain_id: &chain::Id,
+        software: &str,
+        version: &str,
+        count: i64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_relayer_software
+            .with_label_values(&[chain_id.as_ref(), software, version])
+            .set(count);
+    }
+
+    pub fn reset_ibc_relayer_software(&self) {
+        for chain in self.chains() {
+            chain.ibc_relayer_software.reset();
+        }
+    }
+
+    pub fn ibc_relayer_account_sequence(&self, chain_id: &chain::Id, address: &str, sequence: u64) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_relayer_account_sequence
+            .with_label_values(&[chain_id.as_ref(), address])
+            .set(sequence as i64);
+    }
+
+    pub fn ibc_relayer_sequence_gap(&self, chain_id: &chain::Id, address: &str, gap: i64) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_relayer_sequence_gap
+            .with_label_values(&[chain_id.as_ref(), address])
+            .set(gap);
+    }
+
+    pub fn ibc_plugin_events(&self, chain_id: &chain::Id, plugin: &str, event: &str, value: &str) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_plugin_events
+            .with_label_values(&[chain_id.as_ref(), plugin, event, value])
+            .inc();
+    }
+
+    pub fn ibc_stuck_packets(
+        &self,
+        src_chain: &str,
+        dst_chain: &str,
+        src_channel: &str,
+        dst_channel: &str,
+        direction: &str,
+        source: &str,
+        value: i64,
+    ) {
+        self.ibc_stuck_packets
+            .with_label_values(&[
+                src_chain,
+                dst_chain,
+                src_channel,
+                dst_channel,
+                direction,
+                source,
+            ])
+            .set(value);
+    }
+
+    pub fn ibc_stuck_packets_disagreement(&self, src_chain: &str, src_channel: &str, source: &str) {
+        self.ibc_stuck_packets_disagreement
+            .with_label_values(&[src_chain, src_channel, source])
+            .set(1);
+    }
+
+    pub fn reset_ibc_stuck_packets_disagreement(&self) {
+        self.ibc_stuck_packets_disagreement.reset();
+    }
+
+    pub fn ibc_stuck_value(
+        &self,
+        src_chain: &chain::Id,
+        src_channel: &str,
+        dst_channel: &str,
+        denom: &str,
+        amount: f64,
+    ) {
+        let chain = self.chain(src_chain);
+
+        chain
+            .ibc_stuck_value
+            .with_label_values(&[src_chain.as_ref(), src_channel, dst_channel, denom])
+            .set(amount);
+    }
+
+    pub fn ibc_stuck_value_usd(
+        &self,
+        src_chain: &chain::Id,
+        src_channel: &str,
+        dst_channel: &str,
+        usd: f64,
+    ) {
+        let chain = self.chain(src_chain);
+
+        chain
+            .ibc_stuck_value_usd
+            .with_label_values(&[src_chain.as_ref(), src_channel, dst_channel])
+            .set(usd);
+    }
+
+    pub fn reset_ibc_stuck_value(&self) {
+        for chain in self.chains() {
+            chain.ibc_stuck_value.reset();
+            chain.ibc_stuck_value_usd.reset();
+        }
+    }
+
+    pub fn ibc_packets_per_block(&self, chain_id: &chain::Id, count: usize) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_packets_per_block
+            .with_label_values(&[chain_id.as_ref()])
+            .observe(count as f64);
+    }
+
+    pub fn ibc_relay_tx_size_bytes(&self, chain_id: &chain::Id, size: usize) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_relay_tx_size_bytes
+            .with_label_values(&[chain_id.as_ref()])
+            .observe(size as f64);
+    }
+
+    pub fn ibc_failed_relay_txs(
+        &self,
+        chain_id: &chain::Id,
+        signer: &str,
+        codespace: &str,
+        code: u32,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_failed_relay_txs
+            .with_label_values(&[chain_id.as_ref(), signer, codespace, &code.to_string()])
+            .inc();
+    }
+
+    pub fn ibc_relay_gas_price(&self, chain_id: &chain::Id, signer: &str, denom: &str, price: f64) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .ibc_relay_gas_price
+            .with_label_values(&[chain_id.as_ref(), signer, denom])
+            .observe(price);
+    }
+
+    pub fn chain_block_txs(&self, chain_id: &chain::Id, count: usize) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chain_block_txs
+            .with_label_values(&[chain_id.as_ref()])
+            .set(count as i64);
+    }
+
+    pub fn chain_block_size_bytes(&self, chain_id: &chain::Id, size: usize) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chain_block_size_bytes
+            .with_label_values(&[chain_id.as_ref()])
+            .set(size as i64);
+    }
+
+    pub fn chain_block_interval(&self, chain_id: &chain::Id, interval: Duration) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chain_block_interval_seconds
+            .with_label_values(&[chain_id.as_ref()])
+            .observe(interval.as_secs_f64());
+    }
+
+    pub fn chainpulse_block_peak_memory_bytes(&self, chain_id: &chain::Id, bytes: usize) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_block_peak_memory_bytes
+            .with_label_values(&[chain_id.as_ref()])
+            .set(bytes as i64);
+    }
+
+    pub fn chainpulse_chains(&self) {
+        self.chainpulse_chains.with_label_values(&[]).inc();
+    }
+
+    pub fn chainpulse_txs(&self, chain_id: &chain::Id) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_txs
+            .with_label_values(&[chain_id.as_ref()])
+            .inc();
+    }
+
+    pub fn chainpulse_packets(&self, chain_id: &chain::Id) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_packets
+            .with_label_values(&[chain_id.as_ref()])
+            .inc();
+    }
+
+    pub fn chainpulse_reconnects(&self, chain_id: &chain::Id) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_reconnects
             .with_label_values(&[chain_id.as_ref()])
             .inc();
     }
 
     pub fn chainpulse_timeouts(&self, chain_id: &chain::Id) {
-        self.chainpulse_timeouts
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_timeouts
             .with_label_values(&[chain_id.as_ref()])
             .inc();
     }
 
     pub fn chainpulse_errors(&self, chain_id: &chain::Id) {
-        self.chainpulse_errors
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_errors
+            .with_label_values(&[chain_id.as_ref()])
+            .inc();
+    }
+
+    pub fn chainpulse_missed_pongs(&self, chain_id: &chain::Id) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_missed_pongs
+            .with_label_values(&[chain_id.as_ref()])
+            .inc();
+    }
+
+    pub fn chainpulse_dropped_events(&self, chain_id: &chain::Id, dropped: u64) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_dropped_events
+            .with_label_values(&[chain_id.as_ref()])
+            .inc_by(dropped);
+    }
+
+    pub fn chainpulse_invalid_signers(&self, chain_id: &chain::Id) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_invalid_signers
             .with_label_values(&[chain_id.as_ref()])
             .inc();
     }
-}
 
-pub async fn run(port: u16, registry: Registry) -> Result<()> {
-    let app = Router::new()
-        .route("/metrics", get(get_metrics))
-        .with_state(registry);
+    pub fn chainpulse_dead_letters(&self, chain_id: &chain::Id) {
+        let chain = self.chain(chain_id);
 
-    let server =
-        Server::bind(&SocketAddr::from(([0, 0, 0, 0], port))).serve(app.into_make_service());
+        chain
+            .chainpulse_dead_letters
+            .with_label_values(&[chain_id.as_ref()])
+            .inc();
+    }
+
+    pub fn chainpulse_chain_id_mismatches(
+        &self,
+        chain_id: &chain::Id,
+        endpoint: &str,
+        reported_chain_id: &chain::Id,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_chain_id_mismatches
+            .with_label_values(&[chain_id.as_ref(), endpoint, reported_chain_id.as_ref()])
+            .inc();
+    }
+
+    pub fn chainpulse_node_info(
+        &self,
+        chain_id: &chain::Id,
+        moniker: &str,
+        version: &str,
+        endpoint: &str,
+    ) {
+        let chain = self.chain(chain_id);
+
+        // Only keep the series of the node the chain is currently collected from
+        chain.chainpulse_node_info.reset();
+
+        chain
+            .chainpulse_node_info
+            .with_label_values(&[chain_id.as_ref(), moniker, version, endpoint])
+            .set(1);
+    }
+
+    pub fn chainpulse_retry_queue_size(&self, chain_id: &chain::Id, size: usize) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_retry_queue_size
+            .with_label_values(&[chain_id.as_ref()])
+            .set(size as i64);
+    }
+
+    pub fn chainpulse_in_flight_blocks(&self, chain_id: &chain::Id, count: usize) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_in_flight_blocks
+            .with_label_values(&[chain_id.as_ref()])
+            .set(count as i64);
+    }
+
+    pub fn chainpulse_pending_blocks(&self, chain_id: &chain::Id, count: usize) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_pending_blocks
+            .with_label_values(&[chain_id.as_ref()])
+            .set(count as i64);
+    }
+
+    pub fn chainpulse_pending_db_operations(&self, chain_id: &chain::Id, delta: i64) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_pending_db_operations
+            .with_label_values(&[chain_id.as_ref()])
+            .add(delta);
+    }
+
+    pub fn chainpulse_unmatched_acks(
+        &self,
+        chain_id: &chain::Id,
+        src_channel: &str,
+        dst_channel: &str,
+        window: &str,
+        count: i64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_unmatched_acks
+            .with_label_values(&[chain_id.as_ref(), src_channel, dst_channel, window])
+            .set(count);
+    }
+
+    pub fn chainpulse_orphan_receives(
+        &self,
+        chain_id: &chain::Id,
+        src_chain: &str,
+        src_channel: &str,
+        dst_channel: &str,
+        window: &str,
+        count: i64,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_orphan_receives
+            .with_label_values(&[
+                chain_id.as_ref(),
+                src_chain,
+                src_channel,
+                dst_channel,
+                window,
+            ])
+            .set(count);
+    }
+
+    pub fn reset_chainpulse_data_quality(&self) {
+        for chain in self.chains() {
+            chain.chainpulse_unmatched_acks.reset();
+            chain.chainpulse_orphan_receives.reset();
+        }
+    }
+
+    pub fn chainpulse_db_busy(&self, chain_id: &chain::Id) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_db_busy_total
+            .with_label_values(&[chain_id.as_ref()])
+            .inc();
+    }
+
+    pub fn chainpulse_compat_mode(&self, chain_id: &chain::Id, compat_mode: CompatMode) {
+        let chain = self.chain(chain_id);
+
+        for mode in [CompatMode::V0_34, CompatMode::V0_37] {
+            let version = mode.to_string();
 
-    info!("Metrics server listening at http://localhost:{port}/metrics");
-    server.await?;
+            chain
+                .chainpulse_compat_mode
+                .with_label_values(&[chain_id.as_ref(), version.trim_start_matches('v')])
+                .set((mode == compat_mode) as i64);
+        }
+    }
+
+    pub fn chainpulse_circuit_breaks(&self, chain_id: &chain::Id) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_circuit_breaks
+            .with_label_values(&[chain_id.as_ref()])
+            .inc();
+    }
+
+    pub fn chainpulse_circuit_open(&self, chain_id: &chain::Id, open: bool) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_circuit_open
+            .with_label_values(&[chain_id.as_ref()])
+            .set(open as i64);
+    }
+
+    pub fn chainpulse_hermes_up(&self, relayer: &str, up: bool) {
+        self.chainpulse_hermes_up
+            .with_label_values(&[relayer])
+            .set(up as i64);
+    }
+
+    pub fn chainpulse_endpoint_health(&self, chain_id: &chain::Id, endpoint: &str, score: f64) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_endpoint_health
+            .with_label_values(&[chain_id.as_ref(), endpoint])
+            .set(score);
+    }
+
+    pub fn chainpulse_endpoint_latency(
+        &self,
+        chain_id: &chain::Id,
+        endpoint: &str,
+        latency: Duration,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_endpoint_latency_seconds
+            .with_label_values(&[chain_id.as_ref(), endpoint])
+            .set(latency.as_secs_f64());
+    }
+
+    pub fn chainpulse_endpoint_height_lag(&self, chain_id: &chain::Id, endpoint: &str, lag: u64) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_endpoint_height_lag
+            .with_label_values(&[chain_id.as_ref(), endpoint])
+            .set(lag as i64);
+    }
+
+    pub fn chainpulse_event_delay(&self, chain_id: &chain::Id, endpoint: &str, delay: Duration) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_event_delay_seconds
+            .with_label_values(&[chain_id.as_ref(), endpoint])
+            .observe(delay.as_secs_f64());
+    }
+
+    pub fn chainpulse_block_drift(&self, chain_id: &chain::Id, endpoint: &str, drift: f64) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_block_drift_seconds
+            .with_label_values(&[chain_id.as_ref(), endpoint])
+            .set(drift);
+    }
+
+    pub fn chainpulse_rpc_latency(
+        &self,
+        chain_id: &chain::Id,
+        endpoint: &str,
+        method: &str,
+        latency: Duration,
+    ) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_rpc_latency_seconds
+            .with_label_values(&[chain_id.as_ref(), endpoint, method])
+            .observe(latency.as_secs_f64());
+    }
+
+    pub fn chainpulse_db_latency(&self, chain_id: &chain::Id, operation: &str, latency: Duration) {
+        let chain = self.chain(chain_id);
+
+        chain
+            .chainpulse_db_latency_seconds
+            .with_label_values(&[chain_id.as_ref(), operation])
+            .observe(latency.as_secs_f64());
+    }
+
+    pub fn chainpulse_status_fetch(&self, source: &str, outcome: &str, duration: Duration) {
+        self.chainpulse_status_fetch_duration_seconds
+            .with_label_values(&[source, outcome])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn chainpulse_status_last_success(&self, source: &str, timestamp: u64) {
+        self.chainpulse_status_last_success_timestamp_seconds
+            .with_label_values(&[source])
+            .set(timestamp as i64);
+    }
+
+    pub fn chainpulse_status_channels(&self, source: &str, channels: usize) {
+        self.chainpulse_status_channels
+            .with_label_values(&[source])
+            .set(channels as i64);
+    }
+
+    pub fn chainpulse_db_integrity(&self, check: &str, errors: usize, timestamp: u64) {
+        self.chainpulse_db_integrity_errors
+            .with_label_values(&[check])
+            .set(errors as i64);
 
-    Ok(())
+        self.chainpulse_db_integrity_last_check_timestamp_seconds
+            .with_label_values(&[check])
+            .set(timestamp as i64);
+    }
 }
 
-pub async fn get_metrics(registry: State<Registry>) -> String {
+/// Only keep the series of the gathered metrics matching the given filter,
+/// apply the relabeling rules and encode them in the text format.
+pub fn encode(
+    metric_families: Vec<MetricFamily>,
+    relabeler: &Relabeler,
+    filter: impl Fn(&Metric) -> bool,
+) -> String {
     let mut buffer = vec![];
     let encoder = TextEncoder::new();
 
-    let metric_families = registry.gather();
+    let metric_families = metric_families
+        .into_iter()
+        .filter_map(|mut family| {
+            let metrics = family
+                .take_metric()
+                .into_iter()
+                .filter(&filter)
+                .collect::<Vec<_>>();
+
+            // Families without any series cannot be encoded
+            if metrics.is_empty() {
+                return None;
+            }
+
+            family.set_metric(metrics.into());
+            Some(family)
+        })
+        .collect();
+
+    let metric_families = relabeler.apply(metric_families);
     encoder.encode(&metric_families, &mut buffer).unwrap();
 
     String::from_utf8(buffer).unwrap()
 }
+
+/// The chains a series pertains to, ie. the values of its `chain_id`, `src_chain` and `dst_chain` labels
+pub fn chains_of(metric: &Metric) -> impl Iterator<Item = &str> {
+    metric
+        .get_label()
+        .iter()
+        .filter(|pair| matches!(pair.get_name(), "chain_id" | "src_chain" | "dst_chain"))
+        .map(|pair| pair.get_value())
+}
+
+/// The channels a series pertains to, ie. the values of its `src_channel` and `dst_channel` labels,
+/// or of its `channel` and `counterparty_channel` labels
+pub fn channels_of(metric: &Metric) -> impl Iterator<Item = &str> {
+    metric
+        .get_label()
+        .iter()
+        .filter(|pair| {
+            matches!(
+                pair.get_name(),
+                "src_channel" | "dst_channel" | "channel" | "counterparty_channel"
+            )
+        })
+        .map(|pair| pair.get_value())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_per_chain() {
+        let (metrics, registries) = Metrics::new(Memo::default());
+
+        let osmosis = chain::Id::try_from("osmosis-1").unwrap();
+        let cosmoshub = chain::Id::try_from("cosmoshub-4").unwrap();
+
+        metrics.chainpulse_txs(&osmosis);
+        metrics.chainpulse_txs(&cosmoshub);
+        metrics.chainpulse_chains();
+
+        let series = |families: &[MetricFamily], name: &str| {
+            families
+                .iter()
+                .find(|family| family.get_name() == name)
+                .map_or(0, |family| family.get_metric().len())
+        };
+
+        let osmosis_families = registries.gather_chain(&osmosis);
+        assert_eq!(series(&osmosis_families, "chainpulse_txs"), 1);
+        assert_eq!(series(&osmosis_families, "chainpulse_chains"), 0);
+
+        // The series of all the chains are merged in a single family
+        let families = registries.gather();
+        assert_eq!(series(&families, "chainpulse_txs"), 2);
+        assert_eq!(series(&families, "chainpulse_chains"), 1);
+
+        let unknown = chain::Id::try_from("juno-1").unwrap();
+        assert!(registries.gather_chain(&unknown).is_empty());
+    }
+}
@@ -1,4 +1,7 @@
-use std::fmt;
+mod neutron;
+pub mod v2;
+
+use std::{borrow::Cow, collections::HashMap, fmt, sync::Arc};
 
 use ibc_proto::{
     google::protobuf::Any,
@@ -18,6 +21,10 @@ use prost::Message;
 
 use crate::Result;
 
+pub use neutron::{
+    MsgRegisterInterchainQuery, MsgRemoveInterchainQueryRequest, MsgSubmitQueryResult,
+};
+
 #[derive(Clone, Debug)]
 pub enum Msg {
     // Client
@@ -29,6 +36,11 @@ pub enum Msg {
     Acknowledgement(MsgAcknowledgement),
     Timeout(MsgTimeout),
 
+    // Channel (IBC v2)
+    RecvPacketV2(v2::MsgRecvPacket),
+    AcknowledgementV2(v2::MsgAcknowledgement),
+    TimeoutV2(v2::MsgTimeout),
+
     /// Channel handshake
     ChanOpenInit(MsgChannelOpenInit),
     ChanOpenTry(MsgChannelOpenTry),
@@ -38,6 +50,11 @@ pub enum Msg {
     // Transfer
     Transfer(MsgTransfer),
 
+    // Neutron Interchain Queries
+    RegisterInterchainQuery(MsgRegisterInterchainQuery),
+    SubmitQueryResult(MsgSubmitQueryResult),
+    RemoveInterchainQuery(MsgRemoveInterchainQueryRequest),
+
     // Other
     Other(Any),
 }
@@ -54,15 +71,34 @@ impl Msg {
     pub fn is_relevant(&self) -> bool {
         matches!(
             self,
-            Self::RecvPacket(_) | Self::Acknowledgement(_) | Self::Timeout(_)
+            Self::RecvPacket(_)
+                | Self::Acknowledgement(_)
+                | Self::Timeout(_)
+                | Self::RecvPacketV2(_)
+                | Self::AcknowledgementV2(_)
+                | Self::TimeoutV2(_)
         )
     }
 
-    pub fn packet(&self) -> Option<&Packet> {
+    /// The packet carried by this message, if any.
+    ///
+    /// IBC v2 packets are represented as IBC v1 packets, see [`v2::Packet::to_v1`].
+    pub fn packet(&self) -> Option<Cow<'_, Packet>> {
+        match self {
+            Self::RecvPacket(msg) => msg.packet.as_ref().map(Cow::Borrowed),
+            Self::Acknowledgement(msg) => msg.packet.as_ref().map(Cow::Borrowed),
+            Self::Timeout(msg) => msg.packet.as_ref().map(Cow::Borrowed),
+            Self::RecvPacketV2(msg) => msg.packet.as_ref().map(|p| Cow::Owned(p.to_v1())),
+            Self::AcknowledgementV2(msg) => msg.packet.as_ref().map(|p| Cow::Owned(p.to_v1())),
+            Self::TimeoutV2(msg) => msg.packet.as_ref().map(|p| Cow::Owned(p.to_v1())),
+            _ => None,
+        }
+    }
+
+    /// The acknowledgement carried by this message, if any
+    pub fn acknowledgement(&self) -> Option<&[u8]> {
         match self {
-            Self::RecvPacket(msg) => msg.packet.as_ref(),
-            Self::Acknowledgement(msg) => msg.packet.as_ref(),
-            Self::Timeout(msg) => msg.packet.as_ref(),
+            Self::Acknowledgement(msg) => Some(&msg.acknowledgement),
             _ => None,
         }
     }
@@ -74,71 +110,178 @@ impl Msg {
             Self::RecvPacket(msg) => Some(&msg.signer),
             Self::Acknowledgement(msg) => Some(&msg.signer),
             Self::Timeout(msg) => Some(&msg.signer),
+            Self::RecvPacketV2(msg) => Some(&msg.signer),
+            Self::AcknowledgementV2(msg) => Some(&msg.signer),
+            Self::TimeoutV2(msg) => Some(&msg.signer),
             Self::ChanOpenInit(msg) => Some(&msg.signer),
             Self::ChanOpenTry(msg) => Some(&msg.signer),
             Self::ChanOpenAck(msg) => Some(&msg.signer),
             Self::ChanOpenConfirm(msg) => Some(&msg.signer),
+            Self::RegisterInterchainQuery(msg) => Some(&msg.sender),
+            Self::SubmitQueryResult(msg) => Some(&msg.sender),
+            Self::RemoveInterchainQuery(msg) => Some(&msg.sender),
             _ => None,
         }
     }
 
-    pub fn decode(msg: Any) -> Result<Self> {
-        match msg.type_url.as_str() {
-            "/ibc.core.client.v1.MsgCreateClient" => MsgCreateClient::decode(msg.value.as_slice())
-                .map(Msg::CreateClient)
-                .map_err(Into::into),
+    /// The signer of the message, to normalize it in place
+    pub fn signer_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Self::CreateClient(msg) => Some(&mut msg.signer),
+            Self::UpdateClient(msg) => Some(&mut msg.signer),
+            Self::RecvPacket(msg) => Some(&mut msg.signer),
+            Self::Acknowledgement(msg) => Some(&mut msg.signer),
+            Self::Timeout(msg) => Some(&mut msg.signer),
+            Self::RecvPacketV2(msg) => Some(&mut msg.signer),
+            Self::AcknowledgementV2(msg) => Some(&mut msg.signer),
+            Self::TimeoutV2(msg) => Some(&mut msg.signer),
+            Self::ChanOpenInit(msg) => Some(&mut msg.signer),
+            Self::ChanOpenTry(msg) => Some(&mut msg.signer),
+            Self::ChanOpenAck(msg) => Some(&mut msg.signer),
+            Self::ChanOpenConfirm(msg) => Some(&mut msg.signer),
+            Self::RegisterInterchainQuery(msg) => Some(&mut msg.sender),
+            Self::SubmitQueryResult(msg) => Some(&mut msg.sender),
+            Self::RemoveInterchainQuery(msg) => Some(&mut msg.sender),
+            _ => None,
+        }
+    }
+}
 
-            "/ibc.core.client.v1.MsgUpdateClient" => MsgUpdateClient::decode(msg.value.as_slice())
-                .map(Msg::UpdateClient)
-                .map_err(Into::into),
+/// A function decoding a message with a given type URL
+pub type Decoder = fn(&Any) -> Result<Msg>;
 
-            "/ibc.core.channel.v1.MsgTimeout" => MsgTimeout::decode(msg.value.as_slice())
-                .map(Msg::Timeout)
-                .map_err(Into::into),
+macro_rules! decoder {
+    ($ty:ty, $variant:path) => {{
+        fn decode(msg: &Any) -> Result<Msg> {
+            <$ty>::decode(msg.value.as_slice())
+                .map($variant)
+                .map_err(Into::into)
+        }
 
-            "/ibc.core.channel.v1.MsgRecvPacket" => MsgRecvPacket::decode(msg.value.as_slice())
-                .map(Msg::RecvPacket)
-                .map_err(Into::into),
+        decode as Decoder
+    }};
+}
 
-            "/ibc.core.channel.v1.MsgAcknowledgement" => {
-                MsgAcknowledgement::decode(msg.value.as_slice())
-                    .map(Msg::Acknowledgement)
-                    .map_err(Into::into)
-            }
+const BUILTIN_DECODERS: &[(&str, Decoder)] = &[
+    (
+        "/ibc.core.client.v1.MsgCreateClient",
+        decoder!(MsgCreateClient, Msg::CreateClient),
+    ),
+    (
+        "/ibc.core.client.v1.MsgUpdateClient",
+        decoder!(MsgUpdateClient, Msg::UpdateClient),
+    ),
+    (
+        "/ibc.core.channel.v1.MsgTimeout",
+        decoder!(MsgTimeout, Msg::Timeout),
+    ),
+    (
+        "/ibc.core.channel.v1.MsgRecvPacket",
+        decoder!(MsgRecvPacket, Msg::RecvPacket),
+    ),
+    (
+        "/ibc.core.channel.v1.MsgAcknowledgement",
+        decoder!(MsgAcknowledgement, Msg::Acknowledgement),
+    ),
+    (
+        "/ibc.core.channel.v2.MsgRecvPacket",
+        decoder!(v2::MsgRecvPacket, Msg::RecvPacketV2),
+    ),
+    (
+        "/ibc.core.channel.v2.MsgAcknowledgement",
+        decoder!(v2::MsgAcknowledgement, Msg::AcknowledgementV2),
+    ),
+    (
+        "/ibc.core.channel.v2.MsgTimeout",
+        decoder!(v2::MsgTimeout, Msg::TimeoutV2),
+    ),
+    (
+        "/ibc.core.channel.v1.MsgChannelOpenInit",
+        decoder!(MsgChannelOpenInit, Msg::ChanOpenInit),
+    ),
+    (
+        "/ibc.core.channel.v1.MsgChannelOpenTry",
+        decoder!(MsgChannelOpenTry, Msg::ChanOpenTry),
+    ),
+    (
+        "/ibc.core.channel.v1.MsgChannelOpenAck",
+        decoder!(MsgChannelOpenAck, Msg::ChanOpenAck),
+    ),
+    (
+        "/ibc.core.channel.v1.MsgChannelOpenConfirm",
+        decoder!(MsgChannelOpenConfirm, Msg::ChanOpenConfirm),
+    ),
+    (
+        "/ibc.applications.transfer.v1.MsgTransfer",
+        decoder!(MsgTransfer, Msg::Transfer),
+    ),
+];
+
+/// Register the decoders of the Neutron Interchain Queries module
+#[cfg(feature = "neutron")]
+fn register_neutron(decoders: &mut Decoders) {
+    decoders
+        .register(
+            "/neutron.interchainqueries.MsgRegisterInterchainQuery",
+            decoder!(MsgRegisterInterchainQuery, Msg::RegisterInterchainQuery),
+        )
+        .register(
+            "/neutron.interchainqueries.MsgSubmitQueryResult",
+            decoder!(MsgSubmitQueryResult, Msg::SubmitQueryResult),
+        )
+        .register(
+            "/neutron.interchainqueries.MsgRemoveInterchainQueryRequest",
+            decoder!(MsgRemoveInterchainQueryRequest, Msg::RemoveInterchainQuery),
+        );
+}
 
-            "/ibc.core.channel.v1.MsgChannelOpenInit" => {
-                MsgChannelOpenInit::decode(msg.value.as_slice())
-                    .map(Msg::ChanOpenInit)
-                    .map_err(Into::into)
-            }
+/// A registry of message decoders, on top of the built-in ones.
+///
+/// Custom decoders take precedence over the built-in ones, which allows supporting
+/// the messages of chain-specific IBC middlewares. Each set of custom decoders is registered
+/// by `new` behind its own Cargo feature, eg. the decoders of Neutron behind the `neutron` feature,
+/// so that they can be left out of the builds which do not need them.
+#[derive(Clone, Default)]
+pub struct Decoders {
+    custom: Arc<HashMap<String, Decoder>>,
+}
 
-            "/ibc.core.channel.v1.MsgChannelOpenTry" => {
-                MsgChannelOpenTry::decode(msg.value.as_slice())
-                    .map(Msg::ChanOpenTry)
-                    .map_err(Into::into)
-            }
+impl Decoders {
+    /// The built-in decoders, along with the custom ones of the enabled Cargo features
+    pub fn new() -> Self {
+        #[allow(unused_mut)]
+        let mut decoders = Self::default();
 
-            "/ibc.core.channel.v1.MsgChannelOpenAck" => {
-                MsgChannelOpenAck::decode(msg.value.as_slice())
-                    .map(Msg::ChanOpenAck)
-                    .map_err(Into::into)
-            }
+        #[cfg(feature = "neutron")]
+        register_neutron(&mut decoders);
 
-            "/ibc.core.channel.v1.MsgChannelOpenConfirm" => {
-                MsgChannelOpenConfirm::decode(msg.value.as_slice())
-                    .map(Msg::ChanOpenConfirm)
-                    .map_err(Into::into)
-            }
+        decoders
+    }
 
-            "/ibc.applications.transfer.v1.MsgTransfer" => {
-                MsgTransfer::decode(msg.value.as_slice())
-                    .map(Msg::Transfer)
-                    .map_err(Into::into)
-            }
+    pub fn register(&mut self, type_url: impl Into<String>, decoder: Decoder) -> &mut Self {
+        Arc::make_mut(&mut self.custom).insert(type_url.into(), decoder);
+        self
+    }
 
-            _ => Ok(Msg::Other(msg)),
+    pub fn decode(&self, msg: Any) -> Result<Msg> {
+        match self.try_decode(&msg) {
+            Some(result) => result,
+            None => Ok(Msg::Other(msg)),
         }
     }
+
+    /// Decode the given message if there is a decoder for its type URL, leaving it untouched,
+    /// eg. to keep its raw bytes around while the decoded message is processed
+    pub fn try_decode(&self, msg: &Any) -> Option<Result<Msg>> {
+        let decoder = self.custom.get(&msg.type_url).or_else(|| {
+            BUILTIN_DECODERS
+                .iter()
+                .find(|(type_url, _)| *type_url == msg.type_url)
+                .map(|(_, decoder)| decoder)
+        })?;
+
+        Some(decoder(msg))
+    }
 }
 
 impl fmt::Display for Msg {
@@ -182,6 +325,42 @@ impl fmt::Display for Msg {
                 )
             }
 
+            Msg::RecvPacketV2(msg) => {
+                let Some(packet) = &msg.packet else {
+                    return write!(f, "RecvPacket (v2): <no packet>");
+                };
+
+                write!(
+                    f,
+                    "RecvPacket (v2): {} -> {}",
+                    packet.source_client, packet.destination_client
+                )
+            }
+
+            Msg::TimeoutV2(msg) => {
+                let Some(packet) = &msg.packet else {
+                    return write!(f, "Timeout (v2): <no packet>");
+                };
+
+                write!(
+                    f,
+                    "Timeout (v2): {} -> {}",
+                    packet.source_client, packet.destination_client
+                )
+            }
+
+            Msg::AcknowledgementV2(msg) => {
+                let Some(packet) = &msg.packet else {
+                    return write!(f, "Acknowledgement (v2): <no packet>");
+                };
+
+                write!(
+                    f,
+                    "Acknowledgement (v2): {} -> {}",
+                    packet.source_client, packet.destination_client
+                )
+            }
+
             Msg::ChanOpenInit(msg) => {
                 write!(f, "ChanOpenInit: {}", msg.port_id)
             }
@@ -202,6 +381,26 @@ impl fmt::Display for Msg {
                 write!(f, "Transfer: {}/{}", msg.source_channel, msg.source_port)
             }
 
+            Msg::RegisterInterchainQuery(msg) => {
+                write!(
+                    f,
+                    "RegisterInterchainQuery: {} on {}",
+                    msg.query_type, msg.connection_id
+                )
+            }
+
+            Msg::SubmitQueryResult(msg) => {
+                write!(
+                    f,
+                    "SubmitQueryResult: query {} via {}",
+                    msg.query_id, msg.client_id
+                )
+            }
+
+            Msg::RemoveInterchainQuery(msg) => {
+                write!(f, "RemoveInterchainQuery: query {}", msg.query_id)
+            }
+
             Msg::Other(msg) => {
                 write!(f, "Unhandled msg: {}", msg.type_url)
             }
@@ -0,0 +1,36 @@
+//! Messages of the Neutron Interchain Queries (ICQ) module.
+//!
+//! Only the fields needed by Chain Pulse are decoded, the others are skipped.
+
+/// Submit the result of an interchain query, along with a proof against the light client
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgSubmitQueryResult {
+    #[prost(uint64, tag = "1")]
+    pub query_id: u64,
+    #[prost(string, tag = "2")]
+    pub sender: String,
+    #[prost(string, tag = "3")]
+    pub client_id: String,
+}
+
+/// Register a new interchain query on the given connection
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgRegisterInterchainQuery {
+    #[prost(string, tag = "1")]
+    pub query_type: String,
+    #[prost(string, tag = "4")]
+    pub connection_id: String,
+    #[prost(uint64, tag = "5")]
+    pub update_period: u64,
+    #[prost(string, tag = "6")]
+    pub sender: String,
+}
+
+/// Remove a registered interchain query
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgRemoveInterchainQueryRequest {
+    #[prost(uint64, tag = "1")]
+    pub query_id: u64,
+    #[prost(string, tag = "2")]
+    pub sender: String,
+}
@@ -0,0 +1,77 @@
+//! Messages of IBC v2 (aka. IBC Eureka), where packets are sent between clients
+//! instead of channels, and may carry several payloads.
+//!
+//! Only the fields needed by Chain Pulse are decoded, the others are skipped.
+
+use ibc_proto::ibc::core::channel::v1::Packet as PacketV1;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Packet {
+    #[prost(uint64, tag = "1")]
+    pub sequence: u64,
+    #[prost(string, tag = "2")]
+    pub source_client: String,
+    #[prost(string, tag = "3")]
+    pub destination_client: String,
+    #[prost(uint64, tag = "4")]
+    pub timeout_timestamp: u64,
+    #[prost(message, repeated, tag = "5")]
+    pub payloads: Vec<Payload>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Payload {
+    #[prost(string, tag = "1")]
+    pub source_port: String,
+    #[prost(string, tag = "2")]
+    pub destination_port: String,
+    #[prost(string, tag = "3")]
+    pub version: String,
+    #[prost(string, tag = "4")]
+    pub encoding: String,
+    #[prost(bytes = "vec", tag = "5")]
+    pub value: Vec<u8>,
+}
+
+impl Packet {
+    /// Represent this packet as an IBC v1 packet, where the source and destination clients
+    /// take the place of the channels, and only the first payload is retained.
+    pub fn to_v1(&self) -> PacketV1 {
+        let payload = self.payloads.first().cloned().unwrap_or_default();
+
+        PacketV1 {
+            sequence: self.sequence,
+            source_port: payload.source_port,
+            source_channel: self.source_client.clone(),
+            destination_port: payload.destination_port,
+            destination_channel: self.destination_client.clone(),
+            data: payload.value,
+            timeout_height: None,
+            timeout_timestamp: self.timeout_timestamp,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgRecvPacket {
+    #[prost(message, optional, tag = "1")]
+    pub packet: Option<Packet>,
+    #[prost(string, tag = "4")]
+    pub signer: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgAcknowledgement {
+    #[prost(message, optional, tag = "1")]
+    pub packet: Option<Packet>,
+    #[prost(string, tag = "5")]
+    pub signer: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgTimeout {
+    #[prost(message, optional, tag = "1")]
+    pub packet: Option<Packet>,
+    #[prost(string, tag = "5")]
+    pub signer: String,
+}
@@ -0,0 +1,340 @@
+use std::time::Duration;
+
+use ibc_proto::ibc::core::channel::v1::Packet;
+use sqlx::SqlitePool;
+use tendermint::{abci, chain};
+use tokio::time;
+use tracing::error;
+
+use crate::{
+    api::{self, Scope},
+    config::Operator,
+    db::PendingPacketRow,
+    metrics::Metrics,
+    msg::Msg,
+    Result,
+};
+
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A step of the lifecycle of a sent packet: sent, then received, then acknowledged or timed out
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Received,
+    Acknowledged,
+    TimedOut,
+}
+
+impl Stage {
+    /// The step reached by a packet relayed with the given message
+    pub fn of(msg: &Msg) -> Option<Self> {
+        match msg {
+            Msg::RecvPacket(_) | Msg::RecvPacketV2(_) => Some(Self::Received),
+            Msg::Acknowledgement(_) | Msg::AcknowledgementV2(_) => Some(Self::Acknowledged),
+            Msg::Timeout(_) | Msg::TimeoutV2(_) => Some(Self::TimedOut),
+            _ => None,
+        }
+    }
+
+    /// The column of the `sent_packets` table holding when the step was reached
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Received => "received_at",
+            Self::Acknowledged => "acknowledged_at",
+            Self::TimedOut => "timed_out_at",
+        }
+    }
+}
+
+/// A packet sent from a monitored chain, as found in a `send_packet` event
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SendPacket {
+    pub sequence: u64,
+    pub src_channel: String,
+    pub src_port: String,
+    pub dst_channel: String,
+    pub dst_port: String,
+
+    /// The data of the packet, empty if not found in the event
+    pub data: Vec<u8>,
+}
+
+impl SendPacket {
+    pub fn from_event(event: &abci::Event) -> Option<Self> {
+        Self::from_event_of_kind(event, "send_packet")
+    }
+
+    /// The packet received, as found in a `recv_packet` event, which has the same attributes
+    pub fn from_recv_event(event: &abci::Event) -> Option<Self> {
+        Self::from_event_of_kind(event, "recv_packet")
+    }
+
+    fn from_event_of_kind(event: &abci::Event, kind: &str) -> Option<Self> {
+        if event.kind != kind {
+            return None;
+        }
+
+        let attr = |key: &str| {
+            event
+                .attributes
+                .iter()
+                .find(|attr| attr.key == key)
+                .map(|attr| attr.value.clone())
+        };
+
+        Some(Self {
+            sequence: attr("packet_sequence")?.parse().ok()?,
+            src_channel: attr("packet_src_channel")?,
+            src_port: attr("packet_src_port")?,
+            dst_channel: attr("packet_dst_channel")?,
+            dst_port: attr("packet_dst_port")?,
+            data: attr("packet_data_hex")
+                .and_then(|hex| subtle_encoding::hex::decode(hex).ok())
+                .or_else(|| attr("packet_data").map(String::into_bytes))
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Record that a packet was sent, unless it was already seen before
+pub async fn record_send(
+    pool: &SqlitePool,
+    chain_id: &chain::Id,
+    packet: &SendPacket,
+) -> Result<()> {
+    let query = r#"
+        INSERT OR IGNORE INTO sent_packets
+            (chain, sequence, src_channel, src_port, dst_channel, dst_port, first_seen_at)
+        VALUES
+            (?, ?, ?, ?, ?, ?, datetime('now'))
+    "#;
+
+    sqlx::query(query)
+        .bind(chain_id.as_str())
+        .bind(packet.sequence as i64)
+        .bind(&packet.src_channel)
+        .bind(&packet.src_port)
+        .bind(&packet.dst_channel)
+        .bind(&packet.dst_port)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Mark a sent packet as resolved, following a `RecvPacket`, `Acknowledgement` or `Timeout` message,
+/// and record when it reached the corresponding step of its lifecycle
+pub async fn resolve(pool: &SqlitePool, packet: &Packet, stage: Stage) -> Result<()> {
+    let column = stage.column();
+
+    let query = format!(
+        r#"
+        UPDATE sent_packets SET
+            resolved_at = COALESCE(resolved_at, datetime('now')),
+            {column} = COALESCE({column}, datetime('now'))
+        WHERE   src_channel = ?
+            AND src_port = ?
+            AND dst_channel = ?
+            AND dst_port = ?
+            AND sequence = ?
+            AND {column} IS NULL
+        "#
+    );
+
+    sqlx::query(&query)
+        .bind(&packet.source_channel)
+        .bind(&packet.source_port)
+        .bind(&packet.destination_channel)
+        .bind(&packet.destination_port)
+        .bind(packet.sequence as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Fetch the packets whose lifecycle is not finished yet, ie. which were neither acknowledged nor timed out,
+/// within the given scope, oldest first, optionally only the ones sent from the given chain
+/// or sent or received over the given channel.
+///
+/// Packets resolved before the steps of their lifecycle were recorded are left out, as their state is unknown.
+pub async fn unfinished(
+    pool: &SqlitePool,
+    scope: Scope<'_>,
+    chain: Option<&str>,
+    channel: Option<&str>,
+    limit: Option<u32>,
+) -> Result<Vec<PendingPacketRow>> {
+    let query = format!(
+        r#"
+        SELECT chain, src_port, src_channel, dst_port, dst_channel, sequence,
+            CASE WHEN received_at IS NULL THEN 'sent' ELSE 'received' END AS state,
+            first_seen_at AS sent_at, received_at,
+            CAST((julianday('now') - julianday(first_seen_at)) * 86400 AS INTEGER) AS age_seconds
+        FROM sent_packets
+        WHERE   acknowledged_at IS NULL
+            AND timed_out_at IS NULL
+            AND (resolved_at IS NULL OR received_at IS NOT NULL)
+            AND (?1 IS NULL OR chain = ?1)
+            AND (?2 IS NULL OR src_channel = ?2 OR dst_channel = ?2)
+            AND {}
+        ORDER BY first_seen_at
+        LIMIT ?3
+        "#,
+        Scope::filter(4, "chain", &["src_channel", "dst_channel"])
+    );
+
+    let limit = api::limit(limit);
+
+    let rows = sqlx::query_as(&query)
+        .bind(chain)
+        .bind(channel)
+        .bind(limit)
+        .bind(scope.chains()?)
+        .bind(scope.channels()?)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows)
+}
+
+/// Periodically export the age of the oldest packet not yet relayed, per channel,
+/// and the number of packets not yet relayed on the channels of each operator
+pub async fn run(pool: SqlitePool, metrics: Metrics, operators: Vec<Operator>) {
+    loop {
+        if let Err(e) = update(&pool, &metrics, &operators).await {
+            error!("Failed to compute the age of pending packets: {e}");
+        }
+
+        time::sleep(UPDATE_INTERVAL).await;
+    }
+}
+
+async fn update(pool: &SqlitePool, metrics: &Metrics, operators: &[Operator]) -> Result<()> {
+    let query = r#"
+        SELECT chain, src_channel, dst_channel,
+            CAST((julianday('now') - julianday(MIN(first_seen_at))) * 86400 AS INTEGER),
+            COUNT(*)
+        FROM sent_packets
+        WHERE resolved_at IS NULL
+        GROUP BY chain, src_channel, dst_channel
+    "#;
+
+    let rows: Vec<(String, String, String, i64, i64)> =
+        sqlx::query_as(query).fetch_all(pool).await?;
+
+    // Reset the gauges so that channels without pending packets are removed
+    metrics.reset_ibc_oldest_unrelayed_packet_age();
+    metrics.reset_ibc_operator_pending_packets();
+
+    for (chain, src_channel, dst_channel, age, count) in rows {
+        let Ok(chain_id) = chain::Id::try_from(chain) else {
+            continue;
+        };
+
+        metrics.ibc_oldest_unrelayed_packet_age(&chain_id, &src_channel, &dst_channel, age);
+
+        for operator in operators {
+            if operator.relays(chain_id.as_str(), &src_channel) {
+                metrics.ibc_operator_pending_packets(
+                    &operator.name,
+                    &chain_id,
+                    &src_channel,
+                    &dst_channel,
+                    count,
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attr(key: &str, value: &str) -> abci::EventAttribute {
+        abci::EventAttribute {
+            key: key.to_string(),
+            value: value.to_string(),
+            index: true,
+        }
+    }
+
+    #[test]
+    fn test_send_packet_from_event() {
+        let event = abci::Event {
+            kind: "send_packet".to_string(),
+            attributes: vec![
+                attr("packet_data", "{}"),
+                attr("packet_sequence", "42"),
+                attr("packet_src_port", "transfer"),
+                attr("packet_src_channel", "channel-0"),
+                attr("packet_dst_port", "transfer"),
+                attr("packet_dst_channel", "channel-141"),
+            ],
+        };
+
+        let packet = SendPacket::from_event(&event).unwrap();
+        assert_eq!(packet.sequence, 42);
+        assert_eq!(packet.src_channel, "channel-0");
+        assert_eq!(packet.src_port, "transfer");
+        assert_eq!(packet.dst_channel, "channel-141");
+        assert_eq!(packet.dst_port, "transfer");
+        assert_eq!(packet.data, b"{}");
+    }
+
+    #[test]
+    fn test_send_packet_from_other_event() {
+        let event = abci::Event {
+            kind: "recv_packet".to_string(),
+            attributes: vec![attr("packet_sequence", "42")],
+        };
+
+        assert_eq!(SendPacket::from_event(&event), None);
+    }
+
+    async fn insert_sent_packet(pool: &SqlitePool, chain: &str, src_channel: &str, sequence: i64) {
+        sqlx::query(
+            r#"
+            INSERT INTO sent_packets (chain, sequence, src_channel, src_port, dst_channel, dst_port, first_seen_at)
+            VALUES (?1, ?2, ?3, 'transfer', 'channel-9', 'transfer', datetime('now', '-' || ?2 || ' minutes'))
+            "#,
+        )
+        .bind(chain)
+        .bind(sequence)
+        .bind(src_channel)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scoped_unfinished() {
+        let pool = crate::db::connect_in_memory().await.unwrap();
+        crate::db::setup(&pool).await.unwrap();
+
+        // The oldest packets are the ones the key cannot see
+        insert_sent_packet(&pool, "osmosis-1", "channel-0", 1).await;
+        insert_sent_packet(&pool, "osmosis-1", "channel-1", 2).await;
+        insert_sent_packet(&pool, "cosmoshub-4", "channel-0", 3).await;
+
+        let chains = [chain::Id::try_from("osmosis-1").unwrap()];
+        let channels = ["channel-0".to_string()];
+        let scope = Scope {
+            chains: Some(&chains),
+            channels: Some(&channels),
+        };
+
+        let rows = unfinished(&pool, scope, None, None, Some(1)).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].chain, "osmosis-1");
+        assert_eq!(rows[0].src_channel, "channel-0");
+
+        let rows = unfinished(&pool, Scope::default(), None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+    }
+}
@@ -0,0 +1,187 @@
+//! Plugins are WebAssembly modules which are handed every packet processed by Chain Pulse,
+//! and may return derived events, which are exported as the `ibc_plugin_events` metric.
+//!
+//! A plugin module must export:
+//! - its `memory`
+//! - `alloc(len: i32) -> i32`, which allocates `len` bytes and returns a pointer to them
+//! - `on_packet(ptr: i32, len: i32) -> i64`, which receives a JSON-encoded [`PacketEvent`]
+//!   and returns a pointer to a JSON-encoded [`PluginOutput`] in the upper 32 bits,
+//!   and its length in the lower 32 bits. A return value of 0 means no output.
+//!
+//! Plugins are only available when Chain Pulse is built with the `plugins` feature.
+
+use serde::{Deserialize, Serialize};
+
+/// A packet processed by Chain Pulse, as passed to the plugins
+#[derive(Clone, Debug, Serialize)]
+pub struct PacketEvent<'a> {
+    pub chain_id: &'a str,
+    pub type_url: &'a str,
+    pub sequence: u64,
+    pub src_channel: &'a str,
+    pub src_port: &'a str,
+    pub dst_channel: &'a str,
+    pub dst_port: &'a str,
+    pub signer: &'a str,
+    pub memo: &'a str,
+    pub effected: bool,
+    pub tx_hash: &'a str,
+}
+
+/// The output of a plugin for a given packet
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PluginOutput {
+    #[serde(default)]
+    pub events: Vec<DerivedEvent>,
+}
+
+/// An event derived by a plugin from a packet, eg. `{ "name": "memo_class", "value": "hermes" }`
+#[derive(Clone, Debug, Deserialize)]
+pub struct DerivedEvent {
+    pub name: String,
+    #[serde(default)]
+    pub value: String,
+}
+
+#[cfg(feature = "plugins")]
+pub use self::wasm::Plugins;
+
+#[cfg(not(feature = "plugins"))]
+pub use self::disabled::Plugins;
+
+#[cfg(feature = "plugins")]
+mod wasm {
+    use std::{
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{error, info};
+    use wasmtime::{Engine, Instance, Memory, Module, Store, TypedFunc};
+
+    use super::{PacketEvent, PluginOutput};
+    use crate::{config, Result};
+
+    #[derive(Clone, Default)]
+    pub struct Plugins {
+        plugins: Arc<Vec<Mutex<Plugin>>>,
+    }
+
+    impl Plugins {
+        pub fn load(configs: &[config::Plugin]) -> Result<Self> {
+            let engine = Engine::default();
+
+            let plugins = configs
+                .iter()
+                .map(|config| {
+                    info!(
+                        "Loading plugin `{}` from {}",
+                        config.name,
+                        config.path.display()
+                    );
+                    Plugin::load(&engine, &config.name, &config.path).map(Mutex::new)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(Self {
+                plugins: Arc::new(plugins),
+            })
+        }
+
+        /// Run all plugins on the given packet, returning their name along with their output
+        pub fn on_packet(&self, event: &PacketEvent<'_>) -> Vec<(String, PluginOutput)> {
+            let Ok(input) = serde_json::to_vec(event) else {
+                return Vec::new();
+            };
+
+            self.plugins
+                .iter()
+                .filter_map(|plugin| {
+                    let mut plugin = plugin.lock().unwrap();
+
+                    match plugin.on_packet(&input) {
+                        Ok(output) => Some((plugin.name.clone(), output)),
+                        Err(e) => {
+                            error!("Plugin `{}` failed: {e}", plugin.name);
+                            None
+                        }
+                    }
+                })
+                .collect()
+        }
+    }
+
+    struct Plugin {
+        name: String,
+        store: Store<()>,
+        memory: Memory,
+        alloc: TypedFunc<i32, i32>,
+        on_packet: TypedFunc<(i32, i32), i64>,
+    }
+
+    impl Plugin {
+        fn load(engine: &Engine, name: &str, path: &Path) -> Result<Self> {
+            let module = Module::from_file(engine, path)?;
+            let mut store = Store::new(engine, ());
+            let instance = Instance::new(&mut store, &module, &[])?;
+
+            let memory = instance
+                .get_memory(&mut store, "memory")
+                .ok_or("plugin does not export its memory")?;
+
+            let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+            let on_packet = instance.get_typed_func::<(i32, i32), i64>(&mut store, "on_packet")?;
+
+            Ok(Self {
+                name: name.to_string(),
+                store,
+                memory,
+                alloc,
+                on_packet,
+            })
+        }
+
+        fn on_packet(&mut self, input: &[u8]) -> Result<PluginOutput> {
+            let len = i32::try_from(input.len())?;
+            let ptr = self.alloc.call(&mut self.store, len)?;
+            self.memory.write(&mut self.store, ptr as usize, input)?;
+
+            let packed = self.on_packet.call(&mut self.store, (ptr, len))?;
+            if packed == 0 {
+                return Ok(PluginOutput::default());
+            }
+
+            let (out_ptr, out_len) = ((packed >> 32) as u32, packed as u32);
+            let mut output = vec![0; out_len as usize];
+            self.memory
+                .read(&self.store, out_ptr as usize, &mut output)?;
+
+            Ok(serde_json::from_slice(&output)?)
+        }
+    }
+}
+
+#[cfg(not(feature = "plugins"))]
+mod disabled {
+    use tracing::warn;
+
+    use super::{PacketEvent, PluginOutput};
+    use crate::{config, Result};
+
+    #[derive(Clone, Default)]
+    pub struct Plugins;
+
+    impl Plugins {
+        pub fn load(configs: &[config::Plugin]) -> Result<Self> {
+            if !configs.is_empty() {
+                warn!("Plugins are configured but Chain Pulse was built without the `plugins` feature, ignoring them");
+            }
+
+            Ok(Self)
+        }
+
+        pub fn on_packet(&self, _event: &PacketEvent<'_>) -> Vec<(String, PluginOutput)> {
+            Vec::new()
+        }
+    }
+}
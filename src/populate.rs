@@ -3,35 +3,55 @@ use std::{collections::HashSet, time::Instant};
 use futures::StreamExt;
 use sqlx::SqlitePool;
 use tendermint::chain;
-use tracing::{error_span, info};
+use tracing::info;
 
 use crate::{
-    db::{PacketRow, TxRow},
+    db::{self, PacketRow, TxRow},
     metrics::Metrics,
 };
 
-pub async fn run(chain: &chain::Id, pool: &SqlitePool, metrics: &Metrics) -> crate::Result<()> {
-    let _span = error_span!("populate", %chain).entered();
+/// The id of the last packet recorded before live collection starts.
+/// Packets up to it are counted by `run`, while the ones after it are counted by the collectors.
+pub async fn cutoff(pool: &SqlitePool) -> crate::Result<i64> {
+    let (cutoff,): (i64,) = sqlx::query_as("SELECT COALESCE(MAX(id), 0) FROM packets")
+        .fetch_one(pool)
+        .await?;
 
-    info!("Populating metrics...");
+    Ok(cutoff)
+}
+
+/// Populate the metrics from the packets of the chain recorded up to the cutoff
+pub async fn run(
+    chain: &chain::Id,
+    pool: &SqlitePool,
+    metrics: &Metrics,
+    cutoff: i64,
+) -> crate::Result<()> {
+    info!("Populating metrics from the packets recorded up to #{cutoff}...");
 
     let start = Instant::now();
 
     let mut packets =
             sqlx::query_as::<_, PacketRow>(
-            "SELECT packets.* FROM packets LEFT JOIN txs ON packets.tx_id = txs.id WHERE txs.chain = ? ORDER BY id")
+            "SELECT packets.* FROM packets LEFT JOIN txs ON packets.tx_id = txs.id WHERE txs.chain = ? AND packets.id <= ? ORDER BY id")
                 .bind(chain.as_str())
+                .bind(cutoff)
                 .fetch(pool);
 
     let mut ids = HashSet::new();
 
-    while let Some(Ok(packet)) = packets.next().await {
+    while let Some(Ok(packet)) =
+        db::timed(metrics, chain, "populate_fetch_packet", packets.next()).await
+    {
         metrics.chainpulse_packets(chain);
 
-        let tx = sqlx::query_as::<_, TxRow>("SELECT * FROM txs WHERE id = ? LIMIT 1")
-            .bind(packet.tx_id)
-            .fetch_one(pool)
-            .await?;
+        let tx = db::timed(
+            metrics,
+            chain,
+            "populate_fetch_tx",
+            db::retry_busy(metrics, chain, || fetch_tx(pool, packet.tx_id)),
+        )
+        .await?;
 
         if !ids.contains(&tx.id) {
             metrics.chainpulse_txs(chain);
@@ -49,10 +69,15 @@ pub async fn run(chain: &chain::Id, pool: &SqlitePool, metrics: &Metrics) -> cra
                 &tx.memo,
             );
         } else {
-            let effected_tx = sqlx::query_as::<_, TxRow>("SELECT * FROM txs WHERE id = ? LIMIT 1")
-                .bind(packet.effected_tx)
-                .fetch_one(pool)
-                .await?;
+            let effected_tx = db::timed(
+                metrics,
+                chain,
+                "populate_fetch_tx",
+                db::retry_busy(metrics, chain, || {
+                    fetch_tx(pool, packet.effected_tx.unwrap_or_default())
+                }),
+            )
+            .await?;
 
             metrics.ibc_uneffected_packets(
                 chain,
@@ -71,10 +96,19 @@ pub async fn run(chain: &chain::Id, pool: &SqlitePool, metrics: &Metrics) -> cra
                 &packet.dst_channel,
                 &packet.dst_port,
                 &packet.signer,
-                &packet.effected_signer.unwrap_or_default(),
+                packet.effected_signer.as_deref().unwrap_or_default(),
                 &tx.memo,
                 &effected_tx.memo,
-            )
+            );
+
+            metrics.ibc_frontrun_by_proposer(
+                chain,
+                tx.proposer.as_deref().unwrap_or(""),
+                &packet.signer,
+                packet.effected_signer.as_deref().unwrap_or_default(),
+            );
+
+            metrics.ibc_frontrun_inclusion_delay(chain, &packet.signer, &tx, &effected_tx);
         }
     }
 
@@ -83,3 +117,12 @@ pub async fn run(chain: &chain::Id, pool: &SqlitePool, metrics: &Metrics) -> cra
 
     Ok(())
 }
+
+async fn fetch_tx(pool: &SqlitePool, id: i64) -> crate::Result<TxRow> {
+    let tx = sqlx::query_as("SELECT * FROM txs WHERE id = ? LIMIT 1")
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(tx)
+}
@@ -0,0 +1,142 @@
+//! Quality of the collected data, ie. how many packets are missing one of their ends, which points at
+//! gaps in the collection, eg. blocks which were never processed, that would otherwise go unnoticed.
+//!
+//! - Unmatched acks: acknowledgements and timeouts relayed to a monitored chain for packets
+//!   which were never seen being sent from that chain.
+//! - Orphan receives: packets received on a monitored chain from another monitored chain,
+//!   which were never seen being sent from the latter.
+//!
+//! Both are expected for a while after starting to monitor a chain, for the packets sent before.
+
+use std::{collections::BTreeSet, time::Duration};
+
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tokio::time;
+use tracing::error;
+
+use crate::{config::Chains, metrics::Metrics, Result};
+
+const UPDATE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// The windows over which the packets are counted, with their label and SQLite modifier
+const WINDOWS: &[(&str, &str)] = &[("1h", "-1 hours"), ("24h", "-24 hours")];
+
+/// Periodically count the packets missing one of their ends
+pub async fn run(chains: Chains, pool: SqlitePool, metrics: Metrics) {
+    let monitored: BTreeSet<String> = chains
+        .endpoints
+        .keys()
+        .map(|chain_id| chain_id.to_string())
+        .collect();
+
+    loop {
+        if let Err(e) = update(&pool, &metrics, &monitored).await {
+            error!("Failed to check the quality of the collected data: {e}");
+        }
+
+        time::sleep(UPDATE_INTERVAL).await;
+    }
+}
+
+async fn update(pool: &SqlitePool, metrics: &Metrics, monitored: &BTreeSet<String>) -> Result<()> {
+    let unmatched_acks = r#"
+        SELECT packets.chain, packets.src_channel, packets.dst_channel, COUNT(*)
+        FROM packets
+        LEFT JOIN sent_packets
+            ON sent_packets.chain = packets.chain
+            AND sent_packets.src_channel = packets.src_channel
+            AND sent_packets.src_port = packets.src_port
+            AND sent_packets.sequence = packets.sequence
+        WHERE packets.effected
+            AND (packets.msg_type_url LIKE '%MsgAcknowledgement' OR packets.msg_type_url LIKE '%MsgTimeout')
+            AND packets.block_time >= datetime('now', ?)
+            AND sent_packets.id IS NULL
+        GROUP BY packets.chain, packets.src_channel, packets.dst_channel
+    "#;
+
+    let orphan_receives = r#"
+        SELECT packets.chain, channels.counterparty_chain, packets.src_channel, packets.dst_channel, COUNT(*)
+        FROM packets
+        JOIN channels
+            ON channels.chain = packets.chain
+            AND channels.port = packets.dst_port
+            AND channels.channel = packets.dst_channel
+        LEFT JOIN sent_packets
+            ON sent_packets.chain = channels.counterparty_chain
+            AND sent_packets.src_channel = packets.src_channel
+            AND sent_packets.src_port = packets.src_port
+            AND sent_packets.sequence = packets.sequence
+        WHERE packets.effected
+            AND packets.msg_type_url LIKE '%MsgRecvPacket'
+            AND packets.block_time >= datetime('now', ?)
+            AND channels.counterparty_chain IS NOT NULL
+            AND sent_packets.id IS NULL
+        GROUP BY packets.chain, channels.counterparty_chain, packets.src_channel, packets.dst_channel
+    "#;
+
+    let mut acks = Vec::new();
+    let mut receives = Vec::new();
+
+    for (window, modifier) in WINDOWS {
+        let rows: Vec<(Option<String>, String, String, i64)> = sqlx::query_as(unmatched_acks)
+            .bind(*modifier)
+            .fetch_all(pool)
+            .await?;
+
+        for (chain, src_channel, dst_channel, count) in rows {
+            let Some(chain_id) = chain.and_then(|chain| chain::Id::try_from(chain).ok()) else {
+                continue;
+            };
+
+            acks.push((chain_id, src_channel, dst_channel, *window, count));
+        }
+
+        let rows: Vec<(Option<String>, String, String, String, i64)> =
+            sqlx::query_as(orphan_receives)
+                .bind(*modifier)
+                .fetch_all(pool)
+                .await?;
+
+        for (chain, src_chain, src_channel, dst_channel, count) in rows {
+            // The packets sent from a chain which is not monitored are never seen being sent
+            if !monitored.contains(&src_chain) {
+                continue;
+            }
+
+            let Some(chain_id) = chain.and_then(|chain| chain::Id::try_from(chain).ok()) else {
+                continue;
+            };
+
+            receives.push((
+                chain_id,
+                src_chain,
+                src_channel,
+                dst_channel,
+                *window,
+                count,
+            ));
+        }
+    }
+
+    // Only reset the gauges once all windows have been computed,
+    // so that channels without any packet missing an end are removed
+    metrics.reset_chainpulse_data_quality();
+
+    for (chain_id, src_channel, dst_channel, window, count) in acks {
+        metrics.chainpulse_unmatched_acks(&chain_id, &src_channel, &dst_channel, window, count);
+    }
+
+    for (chain_id, src_chain, src_channel, dst_channel, window, count) in receives {
+        metrics.chainpulse_orphan_receives(
+            &chain_id,
+            &src_chain,
+            &src_channel,
+            &dst_channel,
+            window,
+            count,
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,27 @@
+//! gRPC queries of the Cosmos SDK modules, performed via the ABCI query RPC method.
+
+use prost::Message;
+use tendermint_rpc::{Client, WebSocketClient};
+
+use crate::Result;
+
+/// Perform a gRPC query via ABCI, eg. at `/cosmos.bank.v1beta1.Query/AllBalances`
+pub async fn abci_query<Req, Resp>(
+    client: &WebSocketClient,
+    path: &str,
+    request: Req,
+) -> Result<Resp>
+where
+    Req: Message,
+    Resp: Message + Default,
+{
+    let response = client
+        .abci_query(Some(path.to_string()), request.encode_to_vec(), None, false)
+        .await?;
+
+    if response.code.is_err() {
+        return Err(format!("query {path} failed: {}", response.log).into());
+    }
+
+    Ok(Resp::decode(response.value.as_slice())?)
+}
@@ -0,0 +1,175 @@
+//! On-disk queue of the blocks which could not be written to the database,
+//! eg. because it was busy or locked, and which are retried on the following blocks.
+//!
+//! The queue is bounded, and persisted to a JSON file whenever it changes,
+//! so that the blocks it holds survive a restart of chainpulse.
+
+use std::{
+    collections::VecDeque,
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use tendermint::chain;
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::warn;
+
+use crate::{config, Result};
+
+/// A block to retry
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub chain_id: chain::Id,
+    pub height: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct RetryQueue {
+    path: PathBuf,
+    max_size: usize,
+    entries: Arc<Mutex<VecDeque<Entry>>>,
+
+    /// Held while persisting the queue, so that the file is written by one task at a time
+    persisting: Arc<AsyncMutex<()>>,
+}
+
+impl RetryQueue {
+    /// Open the queue, loading the blocks left in it by a previous run, if any
+    pub fn open(config: &config::RetryQueue) -> Result<Self> {
+        let entries = match fs::read_to_string(&config.path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => VecDeque::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path: config.path.clone(),
+            max_size: config.max_size,
+            entries: Arc::new(Mutex::new(entries)),
+            persisting: Arc::new(AsyncMutex::new(())),
+        })
+    }
+
+    /// Queue a block to be retried, returning `false` if the queue is full
+    pub async fn push(&self, chain_id: &chain::Id, height: u64) -> bool {
+        let entry = Entry {
+            chain_id: chain_id.clone(),
+            height,
+        };
+
+        self.update(|entries| {
+            if entries.contains(&entry) {
+                return true;
+            }
+
+            if entries.len() >= self.max_size {
+                return false;
+            }
+
+            entries.push_back(entry);
+            true
+        })
+        .await
+    }
+
+    /// Take up to `max` blocks of the given chain out of the queue, oldest first
+    pub async fn take(&self, chain_id: &chain::Id, max: usize) -> Vec<u64> {
+        self.update(|entries| {
+            let mut taken = Vec::new();
+
+            entries.retain(|entry| {
+                if taken.len() < max && entry.chain_id == *chain_id {
+                    taken.push(entry.height);
+                    false
+                } else {
+                    true
+                }
+            });
+
+            taken
+        })
+        .await
+    }
+
+    /// The number of blocks of the given chain in the queue
+    pub fn len(&self, chain_id: &chain::Id) -> usize {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .filter(|entry| entry.chain_id == *chain_id)
+            .count()
+    }
+
+    /// Apply a change to the queue, persisting it if any entry was added or removed
+    async fn update<R>(&self, f: impl FnOnce(&mut VecDeque<Entry>) -> R) -> R {
+        let (result, changed) = {
+            let mut entries = self.entries.lock().unwrap();
+            let len = entries.len();
+            let result = f(&mut entries);
+            (result, entries.len() != len)
+        };
+
+        if changed {
+            if let Err(e) = self.persist().await {
+                warn!(
+                    "Failed to persist the retry queue to {}: {e}",
+                    self.path.display()
+                );
+            }
+        }
+
+        result
+    }
+
+    async fn persist(&self) -> Result<()> {
+        let _persisting = self.persisting.lock().await;
+
+        // Serialized once the file is ours, so that the latest state is the last one written
+        let content = serde_json::to_vec(&*self.entries.lock().unwrap())?;
+
+        // Write to a temporary file first, so that the queue is never left half-written
+        let tmp = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp, content).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queue() {
+        let dir = std::env::temp_dir().join(format!("chainpulse-queue-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let config = config::RetryQueue {
+            path: dir.join("queue.json"),
+            max_size: 3,
+        };
+
+        let (osmosis, neutron): (chain::Id, chain::Id) =
+            ("osmosis-1".parse().unwrap(), "neutron-1".parse().unwrap());
+
+        let queue = RetryQueue::open(&config).unwrap();
+        assert!(queue.push(&osmosis, 1).await);
+        assert!(queue.push(&neutron, 1).await);
+        assert!(queue.push(&osmosis, 2).await);
+        assert!(queue.push(&osmosis, 2).await);
+        assert!(!queue.push(&osmosis, 3).await);
+        assert_eq!(queue.len(&osmosis), 2);
+
+        // The queue survives a restart
+        let queue = RetryQueue::open(&config).unwrap();
+        assert_eq!(queue.take(&osmosis, 1).await, vec![1]);
+        assert_eq!(queue.take(&osmosis, 10).await, vec![2]);
+        assert_eq!(queue.len(&osmosis), 0);
+        assert_eq!(queue.len(&neutron), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tokio::time;
+use tracing::error;
+
+use crate::{metrics::Metrics, Result};
+
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The windows over which the ratios are computed, with their label and SQLite modifier
+const WINDOWS: &[(&str, &str)] = &[("1h", "-1 hours"), ("24h", "-24 hours")];
+
+/// Periodically compute the ratio of effected packets per signer and chain over
+/// sliding windows from the database, independently of the lifetime of the process.
+pub async fn run(pool: SqlitePool, metrics: Metrics) {
+    loop {
+        if let Err(e) = update(&pool, &metrics).await {
+            error!("Failed to compute effected ratios: {e}");
+        }
+
+        time::sleep(UPDATE_INTERVAL).await;
+    }
+}
+
+async fn update(pool: &SqlitePool, metrics: &Metrics) -> Result<()> {
+    let query = r#"
+        SELECT txs.chain, packets.signer, SUM(packets.effected), COUNT(*)
+        FROM packets JOIN txs ON packets.tx_id = txs.id
+        WHERE txs.block_time >= datetime('now', ?)
+        GROUP BY txs.chain, packets.signer
+    "#;
+
+    let mut ratios = Vec::new();
+
+    for (window, modifier) in WINDOWS {
+        let rows: Vec<(String, Option<String>, i64, i64)> = sqlx::query_as(query)
+            .bind(*modifier)
+            .fetch_all(pool)
+            .await?;
+
+        for (chain, signer, effected, total) in rows {
+            let Ok(chain_id) = chain::Id::try_from(chain) else {
+                continue;
+            };
+
+            let ratio = effected as f64 / total as f64;
+            ratios.push((chain_id, signer.unwrap_or_default(), *window, ratio));
+        }
+    }
+
+    // Only reset the gauges once all windows have been computed,
+    // so that signers which are no longer active are removed.
+    metrics.reset_ibc_effected_ratio();
+
+    for (chain_id, signer, window, ratio) in ratios {
+        metrics.ibc_effected_ratio(&chain_id, &signer, window, ratio);
+    }
+
+    Ok(())
+}
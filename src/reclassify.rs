@@ -0,0 +1,254 @@
+//! Re-run of the frontrun classification of the recorded packets from scratch, diffed against
+//! the stored results, to validate changes to the classification against real traffic before release.
+//!
+//! The packets of each chain are classified again in the order in which they were included on chain,
+//! ie. by height, tx and position in the tx, rather than in the order in which they were recorded,
+//! which may differ when blocks are processed concurrently or fetched again after being dropped.
+//! The first packet relayed for a given sequence is the effected one, and the ones relayed after it
+//! were frontrun by it. The database is only read, never modified.
+
+use std::{collections::HashMap, fmt};
+
+use futures::TryStreamExt;
+use sqlx::SqlitePool;
+use tracing::info;
+
+use crate::{
+    config::Config,
+    db::{self, ClassificationRow},
+    Result,
+};
+
+/// Options of the `reclassify` command
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Only check the given chain
+    pub chain: Option<String>,
+
+    /// How many differences to print per chain
+    pub limit: usize,
+}
+
+/// How the stored classification of a packet differs from the one computed from scratch
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Diff {
+    /// Stored as frontrun, but relayed first
+    NotFrontrun { stored_tx: Option<i64> },
+
+    /// Stored as effected, but frontrun by the given tx
+    MissedFrontrun { effected_tx: i64 },
+
+    /// Stored as frontrun, but by another tx than the one which relayed the packet first
+    WrongEffectedTx {
+        stored_tx: Option<i64>,
+        effected_tx: i64,
+    },
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diff::NotFrontrun { stored_tx } => {
+                write!(
+                    f,
+                    "stored as frontrun by tx {stored_tx:?}, but relayed first"
+                )
+            }
+            Diff::MissedFrontrun { effected_tx } => {
+                write!(f, "stored as effected, but frontrun by tx {effected_tx}")
+            }
+            Diff::WrongEffectedTx {
+                stored_tx,
+                effected_tx,
+            } => write!(
+                f,
+                "stored as frontrun by tx {stored_tx:?}, but frontrun by tx {effected_tx}"
+            ),
+        }
+    }
+}
+
+/// Identifies the relays of the same packet on a chain
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    src_channel: String,
+    src_port: String,
+    dst_channel: String,
+    dst_port: String,
+    sequence: i64,
+    msg_type_url: String,
+}
+
+/// Classifies the packets of a chain, which must be fed in the order in which they were included
+#[derive(Debug, Default)]
+struct Classifier {
+    /// The tx which relayed each packet first
+    effected: HashMap<Key, i64>,
+}
+
+impl Classifier {
+    /// Classify a packet, returning how its stored classification differs, if it does
+    fn classify(&mut self, row: &ClassificationRow) -> Option<Diff> {
+        let key = Key {
+            src_channel: row.src_channel.clone(),
+            src_port: row.src_port.clone(),
+            dst_channel: row.dst_channel.clone(),
+            dst_port: row.dst_port.clone(),
+            sequence: row.sequence,
+            msg_type_url: row.msg_type_url.clone(),
+        };
+
+        match self.effected.get(&key) {
+            None => {
+                self.effected.insert(key, row.tx_id);
+
+                (!row.effected).then_some(Diff::NotFrontrun {
+                    stored_tx: row.effected_tx,
+                })
+            }
+            Some(&effected_tx) if row.effected => Some(Diff::MissedFrontrun { effected_tx }),
+            Some(&effected_tx) if row.effected_tx != Some(effected_tx) => {
+                Some(Diff::WrongEffectedTx {
+                    stored_tx: row.effected_tx,
+                    effected_tx,
+                })
+            }
+            Some(_) => None,
+        }
+    }
+}
+
+/// Classify the packets of each chain again and print the differences with the stored classification.
+/// Fails if there is any, so that it can gate a release.
+pub async fn run(config: &Config, options: Options) -> Result<()> {
+    let pool = db::connect(&config.database.path).await?;
+
+    let chains: Vec<(String,)> = sqlx::query_as(
+        "SELECT DISTINCT chain FROM txs WHERE (?1 IS NULL OR chain = ?1) ORDER BY chain",
+    )
+    .bind(options.chain.as_deref())
+    .fetch_all(&pool)
+    .await?;
+
+    let mut total = 0;
+
+    for (chain,) in chains {
+        info!("Reclassifying the packets of {chain}...");
+
+        let (packets, diffs) = reclassify(&pool, &chain, options.limit).await?;
+        total += diffs;
+
+        println!("{chain}: {packets} packets, {diffs} classified differently");
+    }
+
+    if total > 0 {
+        return Err(format!("{total} packets are classified differently than stored").into());
+    }
+
+    Ok(())
+}
+
+/// Classify the packets of a chain, printing up to `limit` differences,
+/// and return the number of packets and of differences
+async fn reclassify(pool: &SqlitePool, chain: &str, limit: usize) -> Result<(u64, u64)> {
+    let query = r#"
+        SELECT packets.id, packets.tx_id, txs.height, packets.sequence,
+            packets.src_channel, packets.src_port, packets.dst_channel, packets.dst_port,
+            packets.msg_type_url, packets.signer, packets.effected, packets.effected_tx
+        FROM packets JOIN txs ON packets.tx_id = txs.id
+        WHERE txs.chain = ?
+        ORDER BY txs.height, txs.id, packets.id
+    "#;
+
+    let mut rows = sqlx::query_as::<_, ClassificationRow>(query)
+        .bind(chain)
+        .fetch(pool);
+
+    let mut classifier = Classifier::default();
+    let (mut packets, mut diffs) = (0, 0);
+
+    while let Some(row) = rows.try_next().await? {
+        packets += 1;
+
+        let Some(diff) = classifier.classify(&row) else {
+            continue;
+        };
+
+        if diffs < limit as u64 {
+            println!(
+                "  packet #{} ({} {}/{} -> {}/{}, sequence {}) in tx {} at height {}, signed by {}: {diff}",
+                row.id,
+                row.msg_type_url,
+                row.src_port,
+                row.src_channel,
+                row.dst_port,
+                row.dst_channel,
+                row.sequence,
+                row.tx_id,
+                row.height,
+                row.signer.as_deref().unwrap_or("unknown"),
+            );
+        }
+
+        diffs += 1;
+    }
+
+    Ok((packets, diffs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(id: i64, tx_id: i64, sequence: i64, effected_tx: Option<i64>) -> ClassificationRow {
+        ClassificationRow {
+            id,
+            tx_id,
+            height: tx_id,
+            sequence,
+            src_channel: "channel-0".to_string(),
+            src_port: "transfer".to_string(),
+            dst_channel: "channel-1".to_string(),
+            dst_port: "transfer".to_string(),
+            msg_type_url: "/ibc.core.channel.v1.MsgRecvPacket".to_string(),
+            signer: Some("relayer".to_string()),
+            effected: effected_tx.is_none(),
+            effected_tx,
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        let mut classifier = Classifier::default();
+
+        assert_eq!(classifier.classify(&row(1, 1, 1, None)), None);
+        assert_eq!(classifier.classify(&row(2, 2, 1, Some(1))), None);
+
+        assert_eq!(
+            classifier.classify(&row(3, 2, 2, Some(1))),
+            Some(Diff::NotFrontrun { stored_tx: Some(1) })
+        );
+        assert_eq!(
+            classifier.classify(&row(4, 3, 2, None)),
+            Some(Diff::MissedFrontrun { effected_tx: 2 })
+        );
+        assert_eq!(
+            classifier.classify(&row(5, 4, 1, Some(2))),
+            Some(Diff::WrongEffectedTx {
+                stored_tx: Some(2),
+                effected_tx: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_classify_other_packet() {
+        let mut classifier = Classifier::default();
+
+        let mut ack = row(2, 2, 1, None);
+        ack.msg_type_url = "/ibc.core.channel.v1.MsgAcknowledgement".to_string();
+
+        assert_eq!(classifier.classify(&row(1, 1, 1, None)), None);
+        assert_eq!(classifier.classify(&ack), None);
+    }
+}
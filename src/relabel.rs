@@ -0,0 +1,272 @@
+//! Relabeling of the exported metrics, applied to the gathered metric families before encoding.
+//!
+//! Series which end up with identical labels after relabeling, eg. after dropping a label,
+//! are merged together by summing their values.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use prometheus::proto::{Counter, Gauge, LabelPair, Metric, MetricFamily, MetricType};
+
+use crate::config::{RelabelAction, RelabelRule};
+
+#[derive(Clone, Debug, Default)]
+pub struct Relabeler {
+    rules: Arc<Vec<RelabelRule>>,
+}
+
+impl Relabeler {
+    pub fn new(rules: Vec<RelabelRule>) -> Self {
+        Self {
+            rules: Arc::new(rules),
+        }
+    }
+
+    pub fn apply(&self, families: Vec<MetricFamily>) -> Vec<MetricFamily> {
+        if self.rules.is_empty() {
+            return families;
+        }
+
+        families
+            .into_iter()
+            .map(|family| self.apply_family(family))
+            .collect()
+    }
+
+    fn apply_family(&self, mut family: MetricFamily) -> MetricFamily {
+        let rules = self
+            .rules
+            .iter()
+            .filter(|rule| rule.applies_to(family.get_name()))
+            .collect::<Vec<_>>();
+
+        if rules.is_empty() {
+            return family;
+        }
+
+        let field_type = family.get_field_type();
+        let mut merged: BTreeMap<Vec<(String, String)>, Metric> = BTreeMap::new();
+
+        for mut metric in family.take_metric() {
+            let mut labels = metric
+                .take_label()
+                .into_iter()
+                .map(|pair| (pair.get_name().to_string(), pair.get_value().to_string()))
+                .collect::<Vec<_>>();
+
+            for rule in &rules {
+                rule.action.apply(&mut labels);
+            }
+
+            labels.sort();
+
+            match merged.get_mut(&labels) {
+                Some(existing) => merge(field_type, existing, &metric),
+                None => {
+                    let pairs = labels
+                        .iter()
+                        .map(|(name, value)| {
+                            let mut pair = LabelPair::default();
+                            pair.set_name(name.clone());
+                            pair.set_value(value.clone());
+                            pair
+                        })
+                        .collect::<Vec<_>>();
+
+                    metric.set_label(pairs.into());
+                    merged.insert(labels, metric);
+                }
+            }
+        }
+
+        family.set_metric(merged.into_values().collect::<Vec<_>>().into());
+        family
+    }
+}
+
+impl RelabelRule {
+    fn applies_to(&self, metric: &str) -> bool {
+        self.metric.is_none() || self.metric.as_deref() == Some(metric)
+    }
+}
+
+impl RelabelAction {
+    fn apply(&self, labels: &mut Vec<(String, String)>) {
+        match self {
+            RelabelAction::Rename { label, to } => {
+                for (name, _) in labels.iter_mut().filter(|(name, _)| name == label) {
+                    *name = to.clone();
+                }
+            }
+            RelabelAction::Drop { label } => {
+                labels.retain(|(name, _)| name != label);
+            }
+            RelabelAction::Replace {
+                label,
+                value,
+                replacement,
+            } => {
+                for (_, v) in labels
+                    .iter_mut()
+                    .filter(|(name, v)| name == label && v == value)
+                {
+                    *v = replacement.clone();
+                }
+            }
+        }
+    }
+}
+
+/// Merge the value of `other` into `metric`, both having the same labels
+fn merge(field_type: MetricType, metric: &mut Metric, other: &Metric) {
+    match field_type {
+        MetricType::COUNTER => {
+            let mut counter = Counter::default();
+            counter.set_value(metric.get_counter().get_value() + other.get_counter().get_value());
+            metric.set_counter(counter);
+        }
+        MetricType::GAUGE => {
+            let mut gauge = Gauge::default();
+            gauge.set_value(metric.get_gauge().get_value() + other.get_gauge().get_value());
+            metric.set_gauge(gauge);
+        }
+        MetricType::HISTOGRAM => {
+            let mut histogram = metric.get_histogram().clone();
+            let other = other.get_histogram();
+
+            histogram.set_sample_count(histogram.get_sample_count() + other.get_sample_count());
+            histogram.set_sample_sum(histogram.get_sample_sum() + other.get_sample_sum());
+
+            let buckets = histogram
+                .get_bucket()
+                .iter()
+                .zip(other.get_bucket())
+                .map(|(bucket, other)| {
+                    let mut bucket = bucket.clone();
+                    bucket.set_cumulative_count(
+                        bucket.get_cumulative_count() + other.get_cumulative_count(),
+                    );
+                    bucket
+                })
+                .collect::<Vec<_>>();
+
+            histogram.set_bucket(buckets.into());
+            metric.set_histogram(histogram);
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::{IntCounterVec, Opts, Registry};
+
+    use super::*;
+
+    fn gather(rules: Vec<RelabelRule>) -> Vec<MetricFamily> {
+        let registry = Registry::new();
+        let counter = IntCounterVec::new(
+            Opts::new("ibc_effected_packets", "help"),
+            &["src_channel", "src_port"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(counter.clone())).unwrap();
+
+        counter
+            .with_label_values(&["channel-0", "transfer"])
+            .inc_by(2);
+        counter
+            .with_label_values(&["channel-0", "icahost"])
+            .inc_by(3);
+        counter.with_label_values(&["channel-1", "transfer"]).inc();
+
+        Relabeler::new(rules).apply(registry.gather())
+    }
+
+    fn series(family: &MetricFamily) -> Vec<(Vec<(String, String)>, f64)> {
+        family
+            .get_metric()
+            .iter()
+            .map(|metric| {
+                let labels = metric
+                    .get_label()
+                    .iter()
+                    .map(|pair| (pair.get_name().to_string(), pair.get_value().to_string()))
+                    .collect();
+
+                (labels, metric.get_counter().get_value())
+            })
+            .collect()
+    }
+
+    fn label(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn test_drop_merges_series() {
+        let families = gather(vec![RelabelRule {
+            metric: None,
+            action: RelabelAction::Drop {
+                label: "src_port".to_string(),
+            },
+        }]);
+
+        assert_eq!(
+            series(&families[0]),
+            vec![
+                (vec![label("src_channel", "channel-0")], 5.0),
+                (vec![label("src_channel", "channel-1")], 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_and_replace() {
+        let families = gather(vec![
+            RelabelRule {
+                metric: Some("ibc_effected_packets".to_string()),
+                action: RelabelAction::Replace {
+                    label: "src_channel".to_string(),
+                    value: "channel-0".to_string(),
+                    replacement: "osmosis-hub".to_string(),
+                },
+            },
+            RelabelRule {
+                metric: None,
+                action: RelabelAction::Rename {
+                    label: "src_channel".to_string(),
+                    to: "src_path".to_string(),
+                },
+            },
+        ]);
+
+        let series = series(&families[0]);
+        assert!(series.contains(&(
+            vec![
+                label("src_path", "osmosis-hub"),
+                label("src_port", "icahost")
+            ],
+            3.0
+        )));
+        assert!(series.contains(&(
+            vec![
+                label("src_path", "channel-1"),
+                label("src_port", "transfer")
+            ],
+            1.0
+        )));
+    }
+
+    #[test]
+    fn test_rule_for_other_metric() {
+        let families = gather(vec![RelabelRule {
+            metric: Some("ibc_uneffected_packets".to_string()),
+            action: RelabelAction::Drop {
+                label: "src_port".to_string(),
+            },
+        }]);
+
+        assert_eq!(series(&families[0]).len(), 3);
+    }
+}
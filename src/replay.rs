@@ -0,0 +1,129 @@
+//! Replay of a capture file, made with the `capture` field of a chain, through the same processing
+//! as the collectors, ie. decoding, frontrun detection, database and metrics.
+//!
+//! The blocks are processed one after the other, in the order in which they were captured,
+//! so that a replay always gives the same results, and stops at the first block which fails.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use tendermint::abci::response::DeliverTx;
+use tracing::{debug, info, warn};
+
+use crate::{
+    capture::{self, Record},
+    collect,
+    config::Config,
+    db,
+    metrics::{self, Metrics},
+    msg::Decoders,
+    plugins::Plugins,
+    relabel::Relabeler,
+    trace::BlockTrace,
+    Result,
+};
+
+/// Options of the `replay` command
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The capture file to replay
+    pub file: PathBuf,
+
+    /// Where to create the database the blocks are recorded to
+    pub database: PathBuf,
+
+    /// Whether to print the resulting metrics once done
+    pub print_metrics: bool,
+}
+
+/// Feed the blocks of the capture file through the collector
+pub async fn run(config: &Config, options: Options) -> Result<()> {
+    if options.database.exists() {
+        return Err(format!(
+            "refusing to replay into existing database {}",
+            options.database.display()
+        )
+        .into());
+    }
+
+    info!(
+        "Replaying {} into {}",
+        options.file.display(),
+        options.database.display()
+    );
+
+    let pool = db::connect(&options.database).await?;
+    db::setup(&pool).await?;
+
+    let (metrics, registries) = Metrics::new(config.memo);
+    let decoders = Decoders::new();
+    let plugins = Plugins::load(&config.plugins)?;
+
+    let (mut events, mut blocks) = (0, 0);
+
+    // The blocks processed whose results were not replayed yet, by height
+    let mut processed = HashMap::new();
+
+    for record in capture::read(&options.file)? {
+        match record? {
+            Record::NewBlock { height, .. } => {
+                debug!("NewBlock event at height {height}");
+                events += 1;
+            }
+            Record::Block { block } => {
+                let block = block.into_owned();
+                let (chain_id, height) = (block.header.chain_id.clone(), block.header.height);
+                info!("Replaying block {height} of {chain_id}");
+
+                let txs = collect::process_block(
+                    &pool,
+                    &chain_id,
+                    block,
+                    config.memo,
+                    &metrics,
+                    &decoders,
+                    &plugins,
+                    &mut BlockTrace::new(height.value()),
+                )
+                .await
+                .map_err(|e| format!("failed to replay block {height} of {chain_id}: {e}"))?;
+
+                processed.insert(height.value(), (chain_id, txs));
+                blocks += 1;
+            }
+            Record::TxsResults {
+                height,
+                txs_results,
+            } => {
+                let Some((chain_id, txs)) = processed.remove(&height) else {
+                    warn!("Skipping the results of block {height}, which was not captured");
+                    continue;
+                };
+
+                let txs_results = txs_results
+                    .into_iter()
+                    .map(DeliverTx::from)
+                    .collect::<Vec<_>>();
+
+                collect::process_results(&pool, &chain_id, &txs, &txs_results, &metrics)
+                    .await
+                    .map_err(|e| {
+                        format!("failed to replay the results of block {height} of {chain_id}: {e}")
+                    })?;
+            }
+        }
+    }
+
+    pool.close().await;
+
+    info!("Replayed {events} NewBlock events and {blocks} blocks");
+
+    if options.print_metrics {
+        let relabeler = Relabeler::new(config.metrics.relabel.clone());
+        print!(
+            "{}",
+            metrics::encode(registries.gather(), &relabeler, |_| true)
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,272 @@
+//! Retention of the data of the in-memory database, which only keeps the rows of the last window,
+//! so that its size stays bounded when running without a database file.
+//!
+//! Frontrun detection only looks for the earlier relays of a packet within the window, and the
+//! statistics computed from the database, eg. the ratios, net flows and anomalies, only cover it.
+//!
+//! The rows matching one of the `keep` rules of the database are never pruned, so that the data of interest,
+//! eg. the frontruns or the traffic of a given channel, can be collected over long periods.
+
+use std::time::Duration;
+
+use sqlx::SqlitePool;
+use tokio::time;
+use tracing::{debug, error};
+
+use crate::{config::Keep, Result};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A table which is pruned, along with how the criteria of the `keep` rules apply to its rows
+struct Table {
+    name: &'static str,
+
+    /// The column holding the date of a row
+    date: &'static str,
+
+    /// The columns holding the channels of a row
+    channels: &'static [&'static str],
+
+    /// The condition matching the rows which were frontrun, if they can be
+    frontrun: Option<&'static str>,
+
+    /// The expression of the memo of the tx of a row, if it has one
+    memo: Option<&'static str>,
+}
+
+const fn table(name: &'static str, date: &'static str, channels: &'static [&'static str]) -> Table {
+    Table {
+        name,
+        date,
+        channels,
+        frontrun: None,
+        memo: None,
+    }
+}
+
+/// The tables to prune, in an order which respects the references between them
+const TABLES: &[Table] = &[
+    Table {
+        frontrun: Some("effected = 0"),
+        memo: Some("(SELECT memo FROM txs WHERE txs.id = packets.tx_id)"),
+        ..table("packets", "created_at", &["src_channel", "dst_channel"])
+    },
+    table(
+        "sent_packets",
+        "first_seen_at",
+        &["src_channel", "dst_channel"],
+    ),
+    table(
+        "stuck_history",
+        "observed_at",
+        &["src_channel", "dst_channel"],
+    ),
+    table(
+        "transfer_failures",
+        "created_at",
+        &["src_channel", "dst_channel"],
+    ),
+    table(
+        "transfer_flows",
+        "created_at",
+        &["channel", "counterparty_channel"],
+    ),
+    table(
+        "packet_hops",
+        "created_at",
+        &["src_channel", "next_src_channel"],
+    ),
+    Table {
+        frontrun: Some("effected = 0"),
+        ..table("packets_hourly", "hour", &["src_channel", "dst_channel"])
+    },
+    table("dead_letters", "created_at", &[]),
+];
+
+/// The txs, which are pruned once the packets are
+const TXS: Table = Table {
+    memo: Some("memo"),
+    ..table("txs", "created_at", &[])
+};
+
+/// Periodically delete the rows older than the window, except the ones to keep
+pub async fn run(pool: SqlitePool, window: Duration, keep: Vec<Keep>) {
+    loop {
+        time::sleep(PRUNE_INTERVAL).await;
+
+        if let Err(e) = prune(&pool, window, &keep).await {
+            error!("Failed to prune the in-memory database: {e}");
+        }
+    }
+}
+
+async fn prune(pool: &SqlitePool, window: Duration, keep: &[Keep]) -> Result<()> {
+    let modifier = format!("-{} seconds", window.as_secs());
+
+    for table in TABLES {
+        let (kept, binds) = keep_condition(table, keep);
+
+        let query = format!(
+            "DELETE FROM {} WHERE {} < datetime('now', ?) AND NOT ({kept})",
+            table.name, table.date
+        );
+
+        let deleted = binds
+            .iter()
+            .fold(sqlx::query(&query).bind(&modifier), |query, bind| {
+                query.bind(*bind)
+            })
+            .execute(pool)
+            .await?;
+
+        debug!(
+            "Pruned {} rows from {}",
+            deleted.rows_affected(),
+            table.name
+        );
+    }
+
+    // The txs still referenced by a packet are kept
+    let (kept, binds) = keep_condition(&TXS, keep);
+
+    let query = format!(
+        r#"
+        DELETE FROM txs
+        WHERE created_at < datetime('now', ?)
+            AND NOT EXISTS (SELECT 1 FROM packets WHERE packets.tx_id = txs.id)
+            AND NOT EXISTS (SELECT 1 FROM packets WHERE packets.effected_tx = txs.id)
+            AND NOT ({kept})
+        "#
+    );
+
+    let deleted = binds
+        .iter()
+        .fold(sqlx::query(&query).bind(&modifier), |query, bind| {
+            query.bind(*bind)
+        })
+        .execute(pool)
+        .await?;
+
+    debug!("Pruned {} rows from txs", deleted.rows_affected());
+
+    Ok(())
+}
+
+/// The condition matching the rows of a table to keep, along with the values to bind to it, in order.
+/// Rules with a criterion which does not apply to the table, or without any criterion, are ignored.
+fn keep_condition<'a>(table: &Table, keep: &'a [Keep]) -> (String, Vec<&'a str>) {
+    let mut conditions = Vec::new();
+    let mut binds = Vec::new();
+
+    for rule in keep {
+        let mut criteria = Vec::new();
+        let mut rule_binds = Vec::new();
+
+        if rule.frontruns {
+            let Some(frontrun) = table.frontrun else {
+                continue;
+            };
+
+            criteria.push(frontrun.to_string());
+        }
+
+        if let Some(channel) = &rule.channel {
+            if table.channels.is_empty() {
+                continue;
+            }
+
+            let columns = table
+                .channels
+                .iter()
+                .map(|column| format!("{column} = ?"))
+                .collect::<Vec<_>>();
+
+            criteria.push(format!("({})", columns.join(" OR ")));
+            rule_binds.extend(table.channels.iter().map(|_| channel.as_str()));
+        }
+
+        if let Some(memo) = &rule.memo {
+            let Some(expr) = table.memo else {
+                continue;
+            };
+
+            criteria.push(format!("{expr} GLOB ?"));
+            rule_binds.push(memo.as_str());
+        }
+
+        if criteria.is_empty() {
+            continue;
+        }
+
+        conditions.push(format!("({})", criteria.join(" AND ")));
+        binds.extend(rule_binds);
+    }
+
+    if conditions.is_empty() {
+        return ("FALSE".to_string(), binds);
+    }
+
+    (conditions.join(" OR "), binds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> Vec<Keep> {
+        vec![
+            Keep {
+                frontruns: true,
+                ..Keep::default()
+            },
+            Keep {
+                channel: Some("channel-0".to_string()),
+                ..Keep::default()
+            },
+            Keep {
+                channel: Some("channel-1".to_string()),
+                memo: Some("*hermes*".to_string()),
+                ..Keep::default()
+            },
+            Keep::default(),
+        ]
+    }
+
+    #[test]
+    fn test_kept_packets() {
+        let rules = rules();
+        let (kept, binds) = keep_condition(&TABLES[0], &rules);
+
+        assert_eq!(
+            kept,
+            "(effected = 0) OR ((src_channel = ? OR dst_channel = ?)) OR \
+            ((src_channel = ? OR dst_channel = ?) AND (SELECT memo FROM txs WHERE txs.id = packets.tx_id) GLOB ?)"
+        );
+        assert_eq!(
+            binds,
+            [
+                "channel-0",
+                "channel-0",
+                "channel-1",
+                "channel-1",
+                "*hermes*"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kept_other_tables() {
+        let rules = rules();
+
+        let (kept, binds) = keep_condition(&TABLES[4], &rules);
+        assert_eq!(kept, "((channel = ? OR counterparty_channel = ?))");
+        assert_eq!(binds, ["channel-0", "channel-0"]);
+
+        let (kept, binds) = keep_condition(&TXS, &rules);
+        assert_eq!(kept, "FALSE");
+        assert!(binds.is_empty());
+
+        let (kept, _) = keep_condition(&TABLES[0], &[]);
+        assert_eq!(kept, "FALSE");
+    }
+}
@@ -0,0 +1,208 @@
+//! Classification of the signers relaying on each channel as primary or backup relayers,
+//! based on their share of the packets effected on the channel over a baseline period.
+//!
+//! The signers effecting at least a given share of the packets on a channel, or the one effecting
+//! the most if none does, are its primary relayers, and the others its backups. The roles are
+//! recorded in the `relayer_roles` table, so that the collectors can count the packets which only
+//! got relayed because a backup stepped in, ie. which were effected by a backup relayer.
+
+use std::collections::BTreeMap;
+
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tokio::time;
+use tracing::error;
+
+use crate::{config::RelayerRoles, metrics::Metrics, Result};
+
+/// The role of a signer on a channel
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    Primary,
+    Backup,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Primary => "primary",
+            Self::Backup => "backup",
+        }
+    }
+}
+
+/// Classify the signers of a channel from the number of packets each effected on it,
+/// returning their role along with their share of the packets
+pub fn classify(signers: &[(String, u64)], primary_share: f64) -> Vec<(String, Role, f64)> {
+    let total = signers.iter().map(|(_, count)| count).sum::<u64>().max(1) as f64;
+    let top = signers.iter().map(|(_, count)| *count).max().unwrap_or(0);
+
+    signers
+        .iter()
+        .map(|(signer, count)| {
+            let share = *count as f64 / total;
+
+            let role = if share >= primary_share || *count == top {
+                Role::Primary
+            } else {
+                Role::Backup
+            };
+
+            (signer.clone(), role, share)
+        })
+        .collect()
+}
+
+/// Whether a packet effected by the given signer on a channel was relayed by a backup relayer,
+/// ie. the roles of the channel are known and the signer is not one of its primary relayers
+pub async fn is_backup(
+    pool: &SqlitePool,
+    chain_id: &chain::Id,
+    src_channel: &str,
+    dst_channel: &str,
+    signer: &str,
+) -> Result<bool> {
+    let query = r#"
+        SELECT COUNT(*) > 0 AND COALESCE(SUM(signer = ?4 AND role = 'primary'), 0) = 0
+        FROM relayer_roles
+        WHERE chain = ?1 AND src_channel = ?2 AND dst_channel = ?3
+    "#;
+
+    let (is_backup,): (bool,) = sqlx::query_as(query)
+        .bind(chain_id.as_str())
+        .bind(src_channel)
+        .bind(dst_channel)
+        .bind(signer)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(is_backup)
+}
+
+/// Periodically classify the signers relaying on each channel
+pub async fn run(pool: SqlitePool, metrics: Metrics, config: RelayerRoles) {
+    loop {
+        if let Err(e) = update(&pool, &metrics, &config).await {
+            error!("Failed to classify the relayers: {e}");
+        }
+
+        time::sleep(config.interval()).await;
+    }
+}
+
+async fn update(pool: &SqlitePool, metrics: &Metrics, config: &RelayerRoles) -> Result<()> {
+    let query = r#"
+        SELECT chain, src_channel, dst_channel, signer, COUNT(*)
+        FROM packets
+        WHERE effected = 1 AND signer IS NOT NULL AND chain IS NOT NULL
+            AND block_time >= datetime('now', ?)
+        GROUP BY chain, src_channel, dst_channel, signer
+    "#;
+
+    let rows: Vec<(String, String, String, String, i64)> = sqlx::query_as(query)
+        .bind(format!("-{} seconds", config.baseline))
+        .fetch_all(pool)
+        .await?;
+
+    let mut channels: BTreeMap<_, Vec<_>> = BTreeMap::new();
+
+    for (chain, src_channel, dst_channel, signer, count) in rows {
+        channels
+            .entry((chain, src_channel, dst_channel))
+            .or_default()
+            .push((signer, count as u64));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM relayer_roles")
+        .execute(&mut *tx)
+        .await?;
+
+    // Reset the gauge so that signers which are no longer active are removed
+    metrics.reset_ibc_relayer_role();
+
+    for ((chain, src_channel, dst_channel), signers) in channels {
+        let Ok(chain_id) = chain::Id::try_from(chain) else {
+            continue;
+        };
+
+        for (signer, role, share) in classify(&signers, config.primary_share) {
+            sqlx::query(
+                r#"
+                INSERT INTO relayer_roles (chain, src_channel, dst_channel, signer, role, share, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, datetime('now'))
+                "#,
+            )
+            .bind(chain_id.as_str())
+            .bind(&src_channel)
+            .bind(&dst_channel)
+            .bind(&signer)
+            .bind(role.as_str())
+            .bind(share)
+            .execute(&mut *tx)
+            .await?;
+
+            metrics.ibc_relayer_role(
+                &chain_id,
+                &src_channel,
+                &dst_channel,
+                &signer,
+                role.as_str(),
+                share,
+            );
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roles(signers: &[(&str, u64)], primary_share: f64) -> Vec<(String, Role)> {
+        let signers: Vec<_> = signers
+            .iter()
+            .map(|(signer, count)| (signer.to_string(), *count))
+            .collect();
+
+        classify(&signers, primary_share)
+            .into_iter()
+            .map(|(signer, role, _)| (signer, role))
+            .collect()
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            roles(&[("a", 80), ("b", 15), ("c", 5)], 0.2),
+            [
+                ("a".to_string(), Role::Primary),
+                ("b".to_string(), Role::Backup),
+                ("c".to_string(), Role::Backup),
+            ]
+        );
+
+        // Two relayers sharing the channel
+        assert_eq!(
+            roles(&[("a", 50), ("b", 45), ("c", 5)], 0.2),
+            [
+                ("a".to_string(), Role::Primary),
+                ("b".to_string(), Role::Primary),
+                ("c".to_string(), Role::Backup),
+            ]
+        );
+
+        // No signer reaching the share, the top one is still primary
+        assert_eq!(
+            roles(&[("a", 10), ("b", 9)], 0.6),
+            [
+                ("a".to_string(), Role::Primary),
+                ("b".to_string(), Role::Backup),
+            ]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! Full-text search over the memos of the collected txs, backed by the `txs_memo_fts` index.
+
+use sqlx::SqlitePool;
+
+use crate::{
+    api::{self, Scope},
+    db::MemoMatch,
+    Result,
+};
+
+/// Find the txs whose memo contains the given text within the given scope, most recent first,
+/// optionally only on the given chain.
+pub async fn memos(
+    pool: &SqlitePool,
+    scope: Scope<'_>,
+    memo: &str,
+    chain: Option<&str>,
+    limit: Option<u32>,
+) -> Result<Vec<MemoMatch>> {
+    let query = format!(
+        r#"
+        SELECT txs.id, txs.chain, txs.height, txs.hash, txs.memo, txs.block_time, txs.created_at,
+               (SELECT COUNT(*) FROM packets WHERE packets.tx_id = txs.id) AS packets
+        FROM txs_memo_fts
+        JOIN txs ON txs.id = txs_memo_fts.rowid
+        WHERE   txs_memo_fts MATCH ?1
+            AND (?2 IS NULL OR txs.chain = ?2)
+            AND {}
+        ORDER BY txs.id DESC
+        LIMIT ?4
+        "#,
+        Scope::filter(3, "txs.chain", &[])
+    );
+
+    let limit = api::limit(limit);
+
+    let rows = sqlx::query_as(&query)
+        .bind(fts_query(memo))
+        .bind(chain)
+        .bind(scope.chains()?)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows)
+}
+
+/// Quote the searched text as a single FTS5 phrase, so that it is matched literally
+/// instead of being interpreted with the FTS5 query syntax.
+fn fts_query(memo: &str) -> String {
+    format!("\"{}\"", memo.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(fts_query("hermes"), r#""hermes""#);
+        assert_eq!(fts_query("relayed by hermes"), r#""relayed by hermes""#);
+        assert_eq!(fts_query(r#"say "hi" OR *"#), r#""say ""hi"" OR *""#);
+    }
+
+    #[tokio::test]
+    async fn test_scoped_memos() {
+        let pool = crate::db::connect_in_memory().await.unwrap();
+        crate::db::setup(&pool).await.unwrap();
+
+        // More matching txs on the chain the key cannot see than fit in a page
+        for chain in ["osmosis-1", "cosmoshub-4", "cosmoshub-4", "cosmoshub-4"] {
+            sqlx::query(
+                r#"
+                INSERT INTO txs (chain, height, hash, memo, created_at, block_time)
+                VALUES (?1, 1, hex(randomblob(32)), 'relayed by hermes', datetime('now'), datetime('now'))
+                "#,
+            )
+            .bind(chain)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let chains = [tendermint::chain::Id::try_from("osmosis-1").unwrap()];
+        let scope = Scope {
+            chains: Some(&chains),
+            channels: None,
+        };
+
+        let rows = memos(&pool, scope, "hermes", None, Some(2)).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].chain, "osmosis-1");
+
+        let rows = memos(&pool, Scope::default(), "hermes", None, Some(2))
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+    }
+}
@@ -0,0 +1,185 @@
+//! Monitoring of the account sequences of relayers.
+//!
+//! The sequence of the last relay tx landed by each signer is recorded as txs are collected,
+//! and the account sequence of the configured relayer addresses is periodically queried and compared to it.
+//! A relayer whose account sequence stops moving has stopped landing txs entirely, eg. because it is stuck
+//! on a sequence mismatch, while a gap between both means that txs were landed without being collected.
+
+use std::time::Duration;
+
+use ibc_proto::{
+    cosmos::auth::v1beta1::{BaseAccount, QueryAccountRequest, QueryAccountResponse},
+    google::protobuf::Any,
+};
+use prost::Message;
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tendermint_rpc::WebSocketClient;
+use tokio::time;
+use tracing::error;
+
+use crate::{
+    config::{Chains, Endpoint},
+    metrics::Metrics,
+    query::abci_query,
+    Result,
+};
+
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Accounts which wrap a `BaseAccount` in their first field, eg. vesting or Ethermint accounts
+#[derive(Clone, PartialEq, prost::Message)]
+struct WrappedAccount {
+    #[prost(message, optional, tag = "1")]
+    base_account: Option<BaseAccount>,
+}
+
+/// Record the sequence of a tx landed by a signer, if it is the latest one seen
+pub async fn record(
+    pool: &SqlitePool,
+    chain_id: &chain::Id,
+    signer: &str,
+    sequence: u64,
+    height: u64,
+) -> Result<()> {
+    let query = r#"
+        INSERT INTO signer_sequences (chain, signer, sequence, height, updated_at)
+        VALUES (?, ?, ?, ?, datetime('now'))
+        ON CONFLICT (chain, signer) DO UPDATE SET
+            sequence = excluded.sequence,
+            height = excluded.height,
+            updated_at = excluded.updated_at
+        WHERE excluded.sequence > signer_sequences.sequence
+    "#;
+
+    sqlx::query(query)
+        .bind(chain_id.as_str())
+        .bind(signer)
+        .bind(sequence as i64)
+        .bind(height as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Periodically query the account sequence of the configured relayer addresses,
+/// and compare it to the sequence of the last tx they landed
+pub async fn run(chains: Chains, pool: SqlitePool, metrics: Metrics) {
+    let chains = chains
+        .endpoints
+        .into_iter()
+        .filter(|(_, endpoint)| !endpoint.relayer_addresses.is_empty())
+        .collect::<Vec<_>>();
+
+    loop {
+        for (chain_id, endpoint) in &chains {
+            if let Err(e) = update(chain_id, endpoint, &pool, &metrics).await {
+                error!("Failed to query the account sequences on {chain_id}: {e}");
+            }
+        }
+
+        time::sleep(UPDATE_INTERVAL).await;
+    }
+}
+
+async fn update(
+    chain_id: &chain::Id,
+    endpoint: &Endpoint,
+    pool: &SqlitePool,
+    metrics: &Metrics,
+) -> Result<()> {
+    let (client, driver) = WebSocketClient::builder(endpoint.url.clone())
+        .compat_mode(endpoint.comet_version)
+        .build()
+        .await?;
+
+    let driver = tokio::spawn(driver.run());
+
+    let mut result = Ok(());
+
+    for address in &endpoint.relayer_addresses {
+        let sequence = match account_sequence(&client, address).await {
+            Ok(sequence) => sequence,
+            Err(e) => {
+                result = Err(e);
+                continue;
+            }
+        };
+
+        metrics.ibc_relayer_account_sequence(chain_id, address, sequence);
+
+        let last: Option<(i64,)> =
+            sqlx::query_as("SELECT sequence FROM signer_sequences WHERE chain = ? AND signer = ?")
+                .bind(chain_id.as_str())
+                .bind(address)
+                .fetch_optional(pool)
+                .await?;
+
+        // Landing a tx increments the account sequence, which is thus one more than the one of the last tx
+        if let Some((last,)) = last {
+            let gap = sequence as i64 - (last + 1);
+            metrics.ibc_relayer_sequence_gap(chain_id, address, gap);
+        }
+    }
+
+    let _ = client.close();
+    let _ = driver.await;
+
+    result
+}
+
+async fn account_sequence(client: &WebSocketClient, address: &str) -> Result<u64> {
+    let request = QueryAccountRequest {
+        address: address.to_string(),
+    };
+
+    let response: QueryAccountResponse =
+        abci_query(client, "/cosmos.auth.v1beta1.Query/Account", request).await?;
+
+    let account = response.account.ok_or("account not found")?;
+
+    base_account(&account)
+        .map(|account| account.sequence)
+        .ok_or_else(|| format!("unsupported account type {}", account.type_url).into())
+}
+
+fn base_account(account: &Any) -> Option<BaseAccount> {
+    if account.type_url == "/cosmos.auth.v1beta1.BaseAccount" {
+        return BaseAccount::decode(account.value.as_slice()).ok();
+    }
+
+    WrappedAccount::decode(account.value.as_slice())
+        .ok()?
+        .base_account
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_account() {
+        let base = BaseAccount {
+            address: "osmo1relayer".to_string(),
+            pub_key: None,
+            account_number: 42,
+            sequence: 1337,
+        };
+
+        let account = Any {
+            type_url: "/cosmos.auth.v1beta1.BaseAccount".to_string(),
+            value: base.encode_to_vec(),
+        };
+        assert_eq!(base_account(&account), Some(base.clone()));
+
+        let wrapped = Any {
+            type_url: "/injective.types.v1beta1.EthAccount".to_string(),
+            value: WrappedAccount {
+                base_account: Some(base.clone()),
+            }
+            .encode_to_vec(),
+        };
+        assert_eq!(base_account(&wrapped), Some(base));
+    }
+}
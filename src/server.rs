@@ -0,0 +1,927 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use axum::{
+    body::StreamBody,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router, Server,
+};
+use futures::channel::mpsc;
+use prometheus::proto::Metric;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tracing::{error, info};
+
+use crate::{
+    aggregates,
+    api::{self, Page},
+    channels,
+    checkpoint::{self, Snapshot},
+    clear::{self, Suggestion},
+    config::{Admin, ApiKey},
+    db::{
+        ChannelRow, FrontrunRow, HourlyPacketsRow, IgnoredMsgRow, MemoMatch, PacketApiRow,
+        PacketExportRow, PendingPacketRow, StuckRow, TransferFailureRow,
+    },
+    export, failures,
+    hops::{self, Hop},
+    ignored,
+    integrity::{Integrity, Report},
+    metrics::{self, Registries},
+    pending,
+    relabel::Relabeler,
+    search,
+    startup::{self, Phase},
+    state::{Collector, Collectors},
+    status,
+    trace::BlockTrace,
+    Result,
+};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub registries: Registries,
+    pub relabeler: Relabeler,
+    pub chains: Arc<BTreeSet<chain::Id>>,
+    pub collectors: Collectors,
+    pub pool: SqlitePool,
+    pub admin: Option<Admin>,
+    pub snapshot_dir: Option<PathBuf>,
+    pub api_keys: Arc<Vec<ApiKey>>,
+    pub integrity: Integrity,
+    pub startup: startup::State,
+
+    /// Age in seconds after which a pending packet is considered stuck
+    pub stuck_min_age: u64,
+}
+
+pub async fn run(port: u16, state: AppState) -> Result<()> {
+    let mut app = Router::new()
+        .route("/metrics", get(get_metrics))
+        .route("/metrics/:chain_id", get(get_chain_metrics))
+        .route("/status", get(get_status))
+        .route("/health", get(get_health))
+        .route("/stuck_history", get(get_stuck_history))
+        .route("/api/v1/search", get(search_memos))
+        .route("/api/v1/transfer_failures", get(get_transfer_failures))
+        .route("/api/v1/packets", get(get_packets))
+        .route("/api/v1/packets.csv", get(get_packets_csv))
+        .route("/api/v1/packets/hourly", get(get_hourly_packets))
+        .route("/api/v1/packets/pending", get(get_pending_packets))
+        .route("/api/v1/packets/pending/clear", get(get_clear_commands))
+        .route("/api/v1/txs/:hash", get(get_txs))
+        .route("/api/v1/frontruns", get(get_frontruns))
+        .route("/api/v1/ignored_msgs", get(get_ignored_msgs))
+        .route("/api/v1/channels", get(get_channels))
+        .route("/api/v1/trace", get(get_trace))
+        .route("/debug/last-block/:chain_id", get(get_last_block));
+
+    if !state.api_keys.is_empty() {
+        info!("API keys required to access the metrics and status");
+    }
+
+    if state.admin.is_some() {
+        info!("Admin API enabled");
+
+        app = app
+            .route("/admin/chains/:chain_id/pause", post(pause_chain))
+            .route("/admin/chains/:chain_id/resume", post(resume_chain))
+            .route("/admin/db/snapshot", post(snapshot_db));
+    }
+
+    let app = app.with_state(state);
+
+    let server =
+        Server::bind(&SocketAddr::from(([0, 0, 0, 0], port))).serve(app.into_make_service());
+
+    info!("Metrics server listening at http://localhost:{port}/metrics");
+    server.await?;
+
+    Ok(())
+}
+
+/// Query parameters of the `/metrics` endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct MetricsQuery {
+    /// Only return the series for this chain
+    pub chain: Option<String>,
+}
+
+pub async fn get_metrics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<MetricsQuery>,
+) -> std::result::Result<String, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    Ok(metrics::encode(
+        state.registries.gather(),
+        &state.relabeler,
+        |metric| {
+            let chain = query.chain.as_deref();
+            let in_chain =
+                chain.map_or(true, |chain| metrics::chains_of(metric).any(|c| c == chain));
+
+            in_chain && visibility.can_see(metric)
+        },
+    ))
+}
+
+/// Export only the metrics recorded in the registry of the given chain, so that each chain can be scraped separately
+pub async fn get_chain_metrics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(chain_id): Path<chain::Id>,
+) -> std::result::Result<String, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    if !state.chains.contains(&chain_id) || !visibility.can_see_chain(chain_id.as_str()) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(metrics::encode(
+        state.registries.gather_chain(&chain_id),
+        &state.relabeler,
+        |metric| visibility.can_see(metric),
+    ))
+}
+
+pub async fn get_status(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<BTreeMap<chain::Id, Collector>>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    let mut snapshot = state.collectors.snapshot();
+    snapshot.retain(|chain_id, _| visibility.can_see_chain(chain_id.as_str()));
+
+    Ok(Json(snapshot))
+}
+
+/// The health of chainpulse, as served at `/health`
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    pub status: &'static str,
+
+    /// The current phase of the startup, `ready` once the metrics were populated on start
+    pub startup: Phase,
+
+    /// The result of the last check of the integrity of the database, if checked already
+    pub database: Option<Report>,
+}
+
+/// Report whether chainpulse is healthy, failing with 503 when the database was found corrupted
+pub async fn get_health(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    let database = state.integrity.last();
+    let startup = state.startup.phase();
+
+    if database.as_ref().map_or(true, Report::is_ok) {
+        let status = "ok";
+        (
+            StatusCode::OK,
+            Json(HealthResponse {
+                status,
+                startup,
+                database,
+            }),
+        )
+    } else {
+        let status = "database_corrupted";
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(HealthResponse {
+                status,
+                startup,
+                database,
+            }),
+        )
+    }
+}
+
+/// Query parameters of the `/stuck_history` endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct StuckHistoryQuery {
+    /// Only return the observations of channels from or to this chain
+    pub chain: Option<String>,
+
+    /// Only return the observations of this channel, either as source or destination
+    pub channel: Option<String>,
+
+    /// Only return the observations since this date, eg. `2023-10-01` or `2023-10-01 12:00:00`,
+    /// defaults to the last 30 days
+    pub since: Option<String>,
+}
+
+pub async fn get_stuck_history(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<StuckHistoryQuery>,
+) -> std::result::Result<Json<Vec<StuckRow>>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    let mut rows = status::history(
+        &state.pool,
+        query.chain.as_deref(),
+        query.channel.as_deref(),
+        query.since.as_deref(),
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to fetch stuck packets history: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    rows.retain(|row| {
+        (visibility.can_see_chain(&row.src_chain) || visibility.can_see_chain(&row.dst_chain))
+            && (visibility.can_see_channel(&row.src_channel)
+                || visibility.can_see_channel(&row.dst_channel))
+    });
+
+    Ok(Json(rows))
+}
+
+/// Query parameters of the `/api/v1/search` endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct SearchQuery {
+    /// The text to search for in the memos of the txs
+    pub memo: String,
+
+    /// Only return the txs of this chain
+    pub chain: Option<String>,
+
+    /// Maximum number of txs to return, defaults to 100 and is capped at 1000
+    pub limit: Option<u32>,
+}
+
+pub async fn search_memos(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<SearchQuery>,
+) -> std::result::Result<Json<Vec<MemoMatch>>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    if query.memo.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let rows = search::memos(
+        &state.pool,
+        visibility.scope(),
+        &query.memo,
+        query.chain.as_deref(),
+        query.limit,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to search memos: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(rows))
+}
+
+/// Query parameters of the `/api/v1/transfer_failures` endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct TransferFailuresQuery {
+    /// Only return the failures of this chain
+    pub chain: Option<String>,
+
+    /// Only return the failures of transfers sent or received over this channel
+    pub channel: Option<String>,
+
+    /// Maximum number of failures to return, defaults to 100 and is capped at 1000
+    pub limit: Option<u32>,
+}
+
+pub async fn get_transfer_failures(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<TransferFailuresQuery>,
+) -> std::result::Result<Json<Vec<TransferFailureRow>>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    let rows = failures::recent(
+        &state.pool,
+        visibility.scope(),
+        query.chain.as_deref(),
+        query.channel.as_deref(),
+        query.limit,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to fetch transfer failures: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(rows))
+}
+
+/// Query parameters of the `/api/v1/packets` and `/api/v1/frontruns` endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct PacketsQuery {
+    /// Only return the packets relayed on this chain
+    pub chain: Option<String>,
+
+    /// Only return the packets sent or received over this channel
+    pub channel: Option<String>,
+
+    /// Only return the packets relayed by this signer
+    pub signer: Option<String>,
+
+    /// Only return the packets before this cursor, ie. the `next` cursor of the previous page
+    pub before: Option<i64>,
+
+    /// Maximum number of packets to return, defaults to 100 and is capped at 1000
+    pub limit: Option<u32>,
+}
+
+/// List the packets relayed on the monitored chains, most recent first
+pub async fn get_packets(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PacketsQuery>,
+) -> std::result::Result<Json<Page<PacketApiRow>>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    let page = api::packets(
+        &state.pool,
+        visibility.scope(),
+        query.chain.as_deref(),
+        query.channel.as_deref(),
+        query.signer.as_deref(),
+        query.before,
+        query.limit,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to fetch packets: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(page))
+}
+
+/// Query parameters of the `/api/v1/txs/{hash}` endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct TxsQuery {
+    /// Only return the tx of this chain, when the same tx was recorded on several chains
+    pub chain: Option<String>,
+}
+
+/// Show the txs with the given hash, along with the packets they relayed
+pub async fn get_txs(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(hash): Path<String>,
+    Query(query): Query<TxsQuery>,
+) -> std::result::Result<Json<Vec<api::Tx>>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    let mut txs = api::txs(&state.pool, &hash, query.chain.as_deref())
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch tx {hash}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    txs.retain(|tx| visibility.can_see_chain(&tx.tx.chain));
+
+    for tx in &mut txs {
+        tx.packets.retain(|row| {
+            visibility.can_see_channel(&row.src_channel)
+                || visibility.can_see_channel(&row.dst_channel)
+        });
+    }
+
+    if txs.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(txs))
+}
+
+/// List the packets which were frontrun, ie. relayed again after another relayer had already relayed them,
+/// most recent first, optionally only the ones of the given `signer` which were frontrun
+pub async fn get_frontruns(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PacketsQuery>,
+) -> std::result::Result<Json<Page<FrontrunRow>>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    let page = api::frontruns(
+        &state.pool,
+        visibility.scope(),
+        query.chain.as_deref(),
+        query.channel.as_deref(),
+        query.signer.as_deref(),
+        query.before,
+        query.limit,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to fetch frontruns: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(page))
+}
+
+/// Query parameters of the `/api/v1/packets.csv` endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct PacketsCsvQuery {
+    /// Only return the packets of this chain
+    pub chain: Option<String>,
+
+    /// Only return the packets recorded since this date, eg. `2023-10-01` or `2023-10-01 12:00:00`
+    pub from: Option<String>,
+
+    /// Only return the packets recorded before this date
+    pub to: Option<String>,
+}
+
+/// Stream the packets recorded in the database as CSV, along with the tx which relayed them
+pub async fn get_packets_csv(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PacketsCsvQuery>,
+) -> std::result::Result<impl IntoResponse, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    // The rows are filtered in a separate task, which cannot borrow the state
+    let chains = visibility
+        .chains
+        .map(|chains| chains.iter().map(|c| c.to_string()).collect::<Vec<_>>());
+    let channels = visibility.channels.map(<[String]>::to_vec);
+
+    let visible = move |row: &PacketExportRow| {
+        let chain_visible = chains
+            .as_ref()
+            .map_or(true, |chains| chains.contains(&row.chain));
+
+        let channel_visible = channels.as_ref().map_or(true, |channels| {
+            channels.contains(&row.src_channel) || channels.contains(&row.dst_channel)
+        });
+
+        chain_visible && channel_visible
+    };
+
+    let (sender, receiver) = mpsc::channel(export::BUFFERED_CHUNKS);
+    let pool = state.pool.clone();
+
+    tokio::spawn(async move {
+        let result = export::packets_csv(
+            &pool,
+            query.chain.as_deref(),
+            query.from.as_deref(),
+            query.to.as_deref(),
+            visible,
+            sender,
+        )
+        .await;
+
+        if let Err(e) = result {
+            error!("Failed to export packets: {e}");
+        }
+    });
+
+    let headers = [
+        (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+        (
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"packets.csv\"",
+        ),
+    ];
+
+    Ok((headers, StreamBody::new(receiver)))
+}
+
+/// Query parameters of the `/api/v1/packets/hourly` endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct HourlyPacketsQuery {
+    /// Only return the counts of this chain
+    pub chain: Option<String>,
+
+    /// Only return the counts of this channel, either as source or destination
+    pub channel: Option<String>,
+
+    /// Only return the counts of this signer
+    pub signer: Option<String>,
+
+    /// Only return the counts since this date, eg. `2023-10-01` or `2023-10-01 12:00:00`,
+    /// defaults to the last 7 days
+    pub from: Option<String>,
+
+    /// Only return the counts before this date
+    pub to: Option<String>,
+}
+
+pub async fn get_hourly_packets(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<HourlyPacketsQuery>,
+) -> std::result::Result<Json<Vec<HourlyPacketsRow>>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    let rows = aggregates::hourly(
+        &state.pool,
+        visibility.scope(),
+        query.chain.as_deref(),
+        query.channel.as_deref(),
+        query.signer.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to fetch hourly packet counts: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(rows))
+}
+
+/// Query parameters of the `/api/v1/packets/pending` endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct PendingPacketsQuery {
+    /// Only return the packets sent from this chain
+    pub chain: Option<String>,
+
+    /// Only return the packets sent or received over this channel
+    pub channel: Option<String>,
+
+    /// Maximum number of packets to return, defaults to 100 and is capped at 1000
+    pub limit: Option<u32>,
+}
+
+/// List the packets sent from the monitored chains which were neither acknowledged nor timed out yet, oldest first
+pub async fn get_pending_packets(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PendingPacketsQuery>,
+) -> std::result::Result<Json<Vec<PendingPacketRow>>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    let rows = pending::unfinished(
+        &state.pool,
+        visibility.scope(),
+        query.chain.as_deref(),
+        query.channel.as_deref(),
+        query.limit,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to fetch pending packets: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(rows))
+}
+
+/// Query parameters of the `/api/v1/packets/pending/clear` endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct ClearCommandsQuery {
+    /// Only consider the packets sent from this chain
+    pub chain: Option<String>,
+
+    /// Only consider the packets sent or received over this channel
+    pub channel: Option<String>,
+
+    /// Only consider the packets sent at least this many seconds ago,
+    /// defaults to the `native_min_age` of the stuck packets
+    pub min_age: Option<u64>,
+}
+
+/// Suggest the Hermes commands clearing the packets stuck on each channel
+pub async fn get_clear_commands(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ClearCommandsQuery>,
+) -> std::result::Result<Json<Vec<Suggestion>>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    let suggestions = clear::suggestions(
+        &state.pool,
+        visibility.scope(),
+        query.chain.as_deref(),
+        query.channel.as_deref(),
+        query.min_age.unwrap_or(state.stuck_min_age),
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to suggest clear commands: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(suggestions))
+}
+
+/// Query parameters of the `/api/v1/ignored_msgs` endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct IgnoredMsgsQuery {
+    /// Only return the messages of this chain
+    pub chain: Option<String>,
+
+    /// Only return the messages of this type, eg. `/ibc.core.channel.v1.MsgChannelUpgradeInit`
+    pub type_url: Option<String>,
+
+    /// Maximum number of messages to return, defaults to 100 and is capped at 1000
+    pub limit: Option<u32>,
+}
+
+/// List the sampled messages which were ignored by the collectors, most recent first
+pub async fn get_ignored_msgs(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<IgnoredMsgsQuery>,
+) -> std::result::Result<Json<Vec<IgnoredMsgRow>>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    let rows = ignored::samples(
+        &state.pool,
+        visibility.scope(),
+        query.chain.as_deref(),
+        query.type_url.as_deref(),
+        query.limit,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to fetch the ignored messages: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(rows))
+}
+
+/// Query parameters of the `/api/v1/channels` endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct ChannelsQuery {
+    /// Only return the channels on which traffic was seen on this chain
+    pub chain: Option<String>,
+}
+
+pub async fn get_channels(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ChannelsQuery>,
+) -> std::result::Result<Json<Vec<ChannelRow>>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    let mut rows = channels::inventory(&state.pool, query.chain.as_deref())
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch the channel inventory: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    rows.retain(|row| {
+        visibility.can_see_chain(&row.chain)
+            && (visibility.can_see_channel(&row.src_channel)
+                || visibility.can_see_channel(&row.dst_channel))
+    });
+
+    Ok(Json(rows))
+}
+
+/// Query parameters of the `/api/v1/trace` endpoint
+#[derive(Debug, Deserialize)]
+pub struct TraceQuery {
+    /// The chain the packet was sent from
+    pub src_chain: String,
+
+    /// The channel the packet was sent over, on its source chain
+    pub channel: String,
+
+    /// The sequence of the packet on that channel
+    pub sequence: i64,
+}
+
+/// Reconstruct the journey of a packet, hop by hop, through the chains forwarding it
+pub async fn get_trace(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<TraceQuery>,
+) -> std::result::Result<Json<Vec<Hop>>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    let mut hops = hops::trace(
+        &state.pool,
+        &query.src_chain,
+        &query.channel,
+        query.sequence,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to trace packet: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    hops.retain(|hop| {
+        (visibility.can_see_chain(&hop.src_chain)
+            || hop
+                .dst_chain
+                .as_deref()
+                .map_or(false, |chain| visibility.can_see_chain(chain)))
+            && (visibility.can_see_channel(&hop.src_channel)
+                || hop
+                    .dst_channel
+                    .as_deref()
+                    .map_or(false, |channel| visibility.can_see_channel(channel)))
+    });
+
+    if hops.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(hops))
+}
+
+/// Show what was decoded, classified and recorded for the most recent block of a chain
+pub async fn get_last_block(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(chain_id): Path<chain::Id>,
+) -> std::result::Result<Json<BlockTrace>, StatusCode> {
+    let visibility = visibility(&state, &headers)?;
+
+    if !state.chains.contains(&chain_id) || !visibility.can_see_chain(chain_id.as_str()) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let mut trace = state
+        .collectors
+        .last_block(&chain_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Messages without a packet, eg. client updates, can only be seen when the channels are not restricted
+    trace.msgs.retain(|msg| match &msg.packet {
+        Some(packet) => {
+            visibility.can_see_channel(&packet.src_channel)
+                || visibility.can_see_channel(&packet.dst_channel)
+        }
+        None => visibility.channels.is_none(),
+    });
+
+    Ok(Json(trace))
+}
+
+pub async fn pause_chain(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(chain_id): Path<chain::Id>,
+) -> StatusCode {
+    if !is_authorized(&state, &headers) {
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    if !state.collectors.pause(&chain_id) {
+        return StatusCode::NOT_FOUND;
+    }
+
+    info!(chain = %chain_id, "Pausing collector via the admin API");
+    StatusCode::NO_CONTENT
+}
+
+pub async fn resume_chain(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(chain_id): Path<chain::Id>,
+) -> StatusCode {
+    if !is_authorized(&state, &headers) {
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    if !state.collectors.resume(&chain_id) {
+        return StatusCode::NOT_FOUND;
+    }
+
+    info!(chain = %chain_id, "Resuming collector via the admin API");
+    StatusCode::NO_CONTENT
+}
+
+/// Write a consistent snapshot of the database, if snapshots are enabled
+pub async fn snapshot_db(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<Snapshot>, StatusCode> {
+    if !is_authorized(&state, &headers) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let Some(dir) = &state.snapshot_dir else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    match checkpoint::snapshot(&state.pool, dir).await {
+        Ok(snapshot) => Ok(Json(snapshot)),
+        Err(e) => {
+            error!("Failed to snapshot the database: {e}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn is_authorized(state: &AppState, headers: &HeaderMap) -> bool {
+    let Some(admin) = &state.admin else {
+        return false;
+    };
+
+    bearer_token(headers).map_or(false, |token| tokens_match(token, &admin.token))
+}
+
+/// Compare a token to the expected one in constant time, so that the time taken
+/// does not reveal how many of its leading bytes are correct
+fn tokens_match(token: &str, expected: &str) -> bool {
+    if token.len() != expected.len() {
+        return false;
+    }
+
+    let diff = token
+        .bytes()
+        .zip(expected.bytes())
+        .fold(0, |diff, (a, b)| diff | (a ^ b));
+
+    std::hint::black_box(diff) == 0
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// The chains and channels which can be seen by a request, where `None` means all of them
+#[derive(Debug, Default)]
+struct Visibility<'a> {
+    chains: Option<&'a [chain::Id]>,
+    channels: Option<&'a [String]>,
+}
+
+impl<'a> Visibility<'a> {
+    fn can_see_chain(&self, chain: &str) -> bool {
+        self.chains
+            .map_or(true, |chains| chains.iter().any(|c| c.as_str() == chain))
+    }
+
+    fn can_see_channel(&self, channel: &str) -> bool {
+        self.channels
+            .map_or(true, |channels| channels.iter().any(|c| c == channel))
+    }
+
+    /// The chains and channels to restrict the queries of the API to
+    fn scope(&self) -> api::Scope<'a> {
+        api::Scope {
+            chains: self.chains,
+            channels: self.channels,
+        }
+    }
+
+    /// Whether a series can be seen. Series which do not pertain to any chain or channel,
+    /// such as `chainpulse_chains`, can only be seen when the chains or channels are not restricted.
+    fn can_see(&self, metric: &Metric) -> bool {
+        let chains = metrics::chains_of(metric).collect::<Vec<_>>();
+        let channels = metrics::channels_of(metric).collect::<Vec<_>>();
+
+        let chain_visible =
+            self.chains.is_none() || chains.iter().any(|chain| self.can_see_chain(chain));
+
+        // Series without a channel, eg. per-chain metrics, remain visible
+        let channel_visible = self.channels.is_none()
+            || channels.is_empty()
+            || channels.iter().any(|channel| self.can_see_channel(channel));
+
+        chain_visible && channel_visible
+    }
+}
+
+/// Determine what a request can see from its API key, if API keys are configured
+fn visibility<'a>(
+    state: &'a AppState,
+    headers: &HeaderMap,
+) -> std::result::Result<Visibility<'a>, StatusCode> {
+    if state.api_keys.is_empty() {
+        return Ok(Visibility::default());
+    }
+
+    let token = bearer_token(headers).ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let api_key = state
+        .api_keys
+        .iter()
+        .find(|api_key| tokens_match(token, &api_key.key))
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    Ok(Visibility {
+        chains: (!api_key.chains.is_empty()).then_some(api_key.chains.as_slice()),
+        channels: (!api_key.channels.is_empty()).then_some(api_key.channels.as_slice()),
+    })
+}
@@ -0,0 +1,42 @@
+//! Signals on which chainpulse shuts down gracefully.
+//!
+//! Besides Ctrl-C, this covers SIGTERM on Unix, which is what container runtimes, eg. Kubernetes, send on stop,
+//! and the console events sent on Windows when the console is closed or the system shuts down.
+
+use crate::Result;
+
+/// Wait for a shutdown signal, returning its name
+#[cfg(unix)]
+pub async fn signal() -> Result<&'static str> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    let name = tokio::select! {
+        _ = interrupt.recv() => "SIGINT",
+        _ = terminate.recv() => "SIGTERM",
+    };
+
+    Ok(name)
+}
+
+/// Wait for a shutdown signal, returning its name
+#[cfg(windows)]
+pub async fn signal() -> Result<&'static str> {
+    use tokio::signal::windows;
+
+    let mut ctrl_c = windows::ctrl_c()?;
+    let mut ctrl_break = windows::ctrl_break()?;
+    let mut ctrl_close = windows::ctrl_close()?;
+    let mut ctrl_shutdown = windows::ctrl_shutdown()?;
+
+    let name = tokio::select! {
+        _ = ctrl_c.recv() => "Ctrl-C",
+        _ = ctrl_break.recv() => "Ctrl-Break",
+        _ = ctrl_close.recv() => "console close",
+        _ = ctrl_shutdown.recv() => "system shutdown",
+    };
+
+    Ok(name)
+}
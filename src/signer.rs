@@ -0,0 +1,117 @@
+//! Validation of the signers of the decoded messages.
+//!
+//! The signer of a message is a free-form string in its protobuf encoding, and ends up both as
+//! a metric label and as a database row, so malformed values are rejected before they get there,
+//! and valid ones are normalized to lower case, as a bech32 address can be written in either case.
+//!
+//! The same key yields addresses with a different prefix on each chain, but which encode the same
+//! public key hash, which is used as the identity of the relayer operator across chains.
+
+use subtle_encoding::{bech32, hex};
+
+/// Maximum length of a signer, well above the one of a 32-byte address with a long prefix
+const MAX_LEN: usize = 128;
+
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum InvalidSigner {
+    #[error("empty signer")]
+    Empty,
+
+    #[error("signer of {0} characters is too long")]
+    TooLong(usize),
+
+    #[error("signer `{0}` mixes lower and upper case")]
+    MixedCase(String),
+
+    #[error("signer `{signer}` is not a valid bech32 address: {error}")]
+    Bech32 { signer: String, error: String },
+}
+
+/// Validate a signer as a bech32 address, and normalize it to lower case
+pub fn normalize(signer: &str) -> Result<String, InvalidSigner> {
+    if signer.is_empty() {
+        return Err(InvalidSigner::Empty);
+    }
+
+    if signer.len() > MAX_LEN {
+        return Err(InvalidSigner::TooLong(signer.len()));
+    }
+
+    let has_lower = signer.bytes().any(|b| b.is_ascii_lowercase());
+    let has_upper = signer.bytes().any(|b| b.is_ascii_uppercase());
+
+    if has_lower && has_upper {
+        return Err(InvalidSigner::MixedCase(signer.to_string()));
+    }
+
+    let signer = signer.to_ascii_lowercase();
+
+    match bech32::decode(&signer) {
+        Ok(_) => Ok(signer),
+        Err(e) => Err(InvalidSigner::Bech32 {
+            signer,
+            error: e.to_string(),
+        }),
+    }
+}
+
+/// The identity of the operator of a signer across chains, ie. the hex-encoded hash of its public key,
+/// or `None` if the signer is not a valid bech32 address
+pub fn relayer_id(signer: &str) -> Option<String> {
+    let (_prefix, hash) = bech32::decode(signer).ok()?;
+    String::from_utf8(hex::encode(hash)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COSMOS: &str = "cosmos1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqnrql8a";
+
+    #[test]
+    fn test_normalize_valid() {
+        assert_eq!(normalize(COSMOS).unwrap(), COSMOS);
+        assert_eq!(normalize(&COSMOS.to_uppercase()).unwrap(), COSMOS);
+    }
+
+    #[test]
+    fn test_normalize_invalid() {
+        assert_eq!(normalize(""), Err(InvalidSigner::Empty));
+        assert_eq!(
+            normalize(&"a".repeat(200)),
+            Err(InvalidSigner::TooLong(200))
+        );
+
+        assert!(matches!(
+            normalize("Cosmos1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqnrql8a"),
+            Err(InvalidSigner::MixedCase(_))
+        ));
+
+        // Bad checksum
+        assert!(matches!(
+            normalize("cosmos1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqnrql8b"),
+            Err(InvalidSigner::Bech32 { .. })
+        ));
+
+        assert!(matches!(
+            normalize("not a signer"),
+            Err(InvalidSigner::Bech32 { .. })
+        ));
+    }
+
+    #[test]
+    fn test_relayer_id() {
+        let id = "0000000000000000000000000000000000000000";
+
+        assert_eq!(relayer_id(COSMOS).as_deref(), Some(id));
+        assert_eq!(
+            relayer_id("osmo1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqmcn030").as_deref(),
+            Some(id)
+        );
+        assert_ne!(
+            relayer_id("bench1v9k8q6rpqqqqqqqqqqqqqqqqqqqqqqqqpwtdc7").as_deref(),
+            Some(id)
+        );
+        assert_eq!(relayer_id("not a signer"), None);
+    }
+}
@@ -0,0 +1,492 @@
+//! A simulated chain, serving a fake CometBFT RPC endpoint over WebSocket, so that Chain Pulse
+//! can be tested end-to-end without connecting to a real node.
+//!
+//! The simulator produces a block at a fixed interval, made of txs relaying IBC packets generated
+//! like the ones of the benchmark, some of which are frontrun. It publishes a NewBlock event for each
+//! block to the subscribed clients, and answers the `status`, `health`, `block` and `block_results`
+//! requests made by the collectors. It reports the CometBFT version of its choice, to exercise the
+//! detection of the compat mode, and can drop the connections periodically, to exercise reconnects.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_tungstenite::tungstenite::Message;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tendermint::{
+    chain,
+    crypto::{default::Sha256, Sha256 as _},
+    Time,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+    time,
+};
+use tracing::{debug, info, warn};
+
+use crate::{bench::Generator, Result};
+
+/// Number of blocks kept in memory to answer `block` and `block_results` requests
+const KEPT_BLOCKS: usize = 1000;
+
+const PROPOSER: &str = "DD8A65495B6240145764A74E78CF203D51510371";
+const NODE_ID: &str = "0b8e9b2fc583439e365a85f06a8909294f7657e8";
+const VALIDATORS_HASH: &str = "6B95A63B261D3DDC1DFF6FA53F4C591AB8DA58BBA545700BFD45E6A54AAA2A84";
+const CONSENSUS_HASH: &str = "048091BC7DDC283F77BFBF91D73C44DA58C3DF8A9CBC867405D8B7F3DAADA22F";
+const EMPTY_HASH: &str = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
+
+/// Options of the simulated chain
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The port to listen on
+    pub port: u16,
+
+    /// The chain reported by the node
+    pub chain_id: chain::Id,
+
+    /// The CometBFT version reported by the node, eg. `0.34.27` or `0.37.2`
+    pub version: String,
+
+    /// How often to produce a block
+    pub interval: Duration,
+
+    /// Number of txs per block
+    pub txs: usize,
+
+    /// Number of packets relayed per tx
+    pub packets: usize,
+
+    /// Ratio of the packets which are frontrun
+    pub frontruns: f64,
+
+    /// Close the connections after every given number of blocks, or never if zero
+    pub disconnect_every: u64,
+}
+
+struct Block {
+    time: Time,
+    txs: Vec<Vec<u8>>,
+}
+
+/// The blocks produced so far
+struct Chain {
+    options: Options,
+    generator: Generator,
+    blocks: BTreeMap<u64, Block>,
+    height: u64,
+}
+
+impl Chain {
+    fn new(options: Options) -> Self {
+        Self {
+            generator: Generator::new(options.packets, options.frontruns),
+            options,
+            blocks: BTreeMap::new(),
+            height: 0,
+        }
+    }
+
+    fn produce(&mut self) -> u64 {
+        self.height += 1;
+
+        let block = Block {
+            time: Time::now(),
+            txs: self.generator.block(self.options.txs),
+        };
+
+        self.blocks.insert(self.height, block);
+
+        while self.blocks.len() > KEPT_BLOCKS {
+            self.blocks.pop_first();
+        }
+
+        self.height
+    }
+
+    fn status(&self) -> Value {
+        let (latest_hash, latest_time) = match self.blocks.get(&self.height) {
+            Some(block) => (block_hash(self.height), block.time.to_rfc3339()),
+            None => (String::new(), Time::unix_epoch().to_rfc3339()),
+        };
+
+        json!({
+            "node_info": {
+                "protocol_version": { "p2p": "8", "block": "11", "app": "1" },
+                "id": NODE_ID,
+                "listen_addr": "tcp://0.0.0.0:26656",
+                "network": self.options.chain_id.as_str(),
+                "version": self.options.version,
+                "channels": "40202122233038606100",
+                "moniker": "chainpulse-simulator",
+                "other": { "tx_index": "on", "rpc_address": "tcp://0.0.0.0:26657" }
+            },
+            "sync_info": {
+                "latest_block_hash": latest_hash,
+                "latest_app_hash": "",
+                "latest_block_height": self.height.to_string(),
+                "latest_block_time": latest_time,
+                "earliest_block_hash": "",
+                "earliest_app_hash": "",
+                "earliest_block_height": "1",
+                "earliest_block_time": latest_time,
+                "catching_up": false
+            },
+            "validator_info": {
+                "address": PROPOSER,
+                "pub_key": {
+                    "type": "tendermint/PubKeyEd25519",
+                    "value": "OYpM2RXHEO1/R3jJRhAbjY8JhvjTBbiNJKBStEKu12s="
+                },
+                "voting_power": "10"
+            }
+        })
+    }
+
+    fn block(&self, height: u64) -> Option<Value> {
+        let block = self.blocks.get(&height)?;
+
+        let txs = block
+            .txs
+            .iter()
+            .map(|tx| String::from_utf8_lossy(&subtle_encoding::base64::encode(tx)).into_owned())
+            .collect::<Vec<_>>();
+
+        let last_block_id = (height > 1).then(|| block_id(height - 1));
+
+        let last_commit = (height > 1).then(|| {
+            json!({
+                "height": (height - 1).to_string(),
+                "round": 0,
+                "block_id": block_id(height - 1),
+                "signatures": []
+            })
+        });
+
+        Some(json!({
+            "header": {
+                "version": { "block": "11", "app": "1" },
+                "chain_id": self.options.chain_id.as_str(),
+                "height": height.to_string(),
+                "time": block.time.to_rfc3339(),
+                "last_block_id": last_block_id,
+                "last_commit_hash": EMPTY_HASH,
+                "data_hash": EMPTY_HASH,
+                "validators_hash": VALIDATORS_HASH,
+                "next_validators_hash": VALIDATORS_HASH,
+                "consensus_hash": CONSENSUS_HASH,
+                "app_hash": "",
+                "last_results_hash": EMPTY_HASH,
+                "evidence_hash": EMPTY_HASH,
+                "proposer_address": PROPOSER
+            },
+            "data": { "txs": txs },
+            "evidence": { "evidence": [] },
+            "last_commit": last_commit
+        }))
+    }
+
+    fn block_results(&self, height: u64) -> Option<Value> {
+        let block = self.blocks.get(&height)?;
+
+        let txs_results = block
+            .txs
+            .iter()
+            .map(|_| {
+                json!({
+                    "code": 0,
+                    "data": "",
+                    "log": "",
+                    "info": "",
+                    "gas_wanted": "200000",
+                    "gas_used": "150000",
+                    "events": [],
+                    "codespace": ""
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Some(json!({
+            "height": height.to_string(),
+            "txs_results": txs_results,
+            "begin_block_events": null,
+            "end_block_events": null,
+            "validator_updates": null,
+            "consensus_param_updates": null
+        }))
+    }
+}
+
+/// A simulated chain, listening for connections
+pub struct Simulator {
+    listener: TcpListener,
+    chain: Arc<Mutex<Chain>>,
+}
+
+impl Simulator {
+    pub async fn bind(options: Options) -> Result<Self> {
+        let listener = TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], options.port))).await?;
+
+        Ok(Self {
+            listener,
+            chain: Arc::new(Mutex::new(Chain::new(options))),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Produce blocks and serve the connections until interrupted
+    pub async fn run(self) -> Result<()> {
+        let (options, interval) = {
+            let chain = self.chain.lock().unwrap();
+            (chain.options.clone(), chain.options.interval)
+        };
+
+        let (blocks, _) = broadcast::channel(16);
+
+        let producer = {
+            let (chain, blocks) = (self.chain.clone(), blocks.clone());
+
+            async move {
+                let mut interval = time::interval(interval);
+
+                loop {
+                    interval.tick().await;
+
+                    let height = chain.lock().unwrap().produce();
+                    debug!("Produced block {height}");
+
+                    let _ = blocks.send(height);
+                }
+            }
+        };
+
+        tokio::spawn(producer);
+
+        info!(
+            "Simulating chain {} (CometBFT {}) at ws://{}/websocket",
+            options.chain_id,
+            options.version,
+            self.local_addr()?
+        );
+
+        loop {
+            let (stream, addr) = self.listener.accept().await?;
+            debug!("Accepted connection from {addr}");
+
+            let connection = serve(
+                stream,
+                self.chain.clone(),
+                blocks.subscribe(),
+                options.disconnect_every,
+            );
+
+            tokio::spawn(async move {
+                if let Err(e) = connection.await {
+                    warn!("Connection from {addr} failed: {e}");
+                }
+            });
+        }
+    }
+}
+
+/// Run a simulated chain until interrupted
+pub async fn run(options: Options) -> Result<()> {
+    Simulator::bind(options).await?.run().await
+}
+
+async fn serve(
+    stream: TcpStream,
+    chain: Arc<Mutex<Chain>>,
+    mut blocks: broadcast::Receiver<u64>,
+    disconnect_every: u64,
+) -> Result<()> {
+    let mut ws = async_tungstenite::tokio::accept_async(stream).await?;
+
+    // The id of the subscribe request and the query of the subscription, if any
+    let mut subscription: Option<(Value, String)> = None;
+
+    loop {
+        tokio::select! {
+            msg = ws.next() => {
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e.into()),
+                };
+
+                let response = handle(&chain, &text, &mut subscription);
+                ws.send(Message::Text(response.to_string())).await?;
+            }
+            height = blocks.recv() => {
+                let height = match height {
+                    Ok(height) => height,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                };
+
+                let Some((id, query)) = &subscription else {
+                    continue;
+                };
+
+                let block = chain.lock().unwrap().block(height);
+
+                if let Some(block) = block {
+                    let event = new_block_event(id, query, block);
+                    ws.send(Message::Text(event.to_string())).await?;
+                }
+
+                if disconnect_every > 0 && height % disconnect_every == 0 {
+                    info!("Dropping connection after block {height}");
+                    let _ = ws.close(None).await;
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Answer a JSON-RPC request
+fn handle(chain: &Mutex<Chain>, text: &str, subscription: &mut Option<(Value, String)>) -> Value {
+    let Ok(request) = serde_json::from_str::<Value>(text) else {
+        return error(Value::Null, -32700, "Parse error");
+    };
+
+    let id = request["id"].clone();
+    let params = &request["params"];
+
+    let chain = chain.lock().unwrap();
+
+    let height = || match &params["height"] {
+        Value::String(height) => height.parse().unwrap_or(chain.height),
+        Value::Number(height) => height.as_u64().unwrap_or(chain.height),
+        _ => chain.height,
+    };
+
+    let result = match request["method"].as_str().unwrap_or_default() {
+        "status" => chain.status(),
+        "health" | "unsubscribe" | "unsubscribe_all" => json!({}),
+        "subscribe" => {
+            let query = params["query"].as_str().unwrap_or_default().to_string();
+            *subscription = Some((id.clone(), query));
+            json!({})
+        }
+        "block" => match chain.block(height()) {
+            Some(block) => json!({ "block_id": block_id(height()), "block": block }),
+            None => return error(id, -32603, "Internal error"),
+        },
+        "block_results" => match chain.block_results(height()) {
+            Some(results) => results,
+            None => return error(id, -32603, "Internal error"),
+        },
+        _ => return error(id, -32601, "Method not found"),
+    };
+
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message, "data": "" }
+    })
+}
+
+fn new_block_event(id: &Value, query: &str, block: Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": format!("{}#event", id.as_str().unwrap_or_default()),
+        "result": {
+            "query": query,
+            "data": {
+                "type": "tendermint/event/NewBlock",
+                "value": {
+                    "block": block,
+                    "result_begin_block": {},
+                    "result_end_block": { "validator_updates": null }
+                }
+            },
+            "events": { "tm.event": ["NewBlock"] }
+        }
+    })
+}
+
+fn block_hash(height: u64) -> String {
+    let hash = Sha256::digest(height.to_be_bytes());
+    String::from_utf8_lossy(&subtle_encoding::hex::encode_upper(hash)).into_owned()
+}
+
+fn block_id(height: u64) -> Value {
+    json!({
+        "hash": block_hash(height),
+        "parts": { "total": 1, "hash": block_hash(height) }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tendermint::block::Height;
+    use tendermint_rpc::{client::CompatMode, query::EventType, Client, SubscriptionClient};
+    use tendermint_rpc::{event::EventData, WebSocketClient};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_simulator() {
+        let options = Options {
+            port: 0,
+            chain_id: chain::Id::try_from("simulated-1").unwrap(),
+            version: "0.37.2".to_string(),
+            interval: Duration::from_millis(100),
+            txs: 2,
+            packets: 3,
+            frontruns: 0.5,
+            disconnect_every: 0,
+        };
+
+        let simulator = Simulator::bind(options).await.unwrap();
+        let port = simulator.local_addr().unwrap().port();
+        tokio::spawn(simulator.run());
+
+        let url = format!("ws://127.0.0.1:{port}/websocket");
+        let (client, driver) = WebSocketClient::builder(url.parse().unwrap())
+            .compat_mode(CompatMode::V0_37)
+            .build()
+            .await
+            .unwrap();
+        tokio::spawn(driver.run());
+
+        let mut subscription = client.subscribe(EventType::NewBlock.into()).await.unwrap();
+        let event = subscription.next().await.unwrap().unwrap();
+
+        let EventData::NewBlock {
+            block: Some(block), ..
+        } = event.data
+        else {
+            panic!("unexpected event {:?}", event.data);
+        };
+
+        assert_eq!(block.data.len(), 2);
+
+        let status = client.status().await.unwrap();
+        assert_eq!(status.node_info.network.as_str(), "simulated-1");
+        assert_eq!(status.node_info.version.to_string(), "0.37.2");
+
+        let height = Height::try_from(block.header.height.value()).unwrap();
+        let fetched = client.block(height).await.unwrap();
+        assert_eq!(fetched.block.data, block.data);
+
+        let results = client.block_results(height).await.unwrap();
+        assert_eq!(results.txs_results.unwrap().len(), 2);
+
+        client.close().unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+//! Latency objectives for the packets sent on some channels, eg. 95% of the packets relayed within 60 seconds.
+//!
+//! A packet sent from a monitored chain is good if it was relayed, acknowledged or timed out within the latency
+//! of the objective, and bad if it took longer or is still pending past that latency. Packets which are still
+//! pending but not late yet are not counted. The ratio of good packets (the SLI) and the rate at which the error
+//! budget, ie. the fraction of packets allowed to be late, is burning are computed over several sliding windows,
+//! for multiwindow burn-rate alerts.
+
+use std::time::Duration;
+
+use sqlx::SqlitePool;
+use tokio::time;
+use tracing::error;
+
+use crate::{config::Slo, metrics::Metrics, Result};
+
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The windows over which the SLI and burn rate are computed, with their label and length in seconds
+const WINDOWS: &[(&str, u64)] = &[
+    ("5m", 300),
+    ("30m", 1800),
+    ("1h", 3600),
+    ("6h", 21600),
+    ("1d", 86400),
+    ("3d", 259200),
+];
+
+/// The ratio of good packets, if any packet was counted
+pub fn sli(good: i64, total: i64) -> Option<f64> {
+    (total > 0).then(|| good as f64 / total as f64)
+}
+
+/// The rate at which the error budget is burning, 1 meaning that it would be exactly spent by the end of the period
+pub fn burn_rate(good: i64, total: i64, objective: f64) -> Option<f64> {
+    sli(good, total).map(|sli| (1.0 - sli) / (1.0 - objective))
+}
+
+/// Periodically compute the SLIs, burn rates and remaining error budgets of the configured objectives
+pub async fn run(pool: SqlitePool, metrics: Metrics, slos: Vec<Slo>) {
+    loop {
+        if let Err(e) = update(&pool, &metrics, &slos).await {
+            error!("Failed to compute the SLOs: {e}");
+        }
+
+        time::sleep(UPDATE_INTERVAL).await;
+    }
+}
+
+async fn update(pool: &SqlitePool, metrics: &Metrics, slos: &[Slo]) -> Result<()> {
+    let mut windows = Vec::new();
+    let mut budgets = Vec::new();
+
+    for slo in slos {
+        for (window, seconds) in WINDOWS {
+            let (good, total) = count(pool, slo, *seconds).await?;
+
+            if let (Some(sli), Some(burn_rate)) =
+                (sli(good, total), burn_rate(good, total, slo.objective))
+            {
+                windows.push((slo, *window, sli, burn_rate));
+            }
+        }
+
+        let (good, total) = count(pool, slo, slo.period).await?;
+
+        if let Some(burn_rate) = burn_rate(good, total, slo.objective) {
+            budgets.push((slo, 1.0 - burn_rate));
+        }
+    }
+
+    // Only reset the gauges once all windows have been computed,
+    // so that windows without any packet are removed
+    metrics.reset_ibc_slo();
+
+    for (slo, window, sli, burn_rate) in windows {
+        metrics.ibc_slo_sli(&slo.name, &slo.chain, &slo.channel, window, sli);
+        metrics.ibc_slo_burn_rate(&slo.name, &slo.chain, &slo.channel, window, burn_rate);
+    }
+
+    for (slo, remaining) in budgets {
+        metrics.ibc_slo_error_budget_remaining(&slo.name, &slo.chain, &slo.channel, remaining);
+    }
+
+    Ok(())
+}
+
+/// Count the good packets, and all the packets counted, sent on the channel of the objective within the window
+async fn count(pool: &SqlitePool, slo: &Slo, window: u64) -> Result<(i64, i64)> {
+    let query = r#"
+        SELECT
+            COALESCE(SUM(resolved_at IS NOT NULL
+                AND (julianday(resolved_at) - julianday(first_seen_at)) * 86400 <= ?1), 0),
+            COUNT(*)
+        FROM sent_packets
+        WHERE   chain = ?2
+            AND src_channel = ?3
+            AND first_seen_at >= datetime('now', ?4)
+            AND (resolved_at IS NOT NULL OR first_seen_at < datetime('now', ?5))
+    "#;
+
+    let counts = sqlx::query_as(query)
+        .bind(slo.latency as i64)
+        .bind(slo.chain.as_str())
+        .bind(&slo.channel)
+        .bind(format!("-{window} seconds"))
+        .bind(format!("-{} seconds", slo.latency))
+        .fetch_one(pool)
+        .await?;
+
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burn_rate() {
+        assert_eq!(sli(0, 0), None);
+        assert_eq!(burn_rate(0, 0, 0.95), None);
+
+        // Exactly on target
+        assert!((burn_rate(95, 100, 0.95).unwrap() - 1.0).abs() < 1e-9);
+
+        // Burning the budget 10 times too fast
+        assert!((burn_rate(50, 100, 0.95).unwrap() - 10.0).abs() < 1e-9);
+
+        assert_eq!(burn_rate(100, 100, 0.95), Some(0.0));
+    }
+}
@@ -0,0 +1,147 @@
+//! Software and version of the relayers, as advertised in the memos of their txs,
+//! to follow the rollout of new relayer releases and correlate bugs with versions.
+//!
+//! - The Go relayer appends `rly(v2.4.2)` to the memos of its txs.
+//! - Hermes does not, but many operators mention its version in their memo, eg. `relayed with hermes v1.7.0`.
+//!
+//! Each signer which effected a packet over the last day is counted once per chain,
+//! with the version advertised in its most recent memo.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tokio::time;
+use tracing::error;
+
+use crate::{metrics::Metrics, Result};
+
+const UPDATE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// The window over which a signer is considered active, as an SQLite modifier
+const ACTIVE_WINDOW: &str = "-24 hours";
+
+const UNKNOWN: &str = "unknown";
+
+/// The relayer software and version advertised in a memo, if any
+pub fn classify(memo: &str) -> Option<(&'static str, String)> {
+    let lower = memo.to_lowercase();
+
+    if let Some(start) = lower.find("rly(") {
+        let rest = &lower[start + "rly(".len()..];
+        let version = version(rest.split(')').next().unwrap_or_default());
+        return Some(("rly", version.unwrap_or_else(|| UNKNOWN.to_string())));
+    }
+
+    if let Some(start) = lower.find("hermes") {
+        let rest = lower[start + "hermes".len()..].trim_start_matches([' ', '/', ':', '-', '@']);
+        let version = version(rest);
+        return Some(("hermes", version.unwrap_or_else(|| UNKNOWN.to_string())));
+    }
+
+    None
+}
+
+/// The version at the start of the given string, eg. `1.7.0` in `v1.7.0+abcdef | relayed by ...`
+fn version(s: &str) -> Option<String> {
+    let s = s.strip_prefix('v').unwrap_or(s);
+
+    let end = s
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '-'))
+        .unwrap_or(s.len());
+
+    let version = s[..end].trim_end_matches(['.', '-']);
+
+    version
+        .starts_with(|c: char| c.is_ascii_digit())
+        .then(|| version.to_string())
+}
+
+/// Periodically count the active relayers per software and version on each chain
+pub async fn run(pool: SqlitePool, metrics: Metrics) {
+    loop {
+        if let Err(e) = update(&pool, &metrics).await {
+            error!("Failed to count the relayer software versions: {e}");
+        }
+
+        time::sleep(UPDATE_INTERVAL).await;
+    }
+}
+
+async fn update(pool: &SqlitePool, metrics: &Metrics) -> Result<()> {
+    let query = r#"
+        SELECT txs.chain, packets.signer, txs.memo, MAX(packets.block_time)
+        FROM packets
+        JOIN txs ON packets.tx_id = txs.id
+        WHERE packets.effected
+            AND packets.signer IS NOT NULL
+            AND packets.block_time >= datetime('now', ?)
+        GROUP BY txs.chain, packets.signer, txs.memo
+    "#;
+
+    let rows: Vec<(String, String, String, String)> = sqlx::query_as(query)
+        .bind(ACTIVE_WINDOW)
+        .fetch_all(pool)
+        .await?;
+
+    // The most recent memo of each signer
+    let mut latest: BTreeMap<(String, String), (String, String)> = BTreeMap::new();
+
+    for (chain, signer, memo, block_time) in rows {
+        let entry = latest
+            .entry((chain, signer))
+            .or_insert_with(|| (block_time.clone(), memo.clone()));
+
+        if block_time > entry.0 {
+            *entry = (block_time, memo);
+        }
+    }
+
+    let mut relayers: BTreeMap<(String, &str, String), i64> = BTreeMap::new();
+
+    for ((chain, _), (_, memo)) in latest {
+        let (software, version) = classify(&memo).unwrap_or((UNKNOWN, UNKNOWN.to_string()));
+        *relayers.entry((chain, software, version)).or_default() += 1;
+    }
+
+    // Reset the gauge so that the versions which are no longer in use are removed
+    metrics.reset_ibc_relayer_software();
+
+    for ((chain, software, version), count) in relayers {
+        let Ok(chain_id) = chain::Id::try_from(chain) else {
+            continue;
+        };
+
+        metrics.ibc_relayer_software(&chain_id, software, &version, count);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("rly(v2.4.2)"), Some(("rly", "2.4.2".to_string())));
+        assert_eq!(
+            classify("Relayed by Polkachu | rly(v2.5.0-rc1)"),
+            Some(("rly", "2.5.0-rc1".to_string()))
+        );
+        assert_eq!(
+            classify("relayed with Hermes v1.7.0+abcdef"),
+            Some(("hermes", "1.7.0".to_string()))
+        );
+        assert_eq!(
+            classify("hermes/1.6.0"),
+            Some(("hermes", "1.6.0".to_string()))
+        );
+        assert_eq!(
+            classify("Relayed by CryptoCrew via Hermes"),
+            Some(("hermes", "unknown".to_string()))
+        );
+        assert_eq!(classify("Relayed by CryptoCrew"), None);
+        assert_eq!(classify(""), None);
+    }
+}
@@ -0,0 +1,100 @@
+//! Sequencing of the startup, so that nothing touches the database before it is set up:
+//! the database is opened and set up first, retrying with an exponential backoff when it is slow
+//! to open, eg. on network storage, then the metrics are populated, and only then is chainpulse ready.
+//!
+//! The current phase is served at `/health`.
+
+use std::{sync::Arc, time::Duration};
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tokio::{sync::watch, time};
+use tracing::{info, warn};
+
+use crate::{config, db, Result};
+
+/// The longest time to wait before retrying to open the database
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A phase of the startup, in order
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    OpeningDatabase,
+    SettingUpDatabase,
+    Populating,
+    Ready,
+}
+
+/// The current phase of the startup, shared with the server
+#[derive(Clone, Debug)]
+pub struct State(Arc<watch::Sender<Phase>>);
+
+impl State {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(Phase::OpeningDatabase);
+        Self(Arc::new(sender))
+    }
+
+    pub fn phase(&self) -> Phase {
+        *self.0.borrow()
+    }
+
+    pub fn set(&self, phase: Phase) {
+        self.0.send_replace(phase);
+    }
+
+    /// Wait until chainpulse is ready
+    pub async fn ready(&self) {
+        let mut receiver = self.0.subscribe();
+
+        // The sender is kept alive by `self`, so this cannot fail
+        let _ = receiver.wait_for(|phase| *phase == Phase::Ready).await;
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Open and set up the database, retrying up to `config.retries` times with an exponential backoff
+pub async fn open_database(
+    database: &config::Database,
+    config: config::Startup,
+    state: &State,
+) -> Result<SqlitePool> {
+    let mut backoff = config.backoff();
+    let mut attempt = 0;
+
+    loop {
+        match try_open_database(database, state).await {
+            Ok(pool) => return Ok(pool),
+            Err(e) if attempt < config.retries => {
+                attempt += 1;
+
+                warn!(
+                    "Failed to open the database, retrying in {backoff:?} ({attempt}/{}): {e}",
+                    config.retries
+                );
+
+                time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+async fn try_open_database(database: &config::Database, state: &State) -> Result<SqlitePool> {
+    state.set(Phase::OpeningDatabase);
+    let pool = db::open(database).await?;
+
+    state.set(Phase::SettingUpDatabase);
+    db::setup(&pool).await?;
+
+    info!("The database is ready");
+
+    Ok(pool)
+}
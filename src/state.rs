@@ -0,0 +1,355 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tendermint::chain;
+use tokio::sync::watch;
+
+use crate::trace::BlockTrace;
+
+/// Window over which the block rate is computed
+const BLOCK_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Number of most recent processed heights remembered per chain, to skip blocks replayed after a reconnect
+const PROCESSED_BLOCKS: usize = 1000;
+
+/// Number of traces of the most recent processed blocks kept per chain, for `/debug/last-block/{chain}`
+const TRACED_BLOCKS: usize = 5;
+
+/// Shared state of the collectors, one entry per monitored chain
+#[derive(Clone, Default)]
+pub struct Collectors {
+    chains: Arc<Mutex<BTreeMap<chain::Id, Collector>>>,
+    pauses: Arc<Mutex<BTreeMap<chain::Id, watch::Sender<bool>>>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Collector {
+    /// The endpoint currently in use
+    pub endpoint: String,
+
+    /// The state of the WebSocket connection
+    pub connection: Connection,
+
+    /// The last height for which a NewBlock event was received
+    pub last_height: Option<u64>,
+
+    /// The number of blocks received over the last minute
+    pub blocks_per_min: usize,
+
+    /// The number of times the collector had to reconnect
+    pub reconnects: u64,
+
+    /// The last error encountered by the collector
+    pub last_error: Option<String>,
+
+    #[serde(skip)]
+    recent_blocks: VecDeque<Instant>,
+
+    #[serde(skip)]
+    processed: BTreeSet<u64>,
+
+    #[serde(skip)]
+    traces: VecDeque<BlockTrace>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Connection {
+    Connecting,
+    Connected,
+    Disconnected,
+    Paused,
+
+    /// Stopped, as every endpoint reports another chain than the configured one
+    ChainIdMismatch,
+}
+
+/// Allows a collector to find out whether it has been paused or resumed via the admin API
+pub struct PauseHandle(watch::Receiver<bool>);
+
+impl PauseHandle {
+    pub fn is_paused(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Wait until the collector is paused
+    pub async fn paused(&mut self) {
+        self.wait_until(true).await
+    }
+
+    /// Wait until the collector is resumed
+    pub async fn resumed(&mut self) {
+        self.wait_until(false).await
+    }
+
+    async fn wait_until(&mut self, paused: bool) {
+        loop {
+            if self.is_paused() == paused {
+                return;
+            }
+
+            if self.0.changed().await.is_err() {
+                // The sender lives as long as `Collectors`, this should never happen
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+impl Collectors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn connecting(&self, chain_id: &chain::Id, endpoint: &str) {
+        self.update(chain_id, |collector| {
+            collector.endpoint = endpoint.to_string();
+            collector.connection = Connection::Connecting;
+        });
+    }
+
+    pub fn connected(&self, chain_id: &chain::Id) {
+        self.update(chain_id, |collector| {
+            collector.connection = Connection::Connected;
+        });
+    }
+
+    pub fn disconnected(&self, chain_id: &chain::Id) {
+        self.update(chain_id, |collector| {
+            collector.connection = Connection::Disconnected;
+            collector.recent_blocks.clear();
+        });
+    }
+
+    pub fn reconnect(&self, chain_id: &chain::Id) {
+        self.update(chain_id, |collector| {
+            collector.reconnects += 1;
+        });
+    }
+
+    pub fn chain_id_mismatch(&self, chain_id: &chain::Id) {
+        self.update(chain_id, |collector| {
+            collector.connection = Connection::ChainIdMismatch;
+            collector.recent_blocks.clear();
+        });
+    }
+
+    pub fn error(&self, chain_id: &chain::Id, error: &dyn std::fmt::Display) {
+        self.update(chain_id, |collector| {
+            collector.last_error = Some(error.to_string());
+        });
+    }
+
+    /// Register the given chain as pausable, and return a handle to its pause state
+    pub fn pause_handle(&self, chain_id: &chain::Id) -> PauseHandle {
+        let mut pauses = self.pauses.lock().unwrap();
+        let sender = pauses
+            .entry(chain_id.clone())
+            .or_insert_with(|| watch::channel(false).0);
+
+        PauseHandle(sender.subscribe())
+    }
+
+    /// Pause the collector for the given chain.
+    /// Returns `false` if there is no collector for that chain.
+    pub fn pause(&self, chain_id: &chain::Id) -> bool {
+        if !self.set_paused(chain_id, true) {
+            return false;
+        }
+
+        self.update(chain_id, |collector| {
+            collector.connection = Connection::Paused;
+            collector.recent_blocks.clear();
+        });
+
+        true
+    }
+
+    /// Resume the collector for the given chain.
+    /// Returns `false` if there is no collector for that chain.
+    pub fn resume(&self, chain_id: &chain::Id) -> bool {
+        self.set_paused(chain_id, false)
+    }
+
+    fn set_paused(&self, chain_id: &chain::Id, paused: bool) -> bool {
+        let pauses = self.pauses.lock().unwrap();
+
+        match pauses.get(chain_id) {
+            Some(sender) => {
+                sender.send_replace(paused);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Record a new block for the chain, returning the height of the previous one, if any
+    pub fn new_block(&self, chain_id: &chain::Id, height: u64) -> Option<u64> {
+        self.update(chain_id, |collector| {
+            let now = Instant::now();
+
+            collector.recent_blocks.push_back(now);
+            collector.prune_blocks(now);
+
+            // Blocks replayed after a reconnect must not move the last height backwards
+            let previous = collector.last_height;
+            collector.last_height = Some(previous.map_or(height, |previous| previous.max(height)));
+            previous
+        })
+    }
+
+    /// Claim the block at the given height for processing, returning `false` if it was already processed,
+    /// eg. when the same block is delivered again after a reconnect
+    pub fn claim_block(&self, chain_id: &chain::Id, height: u64) -> bool {
+        self.update(chain_id, |collector| {
+            if !collector.processed.insert(height) {
+                return false;
+            }
+
+            while collector.processed.len() > PROCESSED_BLOCKS {
+                collector.processed.pop_first();
+            }
+
+            true
+        })
+    }
+
+    /// Release a block which failed to be processed, so that it can be processed again
+    pub fn release_block(&self, chain_id: &chain::Id, height: u64) {
+        self.update(chain_id, |collector| {
+            collector.processed.remove(&height);
+        });
+    }
+
+    /// Keep the trace of a processed block, forgetting the oldest one once enough are kept
+    pub fn trace_block(&self, chain_id: &chain::Id, trace: BlockTrace) {
+        self.update(chain_id, |collector| {
+            collector.traces.push_back(trace);
+
+            while collector.traces.len() > TRACED_BLOCKS {
+                collector.traces.pop_front();
+            }
+        });
+    }
+
+    /// Returns the trace of the highest block processed among the most recent ones, if any.
+    /// Blocks are processed concurrently, so the last one to finish is not necessarily the highest.
+    pub fn last_block(&self, chain_id: &chain::Id) -> Option<BlockTrace> {
+        let chains = self.chains.lock().unwrap();
+        let collector = chains.get(chain_id)?;
+
+        collector
+            .traces
+            .iter()
+            .max_by_key(|trace| trace.height)
+            .cloned()
+    }
+
+    /// Returns a snapshot of the state of every collector
+    pub fn snapshot(&self) -> BTreeMap<chain::Id, Collector> {
+        let mut chains = self.chains.lock().unwrap();
+        let now = Instant::now();
+
+        for collector in chains.values_mut() {
+            collector.prune_blocks(now);
+        }
+
+        chains.clone()
+    }
+
+    fn update<R>(&self, chain_id: &chain::Id, f: impl FnOnce(&mut Collector) -> R) -> R {
+        let mut chains = self.chains.lock().unwrap();
+        let collector = chains
+            .entry(chain_id.clone())
+            .or_insert_with(Collector::new);
+        f(collector)
+    }
+}
+
+impl Collector {
+    fn new() -> Self {
+        Self {
+            endpoint: String::new(),
+            connection: Connection::Disconnected,
+            last_height: None,
+            blocks_per_min: 0,
+            reconnects: 0,
+            last_error: None,
+            recent_blocks: VecDeque::new(),
+            processed: BTreeSet::new(),
+            traces: VecDeque::new(),
+        }
+    }
+
+    fn prune_blocks(&mut self, now: Instant) {
+        while let Some(at) = self.recent_blocks.front() {
+            if now.duration_since(*at) > BLOCK_RATE_WINDOW {
+                self.recent_blocks.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        self.blocks_per_min = self.recent_blocks.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_block() {
+        let chain_id: chain::Id = "osmosis-1".parse().unwrap();
+        let collectors = Collectors::new();
+
+        assert!(collectors.claim_block(&chain_id, 1));
+        assert!(!collectors.claim_block(&chain_id, 1));
+
+        collectors.release_block(&chain_id, 1);
+        assert!(collectors.claim_block(&chain_id, 1));
+
+        for height in 2..=PROCESSED_BLOCKS as u64 + 1 {
+            assert!(collectors.claim_block(&chain_id, height));
+        }
+
+        // The oldest height has been forgotten
+        assert!(collectors.claim_block(&chain_id, 1));
+    }
+
+    #[test]
+    fn test_new_block_replay() {
+        let chain_id: chain::Id = "osmosis-1".parse().unwrap();
+        let collectors = Collectors::new();
+
+        assert_eq!(collectors.new_block(&chain_id, 10), None);
+        assert_eq!(collectors.new_block(&chain_id, 9), Some(10));
+        assert_eq!(collectors.new_block(&chain_id, 11), Some(10));
+    }
+
+    #[test]
+    fn test_last_block() {
+        let chain_id: chain::Id = "osmosis-1".parse().unwrap();
+        let collectors = Collectors::new();
+
+        assert!(collectors.last_block(&chain_id).is_none());
+
+        collectors.trace_block(&chain_id, BlockTrace::new(2));
+        collectors.trace_block(&chain_id, BlockTrace::new(1));
+        assert_eq!(collectors.last_block(&chain_id).unwrap().height, 2);
+
+        for height in 3..=TRACED_BLOCKS as u64 + 2 {
+            collectors.trace_block(&chain_id, BlockTrace::new(height));
+        }
+
+        assert_eq!(collectors.last_block(&chain_id).unwrap().height, 7);
+
+        // Only the most recent traces are kept
+        let chains = collectors.chains.lock().unwrap();
+        assert_eq!(chains[&chain_id].traces.len(), TRACED_BLOCKS);
+    }
+}
@@ -1,58 +1,585 @@
-use std::{cmp::Reverse, time::Duration};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use serde::Deserialize;
+use sqlx::SqlitePool;
 use tokio::time::sleep;
-use tracing::info;
+use tracing::{error, info, warn};
 
-use crate::{config::Chains, metrics::Metrics, Result};
+use crate::{
+    commitments::{self, Tracker},
+    config::{Chains, StuckPackets},
+    db::StuckRow,
+    metrics::Metrics,
+    Result,
+};
 
 const STATUS_URL: &str = "https://api-osmosis.imperator.co/ibc/v1/raw";
 
-pub async fn run(chains: Chains, metrics: Metrics) -> Result<()> {
+/// The sources of the stuck packets observations, as recorded in the `stuck_history` table
+/// and exported as the `source` label of `ibc_stuck_packets`
+const IMPERATOR: &str = "imperator";
+const NATIVE: &str = "native";
+const RPC: &str = "rpc";
+
+/// The labels of a channel reported as stuck: source chain, destination chain,
+/// source channel, destination channel and direction
+type StuckLabels = (String, String, String, String, &'static str);
+
+/// An observation of a channel on which packets are stuck, by one of the sources
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stuck {
+    pub src_chain: String,
+    pub dst_chain: String,
+    pub src_channel: String,
+    pub dst_channel: String,
+
+    /// Whether packets are stuck leaving or entering a monitored chain
+    pub direction: &'static str,
+
+    pub size_queue: i64,
+}
+
+impl Stuck {
+    fn key(&self) -> (String, String, String) {
+        (
+            self.src_chain.clone(),
+            self.dst_chain.clone(),
+            self.src_channel.clone(),
+        )
+    }
+
+    fn labels(&self) -> StuckLabels {
+        (
+            self.src_chain.clone(),
+            self.dst_chain.clone(),
+            self.src_channel.clone(),
+            self.dst_channel.clone(),
+            self.direction,
+        )
+    }
+}
+
+/// A channel reported as stuck by a source but not by another one, although both cover it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Disagreement {
+    pub src_chain: String,
+    pub src_channel: String,
+
+    /// The source reporting the channel as stuck
+    pub source: &'static str,
+
+    /// The sources covering the channel which do not report it as stuck
+    pub missing: Vec<&'static str>,
+
+    pub size_queue: i64,
+}
+
+/// The channels found stuck by a source in its latest poll
+#[derive(Clone, Debug)]
+pub struct Coverage {
+    pub source: &'static str,
+
+    /// The channels leaving a monitored chain covered by the source, or `None` if it covers all of them
+    pub covered: Option<HashSet<(String, String)>>,
+
+    pub stuck: Vec<Stuck>,
+}
+
+pub async fn run(
+    chains: Chains,
+    config: StuckPackets,
+    pool: SqlitePool,
+    metrics: Metrics,
+) -> Result<()> {
+    let mut hysteresis: HashMap<&'static str, Hysteresis> = HashMap::new();
+    let mut dst_channels = HashMap::new();
+    let mut reported: HashMap<&'static str, HashSet<StuckLabels>> = HashMap::new();
+    let mut tracker = Tracker::default();
+
+    let is_monitored = |chain: &str| chains.endpoints.keys().any(|id| id.as_str() == chain);
+
     loop {
-        let Ok(status) = fetch_status().await else {
-            sleep(Duration::from_secs(120)).await;
-            continue;
+        let started = Instant::now();
+        // The sources which failed to be polled, in which case the next poll is delayed by the backoff
+        let mut failed = Vec::new();
+
+        let status = if config.imperator {
+            match fetch_status().await {
+                Ok(status) => {
+                    metrics.chainpulse_status_fetch(IMPERATOR, "success", started.elapsed());
+                    Some(status)
+                }
+                Err(e) => {
+                    error!("Failed to fetch the status of the IBC channels: {e}");
+                    metrics.chainpulse_status_fetch(IMPERATOR, "error", started.elapsed());
+                    failed.push(IMPERATOR);
+                    None
+                }
+            }
+        } else {
+            None
         };
 
-        let mut stuck = Vec::new();
-
-        for chain_id in chains.endpoints.keys() {
-            stuck.extend(
-                status
-                    .by_chain(chain_id.as_str())
-                    .filter(|channel| channel.status.size_queue > 0),
+        // The channels found stuck by each source which could be polled, to be reconciled
+        let mut sources = Vec::new();
+
+        if let Some(status) = &status {
+            // Tells a feed which reports nothing stuck apart from one which stopped reporting the monitored chains
+            let covered = status
+                .channels()
+                .filter(|channel| {
+                    is_monitored(&channel.src_chain) || is_monitored(&channel.dst_chain)
+                })
+                .count();
+
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            metrics.chainpulse_status_last_success(IMPERATOR, now);
+            metrics.chainpulse_status_channels(IMPERATOR, covered);
+
+            let stuck = status
+                .channels()
+                .filter(|channel| {
+                    is_monitored(&channel.src_chain) || is_monitored(&channel.dst_chain)
+                })
+                .filter(|channel| channel.status.size_queue >= config.min_queue_size)
+                .collect();
+
+            let stuck = hysteresis.entry(IMPERATOR).or_default().update(
+                stuck,
+                |channel: &&ChannelStatus| channel.key(),
+                config.min_polls,
             );
+            let mut observations = Vec::with_capacity(stuck.len());
+
+            for channel in stuck {
+                let key = channel.key();
+
+                if !dst_channels.contains_key(&key) {
+                    match resolve_dst_channel(&pool, channel).await {
+                        Ok(Some(dst_channel)) => {
+                            dst_channels.insert(key.clone(), dst_channel);
+                        }
+                        Ok(None) => (),
+                        Err(e) => error!("Failed to resolve the destination channel: {e}"),
+                    }
+                }
+
+                observations.push(Stuck {
+                    src_chain: channel.src_chain.clone(),
+                    dst_chain: channel.dst_chain.clone(),
+                    src_channel: channel.src_channel.clone(),
+                    dst_channel: dst_channels.get(&key).cloned().unwrap_or_default(),
+                    direction: if is_monitored(&channel.src_chain) {
+                        "outgoing"
+                    } else {
+                        "incoming"
+                    },
+                    size_queue: channel.status.size_queue,
+                });
+            }
+
+            let outgoing = status
+                .channels()
+                .filter(|channel| is_monitored(&channel.src_chain))
+                .map(|channel| (channel.src_chain.clone(), channel.src_channel.clone()))
+                .collect::<HashSet<_>>();
+
+            report(&pool, &metrics, IMPERATOR, &observations, &mut reported).await;
+
+            sources.push(Coverage {
+                source: IMPERATOR,
+                covered: Some(outgoing),
+                stuck: observations,
+            });
+        }
+
+        match native_stuck(&pool, &config).await {
+            Ok(observations) => {
+                let observations = hysteresis.entry(NATIVE).or_default().update(
+                    observations,
+                    Stuck::key,
+                    config.min_polls,
+                );
+
+                report(&pool, &metrics, NATIVE, &observations, &mut reported).await;
+
+                // All the packets sent from the monitored chains are collected
+                sources.push(Coverage {
+                    source: NATIVE,
+                    covered: None,
+                    stuck: observations,
+                });
+            }
+            Err(e) => {
+                error!("Failed to find the stuck packets in the database: {e}");
+                failed.push(NATIVE);
+            }
+        }
+
+        if config.rpc {
+            let started = Instant::now();
+
+            match commitments::stuck(&chains, &pool, &config, &mut tracker).await {
+                Ok((covered, observations)) => {
+                    metrics.chainpulse_status_fetch(RPC, "success", started.elapsed());
+
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                    metrics.chainpulse_status_last_success(RPC, now);
+                    metrics.chainpulse_status_channels(RPC, covered.len());
+
+                    let observations = hysteresis.entry(RPC).or_default().update(
+                        observations,
+                        Stuck::key,
+                        config.min_polls,
+                    );
+
+                    report(&pool, &metrics, RPC, &observations, &mut reported).await;
+
+                    sources.push(Coverage {
+                        source: RPC,
+                        covered: Some(covered),
+                        stuck: observations,
+                    });
+                }
+                Err(e) => {
+                    error!("Failed to query the stuck packets from the chains: {e}");
+                    metrics.chainpulse_status_fetch(RPC, "error", started.elapsed());
+                    failed.push(RPC);
+                }
+            }
         }
 
-        stuck.sort_by_key(|channel| Reverse(channel.status.size_queue));
+        metrics.reset_ibc_stuck_packets_disagreement();
 
-        info!("IBC packets are stuck on {} channels:", stuck.len());
+        for disagreement in disagreements(&sources) {
+            warn!(
+                "{} [{}]: {} packets stuck according to {}, but not to {}",
+                disagreement.src_chain,
+                disagreement.src_channel,
+                disagreement.size_queue,
+                disagreement.source,
+                disagreement.missing.join(", ")
+            );
 
-        for channel in stuck {
-            metrics.ibc_stuck_packets(
-                channel.src_chain.as_str(),
-                channel.dst_chain.as_str(),
-                channel.src_channel.as_str(),
-                channel.status.size_queue,
+            metrics.ibc_stuck_packets_disagreement(
+                &disagreement.src_chain,
+                &disagreement.src_channel,
+                disagreement.source,
             );
+        }
 
-            info!(
-                "{} [{}] --> {}: {}",
-                channel.src_chain,
-                channel.src_channel,
-                channel.dst_chain,
-                channel.status.size_queue
+        let delay = poll_delay(&config, &failed);
+
+        if !failed.is_empty() {
+            warn!(
+                "Failed to poll {}, polling again in {delay:?}",
+                failed.join(", ")
             );
         }
 
-        sleep(Duration::from_secs(60)).await;
+        sleep(delay).await;
+    }
+}
+
+/// The delay until the next poll, backing off if any of the sources failed to be polled
+fn poll_delay(config: &StuckPackets, failed: &[&'static str]) -> Duration {
+    if failed.is_empty() {
+        config.interval()
+    } else {
+        config.error_backoff()
+    }
+}
+
+/// Export and record the channels found stuck by a source,
+/// and clear the gauges of the channels which have recovered since its last poll
+async fn report(
+    pool: &SqlitePool,
+    metrics: &Metrics,
+    source: &'static str,
+    observations: &[Stuck],
+    reported: &mut HashMap<&'static str, HashSet<StuckLabels>>,
+) {
+    let mut observations = observations.iter().collect::<Vec<_>>();
+    observations.sort_by_key(|stuck| Reverse(stuck.size_queue));
+
+    info!(
+        "IBC packets are stuck on {} channels according to {source}:",
+        observations.len()
+    );
+
+    let mut now_reported: HashSet<StuckLabels> = HashSet::new();
+
+    for stuck in observations {
+        metrics.ibc_stuck_packets(
+            stuck.src_chain.as_str(),
+            stuck.dst_chain.as_str(),
+            stuck.src_channel.as_str(),
+            stuck.dst_channel.as_str(),
+            stuck.direction,
+            source,
+            stuck.size_queue,
+        );
+
+        if let Err(e) = record_history(pool, stuck, source).await {
+            error!("Failed to record stuck packets history: {e}");
+        }
+
+        info!(
+            "{} [{}] --> {} [{}]: {}",
+            stuck.src_chain,
+            stuck.src_channel,
+            stuck.dst_chain,
+            stuck.dst_channel,
+            stuck.size_queue
+        );
+
+        now_reported.insert(stuck.labels());
+    }
+
+    let reported = reported.entry(source).or_default();
+
+    for (src_chain, dst_chain, src_channel, dst_channel, direction) in
+        reported.difference(&now_reported)
+    {
+        info!("{src_chain} [{src_channel}] --> {dst_chain} [{dst_channel}]: recovered");
+
+        metrics.ibc_stuck_packets(
+            src_chain,
+            dst_chain,
+            src_channel,
+            dst_channel,
+            direction,
+            source,
+            0,
+        );
+    }
+
+    *reported = now_reported;
+}
+
+/// Find the channels on which packets sent from a monitored chain have not been relayed for at least `native_min_age`
+async fn native_stuck(pool: &SqlitePool, config: &StuckPackets) -> Result<Vec<Stuck>> {
+    let query = r#"
+        SELECT sent_packets.chain, COALESCE(channels.counterparty_chain, ''),
+            sent_packets.src_channel, sent_packets.dst_channel, COUNT(*)
+        FROM sent_packets
+        LEFT JOIN channels
+            ON channels.chain = sent_packets.chain
+            AND channels.port = sent_packets.src_port
+            AND channels.channel = sent_packets.src_channel
+        WHERE sent_packets.resolved_at IS NULL
+            AND sent_packets.first_seen_at < datetime('now', ?)
+        GROUP BY 1, 2, 3, 4
+        HAVING COUNT(*) >= ?
+    "#;
+
+    let rows: Vec<(String, String, String, String, i64)> = sqlx::query_as(query)
+        .bind(format!("-{} seconds", config.native_min_age))
+        .bind(config.min_queue_size)
+        .fetch_all(pool)
+        .await?;
+
+    let stuck = rows
+        .into_iter()
+        .map(
+            |(src_chain, dst_chain, src_channel, dst_channel, size_queue)| Stuck {
+                src_chain,
+                dst_chain,
+                src_channel,
+                dst_channel,
+                direction: "outgoing",
+                size_queue,
+            },
+        )
+        .collect();
+
+    Ok(stuck)
+}
+
+/// The channels leaving a monitored chain which a source reports as stuck, but which another source covering them does not.
+/// Only the outgoing direction is compared, as it is the only one covered by all the sources.
+pub fn disagreements(sources: &[Coverage]) -> Vec<Disagreement> {
+    let outgoing = |coverage: &Coverage| {
+        coverage
+            .stuck
+            .iter()
+            .filter(|stuck| stuck.direction == "outgoing")
+            .map(|stuck| {
+                (
+                    (stuck.src_chain.clone(), stuck.src_channel.clone()),
+                    stuck.size_queue,
+                )
+            })
+            .collect::<HashMap<_, _>>()
+    };
+
+    let stuck = sources.iter().map(outgoing).collect::<Vec<_>>();
+    let mut disagreements = Vec::new();
+
+    for (source, reported) in sources.iter().zip(&stuck) {
+        for (key, size_queue) in reported {
+            let missing = sources
+                .iter()
+                .zip(&stuck)
+                .filter(|(other, _)| other.source != source.source)
+                .filter(|(other, _)| other.covered.as_ref().map_or(true, |c| c.contains(key)))
+                .filter(|(_, other_reported)| !other_reported.contains_key(key))
+                .map(|(other, _)| other.source)
+                .collect::<Vec<_>>();
+
+            if missing.is_empty() {
+                continue;
+            }
+
+            disagreements.push(Disagreement {
+                src_chain: key.0.clone(),
+                src_channel: key.1.clone(),
+                source: source.source,
+                missing,
+                size_queue: *size_queue,
+            });
+        }
+    }
+
+    disagreements.sort_by(|a, b| {
+        (&a.src_chain, &a.src_channel, a.source).cmp(&(&b.src_chain, &b.src_channel, b.source))
+    });
+
+    disagreements
+}
+
+/// Record an observation of a channel on which packets are stuck
+async fn record_history(pool: &SqlitePool, stuck: &Stuck, source: &str) -> Result<()> {
+    let query = r#"
+        INSERT INTO stuck_history
+            (observed_at, src_chain, dst_chain, src_channel, dst_channel, direction, size_queue, source)
+        VALUES
+            (datetime('now'), ?, ?, ?, ?, ?, ?, ?)
+    "#;
+
+    sqlx::query(query)
+        .bind(&stuck.src_chain)
+        .bind(&stuck.dst_chain)
+        .bind(&stuck.src_channel)
+        .bind(&stuck.dst_channel)
+        .bind(stuck.direction)
+        .bind(stuck.size_queue)
+        .bind(source)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Fetch the observations of stuck channels since the given date, or over the last 30 days,
+/// optionally only for the given chain and channel.
+pub async fn history(
+    pool: &SqlitePool,
+    chain: Option<&str>,
+    channel: Option<&str>,
+    since: Option<&str>,
+) -> Result<Vec<StuckRow>> {
+    let query = r#"
+        SELECT * FROM stuck_history
+        WHERE   observed_at >= COALESCE(?, datetime('now', '-30 days'))
+            AND (? IS NULL OR src_chain = ? OR dst_chain = ?)
+            AND (? IS NULL OR src_channel = ? OR dst_channel = ?)
+        ORDER BY observed_at ASC
+        LIMIT 10000
+    "#;
+
+    let rows = sqlx::query_as(query)
+        .bind(since)
+        .bind(chain)
+        .bind(chain)
+        .bind(chain)
+        .bind(channel)
+        .bind(channel)
+        .bind(channel)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows)
+}
+
+/// Resolve the destination channel of a channel from the packets seen on it, if any.
+/// The IBC status API only reports the source channel.
+async fn resolve_dst_channel(pool: &SqlitePool, channel: &ChannelStatus) -> Result<Option<String>> {
+    let query = r#"
+        SELECT dst_channel FROM sent_packets
+        WHERE chain = ? AND src_channel = ?
+        LIMIT 1
+    "#;
+
+    let sent: Option<(String,)> = sqlx::query_as(query)
+        .bind(&channel.src_chain)
+        .bind(&channel.src_channel)
+        .fetch_optional(pool)
+        .await?;
+
+    if let Some((dst_channel,)) = sent {
+        return Ok(Some(dst_channel));
+    }
+
+    // Packets are relayed on the destination chain, and acknowledged or timed out on the source chain
+    let query = r#"
+        SELECT packets.dst_channel
+        FROM packets JOIN txs ON packets.tx_id = txs.id
+        WHERE txs.chain IN (?, ?) AND packets.src_channel = ?
+        LIMIT 1
+    "#;
+
+    let relayed: Option<(String,)> = sqlx::query_as(query)
+        .bind(&channel.src_chain)
+        .bind(&channel.dst_chain)
+        .bind(&channel.src_channel)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(relayed.map(|(dst_channel,)| dst_channel))
+}
+
+/// Tracks for how many consecutive polls of a source each channel has been stuck,
+/// so that channels on which packets are only transiting are not reported.
+#[derive(Debug, Default)]
+pub struct Hysteresis {
+    polls: HashMap<(String, String, String), u32>,
+}
+
+impl Hysteresis {
+    /// Record the channels found stuck in the latest poll, identified by `key`, and return
+    /// those which have been stuck for at least `min_polls` consecutive polls.
+    pub fn update<T>(
+        &mut self,
+        stuck: Vec<T>,
+        key: impl Fn(&T) -> (String, String, String),
+        min_polls: u32,
+    ) -> Vec<T> {
+        let mut polls = HashMap::with_capacity(stuck.len());
+
+        for channel in &stuck {
+            let key = key(channel);
+            let count = self.polls.get(&key).copied().unwrap_or(0) + 1;
+            polls.insert(key, count);
+        }
+
+        // Channels which are no longer stuck are forgotten
+        self.polls = polls;
+
+        stuck
+            .into_iter()
+            .filter(|channel| self.polls[&key(channel)] >= min_polls)
+            .collect()
     }
 }
 
 pub async fn fetch_status() -> Result<IbcStatus> {
-    let resp = reqwest::get(STATUS_URL).await.unwrap();
-    let body = resp.text().await.unwrap();
+    let resp = reqwest::get(STATUS_URL).await?.error_for_status()?;
+    let body = resp.text().await?;
     let status = serde_json::from_str(&body)?;
     Ok(status)
 }
@@ -64,12 +591,6 @@ impl IbcStatus {
     pub fn channels(&self) -> impl Iterator<Item = &ChannelStatus> {
         self.0.iter()
     }
-
-    pub fn by_chain<'a>(&'a self, chain: &'a str) -> impl Iterator<Item = &'a ChannelStatus> {
-        self.0
-            .iter()
-            .filter(move |status| status.src_chain == chain || status.dst_chain == chain)
-    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -81,6 +602,14 @@ pub struct ChannelStatus {
 }
 
 impl ChannelStatus {
+    fn key(&self) -> (String, String, String) {
+        (
+            self.src_chain.clone(),
+            self.dst_chain.clone(),
+            self.src_channel.clone(),
+        )
+    }
+
     fn from_desc(desc: &str, status: Status) -> Result<ChannelStatus> {
         let (src_chain, src_channel, dst_chain) = parse_desc(desc)?;
 
@@ -189,4 +718,128 @@ mod tests {
         assert_eq!(src_channel, "channel-169");
         assert_eq!(dst_chain, "juno-1");
     }
+
+    #[test]
+    fn test_poll_delay() {
+        let config = StuckPackets {
+            interval: 60,
+            error_backoff: 120,
+            ..StuckPackets::default()
+        };
+
+        assert_eq!(poll_delay(&config, &[]), Duration::from_secs(60));
+
+        // Any failing source backs off, not only the Imperator feed
+        for source in [IMPERATOR, NATIVE, RPC] {
+            assert_eq!(poll_delay(&config, &[source]), Duration::from_secs(120));
+        }
+
+        assert_eq!(
+            poll_delay(&config, &[NATIVE, RPC]),
+            Duration::from_secs(120)
+        );
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let status = Status {
+            name: String::new(),
+            token_name: String::new(),
+            last_tx: String::new(),
+            counter: 0,
+            size_queue: 1,
+            is_trigger: false,
+        };
+
+        let a = ChannelStatus::from_desc("osmosis-1 [channel-0] --> cosmoshub-4", status.clone())
+            .unwrap();
+        let b = ChannelStatus::from_desc("osmosis-1 [channel-1] --> juno-1", status).unwrap();
+
+        let mut hysteresis = Hysteresis::default();
+
+        let key = |channel: &&ChannelStatus| channel.key();
+
+        assert!(hysteresis.update(vec![&a, &b], key, 2).is_empty());
+        assert_eq!(hysteresis.update(vec![&a], key, 2), vec![&a]);
+
+        // `b` was not stuck on the previous poll, so it starts over
+        assert_eq!(hysteresis.update(vec![&a, &b], key, 2), vec![&a]);
+        assert_eq!(hysteresis.update(vec![&a, &b], key, 2), vec![&a, &b]);
+    }
+
+    #[test]
+    fn test_disagreements() {
+        let stuck = |src_chain: &str, src_channel: &str, direction| Stuck {
+            src_chain: src_chain.to_string(),
+            dst_chain: String::new(),
+            src_channel: src_channel.to_string(),
+            dst_channel: String::new(),
+            direction,
+            size_queue: 3,
+        };
+
+        let covered = [("osmosis-1", "channel-0"), ("osmosis-1", "channel-1")]
+            .into_iter()
+            .map(|(chain, channel)| (chain.to_string(), channel.to_string()))
+            .collect();
+
+        let imperator = [
+            stuck("osmosis-1", "channel-0", "outgoing"),
+            stuck("juno-1", "channel-42", "incoming"),
+        ];
+
+        let native = [
+            stuck("osmosis-1", "channel-1", "outgoing"),
+            // Not covered by the feed
+            stuck("osmosis-1", "channel-2", "outgoing"),
+        ];
+
+        let coverage =
+            |source, covered: Option<&HashSet<(String, String)>>, stuck: &[Stuck]| Coverage {
+                source,
+                covered: covered.cloned(),
+                stuck: stuck.to_vec(),
+            };
+
+        let disagreements = disagreements(&[
+            coverage(IMPERATOR, Some(&covered), &imperator),
+            coverage(NATIVE, None, &native),
+        ]);
+
+        assert_eq!(
+            disagreements
+                .iter()
+                .map(|d| (d.src_channel.as_str(), d.source))
+                .collect::<Vec<_>>(),
+            [("channel-0", IMPERATOR), ("channel-1", NATIVE)]
+        );
+
+        assert!(super::disagreements(&[
+            coverage(IMPERATOR, Some(&covered), &imperator[..1]),
+            coverage(NATIVE, None, &imperator[..1]),
+        ])
+        .is_empty());
+
+        // The RPC source only covers channel-0, so it only disagrees with the native source on it
+        let rpc_covered = [("osmosis-1".to_string(), "channel-0".to_string())]
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let disagreements = super::disagreements(&[
+            coverage(IMPERATOR, Some(&covered), &imperator),
+            coverage(NATIVE, None, &native),
+            coverage(RPC, Some(&rpc_covered), &[]),
+        ]);
+
+        assert_eq!(
+            disagreements
+                .iter()
+                .map(|d| (d.src_channel.as_str(), d.source, d.missing.clone()))
+                .collect::<Vec<_>>(),
+            [
+                ("channel-0", IMPERATOR, vec![NATIVE, RPC]),
+                ("channel-1", NATIVE, vec![IMPERATOR]),
+            ]
+        );
+    }
 }
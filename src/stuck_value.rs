@@ -0,0 +1,190 @@
+//! Value of the ICS-20 transfers stuck on each channel, ie. sent from a monitored chain at least
+//! `native_min_age` ago and not relayed yet, to prioritize the channels to clear during incidents.
+//!
+//! The amounts of the tokens of the stuck transfers, as recorded in the `transfer_flows` table when they
+//! were sent, are summed per channel and denomination, and converted to USD with the configured prices,
+//! either static or fetched from a price feed.
+
+use std::collections::BTreeMap;
+
+use sqlx::SqlitePool;
+use tendermint::chain;
+use tokio::time;
+use tracing::{error, warn};
+
+use crate::{config::StuckValue, metrics::Metrics, Result};
+
+/// The amount of each denomination stuck on a channel
+pub type Amounts = BTreeMap<(String, String, String, String), f64>;
+
+/// Periodically compute the value of the transfers stuck on each channel
+pub async fn run(pool: SqlitePool, metrics: Metrics, config: StuckValue, min_age: u64) {
+    loop {
+        let mut prices = config.prices.clone();
+
+        if let Some(url) = &config.price_feed {
+            match fetch_prices(url).await {
+                // The static prices take precedence over the ones of the feed
+                Ok(fetched) => {
+                    for (denom, price) in fetched {
+                        prices.entry(denom).or_insert(price);
+                    }
+                }
+                Err(e) => warn!("Failed to fetch the prices from the price feed: {e}"),
+            }
+        }
+
+        if let Err(e) = update(&pool, &metrics, &prices, min_age).await {
+            error!("Failed to compute the value of stuck transfers: {e}");
+        }
+
+        time::sleep(config.interval()).await;
+    }
+}
+
+/// Fetch the prices of the price feed, a JSON object mapping denominations to their price in USD per base unit
+async fn fetch_prices(url: &str) -> Result<BTreeMap<String, f64>> {
+    let body = reqwest::get(url).await?.error_for_status()?.text().await?;
+
+    Ok(serde_json::from_str(&body)?)
+}
+
+async fn update(
+    pool: &SqlitePool,
+    metrics: &Metrics,
+    prices: &BTreeMap<String, f64>,
+    min_age: u64,
+) -> Result<()> {
+    let query = r#"
+        SELECT sent_packets.chain, sent_packets.src_channel, sent_packets.dst_channel,
+            transfer_flows.denom, transfer_flows.amount
+        FROM sent_packets
+        JOIN transfer_flows
+            ON transfer_flows.chain = sent_packets.chain
+            AND transfer_flows.channel = sent_packets.src_channel
+            AND transfer_flows.sequence = sent_packets.sequence
+            AND transfer_flows.kind = 'send'
+        WHERE sent_packets.resolved_at IS NULL
+            AND sent_packets.first_seen_at < datetime('now', ?)
+    "#;
+
+    let rows: Vec<(String, String, String, String, String)> = sqlx::query_as(query)
+        .bind(format!("-{min_age} seconds"))
+        .fetch_all(pool)
+        .await?;
+
+    let amounts = sum(rows);
+    let values = value(&amounts, prices);
+
+    // Reset the gauges so that channels which are no longer stuck are removed
+    metrics.reset_ibc_stuck_value();
+
+    for ((chain, src_channel, dst_channel, denom), amount) in amounts {
+        let Ok(chain_id) = chain::Id::try_from(chain) else {
+            continue;
+        };
+
+        metrics.ibc_stuck_value(&chain_id, &src_channel, &dst_channel, &denom, amount);
+    }
+
+    for ((chain, src_channel, dst_channel), usd) in values {
+        let Ok(chain_id) = chain::Id::try_from(chain) else {
+            continue;
+        };
+
+        metrics.ibc_stuck_value_usd(&chain_id, &src_channel, &dst_channel, usd);
+    }
+
+    Ok(())
+}
+
+/// Sum the amounts of the tokens per channel and denomination, skipping the amounts which are not numbers
+pub fn sum(rows: Vec<(String, String, String, String, String)>) -> Amounts {
+    let mut amounts = Amounts::new();
+
+    for (chain, src_channel, dst_channel, denom, amount) in rows {
+        let Ok(amount) = amount.parse::<f64>() else {
+            continue;
+        };
+
+        *amounts
+            .entry((chain, src_channel, dst_channel, denom))
+            .or_default() += amount;
+    }
+
+    amounts
+}
+
+/// The value in USD stuck on each channel, of the denominations with a known price
+pub fn value(
+    amounts: &Amounts,
+    prices: &BTreeMap<String, f64>,
+) -> BTreeMap<(String, String, String), f64> {
+    let mut values = BTreeMap::new();
+
+    for ((chain, src_channel, dst_channel, denom), amount) in amounts {
+        let Some(price) = prices.get(denom) else {
+            continue;
+        };
+
+        *values
+            .entry((chain.clone(), src_channel.clone(), dst_channel.clone()))
+            .or_default() += amount * price;
+    }
+
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(channel: &str, denom: &str, amount: &str) -> (String, String, String, String, String) {
+        (
+            "osmosis-1".to_string(),
+            channel.to_string(),
+            "channel-141".to_string(),
+            denom.to_string(),
+            amount.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_value() {
+        let amounts = sum(vec![
+            row("channel-0", "uosmo", "1000000"),
+            row("channel-0", "uosmo", "500000"),
+            row("channel-0", "uatom", "2000000"),
+            row("channel-0", "unknown", "42"),
+            row("channel-0", "uosmo", "not a number"),
+            row("channel-1", "uosmo", "10"),
+        ]);
+
+        let key = |channel: &str, denom: &str| {
+            (
+                "osmosis-1".to_string(),
+                channel.to_string(),
+                "channel-141".to_string(),
+                denom.to_string(),
+            )
+        };
+
+        assert_eq!(amounts[&key("channel-0", "uosmo")], 1500000.0);
+        assert_eq!(amounts.len(), 4);
+
+        let prices = BTreeMap::from([
+            ("uosmo".to_string(), 0.000001),
+            ("uatom".to_string(), 0.00001),
+        ]);
+
+        let values = value(&amounts, &prices);
+        let usd = values[&(
+            "osmosis-1".to_string(),
+            "channel-0".to_string(),
+            "channel-141".to_string(),
+        )];
+
+        assert!((usd - 21.5).abs() < 1e-9, "{usd}");
+        assert_eq!(values.len(), 2);
+    }
+}
@@ -0,0 +1,231 @@
+//! Live leaderboard of the relayers, in the terminal.
+//!
+//! Reads the packets recorded in the database over the last few minutes, ranks the signers
+//! of each chain by the number of packets they effected, and refreshes the screen periodically,
+//! which is handy to follow competitive relaying as it happens.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use sqlx::SqlitePool;
+use tokio::time;
+
+use crate::{config::Config, db, Result};
+
+/// Clear the screen and move the cursor to its top-left corner
+const CLEAR: &str = "\x1b[2J\x1b[H";
+
+/// What a signer relayed on a chain over the window
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Row {
+    signer: String,
+    effected: u64,
+    uneffected: u64,
+    frontruns: u64,
+}
+
+/// Options of the `top` command
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Only consider the packets relayed over the last given minutes
+    pub minutes: u64,
+
+    /// How often to refresh the leaderboard
+    pub interval: Duration,
+
+    /// How many relayers to show per chain
+    pub limit: usize,
+
+    /// Only show the given chain
+    pub chain: Option<String>,
+}
+
+/// Refresh the leaderboard until interrupted
+pub async fn run(config: &Config, options: Options) -> Result<()> {
+    let pool = db::connect(&config.database.path).await?;
+
+    loop {
+        let chains = leaderboard(&pool, &options).await?;
+        print!("{CLEAR}{}", render(&chains, &options));
+
+        time::sleep(options.interval).await;
+    }
+}
+
+/// The signers of each chain, ranked by the number of packets they effected
+async fn leaderboard(pool: &SqlitePool, options: &Options) -> Result<BTreeMap<String, Vec<Row>>> {
+    let query = r#"
+        SELECT txs.chain, packets.signer,
+            SUM(packets.effected), SUM(NOT packets.effected), 0
+        FROM packets JOIN txs ON packets.tx_id = txs.id
+        WHERE packets.block_time >= datetime('now', ?1)
+            AND packets.signer IS NOT NULL
+            AND (?2 IS NULL OR txs.chain = ?2)
+        GROUP BY txs.chain, packets.signer
+
+        UNION ALL
+
+        SELECT txs.chain, packets.effected_signer, 0, 0, COUNT(*)
+        FROM packets JOIN txs ON packets.tx_id = txs.id
+        WHERE packets.block_time >= datetime('now', ?1)
+            AND NOT packets.effected
+            AND packets.effected_signer IS NOT NULL
+            AND packets.effected_signer != packets.signer
+            AND (?2 IS NULL OR txs.chain = ?2)
+        GROUP BY txs.chain, packets.effected_signer
+    "#;
+
+    let rows: Vec<(String, String, i64, i64, i64)> = sqlx::query_as(query)
+        .bind(format!("-{} minutes", options.minutes))
+        .bind(options.chain.as_deref())
+        .fetch_all(pool)
+        .await?;
+
+    let mut signers = BTreeMap::<_, BTreeMap<_, Row>>::new();
+
+    for (chain, signer, effected, uneffected, frontruns) in rows {
+        let row = signers
+            .entry(chain)
+            .or_default()
+            .entry(signer.clone())
+            .or_insert_with(|| Row {
+                signer,
+                ..Row::default()
+            });
+
+        row.effected += effected as u64;
+        row.uneffected += uneffected as u64;
+        row.frontruns += frontruns as u64;
+    }
+
+    Ok(signers
+        .into_iter()
+        .map(|(chain, signers)| (chain, rank(signers.into_values().collect(), options.limit)))
+        .collect())
+}
+
+/// Sort the signers by the number of packets they effected then frontran, keeping the first ones
+fn rank(mut rows: Vec<Row>, limit: usize) -> Vec<Row> {
+    rows.sort_by(|a, b| {
+        (b.effected, b.frontruns)
+            .cmp(&(a.effected, a.frontruns))
+            .then_with(|| a.signer.cmp(&b.signer))
+    });
+
+    rows.truncate(limit);
+    rows
+}
+
+fn render(chains: &BTreeMap<String, Vec<Row>>, options: &Options) -> String {
+    let mut out = format!(
+        "chainpulse top - last {} minutes, refreshed every {}s\n",
+        options.minutes,
+        options.interval.as_secs()
+    );
+
+    if chains.is_empty() {
+        out.push_str("\nNo packets relayed over the window\n");
+        return out;
+    }
+
+    for (chain, rows) in chains {
+        out.push_str(&format!("\n{chain}\n"));
+
+        let width = rows
+            .iter()
+            .map(|row| row.signer.len())
+            .max()
+            .unwrap_or_default()
+            .max("SIGNER".len());
+
+        out.push_str(&format!(
+            "{:>4}  {:width$}  {:>8}  {:>10}  {:>9}  {:>7}\n",
+            "RANK", "SIGNER", "EFFECTED", "UNEFFECTED", "FRONTRUNS", "WIN %"
+        ));
+
+        for (rank, row) in rows.iter().enumerate() {
+            let total = row.effected + row.uneffected;
+            let win = 100.0 * row.effected as f64 / total.max(1) as f64;
+
+            out.push_str(&format!(
+                "{:>4}  {:width$}  {:>8}  {:>10}  {:>9}  {:>7.1}\n",
+                rank + 1,
+                row.signer,
+                row.effected,
+                row.uneffected,
+                row.frontruns,
+                win
+            ));
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(signer: &str, effected: u64, uneffected: u64, frontruns: u64) -> Row {
+        Row {
+            signer: signer.to_string(),
+            effected,
+            uneffected,
+            frontruns,
+        }
+    }
+
+    #[test]
+    fn test_rank() {
+        let rows = vec![
+            row("osmo1a", 10, 5, 0),
+            row("osmo1b", 30, 0, 2),
+            row("osmo1c", 10, 0, 4),
+            row("osmo1d", 1, 0, 0),
+        ];
+
+        let ranked = rank(rows, 3);
+
+        let signers = ranked.iter().map(|r| r.signer.as_str()).collect::<Vec<_>>();
+        assert_eq!(signers, ["osmo1b", "osmo1c", "osmo1a"]);
+    }
+
+    #[test]
+    fn test_render_empty() {
+        let options = Options {
+            minutes: 5,
+            interval: Duration::from_secs(2),
+            limit: 10,
+            chain: Some("osmosis-1".to_string()),
+        };
+
+        let out = render(&BTreeMap::new(), &options);
+        assert!(out.ends_with("No packets relayed over the window\n"));
+    }
+
+    #[test]
+    fn test_render() {
+        let options = Options {
+            minutes: 15,
+            interval: Duration::from_secs(5),
+            limit: 10,
+            chain: None,
+        };
+
+        let chains =
+            BTreeMap::from([("osmosis-1".to_string(), vec![row("osmo1relayer", 3, 1, 2)])]);
+
+        let out = render(&chains, &options);
+        let lines = out.lines().collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            [
+                "chainpulse top - last 15 minutes, refreshed every 5s",
+                "",
+                "osmosis-1",
+                "RANK  SIGNER        EFFECTED  UNEFFECTED  FRONTRUNS    WIN %",
+                "   1  osmo1relayer         3           1          2     75.0",
+            ]
+        );
+    }
+}
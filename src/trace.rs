@@ -0,0 +1,124 @@
+//! Traces of the last blocks processed for each chain, served at `/debug/last-block/{chain}`,
+//! to find out why a packet was or was not counted without digging through the logs.
+//!
+//! Only the IBC messages of a block are traced, along with the messages which failed to decode.
+
+use serde::Serialize;
+use tendermint::Time;
+
+/// What was done with a block
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BlockTrace {
+    pub height: u64,
+
+    /// The number of txs in the block
+    pub txs: usize,
+
+    /// When the block was processed
+    pub processed_at: String,
+
+    /// The IBC messages of the block, in order
+    pub msgs: Vec<MsgTrace>,
+
+    /// The error which stopped the processing of the block, if any
+    pub error: Option<String>,
+
+    /// The first message of each type which was ignored, to be sampled, see `ignored::Sampler`
+    #[serde(skip)]
+    pub ignored: Vec<IgnoredMsg>,
+}
+
+impl BlockTrace {
+    pub fn new(height: u64) -> Self {
+        Self {
+            height,
+            processed_at: Time::now().to_rfc3339(),
+            ..Self::default()
+        }
+    }
+
+    /// Record that a message was ignored, unless a message of the same type already was in this block
+    pub fn ignore(&mut self, type_url: &str, reason: &'static str, tx_hash: &str) {
+        if self.ignored.iter().any(|msg| msg.type_url == type_url) {
+            return;
+        }
+
+        self.ignored.push(IgnoredMsg {
+            type_url: type_url.to_string(),
+            reason,
+            tx_hash: tx_hash.to_string(),
+        });
+    }
+}
+
+/// A message which was ignored, either `other` when it is not one of the supported messages,
+/// or `not_relevant` when it does not relay any packet
+#[derive(Clone, Debug)]
+pub struct IgnoredMsg {
+    pub type_url: String,
+    pub reason: &'static str,
+    pub tx_hash: String,
+}
+
+/// What was done with a message
+#[derive(Clone, Debug, Serialize)]
+pub struct MsgTrace {
+    /// The index of the tx of the message in the block
+    pub tx_index: usize,
+
+    pub tx_hash: String,
+
+    /// The id of the tx in the database
+    pub tx_id: i64,
+
+    pub type_url: String,
+
+    /// A summary of the decoded message
+    pub summary: Option<String>,
+
+    pub signer: Option<String>,
+
+    /// The packet relayed by the message, if any
+    pub packet: Option<PacketTrace>,
+
+    pub outcome: Outcome,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PacketTrace {
+    pub sequence: u64,
+    pub src_port: String,
+    pub src_channel: String,
+    pub dst_port: String,
+    pub dst_channel: String,
+}
+
+/// How a message was classified, and what was recorded in the database for it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Outcome {
+    /// The message could not be decoded
+    DecodeFailed { error: String },
+
+    /// The signer of the message is not a valid address, and the message was skipped
+    InvalidSigner { error: String },
+
+    /// The message failed to be processed, and was recorded as a dead letter to be replayed later on
+    DeadLetter { error: String },
+
+    /// The message does not relay a packet, eg. a client update
+    NotRelevant,
+
+    /// The packet was already recorded for this tx, eg. by a previous run, and was skipped
+    AlreadyRecorded,
+
+    /// The packet was relayed first by this message, and recorded as effected
+    Effected,
+
+    /// The packet was already relayed by another tx, and recorded as frontrun by it
+    Frontrun {
+        effected_tx: i64,
+        effected_tx_hash: String,
+        effected_signer: String,
+    },
+}
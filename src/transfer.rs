@@ -0,0 +1,390 @@
+//! Decoding of ICS-20 fungible token transfer packet data.
+//!
+//! Both the original v1 format (a single token, JSON-encoded) and the v2 format
+//! (multiple tokens and forwarding info, either Protobuf or JSON-encoded) are supported,
+//! along with the acknowledgements of the packets, which tell whether the transfer failed.
+
+use ibc_proto::ibc::core::channel::v1::{acknowledgement::Response, Acknowledgement, Packet};
+use prost::Message;
+use serde::{de::IgnoredAny, Deserialize};
+
+/// A token carried by a transfer packet, with its full denomination path, eg. `transfer/channel-0/uatom`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub denom: String,
+    pub amount: String,
+}
+
+/// The decoded data of a transfer packet, independently of its version
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransferData {
+    pub tokens: Vec<Token>,
+    pub sender: String,
+    pub receiver: String,
+    pub memo: String,
+
+    /// The channels the tokens will be forwarded through after being received
+    pub forwarding: Vec<Hop>,
+}
+
+impl TransferData {
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if let Ok(v1) = serde_json::from_slice::<PacketDataV1>(data) {
+            return Some(v1.into());
+        }
+
+        if let Ok(v2) = serde_json::from_slice::<PacketDataV2>(data) {
+            return Some(v2.into());
+        }
+
+        if let Ok(v2) = PacketDataV2::decode(data) {
+            if !v2.tokens.is_empty() {
+                return Some(v2.into());
+            }
+        }
+
+        None
+    }
+
+    /// The channel the tokens will be forwarded through after being received, if any,
+    /// either from the forwarding info of a v2 packet, or from a packet-forward-middleware memo,
+    /// eg. `{"forward": {"receiver": "...", "port": "transfer", "channel": "channel-0"}}`
+    pub fn next_hop(&self) -> Option<Hop> {
+        if let Some(hop) = self.forwarding.first() {
+            return Some(hop.clone());
+        }
+
+        let memo = serde_json::from_str::<ForwardMemo>(&self.memo).ok()?;
+
+        Some(Hop {
+            port_id: memo.forward.port,
+            channel_id: memo.forward.channel,
+        })
+    }
+}
+
+/// The memo of a transfer to be forwarded by the packet-forward-middleware.
+/// The memo of the next hop, if any, is left for the forwarding chain to parse.
+#[derive(Clone, Debug, Deserialize)]
+struct ForwardMemo {
+    forward: Forward,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct Forward {
+    port: String,
+    channel: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct PacketDataV1 {
+    denom: String,
+    amount: String,
+    sender: String,
+    receiver: String,
+    #[serde(default)]
+    memo: String,
+}
+
+impl From<PacketDataV1> for TransferData {
+    fn from(data: PacketDataV1) -> Self {
+        Self {
+            tokens: vec![Token {
+                denom: data.denom,
+                amount: data.amount,
+            }],
+            sender: data.sender,
+            receiver: data.receiver,
+            memo: data.memo,
+            forwarding: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Deserialize, prost::Message)]
+struct PacketDataV2 {
+    #[prost(message, repeated, tag = "1")]
+    tokens: Vec<TokenV2>,
+    #[prost(string, tag = "2")]
+    sender: String,
+    #[prost(string, tag = "3")]
+    receiver: String,
+    #[prost(string, tag = "4")]
+    #[serde(default)]
+    memo: String,
+    #[prost(message, optional, tag = "5")]
+    #[serde(default)]
+    forwarding: Option<ForwardingV2>,
+}
+
+#[derive(Clone, PartialEq, Deserialize, prost::Message)]
+struct TokenV2 {
+    #[prost(message, optional, tag = "1")]
+    denom: Option<DenomV2>,
+    #[prost(string, tag = "2")]
+    amount: String,
+}
+
+#[derive(Clone, PartialEq, Deserialize, prost::Message)]
+struct DenomV2 {
+    #[prost(string, tag = "1")]
+    base: String,
+    #[prost(message, repeated, tag = "3")]
+    #[serde(default)]
+    trace: Vec<Hop>,
+}
+
+#[derive(Clone, PartialEq, Deserialize, prost::Message)]
+struct ForwardingV2 {
+    #[prost(string, tag = "1")]
+    #[serde(default)]
+    destination_memo: String,
+    #[prost(message, repeated, tag = "2")]
+    #[serde(default)]
+    hops: Vec<Hop>,
+}
+
+#[derive(Clone, Eq, PartialEq, Deserialize, prost::Message)]
+pub struct Hop {
+    #[prost(string, tag = "1")]
+    pub port_id: String,
+    #[prost(string, tag = "2")]
+    pub channel_id: String,
+}
+
+impl From<PacketDataV2> for TransferData {
+    fn from(data: PacketDataV2) -> Self {
+        let tokens = data
+            .tokens
+            .into_iter()
+            .map(|token| {
+                let denom = token.denom.unwrap_or_default();
+                let path = denom
+                    .trace
+                    .iter()
+                    .flat_map(|hop| [hop.port_id.as_str(), hop.channel_id.as_str()])
+                    .chain([denom.base.as_str()])
+                    .collect::<Vec<_>>()
+                    .join("/");
+
+                Token {
+                    denom: path,
+                    amount: token.amount,
+                }
+            })
+            .collect();
+
+        Self {
+            tokens,
+            sender: data.sender,
+            receiver: data.receiver,
+            memo: data.memo,
+            forwarding: data.forwarding.map(|f| f.hops).unwrap_or_default(),
+        }
+    }
+}
+
+/// The denomination under which a token received over a channel is known on the receiving chain.
+///
+/// Tokens returning to their origin chain lose the hop of the channel they are received over,
+/// eg. `transfer/channel-141/uatom` received over `transfer/channel-141` becomes `uatom`,
+/// while the other tokens gain the hop of the receiving channel, following ICS-20.
+pub fn received_denom(packet: &Packet, denom: &str) -> String {
+    let prefix = format!("{}/{}/", packet.source_port, packet.source_channel);
+
+    match denom.strip_prefix(&prefix) {
+        Some(base) => base.to_string(),
+        None => format!(
+            "{}/{}/{denom}",
+            packet.destination_port, packet.destination_channel
+        ),
+    }
+}
+
+/// The outcome of a packet, as reported by its ICS-04 acknowledgement
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ack {
+    Success,
+    Error(String),
+}
+
+impl Ack {
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if let Ok(ack) = serde_json::from_slice::<AckJson>(data) {
+            return Some(match ack {
+                AckJson::Result(_) => Self::Success,
+                AckJson::Error(error) => Self::Error(error),
+            });
+        }
+
+        match Acknowledgement::decode(data).ok()?.response? {
+            Response::Result(_) => Some(Self::Success),
+            Response::Error(error) => Some(Self::Error(error)),
+        }
+    }
+}
+
+/// The JSON encoding of acknowledgements used by ICS-20, eg. `{"result":"AQ=="}` or `{"error":"..."}`
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AckJson {
+    Result(IgnoredAny),
+    Error(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_hop() {
+        let data = |memo: &str| TransferData {
+            tokens: Vec::new(),
+            sender: String::new(),
+            receiver: String::new(),
+            memo: memo.to_string(),
+            forwarding: Vec::new(),
+        };
+
+        let memo = r#"{"forward": {"receiver": "osmo1...", "port": "transfer", "channel": "channel-0",
+            "timeout": "10m", "retries": 2, "next": {"forward": {"receiver": "juno1...", "port": "transfer", "channel": "channel-42"}}}}"#;
+
+        assert_eq!(
+            data(memo).next_hop(),
+            Some(Hop {
+                port_id: "transfer".to_string(),
+                channel_id: "channel-0".to_string()
+            })
+        );
+
+        assert_eq!(data("").next_hop(), None);
+        assert_eq!(data("relayed by hermes").next_hop(), None);
+        assert_eq!(
+            data(r#"{"wasm": {"contract": "osmo1..."}}"#).next_hop(),
+            None
+        );
+
+        let hop = Hop {
+            port_id: "transfer".to_string(),
+            channel_id: "channel-141".to_string(),
+        };
+
+        let forwarded = TransferData {
+            forwarding: vec![hop.clone()],
+            ..data("")
+        };
+
+        assert_eq!(forwarded.next_hop(), Some(hop));
+    }
+
+    #[test]
+    fn test_decode_v1() {
+        let data = br#"{"amount":"1000","denom":"transfer/channel-0/uatom","receiver":"osmo1abc","sender":"cosmos1abc"}"#;
+        let data = TransferData::decode(data).unwrap();
+
+        assert_eq!(
+            data.tokens,
+            vec![Token {
+                denom: "transfer/channel-0/uatom".to_string(),
+                amount: "1000".to_string(),
+            }]
+        );
+        assert_eq!(data.sender, "cosmos1abc");
+        assert_eq!(data.receiver, "osmo1abc");
+        assert_eq!(data.memo, "");
+        assert!(data.forwarding.is_empty());
+    }
+
+    #[test]
+    fn test_decode_v2_json() {
+        let data = br#"{
+            "tokens": [
+                {"denom": {"base": "uatom", "trace": [{"port_id": "transfer", "channel_id": "channel-0"}]}, "amount": "1000"},
+                {"denom": {"base": "uosmo", "trace": []}, "amount": "42"}
+            ],
+            "sender": "cosmos1abc",
+            "receiver": "osmo1abc",
+            "memo": "hello",
+            "forwarding": {"hops": [{"port_id": "transfer", "channel_id": "channel-141"}], "destination_memo": ""}
+        }"#;
+        let data = TransferData::decode(data).unwrap();
+
+        assert_eq!(data.tokens.len(), 2);
+        assert_eq!(data.tokens[0].denom, "transfer/channel-0/uatom");
+        assert_eq!(data.tokens[0].amount, "1000");
+        assert_eq!(data.tokens[1].denom, "uosmo");
+        assert_eq!(data.tokens[1].amount, "42");
+        assert_eq!(data.memo, "hello");
+        assert_eq!(data.forwarding[0].channel_id, "channel-141");
+    }
+
+    #[test]
+    fn test_decode_v2_proto() {
+        let packet = PacketDataV2 {
+            tokens: vec![TokenV2 {
+                denom: Some(DenomV2 {
+                    base: "uatom".to_string(),
+                    trace: vec![],
+                }),
+                amount: "1000".to_string(),
+            }],
+            sender: "cosmos1abc".to_string(),
+            receiver: "osmo1abc".to_string(),
+            memo: String::new(),
+            forwarding: None,
+        };
+
+        let data = TransferData::decode(&packet.encode_to_vec()).unwrap();
+
+        assert_eq!(data.tokens[0].denom, "uatom");
+        assert_eq!(data.tokens[0].amount, "1000");
+        assert_eq!(data.sender, "cosmos1abc");
+    }
+
+    #[test]
+    fn test_decode_garbage() {
+        assert_eq!(TransferData::decode(b"not a transfer packet"), None);
+    }
+
+    #[test]
+    fn test_received_denom() {
+        let packet = Packet {
+            source_port: "transfer".to_string(),
+            source_channel: "channel-141".to_string(),
+            destination_port: "transfer".to_string(),
+            destination_channel: "channel-0".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            received_denom(&packet, "transfer/channel-141/uatom"),
+            "uatom"
+        );
+        assert_eq!(received_denom(&packet, "uosmo"), "transfer/channel-0/uosmo");
+        assert_eq!(
+            received_denom(&packet, "transfer/channel-42/uatom"),
+            "transfer/channel-0/transfer/channel-42/uatom"
+        );
+    }
+
+    #[test]
+    fn test_decode_ack() {
+        assert_eq!(Ack::decode(br#"{"result":"AQ=="}"#), Some(Ack::Success));
+        assert_eq!(
+            Ack::decode(
+                br#"{"error":"ABCI code: 5: error handling packet: see events for details"}"#
+            ),
+            Some(Ack::Error(
+                "ABCI code: 5: error handling packet: see events for details".to_string()
+            ))
+        );
+
+        let ack = Acknowledgement {
+            response: Some(Response::Error("insufficient funds".to_string())),
+        };
+        assert_eq!(
+            Ack::decode(&ack.encode_to_vec()),
+            Some(Ack::Error("insufficient funds".to_string()))
+        );
+    }
+}